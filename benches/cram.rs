@@ -0,0 +1,93 @@
+//! Compares scanning a generated CRAM file with 200k records with all fields decoded and with
+//! only FLAG, RNAME and POS decoded (see `bam::Read::set_cram_decode_options`).
+//!
+//! Run with `cargo bench --bench cram`.
+
+use std::time::{Duration, Instant};
+
+use rust_htslib::bam::{self, header::HeaderRecord, Fields, Read};
+use rust_htslib::utils::{set_log_level, LogLevel};
+
+const N_RECORDS: usize = 200_000;
+const REF_LEN: usize = 1_000_000;
+const ROUNDS: u32 = 3;
+
+fn write_cram(path: &std::path::Path) {
+    // a pseudo-random reference, so that reads are not trivially compressible
+    let mut state = 42u32;
+    let reference: Vec<u8> = (0..REF_LEN)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            b"ACGT"[(state >> 16) as usize % 4]
+        })
+        .collect();
+    let mut header = bam::Header::new();
+    header.push_record(
+        HeaderRecord::new(b"SQ")
+            .push_tag(b"SN", "chr1")
+            .push_tag(b"LN", REF_LEN),
+    );
+    // without a reference, htslib embeds a consensus reference (and warns about it)
+    let mut writer = bam::Writer::from_path(path, &header, bam::Format::Cram).unwrap();
+    let cigar = bam::record::CigarString(vec![bam::record::Cigar::Match(100)]);
+    let mut record = bam::Record::new();
+    for i in 0..N_RECORDS {
+        let pos = i * (REF_LEN - 100) / N_RECORDS;
+        let mut seq = reference[pos..pos + 100].to_vec();
+        // a mismatch, to be encoded as a feature of the read
+        seq[i % 100] = if seq[i % 100] == b'A' { b'C' } else { b'A' };
+        let qual: Vec<u8> = (0..100).map(|j| 20 + ((i + j) % 20) as u8).collect();
+        record.set(format!("read{}", i).as_bytes(), Some(&cigar), &seq, &qual);
+        record.set_tid(0);
+        record.set_pos(pos as i64);
+        record.set_mapq(60);
+        record.set_flags(if i % 2 == 0 { 0 } else { 16 });
+        record
+            .push_aux(b"XS", bam::record::Aux::I32(i as i32))
+            .unwrap();
+        writer.write(&record).unwrap();
+        record.remove_aux(b"XS").unwrap();
+    }
+}
+
+/// Scan the file a few times and return the fastest run.
+fn bench(path: &std::path::Path, fields: Option<Fields>) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let mut reader = bam::Reader::from_path(path).unwrap();
+            if let Some(fields) = fields {
+                reader.set_cram_decode_options(fields, false).unwrap();
+            }
+            let start = Instant::now();
+            let mut record = bam::Record::new();
+            let mut n_reverse = 0;
+            while let Some(result) = reader.read(&mut record) {
+                result.unwrap();
+                n_reverse += record.is_reverse() as usize;
+            }
+            let elapsed = start.elapsed();
+            assert_eq!(n_reverse, N_RECORDS / 2);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let tmp = tempfile::Builder::new()
+        .prefix("rust-htslib-bench")
+        .tempdir()
+        .unwrap();
+    let path = tmp.path().join("bench.cram");
+    set_log_level(LogLevel::Error);
+    write_cram(&path);
+
+    let all = bench(&path, None);
+    let reduced = bench(&path, Some(Fields::FLAG | Fields::RNAME | Fields::POS));
+
+    println!("{} records, best of {} rounds:", N_RECORDS, ROUNDS);
+    println!("all fields:        {:?}", all);
+    println!("FLAG, RNAME, POS:  {:?}", reduced);
+
+    tmp.close().unwrap();
+}
@@ -0,0 +1,78 @@
+//! Compares retrieving per-read bases and qualities in a pileup via `Alignment::record` and via
+//! the `Alignment` accessors, on a generated BAM file with 200k records.
+//!
+//! Run with `cargo bench --bench pileup`.
+
+use std::time::{Duration, Instant};
+
+use rust_htslib::bam::{self, header::HeaderRecord, Read};
+
+const N_RECORDS: usize = 200_000;
+const ROUNDS: u32 = 3;
+
+fn write_bam(path: &std::path::Path) {
+    let mut header = bam::Header::new();
+    header.push_record(
+        HeaderRecord::new(b"SQ")
+            .push_tag(b"SN", "chr1")
+            .push_tag(b"LN", 250_000_000),
+    );
+    let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam).unwrap();
+    let cigar = bam::record::CigarString(vec![bam::record::Cigar::Match(100)]);
+    let seq = [b'A'; 100];
+    let qual = [30; 100];
+    let mut record = bam::Record::new();
+    for i in 0..N_RECORDS {
+        record.set(format!("read{}", i).as_bytes(), Some(&cigar), &seq, &qual);
+        record.set_tid(0);
+        record.set_pos(i as i64 * 10);
+        record.set_flags(if i % 2 == 0 { 0 } else { 16 });
+        writer.write(&record).unwrap();
+    }
+}
+
+/// Sum up the base qualities of all pileups with `f` a few times and return the fastest run.
+fn bench<F: Fn(&bam::pileup::Alignment<'_>) -> u64>(path: &std::path::Path, f: F) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let mut reader = bam::Reader::from_path(path).unwrap();
+            let start = Instant::now();
+            let mut sum = 0;
+            for pileup in reader.pileup() {
+                for alignment in pileup.unwrap().alignments() {
+                    sum += f(&alignment);
+                }
+            }
+            let elapsed = start.elapsed();
+            assert_eq!(sum, N_RECORDS as u64 * 100 * 30);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let tmp = tempfile::Builder::new()
+        .prefix("rust-htslib-bench")
+        .tempdir()
+        .unwrap();
+    let path = tmp.path().join("bench.bam");
+    write_bam(&path);
+
+    let record = bench(&path, |alignment| {
+        let qpos = alignment.qpos().unwrap();
+        let record = alignment.record();
+        assert_eq!(record.seq()[qpos], b'A');
+        u64::from(record.qual()[qpos])
+    });
+    let accessors = bench(&path, |alignment| {
+        assert_eq!(alignment.base(), Some(b'A'));
+        u64::from(alignment.base_qual().unwrap())
+    });
+
+    println!("{} records, best of {} rounds:", N_RECORDS, ROUNDS);
+    println!("record():           {:?}", record);
+    println!("base()/base_qual(): {:?}", accessors);
+
+    tmp.close().unwrap();
+}
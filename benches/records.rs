@@ -0,0 +1,84 @@
+//! Compares the record iteration APIs of `bam::Read` on a generated BAM file with 1M records.
+//!
+//! Run with `cargo bench --bench records`.
+
+use std::time::{Duration, Instant};
+
+use rust_htslib::bam::{self, header::HeaderRecord, Read};
+
+const N_RECORDS: usize = 1_000_000;
+const ROUNDS: u32 = 3;
+
+fn write_bam(path: &std::path::Path) {
+    let mut header = bam::Header::new();
+    header.push_record(
+        HeaderRecord::new(b"SQ")
+            .push_tag(b"SN", "chr1")
+            .push_tag(b"LN", 250_000_000),
+    );
+    let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam).unwrap();
+    let cigar = bam::record::CigarString(vec![bam::record::Cigar::Match(100)]);
+    let seq = [b'A'; 100];
+    let qual = [30; 100];
+    let mut record = bam::Record::new();
+    for i in 0..N_RECORDS {
+        record.set(format!("read{}", i).as_bytes(), Some(&cigar), &seq, &qual);
+        record.set_tid(0);
+        record.set_pos(i as i64 * 100);
+        record.set_flags(if i % 2 == 0 { 0 } else { 16 });
+        writer.write(&record).unwrap();
+    }
+}
+
+/// Run `f` over a freshly opened reader a few times and return the fastest run.
+fn bench<F: FnMut(&mut bam::Reader) -> usize>(path: &std::path::Path, mut f: F) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let mut reader = bam::Reader::from_path(path).unwrap();
+            let start = Instant::now();
+            let n_reverse = f(&mut reader);
+            let elapsed = start.elapsed();
+            assert_eq!(n_reverse, N_RECORDS / 2);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let tmp = tempfile::Builder::new()
+        .prefix("rust-htslib-bench")
+        .tempdir()
+        .unwrap();
+    let path = tmp.path().join("bench.bam");
+    write_bam(&path);
+
+    let records = bench(&path, |reader| {
+        reader
+            .records()
+            .filter(|r| r.as_ref().unwrap().is_reverse())
+            .count()
+    });
+    let rc_records = bench(&path, |reader| {
+        reader
+            .rc_records()
+            .filter(|r| r.as_ref().unwrap().is_reverse())
+            .count()
+    });
+    let read = bench(&path, |reader| {
+        let mut record = bam::Record::new();
+        let mut n = 0;
+        while let Some(result) = reader.read(&mut record) {
+            result.unwrap();
+            n += record.is_reverse() as usize;
+        }
+        n
+    });
+
+    println!("{} records, best of {} rounds:", N_RECORDS, ROUNDS);
+    println!("records():    {:?}", records);
+    println!("rc_records(): {:?}", rc_records);
+    println!("read():       {:?}", read);
+
+    tmp.close().unwrap();
+}
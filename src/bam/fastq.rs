@@ -0,0 +1,278 @@
+//! Conversion of SAM/BAM/CRAM records to FASTQ or FASTA, like `samtools fastq`.
+//!
+//! # Example
+//!
+//! ```
+//! use rust_htslib::bam::{self, fastq, Read};
+//!
+//! let mut bam = bam::Reader::from_path("test/test.bam").unwrap();
+//! let mut writer = fastq::Writer::new(Vec::new(), fastq::Format::Fastq);
+//! for record in bam.records() {
+//!     writer.write(&record.unwrap()).unwrap();
+//! }
+//! let fastq = writer.into_inner();
+//! assert!(fastq.starts_with(b"@I\n"));
+//! ```
+
+use std::io;
+
+use crate::bam::record::{Aux, Record};
+use crate::errors::{Error, Result};
+use crate::htslib;
+use crate::utils;
+
+/// Output format of a `Writer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Fastq,
+    Fasta,
+}
+
+/// Quality written for records without base qualities, like `samtools fastq -v 1`.
+const DEFAULT_QUAL: u8 = 1;
+
+/// Writes records as FASTQ or FASTA.
+///
+/// The sequence of reads aligned to the reverse strand is reverse complemented, and their
+/// qualities are reversed, so that the original read is written. By default, secondary and
+/// supplementary records are skipped, so that each read is written once, and `/1` or `/2` is
+/// appended to the names of paired reads.
+#[derive(Debug)]
+pub struct Writer<W: io::Write> {
+    inner: W,
+    format: Format,
+    tags: Vec<[u8; 2]>,
+    skip_secondary_supplementary: bool,
+    read_number_suffix: bool,
+    buffer: Vec<u8>,
+}
+
+impl<W: io::Write> Writer<W> {
+    /// Create a new writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - where to write to, e.g. a `std::io::BufWriter<std::fs::File>`
+    /// * `format` - whether to write FASTQ or FASTA
+    pub fn new(inner: W, format: Format) -> Self {
+        Writer {
+            inner,
+            format,
+            tags: Vec::new(),
+            skip_secondary_supplementary: true,
+            read_number_suffix: true,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Append the given aux tags (e.g. `CB`, `UB`) to the name line, in SAM format
+    /// (`TAG:TYPE:VALUE`) separated by tabs, like `samtools fastq -T`. Tags missing
+    /// in a record are left out.
+    pub fn set_tags(&mut self, tags: &[&[u8; 2]]) {
+        self.tags = tags.iter().map(|&&tag| tag).collect();
+    }
+
+    /// Whether to skip secondary and supplementary records (default: true).
+    pub fn set_skip_secondary_supplementary(&mut self, skip: bool) {
+        self.skip_secondary_supplementary = skip;
+    }
+
+    /// Whether to append `/1` and `/2` to the names of the first and second read of a pair
+    /// (default: true).
+    pub fn set_read_number_suffix(&mut self, suffix: bool) {
+        self.read_number_suffix = suffix;
+    }
+
+    /// Write the given record, unless it is skipped because it is secondary or supplementary.
+    /// Returns `Error::WriteRecord` if writing to the underlying writer fails.
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        let skip_flags = htslib::BAM_FSECONDARY | htslib::BAM_FSUPPLEMENTARY;
+        if self.skip_secondary_supplementary && u32::from(record.flags()) & skip_flags != 0 {
+            return Ok(());
+        }
+        self.buffer.clear();
+        format_record(
+            record,
+            self.format,
+            &self.tags,
+            self.read_number_suffix,
+            &mut self.buffer,
+        );
+        self.inner
+            .write_all(&self.buffer)
+            .map_err(|_| Error::WriteRecord)
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+pub(crate) fn format_record(
+    record: &Record,
+    format: Format,
+    tags: &[[u8; 2]],
+    read_number_suffix: bool,
+    out: &mut Vec<u8>,
+) {
+    out.push(match format {
+        Format::Fastq => b'@',
+        Format::Fasta => b'>',
+    });
+    out.extend_from_slice(record.qname());
+    if read_number_suffix {
+        if record.is_first_in_template() && !record.is_last_in_template() {
+            out.extend_from_slice(b"/1");
+        } else if record.is_last_in_template() && !record.is_first_in_template() {
+            out.extend_from_slice(b"/2");
+        }
+    }
+    for tag in tags {
+        if let Ok(aux) = record.aux(tag) {
+            out.push(b'\t');
+            push_aux(out, tag, &aux);
+        }
+    }
+    out.push(b'\n');
+
+    let mut seq = record.seq().as_bytes();
+    if record.is_reverse() {
+        seq.reverse();
+        for base in &mut seq {
+            *base = complement(*base);
+        }
+    }
+    out.extend_from_slice(&seq);
+    out.push(b'\n');
+
+    if format == Format::Fastq {
+        out.extend_from_slice(b"+\n");
+        let qual = record.qual();
+        let start = out.len();
+        if qual.first() == Some(&0xff) {
+            // qualities are missing
+            out.extend(qual.iter().map(|_| DEFAULT_QUAL));
+        } else {
+            out.extend_from_slice(qual);
+        }
+        utils::phred_to_ascii_in_place(&mut out[start..], utils::PHRED33);
+        if record.is_reverse() {
+            out[start..].reverse();
+        }
+        out.push(b'\n');
+    }
+}
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'M' => b'K',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'V' => b'B',
+        b'H' => b'D',
+        b'D' => b'H',
+        b'B' => b'V',
+        // =, S, W, N
+        base => base,
+    }
+}
+
+fn push_aux(out: &mut Vec<u8>, tag: &[u8], aux: &Aux<'_>) {
+    fn push_array<T: ToString>(out: &mut Vec<u8>, subtype: &str, values: impl Iterator<Item = T>) {
+        out.extend_from_slice(b"B:");
+        out.extend_from_slice(subtype.as_bytes());
+        for value in values {
+            out.push(b',');
+            out.extend_from_slice(value.to_string().as_bytes());
+        }
+    }
+
+    out.extend_from_slice(tag);
+    out.push(b':');
+    match aux {
+        Aux::Char(c) => out.extend_from_slice(&[b'A', b':', *c]),
+        Aux::I8(v) => out.extend_from_slice(format!("i:{}", v).as_bytes()),
+        Aux::U8(v) => out.extend_from_slice(format!("i:{}", v).as_bytes()),
+        Aux::I16(v) => out.extend_from_slice(format!("i:{}", v).as_bytes()),
+        Aux::U16(v) => out.extend_from_slice(format!("i:{}", v).as_bytes()),
+        Aux::I32(v) => out.extend_from_slice(format!("i:{}", v).as_bytes()),
+        Aux::U32(v) => out.extend_from_slice(format!("i:{}", v).as_bytes()),
+        Aux::Float(v) => out.extend_from_slice(format!("f:{}", v).as_bytes()),
+        Aux::Double(v) => out.extend_from_slice(format!("d:{}", v).as_bytes()),
+        Aux::String(v) => out.extend_from_slice(format!("Z:{}", v).as_bytes()),
+        Aux::HexByteArray(v) => out.extend_from_slice(format!("H:{}", v).as_bytes()),
+        Aux::ArrayI8(a) => push_array(out, "c", a.iter()),
+        Aux::ArrayU8(a) => push_array(out, "C", a.iter()),
+        Aux::ArrayI16(a) => push_array(out, "s", a.iter()),
+        Aux::ArrayU16(a) => push_array(out, "S", a.iter()),
+        Aux::ArrayI32(a) => push_array(out, "i", a.iter()),
+        Aux::ArrayU32(a) => push_array(out, "I", a.iter()),
+        Aux::ArrayFloat(a) => push_array(out, "f", a.iter()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bam::HeaderView;
+
+    fn records(lines: &[&str]) -> Vec<Record> {
+        let header = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n");
+        lines
+            .iter()
+            .map(|line| Record::from_sam(&header, line.as_bytes()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_to_fastq_reverse_strand() {
+        let records = records(&[
+            "fwd\t0\tchr1\t10\t60\t6M\t*\t0\t0\tAACGTN\tABCDEF",
+            "rev\t16\tchr1\t10\t60\t6M\t*\t0\t0\tAACGTN\tABCDEF",
+            "noqual\t16\tchr1\t10\t60\t3M\t*\t0\t0\tAAC\t*",
+            "noseq\t0\tchr1\t10\t60\t*\t*\t0\t0\t*\t*",
+        ]);
+        assert_eq!(records[0].to_fastq(), b"@fwd\nAACGTN\n+\nABCDEF\n");
+        assert_eq!(records[1].to_fastq(), b"@rev\nNACGTT\n+\nFEDCBA\n");
+        assert_eq!(records[2].to_fastq(), b"@noqual\nGTT\n+\n\"\"\"\n");
+        assert_eq!(records[3].to_fastq(), b"@noseq\n\n+\n\n");
+    }
+
+    #[test]
+    fn test_writer_pairs_and_tags() {
+        let records = records(&[
+            "pair\t99\tchr1\t10\t60\t4M\t=\t20\t14\tACGT\tIIII\tCB:Z:AAAC\tUB:Z:GGT\tXN:i:-3",
+            "pair\t2195\tchr1\t15\t60\t2S2M\t=\t10\t0\tACGT\tIIII\tCB:Z:AAAC",
+            "pair\t147\tchr1\t20\t60\t4M\t=\t10\t-14\tACGG\tIIIH\tCB:Z:AAAC",
+            "pair\t355\tchr1\t30\t0\t4M\t=\t20\t0\tACGT\tIIII",
+        ]);
+
+        let mut writer = Writer::new(Vec::new(), Format::Fastq);
+        writer.set_tags(&[b"CB", b"UB", b"XN"]);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "@pair/1\tCB:Z:AAAC\tUB:Z:GGT\tXN:i:-3\nACGT\n+\nIIII\n\
+             @pair/2\tCB:Z:AAAC\nCCGT\n+\nHIII\n"
+        );
+
+        let mut writer = Writer::new(Vec::new(), Format::Fasta);
+        writer.set_skip_secondary_supplementary(false);
+        writer.set_read_number_suffix(false);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            ">pair\nACGT\n>pair\nACGT\n>pair\nCCGT\n>pair\nACGT\n"
+        );
+    }
+}
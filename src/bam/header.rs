@@ -30,8 +30,16 @@ impl Header {
         }
     }
 
+    /// Create a header from the text of an existing one, keeping all its lines (including
+    /// comments) verbatim and in order.
     pub fn from_template(header: &HeaderView) -> Self {
-        let mut record = header.as_bytes().to_owned();
+        Self::from_bytes(header.as_bytes())
+    }
+
+    /// Create a header from raw SAM header text, e.g. as returned by `HeaderView::as_bytes`.
+    /// The lines are kept verbatim and in order, so they are written out unchanged.
+    pub fn from_bytes(text: &[u8]) -> Self {
+        let mut record = text.to_owned();
         // Strip off any trailing newline character.
         // Otherwise there could be a blank line in the
         // header which samtools (<=1.6) will complain
@@ -54,7 +62,8 @@ impl Header {
         self
     }
 
-    /// Add a comment to the header.
+    /// Add a comment (`@CO` line) to the header, after all records added so far.
+    /// The comment must not contain newline characters.
     pub fn push_comment(&mut self, comment: &[u8]) -> &mut Self {
         self.records.push([&b"@CO"[..], comment].join(&b'\t'));
         self
@@ -67,32 +76,32 @@ impl Header {
     /// This returns a header as a HashMap.
     /// Comment lines starting with "@CO" will NOT be included in the HashMap.
     /// Comment lines can be obtained by the `comments` function.
+    ///
+    /// Values are everything after the first colon of a field, so they may contain colons
+    /// themselves (e.g. URIs in `UR` tags or command lines in `CL` tags). Lines that are not
+    /// header records and fields that are not of the form `TAG:VALUE` are skipped.
     pub fn to_hashmap(&self) -> HashMap<String, Vec<LinearMap<String, String>>> {
         let mut header_map = HashMap::default();
 
         lazy_static! {
-            static ref REC_TYPE_RE: Regex = Regex::new(r"@([A-Z][A-Z])").unwrap();
-            static ref TAG_RE: Regex = Regex::new(r"([A-Za-z][A-Za-z0-9]):([ -~]*)").unwrap();
+            static ref REC_TYPE_RE: Regex = Regex::new(r"^@([A-Z][A-Z])$").unwrap();
+            static ref TAG_RE: Regex = Regex::new(r"^([A-Za-z][A-Za-z0-9]):(.*)$").unwrap();
         }
 
-        let header_string = String::from_utf8(self.to_bytes()).unwrap();
+        let header_bytes = self.to_bytes();
+        let header_string = String::from_utf8_lossy(&header_bytes);
 
         for line in header_string.split('\n').filter(|x| !x.is_empty()) {
-            let parts: Vec<_> = line.split('\t').filter(|x| !x.is_empty()).collect();
-            // assert!(rec_type_re.is_match(parts[0]));
-            let record_type = REC_TYPE_RE
-                .captures(parts[0])
-                .unwrap()
-                .get(1)
-                .unwrap()
-                .as_str()
-                .to_owned();
+            let mut parts = line.split('\t').filter(|x| !x.is_empty());
+            let record_type = match parts.next().and_then(|part| REC_TYPE_RE.captures(part)) {
+                Some(cap) => cap.get(1).unwrap().as_str().to_owned(),
+                None => continue,
+            };
             if record_type.eq("CO") {
                 continue;
             }
             let mut field = LinearMap::default();
-            for part in parts.iter().skip(1) {
-                let cap = TAG_RE.captures(part).unwrap();
+            for cap in parts.filter_map(|part| TAG_RE.captures(part)) {
                 let tag = cap.get(1).unwrap().as_str().to_owned();
                 let value = cap.get(2).unwrap().as_str().to_owned();
                 field.insert(tag, value);
@@ -105,6 +114,14 @@ impl Header {
         header_map
     }
 
+    /// Return the tags of the read group (`@RG` record) with the given ID, if present.
+    pub fn rg_by_id(&self, id: &str) -> Option<LinearMap<String, String>> {
+        self.to_hashmap()
+            .remove("RG")?
+            .into_iter()
+            .find(|rg| rg.get("ID").map(|v| v.as_str()) == Some(id))
+    }
+
     /// Returns an iterator of comment lines.
     pub fn comments(&self) -> impl Iterator<Item = Cow<str>> {
         self.records.iter().flat_map(|r| {
@@ -159,7 +176,8 @@ impl<'a> HeaderRecord<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::HeaderRecord;
+    use super::{Header, HeaderRecord};
+    use crate::bam::HeaderView;
 
     #[test]
     fn test_push_tag() {
@@ -174,4 +192,99 @@ mod tests {
 
         assert_eq!(record.to_bytes(), b"@HD\tX1:0\tX2:0\tX3:x\tX4:x\tX5:x");
     }
+
+    #[test]
+    fn test_to_hashmap() {
+        let text = b"@HD\tVN:1.6\tSO:coordinate\n\
+            @SQ\tSN:chr1\tLN:1000\tUR:file:///data/ref.fa\n\
+            @RG\tID:rg1\tSM:sample:1\tDS:S\xc3\xa4mple\n\
+            @RG\tID:rg2\tSM:sample2\n\
+            @PG\tID:bwa\tPN:bwa\tCL:bwa mem -R @RG\\tID:rg1 ref.fa\n\
+            @CO\tfree text: with colons";
+        let view = HeaderView::from_bytes(text);
+        let header = Header::from_template(&view);
+        let map = header.to_hashmap();
+
+        assert_eq!(map["HD"][0]["SO"], "coordinate");
+        assert_eq!(map["SQ"][0]["UR"], "file:///data/ref.fa");
+        assert_eq!(map["RG"].len(), 2);
+        assert_eq!(map["RG"][0]["SM"], "sample:1");
+        assert_eq!(map["RG"][0]["DS"], "S\u{e4}mple");
+        assert_eq!(map["PG"][0]["CL"], "bwa mem -R @RG\\tID:rg1 ref.fa");
+        assert!(!map.contains_key("CO"));
+        assert_eq!(
+            header.comments().collect::<Vec<_>>(),
+            vec!["free text: with colons"]
+        );
+
+        assert_eq!(header.rg_by_id("rg2").unwrap()["SM"], "sample2");
+        assert!(header.rg_by_id("rg3").is_none());
+        assert!(Header::new().rg_by_id("rg1").is_none());
+    }
+
+    #[test]
+    fn test_to_hashmap_malformed() {
+        let mut header = Header::new();
+        header.push_record(HeaderRecord::new(b"SQ").push_tag(b"SN", "chr1"));
+        header.records.push(b"not a header line".to_vec());
+        header.records.push(b"@RG\tID:rg1\tbroken\tSM:s1".to_vec());
+        let map = header.to_hashmap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["RG"][0].len(), 2);
+        assert_eq!(map["RG"][0]["SM"], "s1");
+    }
+
+    #[test]
+    fn test_modified_header_is_valid() {
+        let view = HeaderView::from_bytes(b"@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\n");
+        let mut header = Header::from_template(&view);
+        header.push_record(
+            HeaderRecord::new(b"RG")
+                .push_tag(b"ID", "rg1")
+                .push_tag(b"SM", "sample1"),
+        );
+        header.push_comment(b"added");
+
+        let view = HeaderView::from_header(&header);
+        assert_eq!(view.target_count(), 1);
+        assert_eq!(view.tid(b"chr1"), Some(0));
+        let header = Header::from_template(&view);
+        assert_eq!(header.rg_by_id("rg1").unwrap()["SM"], "sample1");
+        assert_eq!(header.comments().collect::<Vec<_>>(), vec!["added"]);
+    }
+
+    #[test]
+    fn test_from_bytes_round_trip() {
+        use crate::bam::{Format, Read, Reader, Writer};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let text: &[u8] = b"@HD\tVN:1.6\tSO:coordinate\n\
+            @CO\tcomment before the references\n\
+            @SQ\tSN:chr2\tLN:2000\tM5:abc\tAS:x\n\
+            @SQ\tLN:1000\tSN:chr1\n\
+            @PG\tID:tool\tPN:tool\tCL:tool --flag\n";
+        let sampath = tmp.path().join("in.sam");
+        std::fs::write(&sampath, text).unwrap();
+        let reader = Reader::from_path(&sampath).unwrap();
+        assert_eq!(reader.header().as_bytes(), text);
+
+        let bampath = tmp.path().join("out.bam");
+        let header = Header::from_bytes(reader.header().as_bytes());
+        Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+        let reader = Reader::from_path(&bampath).unwrap();
+        assert_eq!(reader.header().as_bytes(), text);
+        assert_eq!(reader.header().tid(b"chr1"), Some(1));
+
+        // a SAM file without any header lines
+        let sampath = tmp.path().join("headerless.sam");
+        std::fs::write(&sampath, "r1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n").unwrap();
+        let mut reader = Reader::from_path(&sampath).unwrap();
+        assert_eq!(reader.header().as_bytes(), b"");
+        assert_eq!(reader.records().count(), 1);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
 }
@@ -8,6 +8,7 @@
 use std::path::Path;
 use std::ptr;
 
+use crate::bam::{self, Read};
 use crate::errors::{Error, Result};
 use crate::htslib;
 use crate::utils;
@@ -21,6 +22,15 @@ pub enum Type {
 }
 
 /// Build a BAM index.
+///
+/// If `idx_path` is `None`, the index is written next to `bam_path`, with the extension
+/// `.bai` or `.csi` depending on `idx_type`. If htslib fails to index the file, it is scanned
+/// to report why: `Error::BamUnsorted` if it is not sorted by position, or the read error
+/// (e.g. `Error::BamTruncatedRecord`) if it is truncated or corrupt.
+///
+/// htslib's multi-threaded BGZF reader can deadlock on a truncated file. Therefore, the EOF
+/// marker is checked first, and files without one are indexed single-threaded regardless of
+/// `n_threads`.
 pub fn build<P: AsRef<Path>>(
     bam_path: P,
     idx_path: Option<P>,
@@ -39,6 +49,11 @@ pub fn build<P: AsRef<Path>>(
     } else {
         ptr::null()
     };
+    let n_threads = if n_threads > 0 && !has_eof_marker(bam_path.as_ref()) {
+        0
+    } else {
+        n_threads
+    };
     let ret = unsafe {
         htslib::sam_index_build3(
             utils::path_to_cstring(&bam_path).unwrap().as_ptr(),
@@ -49,7 +64,7 @@ pub fn build<P: AsRef<Path>>(
     };
     match ret {
         0 => Ok(()),
-        -1 => Err(Error::BamBuildIndex),
+        -1 => Err(diagnose(bam_path.as_ref())),
         -2 => Err(Error::BamOpen {
             target: bam_path.as_ref().to_str().unwrap().to_owned(),
         }),
@@ -59,9 +74,136 @@ pub fn build<P: AsRef<Path>>(
     }
 }
 
+/// Check without threads whether the file ends with a valid EOF marker. Files that cannot be
+/// opened are reported as fine here and left to `sam_index_build3` to complain about.
+fn has_eof_marker(bam_path: &Path) -> bool {
+    let htsfile = match utils::path_as_bytes(bam_path, true).and_then(|p| bam::hts_open(&p, b"r")) {
+        Ok(htsfile) => htsfile,
+        Err(_) => return true,
+    };
+    unsafe {
+        // 0 means the marker is absent, -1 that the check failed; 2 and 3 are for files
+        // where there is no marker to check.
+        let ret = htslib::hts_check_EOF(htsfile);
+        htslib::hts_close(htsfile);
+        ret > 0
+    }
+}
+
+/// Find out why indexing the given file failed.
+fn diagnose(bam_path: &Path) -> Error {
+    let mut reader = match bam::Reader::from_path(bam_path) {
+        Ok(reader) => reader,
+        Err(e) => return e,
+    };
+    let mut record = bam::Record::new();
+    // Unplaced reads (tid -1) are expected to come last and are not checked.
+    let unsorted = utils::find_unsorted(|| {
+        let res = reader.read(&mut record)?;
+        Some(res.map(|()| {
+            if record.tid() >= 0 {
+                Some((record.tid(), record.pos()))
+            } else {
+                None
+            }
+        }))
+    });
+    match unsorted {
+        Ok(Some(_)) => Error::BamUnsorted,
+        Ok(None) => Error::BamBuildIndex,
+        Err(e) => e,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bam::{IndexedReader, Record};
+    use crate::utils::fixtures::{bam_records, truncate, write_bam};
+
+    fn test_records() -> (Vec<Record>, bam::HeaderView) {
+        bam_records("test/test_spliced_reads.bam")
+    }
+
+    #[test]
+    fn test_index_build_written_bam() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bam_path = tmp.path().join("sorted.bam");
+        let (records, header) = test_records();
+        write_bam(&bam_path, &records, &header);
+
+        build(&bam_path, None, Type::Bai, 1).unwrap();
+        assert!(tmp.path().join("sorted.bam.bai").exists());
+
+        let mut reader = IndexedReader::from_path(&bam_path).unwrap();
+        let first = &records[0];
+        reader
+            .fetch((first.tid(), first.pos(), first.pos() + 1))
+            .unwrap();
+        let fetched: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert!(fetched.iter().any(|r| r.qname() == first.qname()));
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_index_build_unsorted_or_truncated() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let (mut records, header) = test_records();
+
+        let unsorted_path = tmp.path().join("unsorted.bam");
+        records.reverse();
+        write_bam(&unsorted_path, &records, &header);
+        assert_eq!(
+            build(&unsorted_path, None, Type::Bai, 0),
+            Err(Error::BamUnsorted)
+        );
+
+        let truncated_path = tmp.path().join("truncated.bam");
+        records.reverse();
+        write_bam(&truncated_path, &records, &header);
+        truncate(&truncated_path);
+        assert_eq!(
+            build(&truncated_path, None, Type::Bai, 0),
+            Err(Error::BamTruncatedRecord)
+        );
+        assert_eq!(
+            build(&truncated_path, None, Type::Bai, 2),
+            Err(Error::BamTruncatedRecord)
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    // Without the single-threaded fallback, htslib's threaded reader deadlocked on this input
+    // about once in a thousand runs, so this takes a while and is not run by default.
+    #[test]
+    #[ignore]
+    fn test_index_build_truncated_threaded() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let (records, header) = test_records();
+        let truncated_path = tmp.path().join("truncated.bam");
+        write_bam(&truncated_path, &records, &header);
+        truncate(&truncated_path);
+
+        for _ in 0..5000 {
+            assert_eq!(
+                build(&truncated_path, None, Type::Bai, 2),
+                Err(Error::BamTruncatedRecord)
+            );
+        }
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
 
     #[test]
     fn test_index_build() {
@@ -7,17 +7,21 @@
 
 pub mod buffer;
 pub mod ext;
+pub mod fastq;
 pub mod header;
 pub mod index;
 pub mod pileup;
 pub mod record;
+pub mod stats;
 
 #[cfg(feature = "serde_feature")]
 pub mod record_serde;
 
+use std::borrow::Borrow;
+use std::collections::HashSet;
 use std::ffi;
 use std::os::raw::c_char;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::slice;
 use std::str;
@@ -25,16 +29,16 @@ use std::str;
 use url::Url;
 
 use crate::errors::{Error, Result};
+use crate::faidx;
 use crate::htslib;
 use crate::tpool::ThreadPool;
-use crate::utils::path_as_bytes;
+use crate::utils::{self, contig_not_found, is_network_errno, parse_region, path_as_bytes};
 
 pub use crate::bam::buffer::RecordBuffer;
 pub use crate::bam::header::Header;
 pub use crate::bam::record::Record;
 use hts_sys::{hts_fmt_option, sam_fields};
 use std::convert::{TryFrom, TryInto};
-use std::mem::MaybeUninit;
 
 /// # Safety
 ///
@@ -49,6 +53,21 @@ unsafe fn set_threads(htsfile: *mut htslib::htsFile, n_threads: usize) -> Result
     }
 }
 
+/// # Safety
+///
+/// Implementation for `Read::set_cram_options`.
+unsafe fn set_cram_options(
+    htsfile: *mut htslib::htsFile,
+    fmt_opt: hts_fmt_option,
+    fields: sam_fields,
+) -> Result<()> {
+    if hts_sys::hts_set_opt(htsfile, fmt_opt, fields) != 0 {
+        Err(Error::HtsSetOpt)
+    } else {
+        Ok(())
+    }
+}
+
 unsafe fn set_thread_pool(htsfile: *mut htslib::htsFile, tpool: &ThreadPool) -> Result<()> {
     let mut b = tpool.handle.borrow_mut();
 
@@ -82,6 +101,71 @@ pub unsafe fn set_fai_filename<P: AsRef<Path>>(
     }
 }
 
+/// A set of SAM fields (e.g. `Fields::FLAG | Fields::POS`), used to tell the CRAM decoder
+/// which fields of the records are needed, see `Read::set_cram_decode_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fields(sam_fields);
+
+impl Fields {
+    pub const QNAME: Fields = Fields(htslib::sam_fields_SAM_QNAME);
+    pub const FLAG: Fields = Fields(htslib::sam_fields_SAM_FLAG);
+    pub const RNAME: Fields = Fields(htslib::sam_fields_SAM_RNAME);
+    pub const POS: Fields = Fields(htslib::sam_fields_SAM_POS);
+    pub const MAPQ: Fields = Fields(htslib::sam_fields_SAM_MAPQ);
+    pub const CIGAR: Fields = Fields(htslib::sam_fields_SAM_CIGAR);
+    pub const RNEXT: Fields = Fields(htslib::sam_fields_SAM_RNEXT);
+    pub const PNEXT: Fields = Fields(htslib::sam_fields_SAM_PNEXT);
+    pub const TLEN: Fields = Fields(htslib::sam_fields_SAM_TLEN);
+    pub const SEQ: Fields = Fields(htslib::sam_fields_SAM_SEQ);
+    pub const QUAL: Fields = Fields(htslib::sam_fields_SAM_QUAL);
+    pub const AUX: Fields = Fields(htslib::sam_fields_SAM_AUX);
+    /// Only the `RG` aux tag.
+    pub const RGAUX: Fields = Fields(htslib::sam_fields_SAM_RGAUX);
+
+    /// All fields, which is what htslib decodes by default.
+    pub fn all() -> Self {
+        Fields(
+            htslib::sam_fields_SAM_QNAME
+                | htslib::sam_fields_SAM_FLAG
+                | htslib::sam_fields_SAM_RNAME
+                | htslib::sam_fields_SAM_POS
+                | htslib::sam_fields_SAM_MAPQ
+                | htslib::sam_fields_SAM_CIGAR
+                | htslib::sam_fields_SAM_RNEXT
+                | htslib::sam_fields_SAM_PNEXT
+                | htslib::sam_fields_SAM_TLEN
+                | htslib::sam_fields_SAM_SEQ
+                | htslib::sam_fields_SAM_QUAL
+                | htslib::sam_fields_SAM_AUX
+                | htslib::sam_fields_SAM_RGAUX,
+        )
+    }
+
+    /// Whether all of the given fields are contained in this set.
+    pub fn contains(self, other: Fields) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The raw `sam_fields` bits, as expected by htslib.
+    pub fn bits(self) -> sam_fields {
+        self.0
+    }
+}
+
+impl std::ops::BitOr for Fields {
+    type Output = Fields;
+
+    fn bitor(self, rhs: Fields) -> Fields {
+        Fields(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Fields {
+    fn bitor_assign(&mut self, rhs: Fields) {
+        self.0 |= rhs.0;
+    }
+}
+
 /// A trait for a BAM reader with a read method.
 pub trait Read: Sized {
     /// Read next BAM record into given record.
@@ -131,6 +215,11 @@ pub trait Read: Sized {
     /// This is about 1% slower than the [`read`](#tymethod.read) based API in micro benchmarks,
     /// but has nicer ergonomics (and might not actually be slower in your applications).
     ///
+    /// The record is read into the previous one if that has been dropped already, so as long
+    /// as no record is kept around, no memory is allocated per record. A record that is still
+    /// in use is never overwritten, a new one is allocated instead. See `benches/records.rs`
+    /// for a comparison with [`records`](#tymethod.records).
+    ///
     /// Example:
     /// ```
     /// use rust_htslib::errors::Error;
@@ -160,16 +249,53 @@ pub trait Read: Sized {
     /// ```
     fn rc_records(&mut self) -> RcRecords<'_, Self>;
 
-    /// Iterator over pileups.
+    /// Iterator over groups of consecutive records with the same query name, e.g. all
+    /// alignments of a read pair in a file sorted with `samtools sort -n` or grouped with
+    /// `samtools collate`. Yields `Error::BamCoordinateSorted` if the header declares the
+    /// file to be sorted by coordinate, and `Error::BamNotQueryGrouped` if the records of a
+    /// query name are not next to each other. The iterator ends after either error.
+    fn record_groups(&mut self) -> RecordGroups<'_, Self> {
+        RecordGroups {
+            reader: self,
+            next: None,
+            checked_sort_order: false,
+            seen_qnames: HashSet::new(),
+            done: false,
+        }
+    }
+
+    /// Iterator over read pairs in a file grouped by query name (see `record_groups`).
+    /// Yields the primary alignments of the first and second read of each pair (in that
+    /// order), and `(record, None)` for unpaired reads. Secondary and supplementary
+    /// alignments are skipped, use `record_groups` to get them as well. If the mate of a
+    /// paired read is not next to it, `Error::BamNotQueryGrouped` is returned.
+    fn read_pairs(&mut self) -> ReadPairs<'_, Self> {
+        ReadPairs {
+            groups: self.record_groups(),
+        }
+    }
+
+    /// Iterator over pileups. After `IndexedReader::fetch`, this also yields positions outside
+    /// of the fetched region, use `Pileups::set_region` (or `IndexedReader::fetch_and_pileup`)
+    /// to restrict it.
     fn pileup(&mut self) -> pileup::Pileups<'_, Self>;
 
+    /// Iterator over owned pileups, which can be sent to other threads. This is the same as
+    /// `pileup().owned()`, see `Pileups::owned`.
+    fn pileup_owned(&mut self) -> pileup::OwnedPileups<'_, Self> {
+        self.pileup().owned()
+    }
+
     /// Return the htsFile struct
     fn htsfile(&self) -> *mut htslib::htsFile;
 
     /// Return the header.
     fn header(&self) -> &HeaderView;
 
-    /// Seek to the given virtual offset in the file
+    /// Seek to the given virtual offset in the file, e.g. one returned by `tell`. Reading then
+    /// resumes at the record that was next when the offset was obtained. Returns
+    /// `Error::FileSeek` for streams that are not BGZF-compressed (e.g. plain SAM) or cannot be
+    /// seeked (e.g. STDIN).
     fn seek(&mut self, offset: i64) -> Result<()> {
         let htsfile = unsafe { self.htsfile().as_ref() }.expect("bug: null pointer to htsFile");
         let ret = match htsfile.format.format {
@@ -180,6 +306,7 @@ pub trait Read: Sized {
                     libc::SEEK_SET,
                 ))
             },
+            _ if htsfile.is_bgzf() == 0 => return Err(Error::FileSeek),
             _ => unsafe { htslib::bgzf_seek(htsfile.fp.bgzf, offset, libc::SEEK_SET) },
         };
 
@@ -190,23 +317,45 @@ pub trait Read: Sized {
         }
     }
 
-    /// Report the current virtual offset
+    /// Report the current virtual offset, i.e. the offset of the next record when called
+    /// between reads. Returns -1 if the file is not BGZF-compressed (e.g. SAM or CRAM).
     fn tell(&self) -> i64 {
         // this reimplements the bgzf_tell macro
         let htsfile = unsafe { self.htsfile().as_ref() }.expect("bug: null pointer to htsFile");
+        if htsfile.is_bgzf() == 0 {
+            return -1;
+        }
         let bgzf = unsafe { *htsfile.fp.bgzf };
         (bgzf.block_address << 16) | (i64::from(bgzf.block_offset) & 0xFFFF)
     }
 
+    /// Check whether the file ends with the EOF marker block of BGZF (BAM) or CRAM files.
+    /// Returns `Ok(false)` if the marker is missing, which usually means that the file is
+    /// truncated, e.g. because the job writing it crashed. Returns `Error::CheckEof` if the
+    /// check is not possible, i.e. for streams (e.g. stdin) and uncompressed files, like SAM.
+    ///
+    /// The check does not change the position in the file, so it can be done at any time.
+    fn check_eof(&self) -> Result<bool> {
+        match unsafe { htslib::hts_check_EOF(self.htsfile()) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            _ => Err(Error::CheckEof),
+        }
+    }
+
     /// Activate multi-threaded BAM read support in htslib. This should permit faster
     /// reading of large BAM files.
     ///
     /// Setting `nthreads` to `0` does not change the current state.  Note that it is not
     /// possible to set the number of background threads below `1` once it has been set.
     ///
+    /// The background threads are only used for BGZF decompression. The reader itself
+    /// is still not `Sync`: records have to be read from a single thread, so call this
+    /// right after opening the file and before iterating.
+    ///
     /// # Arguments
     ///
-    /// * `n_threads` - number of extra background writer threads to use, must be `> 0`.
+    /// * `n_threads` - number of extra background reader threads to use, must be `> 0`.
     fn set_threads(&mut self, n_threads: usize) -> Result<()> {
         unsafe { set_threads(self.htsfile(), n_threads) }
     }
@@ -236,13 +385,45 @@ pub trait Read: Sized {
     ///             hts_sys::sam_fields_SAM_RNAME | hts_sys::sam_fields_SAM_FLAG).unwrap();
     /// ```
     fn set_cram_options(&mut self, fmt_opt: hts_fmt_option, fields: sam_fields) -> Result<()> {
-        unsafe {
-            if hts_sys::hts_set_opt(self.htsfile(), fmt_opt, fields) != 0 {
-                Err(Error::HtsSetOpt)
-            } else {
-                Ok(())
-            }
+        unsafe { set_cram_options(self.htsfile(), fmt_opt, fields) }
+    }
+
+    /// Only decode the given fields of CRAM records, e.g. `Fields::FLAG | Fields::RNAME |
+    /// Fields::POS` for computing coverage or flag statistics, which avoids the costly
+    /// decoding of sequences, qualities and aux tags. Skipped fields are set to placeholders:
+    /// The read name is `?`, the sequence and qualities are empty as if they were `*`, the CIGAR
+    /// is empty, there are no aux tags, and the mate is unmapped (RNEXT and PNEXT are -1).
+    /// Since htslib needs some fields to decode others, fields like MAPQ can still be filled.
+    ///
+    /// `decode_md` controls whether the MD and NM tags are regenerated from the reference
+    /// (htslib's default), which requires the sequence. Has to be called before reading the
+    /// first record. This is a no-op for SAM and BAM files, which are always fully decoded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::{Fields, Read, Reader};
+    ///
+    /// let mut cram = Reader::from_path("test/test_cram.cram").unwrap();
+    /// cram.set_reference("test/test_cram.fa").unwrap();
+    /// cram.set_cram_decode_options(Fields::FLAG | Fields::RNAME | Fields::POS, false)
+    ///     .unwrap();
+    /// let record = cram.records().next().unwrap().unwrap();
+    /// assert_eq!(record.qname(), b"?");
+    /// assert!(record.seq().is_empty());
+    /// ```
+    fn set_cram_decode_options(&mut self, required_fields: Fields, decode_md: bool) -> Result<()> {
+        if unsafe { (*self.htsfile()).format.format } != htslib::htsExactFormat_cram {
+            return Ok(());
         }
+        self.set_cram_options(
+            hts_sys::hts_fmt_option_CRAM_OPT_REQUIRED_FIELDS,
+            required_fields.bits(),
+        )?;
+        self.set_cram_options(
+            hts_sys::hts_fmt_option_CRAM_OPT_DECODE_MD,
+            decode_md as sam_fields,
+        )
     }
 }
 
@@ -252,6 +433,7 @@ pub struct Reader {
     htsfile: *mut htslib::htsFile,
     header: Rc<HeaderView>,
     tpool: Option<ThreadPool>,
+    reference: Option<faidx::Reader>,
 }
 
 unsafe impl Send for Reader {}
@@ -266,7 +448,8 @@ impl Reader {
         Self::new(&path_as_bytes(path, true)?)
     }
 
-    /// Create a new Reader from STDIN.
+    /// Create a new Reader from STDIN. SAM, BAM (compressed or not) and CRAM input is detected
+    /// automatically, so this works with e.g. `samtools view -u ... | mytool`.
     pub fn from_stdin() -> Result<Self> {
         Self::new(b"-")
     }
@@ -300,6 +483,7 @@ impl Reader {
             htsfile,
             header: Rc::new(HeaderView::new(header)),
             tpool: None,
+            reference: None,
         })
     }
 
@@ -334,13 +518,27 @@ impl Reader {
         ChunkIterator { reader: self, end }
     }
 
-    /// Set the reference path for reading CRAM files.
+    /// Set the reference, which is needed for reading CRAM files and used by `fill_md`. A
+    /// missing FASTA index is built.
     ///
     /// # Arguments
     ///
     /// * `path` - path to the FASTA reference
     pub fn set_reference<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        unsafe { set_fai_filename(self.htsfile, path) }
+        let reference = faidx::Reader::from_path(&path)?;
+        unsafe { set_fai_filename(self.htsfile, path)? }
+        self.reference = Some(reference);
+        Ok(())
+    }
+
+    /// Compute the `MD` and `NM` tags of the given record from the reference set with
+    /// `set_reference`, replacing existing values. See `Record::cal_md_nm` for details; unlike
+    /// there, the contig of the record is looked up in the header of this reader. Returns
+    /// `Error::BamReferenceNotSet` if no reference has been set.
+    pub fn fill_md(&self, record: &mut record::Record) -> Result<()> {
+        let reference = self.reference.as_ref().ok_or(Error::BamReferenceNotSet)?;
+        let contig = record.mapped_contig_name(&self.header)?;
+        record.cal_md_nm_on_contig(contig, reference)
     }
 }
 
@@ -493,9 +691,11 @@ pub enum FetchDefinition<'a> {
     CompleteTid(i32),
     ///complete reference by name (&[u8] or &str)
     String(&'a [u8]),
-    /// Every read
+    /// Every read, mapped and unmapped, in file order (htslib's `HTS_IDX_START`).
     All,
-    /// Only reads with the BAM flag BAM_FUNMAP (which might not be all reads with reference = -1)
+    /// Only the block of reads without a coordinate (reference = -1) at the end of a
+    /// coordinate-sorted file (htslib's `HTS_IDX_NOCOOR`). Unmapped reads that were placed next
+    /// to their mate are not part of this block.
     Unmapped,
 }
 
@@ -595,6 +795,13 @@ pub struct IndexedReader {
     idx: Rc<IndexView>,
     itr: Option<*mut htslib::hts_itr_t>,
     tpool: Option<ThreadPool>,
+    /// The path or URL and the index path it was opened with, for `try_clone`.
+    path: Vec<u8>,
+    index_path: Option<Vec<u8>>,
+    /// Options set on this reader, which are applied to clones as well.
+    reference: Option<PathBuf>,
+    threads: Option<usize>,
+    cram_options: Vec<(hts_fmt_option, sam_fields)>,
 }
 
 unsafe impl Send for IndexedReader {}
@@ -609,6 +816,13 @@ impl IndexedReader {
         Self::new(&path_as_bytes(path, true)?)
     }
 
+    /// Create a new Reader from path, using the BAI, CSI or CRAI index at `index_path`
+    /// instead of looking for one next to `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to open.
+    /// * `index_path` - the path to the index.
     pub fn from_path_and_index<P: AsRef<Path>>(path: P, index_path: P) -> Result<Self> {
         Self::new_with_index_path(
             &path_as_bytes(path, true)?,
@@ -616,10 +830,27 @@ impl IndexedReader {
         )
     }
 
+    /// Create a new Reader from URL (e.g. `https://`, `s3://`, `gs://` or `file://`), looking
+    /// for a BAI, CSI or CRAI index next to the file. A remote index is downloaded into the
+    /// current working directory, where htslib reuses it as long as it is up to date.
+    ///
+    /// Returns `Error::FileNotFound` if the file does not exist, `Error::BamIndexNotFound` if
+    /// there is no index and `Error::Network` if the server cannot be reached.
     pub fn from_url(url: &Url) -> Result<Self> {
         Self::new(url.as_str().as_bytes())
     }
 
+    /// Create a new Reader from URL, using the index at `index_url` instead of looking for one
+    /// next to the file. Otherwise like [`from_url`](#method.from_url).
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - the URL to open.
+    /// * `index_url` - the URL of the index.
+    pub fn from_url_and_index(url: &Url, index_url: &Url) -> Result<Self> {
+        Self::new_with_index_path(url.as_str().as_bytes(), index_url.as_str().as_bytes())
+    }
+
     /// Create a new Reader.
     ///
     /// # Arguments
@@ -631,9 +862,7 @@ impl IndexedReader {
         let c_str = ffi::CString::new(path).unwrap();
         let idx = unsafe { htslib::sam_index_load(htsfile, c_str.as_ptr()) };
         if idx.is_null() {
-            Err(Error::BamInvalidIndex {
-                target: str::from_utf8(path).unwrap().to_owned(),
-            })
+            Err(index_error(path))
         } else {
             Ok(IndexedReader {
                 htsfile,
@@ -641,6 +870,11 @@ impl IndexedReader {
                 idx: Rc::new(IndexView::new(idx)),
                 itr: None,
                 tpool: None,
+                path: path.to_owned(),
+                index_path: None,
+                reference: None,
+                threads: None,
+                cram_options: Vec::new(),
             })
         }
     }
@@ -659,9 +893,7 @@ impl IndexedReader {
             htslib::sam_index_load2(htsfile, c_str_path.as_ptr(), c_str_index_path.as_ptr())
         };
         if idx.is_null() {
-            Err(Error::BamInvalidIndex {
-                target: str::from_utf8(path).unwrap().to_owned(),
-            })
+            Err(index_error(path))
         } else {
             Ok(IndexedReader {
                 htsfile,
@@ -669,10 +901,60 @@ impl IndexedReader {
                 idx: Rc::new(IndexView::new(idx)),
                 itr: None,
                 tpool: None,
+                path: path.to_owned(),
+                index_path: Some(index_path.to_owned()),
+                reference: None,
+                threads: None,
+                cram_options: Vec::new(),
             })
         }
     }
 
+    /// Open the file of this reader again, e.g. to process several regions in parallel. The
+    /// clone has its own file handle, header, index and fetched region, so that it can be
+    /// moved to another thread and used independently of this reader. The reference, the
+    /// number of threads, the thread pool (which is shared) and the CRAM options set on this
+    /// reader are applied to the clone as well.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::{IndexedReader, Read};
+    /// use std::thread;
+    ///
+    /// let bam = IndexedReader::from_path("test/test.bam").unwrap();
+    /// let handles: Vec<_> = (0..2)
+    ///     .map(|tid| {
+    ///         let mut reader = bam.try_clone().unwrap();
+    ///         thread::spawn(move || {
+    ///             reader.fetch(tid).unwrap();
+    ///             reader.records().count()
+    ///         })
+    ///     })
+    ///     .collect();
+    /// let counts: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    /// assert_eq!(counts, vec![6, 0]);
+    /// ```
+    pub fn try_clone(&self) -> Result<Self> {
+        let mut clone = match &self.index_path {
+            Some(index_path) => Self::new_with_index_path(&self.path, index_path)?,
+            None => Self::new(&self.path)?,
+        };
+        if let Some(reference) = &self.reference {
+            clone.set_reference(reference)?;
+        }
+        if let Some(n_threads) = self.threads {
+            clone.set_threads(n_threads)?;
+        }
+        if let Some(tpool) = &self.tpool {
+            clone.set_thread_pool(tpool)?;
+        }
+        for &(fmt_opt, fields) in &self.cram_options {
+            clone.set_cram_options(fmt_opt, fields)?;
+        }
+        Ok(clone)
+    }
+
     /// Define the region from which .read() or .records will retrieve reads.
     ///
     /// Both iterating (with [.records()](trait.Read.html#tymethod.records)) and looping without allocation (with [.read()](trait.Read.html#tymethod.read) are a two stage process:
@@ -694,22 +976,298 @@ impl IndexedReader {
     ///
     /// * fetch(tid: u32) -> fetch everything on this reference
     /// * fetch(reference_name: &[u8] | &str) -> fetch everything on this reference
+    /// * fetch("chr1:1000-2000") -> fetch a samtools style region (1-based, inclusive); also
+    ///   accepts "chr1" and "chr1:1000", and "{chr1:A}:1000-2000" for contig names with colons
     /// * fetch((tid: i32, start: i64, stop: i64)): -> fetch in this region on this tid
     /// * fetch((reference_name: &[u8] | &str, start: i64, stop: i64) -> fetch in this region on this tid
     /// * fetch(FetchDefinition::All) or fetch(".") -> Fetch overything
-    /// * fetch(FetchDefinition::Unmapped) or fetch("*") -> Fetch unmapped (as signified by the 'unmapped' flag in the BAM - might be unreliable with some aligners.
+    /// * fetch(FetchDefinition::Unmapped) or fetch("*") -> Fetch the reads without a coordinate (reference = -1).
+    ///   Unmapped reads that were placed next to their mate are returned by regular region fetches instead.
     ///
     /// The start / stop coordinates will take i64 (the correct type as of htslib's 'large
     /// coordinates' expansion), i32, u32, and u64 (with a possible panic! if the coordinate
     /// won't fit an i64).
     ///
     /// This replaces the old fetch and fetch_str implementations.
+    ///
+    /// Fetching a contig that is not in the header yields `Error::UnknownSequence`, and a region
+    /// string with malformed coordinates yields `Error::InvalidRegion`.
     pub fn fetch<'a, T: Into<FetchDefinition<'a>>>(&mut self, fetch_definition: T) -> Result<()> {
         //this 'compile time redirect' safes us
         //from monomorphing the 'meat' of the fetch function
         self._inner_fetch(fetch_definition.into())
     }
 
+    /// Pileup over the region `[beg, end)` of the given target (with 0-based coordinates), i.e.
+    /// `fetch((tid, beg, end))` followed by `pileup` and `Pileups::set_region`. Filters and
+    /// other settings can still be applied to the returned pileups.
+    ///
+    /// The pileup engine reads the fetched records directly from htslib's region iterator, so
+    /// the region is looked up in the index once, and records are neither read twice nor
+    /// buffered in Rust (unless `Pileups::set_filter` or `set_min_mapq` is used). Since the
+    /// fetch includes all reads overlapping the region, the depth at each position of the
+    /// region is the same as in a pileup over the whole file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::IndexedReader;
+    ///
+    /// let mut bam = IndexedReader::from_path("test/test.bam").unwrap();
+    /// let depths: Vec<_> = bam
+    ///     .fetch_and_pileup(0, 20, 30)
+    ///     .unwrap()
+    ///     .map(|p| p.unwrap().depth())
+    ///     .collect();
+    /// assert_eq!(depths, vec![6; 10]);
+    /// ```
+    pub fn fetch_and_pileup(
+        &mut self,
+        tid: u32,
+        beg: u64,
+        end: u64,
+    ) -> Result<pileup::Pileups<'_, Self>> {
+        self.fetch((tid, beg, end))?;
+        let mut pileups = self.pileup();
+        pileups.set_region(tid, beg, end);
+        Ok(pileups)
+    }
+
+    /// Fetch reads overlapping any of the given regions, as `(tid, beg, end)` with 0-based,
+    /// half-open coordinates. Using htslib's multi-region iterator, overlapping and adjacent
+    /// regions are merged and `records` yields each read exactly once, in coordinate order,
+    /// even if it overlaps several regions. An empty list of regions yields no reads.
+    ///
+    /// A target id that is not in the header yields `Error::InvalidTid`.
+    pub fn fetch_multiple(&mut self, regions: &[(u32, u64, u64)]) -> Result<()> {
+        let mut region_strings = Vec::with_capacity(regions.len());
+        for &(tid, beg, end) in regions {
+            let name = self.header().try_tid2name(tid as i32)?;
+            if end > beg {
+                let mut region = b"{".to_vec();
+                region.extend_from_slice(name);
+                region.extend_from_slice(format!("}}:{}-{}", beg + 1, end).as_bytes());
+                let c_region = ffi::CString::new(region.as_slice()).map_err(|_| Error::Fetch {
+                    region: String::from_utf8_lossy(&region).into_owned(),
+                })?;
+                region_strings.push(c_region);
+            }
+        }
+
+        if let Some(itr) = self.itr.take() {
+            unsafe { htslib::hts_itr_destroy(itr) }
+        }
+        let itr = if region_strings.is_empty() {
+            unsafe { htslib::sam_itr_queryi(self.index().inner_ptr(), htslib::HTS_IDX_NONE, 0, 0) }
+        } else {
+            let mut region_ptrs: Vec<*mut c_char> = region_strings
+                .iter()
+                .map(|r| r.as_ptr() as *mut c_char)
+                .collect();
+            unsafe {
+                htslib::sam_itr_regarray(
+                    self.index().inner_ptr(),
+                    self.header().inner_ptr() as *mut hts_sys::sam_hdr_t,
+                    region_ptrs.as_mut_ptr(),
+                    region_ptrs.len() as u32,
+                )
+            }
+        };
+        if itr.is_null() {
+            let regions: Vec<_> = region_strings.iter().map(|r| r.to_string_lossy()).collect();
+            Err(Error::Fetch {
+                region: regions.join(","),
+            })
+        } else {
+            self.itr = Some(itr);
+            Ok(())
+        }
+    }
+
+    /// Compute the depth at each position of the region `[beg, end)` of the given target (with
+    /// 0-based coordinates), like `samtools depth -a -J -Q min_mapq -G exclude_flags`. Reads
+    /// with any of the `exclude_flags` (e.g. `htslib::BAM_FDUP`) or a mapping quality below
+    /// `min_mapq` are ignored. Deletions count towards the depth, reference skips (`N` in the
+    /// CIGAR, e.g. introns) do not.
+    ///
+    /// The depth is computed from the CIGAR strings of the fetched reads, which is much faster
+    /// than using the pileup engine. Note that this fetches the region, replacing any region
+    /// fetched before. Returns `Error::InvalidRegion` if `end < beg`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::IndexedReader;
+    ///
+    /// let mut bam = IndexedReader::from_path("test/test.bam").unwrap();
+    /// let depth = bam.coverage(0, 0, 200, 0, 0).unwrap();
+    /// assert_eq!(depth.len(), 200);
+    /// assert_eq!(depth[50], 6);
+    /// ```
+    pub fn coverage(
+        &mut self,
+        tid: u32,
+        beg: u64,
+        end: u64,
+        min_mapq: u8,
+        exclude_flags: u16,
+    ) -> Result<Vec<u32>> {
+        // increments and decrements at the start and end of each covered range
+        let mut changes = vec![0i64; end.saturating_sub(beg) as usize + 1];
+        self.for_each_covered_range(tid, beg, end, min_mapq, exclude_flags, |start, stop| {
+            changes[(start - beg) as usize] += 1;
+            changes[(stop - beg) as usize] -= 1;
+        })?;
+        changes.pop();
+        let mut depth = 0;
+        Ok(changes
+            .into_iter()
+            .map(|change| {
+                depth += change;
+                depth as u32
+            })
+            .collect())
+    }
+
+    /// Compute the mean depth over the region `[beg, end)` of the given target, with the same
+    /// semantics as `coverage`, but without storing the depth of each position. This is e.g.
+    /// useful for large windows. Returns `NaN` if the region is empty.
+    pub fn mean_coverage(
+        &mut self,
+        tid: u32,
+        beg: u64,
+        end: u64,
+        min_mapq: u8,
+        exclude_flags: u16,
+    ) -> Result<f64> {
+        let mut covered = 0u64;
+        self.for_each_covered_range(tid, beg, end, min_mapq, exclude_flags, |start, stop| {
+            covered += stop - start;
+        })?;
+        Ok(covered as f64 / (end - beg) as f64)
+    }
+
+    /// Call `f` with each range of the region `[beg, end)` covered by an aligned base or a
+    /// deletion of a read passing the filters.
+    fn for_each_covered_range<F: FnMut(u64, u64)>(
+        &mut self,
+        tid: u32,
+        beg: u64,
+        end: u64,
+        min_mapq: u8,
+        exclude_flags: u16,
+        mut f: F,
+    ) -> Result<()> {
+        if end < beg {
+            return Err(Error::InvalidRegion {
+                region: self.region_string(tid as i32, beg as i64, end as i64),
+            });
+        }
+        self.fetch((tid, beg, end))?;
+        let mut record = record::Record::new();
+        while let Some(result) = self.read(&mut record) {
+            result?;
+            if record.flags() & exclude_flags != 0 || record.mapq() < min_mapq {
+                continue;
+            }
+            let mut pos = record.pos() as u64;
+            for &op in record.raw_cigar() {
+                let len = u64::from(op >> htslib::BAM_CIGAR_SHIFT);
+                match op & htslib::BAM_CIGAR_MASK {
+                    htslib::BAM_CMATCH
+                    | htslib::BAM_CEQUAL
+                    | htslib::BAM_CDIFF
+                    | htslib::BAM_CDEL => {
+                        let (start, stop) = (pos.max(beg), (pos + len).min(end));
+                        if start < stop {
+                            f(start, stop);
+                        }
+                        pos += len;
+                    }
+                    htslib::BAM_CREF_SKIP => pos += len,
+                    _ => (),
+                }
+                if pos >= end {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the primary alignment of the mate of the given record, i.e. the record at the
+    /// mate position with the same query name and the opposite first/last in template flag.
+    /// Mates that are unmapped but placed at the position of the record are found as well.
+    ///
+    /// Returns `None` if the record is not paired, its mate is unmapped and not placed
+    /// (mate tid -1), or the mate is not in the file. For BAM files, a region fetched before
+    /// is not affected, reading its records can be continued afterwards. For CRAM files, the
+    /// fetched region is reset, so `fetch` has to be called again.
+    pub fn find_mate(&mut self, record: &record::Record) -> Result<Option<record::Record>> {
+        if !record.is_paired() || record.mtid() < 0 {
+            return Ok(None);
+        }
+        // The offset of the next record of the fetched region (-1 for CRAM).
+        let offset = self.tell();
+        let (mtid, mpos) = (record.mtid(), record.mpos());
+        let itr = unsafe { htslib::sam_itr_queryi(self.index().inner_ptr(), mtid, mpos, mpos + 1) };
+        if itr.is_null() {
+            return Err(Error::Fetch {
+                region: self.region_string(mtid, mpos, mpos + 1),
+            });
+        }
+
+        let is_mate = |candidate: &record::Record| {
+            candidate.pos() == mpos
+                && candidate.qname() == record.qname()
+                && !candidate.is_secondary()
+                && !candidate.is_supplementary()
+                && candidate.is_first_in_template() == record.is_last_in_template()
+                && candidate.is_last_in_template() == record.is_first_in_template()
+        };
+        let mut candidate = record::Record::new();
+        let result = loop {
+            match itr_next(
+                self.htsfile,
+                itr,
+                &mut candidate.inner as *mut htslib::bam1_t,
+            ) {
+                -1 => break Ok(None),
+                -2 => break Err(Error::BamTruncatedRecord),
+                -4 => break Err(Error::BamInvalidRecord),
+                _ if candidate.pos() > mpos => break Ok(None),
+                _ if is_mate(&candidate) => {
+                    candidate.set_header(Rc::clone(&self.header));
+                    break Ok(Some(candidate));
+                }
+                _ => (),
+            }
+        };
+        unsafe { htslib::hts_itr_destroy(itr) };
+
+        // Move the file back to where the fetched region was left. The iterator only seeks
+        // by itself when it starts reading a new chunk.
+        if offset >= 0 {
+            self.seek(offset)?;
+        } else if let Some(itr) = self.itr.take() {
+            unsafe { htslib::hts_itr_destroy(itr) }
+        }
+        result
+    }
+
+    /// Fetch the 0-based, half-open region `[beg, end)` of the contig with the given name.
+    /// `end` is clamped to the length of the contig, so `u64::MAX` fetches everything from
+    /// `beg` onwards. Returns `Error::ContigNotFound`, which lists some contigs of the header,
+    /// if there is no contig with this name.
+    pub fn fetch_by_name(&mut self, name: &[u8], beg: u64, end: u64) -> Result<()> {
+        let header = self.header();
+        let tid = header
+            .tid(name)
+            .ok_or_else(|| contig_not_found(name, header.target_names()))?;
+        let end = end.min(header.target_len(tid).unwrap_or(u64::MAX));
+        self.fetch((tid, beg, end))
+    }
+
     fn _inner_fetch(&mut self, fetch_definition: FetchDefinition) -> Result<()> {
         match fetch_definition {
             FetchDefinition::Region(tid, start, stop) => {
@@ -719,14 +1277,16 @@ impl IndexedReader {
                 let tid = self.header().tid(s);
                 match tid {
                     Some(tid) => self._fetch_by_coord_tuple(tid as i32, start, stop),
-                    None => Err(Error::Fetch),
+                    None => Err(Error::UnknownSequence {
+                        sequence: String::from_utf8_lossy(s).into_owned(),
+                    }),
                 }
             }
             FetchDefinition::CompleteTid(tid) => {
                 let len = self.header().target_len(tid as u32);
                 match len {
                     Some(len) => self._fetch_by_coord_tuple(tid, 0, len as i64),
-                    None => Err(Error::Fetch),
+                    None => Err(Error::InvalidTid { tid }),
                 }
             }
             FetchDefinition::String(s) => {
@@ -753,13 +1313,23 @@ impl IndexedReader {
         let itr = unsafe { htslib::sam_itr_queryi(self.index().inner_ptr(), tid, beg, end) };
         if itr.is_null() {
             self.itr = None;
-            Err(Error::Fetch)
+            Err(Error::Fetch {
+                region: self.region_string(tid, beg, end),
+            })
         } else {
             self.itr = Some(itr);
             Ok(())
         }
     }
 
+    /// Format the given region like samtools (1-based, inclusive) for error messages.
+    fn region_string(&self, tid: i32, beg: i64, end: i64) -> String {
+        match self.header().try_tid2name(tid) {
+            Ok(name) => format!("{}:{}-{}", String::from_utf8_lossy(name), beg + 1, end),
+            Err(_) => format!("{}:{}-{}", tid, beg + 1, end),
+        }
+    }
+
     fn _fetch_by_str(&mut self, region: &[u8]) -> Result<()> {
         if let Some(itr) = self.itr {
             unsafe { htslib::hts_itr_destroy(itr) }
@@ -775,7 +1345,15 @@ impl IndexedReader {
         };
         if itr.is_null() {
             self.itr = None;
-            Err(Error::Fetch)
+            // Find out why htslib rejected the region, so that unknown contigs and
+            // malformed coordinates can be reported as such.
+            let header = self.header();
+            match parse_region(region, |name| header.tid(name).map(|tid| tid as i32)) {
+                Err(e) => Err(e),
+                Ok(_) => Err(Error::Fetch {
+                    region: String::from_utf8_lossy(region).into_owned(),
+                }),
+            }
         } else {
             self.itr = Some(itr);
             Ok(())
@@ -805,7 +1383,9 @@ impl IndexedReader {
     ///
     /// * `path` - path to the FASTA reference
     pub fn set_reference<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        unsafe { set_fai_filename(self.htsfile, path) }
+        unsafe { set_fai_filename(self.htsfile, &path)? }
+        self.reference = Some(path.as_ref().to_owned());
+        Ok(())
     }
 
     pub fn index(&self) -> &IndexView {
@@ -815,7 +1395,8 @@ impl IndexedReader {
     // Analogous to slow_idxstats in samtools, see
     // https://github.com/samtools/samtools/blob/556c60fdff977c0e6cadc4c2581661f187098b4d/bam_index.c#L140-L199
     unsafe fn slow_idxstats(&mut self) -> Result<Vec<(i64, u64, u64, u64)>> {
-        self.set_cram_options(
+        set_cram_options(
+            self.htsfile,
             hts_sys::hts_fmt_option_CRAM_OPT_REQUIRED_FIELDS,
             hts_sys::sam_fields_SAM_RNAME | hts_sys::sam_fields_SAM_FLAG,
         )?;
@@ -827,12 +1408,9 @@ impl IndexedReader {
 
         let nref =
             usize::try_from(hts_sys::sam_hdr_nref(h)).map_err(|_| Error::NoSequencesInReference)?;
-        if nref == 0 {
-            return Ok(vec![]);
-        }
         let mut counts = vec![vec![0; 2]; nref + 1];
-        let mut bb: hts_sys::bam1_t = MaybeUninit::zeroed().assume_init();
-        let b = &mut bb as *mut hts_sys::bam1_t;
+        let mut record = Record::new();
+        let b = record.inner_ptr_mut();
         loop {
             ret = hts_sys::sam_read1(fp, h, b);
             if ret < 0 {
@@ -844,7 +1422,8 @@ impl IndexedReader {
             }
 
             if tid != last_tid {
-                if (last_tid >= -1) && (counts[tid as usize][0] + counts[tid as usize][1]) > 0 {
+                let i = if tid < 0 { nref } else { tid as usize };
+                if (last_tid >= -1) && (counts[i][0] + counts[i][1]) > 0 {
                     return Err(Error::BamUnsorted);
                 }
                 last_tid = tid;
@@ -855,7 +1434,9 @@ impl IndexedReader {
             } else {
                 0
             };
-            counts[(*b).core.tid as usize][idx] += 1;
+            // reads without coordinates (tid -1) are counted in the last entry
+            let i = if tid < 0 { nref } else { tid as usize };
+            counts[i][idx] += 1;
         }
 
         if ret == -1 {
@@ -880,11 +1461,18 @@ impl IndexedReader {
     /// containing the target id, length, number of mapped reads, and number of unmapped reads.
     /// The last entry in the vector corresponds to the unmapped reads for the entire file, with
     /// the tid set to -1.
+    ///
+    /// For BAM files, the counts are taken from the BAI or CSI index without reading any
+    /// records. Other formats (e.g. CRAM) are read from the current position until the end
+    /// of the file.
     pub fn index_stats(&mut self) -> Result<Vec<(i64, u64, u64, u64)>> {
         let header = self.header();
         let index = self.index();
         if index.inner_ptr().is_null() {
-            panic!("Index is null");
+            let target = unsafe { ffi::CStr::from_ptr((*self.htsfile()).fn_) };
+            return Err(Error::BamInvalidIndex {
+                target: target.to_string_lossy().into_owned(),
+            });
         }
         // the quick index stats method only works for BAM files, not SAM or CRAM
         unsafe {
@@ -1024,6 +1612,18 @@ impl Read for IndexedReader {
         self.tpool = Some(tpool.clone());
         Ok(())
     }
+
+    fn set_threads(&mut self, n_threads: usize) -> Result<()> {
+        unsafe { set_threads(self.htsfile(), n_threads)? }
+        self.threads = Some(n_threads);
+        Ok(())
+    }
+
+    fn set_cram_options(&mut self, fmt_opt: hts_fmt_option, fields: sam_fields) -> Result<()> {
+        unsafe { set_cram_options(self.htsfile(), fmt_opt, fields)? }
+        self.cram_options.push((fmt_opt, fields));
+        Ok(())
+    }
 }
 
 impl Drop for IndexedReader {
@@ -1039,8 +1639,14 @@ impl Drop for IndexedReader {
 
 #[derive(Debug, Clone, Copy)]
 pub enum Format {
+    /// SAM text, including the header (htslib mode `w`).
     Sam,
+    /// BGZF-compressed BAM (htslib mode `wb`).
     Bam,
+    /// BAM with BGZF blocks stored at compression level 0, as written by `samtools view -u`
+    /// (htslib mode `wbu`). Useful for piping data between processes.
+    UncompressedBam,
+    /// CRAM (htslib mode `wc`), see `Writer::set_reference`.
     Cram,
 }
 
@@ -1049,9 +1655,31 @@ impl Format {
         match self {
             Format::Sam => b"w",
             Format::Bam => b"wb",
+            Format::UncompressedBam => b"wbu",
             Format::Cram => b"wc",
         }
     }
+
+    /// The format indicated by the extension of the given path (`.sam`, `.bam` or `.cram`,
+    /// case-insensitive), or `None` if the extension is unknown.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "sam" => Some(Format::Sam),
+            "bam" => Some(Format::Bam),
+            "cram" => Some(Format::Cram),
+            _ => None,
+        }
+    }
+
+    /// Resolve the format to use for writing to the given path: the extension takes precedence
+    /// over the given format, unless both agree (e.g. `Format::UncompressedBam` for `.bam`).
+    fn for_path<P: AsRef<Path>>(self, path: P) -> Self {
+        match (Format::from_extension(path), self) {
+            (Some(Format::Bam), Format::UncompressedBam) | (None, _) => self,
+            (Some(inferred), _) => inferred,
+        }
+    }
 }
 
 /// A BAM writer.
@@ -1060,6 +1688,12 @@ pub struct Writer {
     f: *mut htslib::htsFile,
     header: Rc<HeaderView>,
     tpool: Option<ThreadPool>,
+    sort_order: SortOrder,
+    last_pos: Option<(u32, i64)>,
+    /// The header of the last written record, together with the translation of its tids to
+    /// the tids of this writer (`None` if they are the same).
+    tid_translation: Option<(Rc<HeaderView>, Option<Vec<i32>>)>,
+    validate: bool,
 }
 
 unsafe impl Send for Writer {}
@@ -1067,21 +1701,74 @@ unsafe impl Send for Writer {}
 impl Writer {
     /// Create a new SAM/BAM/CRAM file.
     ///
+    /// The format is inferred from the extension of the path (`.sam`, `.bam` or `.cram`), so
+    /// that e.g. `out.sam` is always written as SAM text. The given format is used for other
+    /// extensions, and for `.bam` if it is `Format::UncompressedBam`. Use
+    /// `from_path_with_format` to enforce a format regardless of the extension.
+    ///
     /// # Arguments
     ///
     /// * `path` - the path.
     /// * `header` - header definition to use
-    /// * `format` - the format to use (SAM/BAM/CRAM)
+    /// * `format` - the format to use (SAM/BAM/CRAM) if the extension is unknown
     pub fn from_path<P: AsRef<Path>>(
         path: P,
         header: &header::Header,
         format: Format,
+    ) -> Result<Self> {
+        let format = format.for_path(&path);
+        Self::from_path_with_format(path, header, format)
+    }
+
+    /// Create a new SAM/BAM/CRAM file in the given format, regardless of the extension of
+    /// the path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path.
+    /// * `header` - header definition to use
+    /// * `format` - the format to use (SAM/BAM/CRAM)
+    pub fn from_path_with_format<P: AsRef<Path>>(
+        path: P,
+        header: &header::Header,
+        format: Format,
     ) -> Result<Self> {
         Self::new(&path_as_bytes(path, false)?, format.write_mode(), header)
     }
 
+    /// Create a new SAM/BAM/CRAM file with the given compression level. In contrast to
+    /// calling `set_compression_level` after construction, this also applies to the header.
+    /// With `Format::Sam`, this writes BGZF-compressed SAM, and with `Format::UncompressedBam`
+    /// the given level takes precedence.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path.
+    /// * `header` - header definition to use
+    /// * `format` - the format to use (SAM/BAM/CRAM)
+    /// * `compression_level` - `CompressionLevel` enum variant
+    pub fn from_path_with_level<P: AsRef<Path>>(
+        path: P,
+        header: &header::Header,
+        format: Format,
+        compression_level: CompressionLevel,
+    ) -> Result<Self> {
+        let level = compression_level.convert()?;
+        let mode: &[u8] = match format {
+            Format::Sam => b"wz",
+            Format::UncompressedBam => Format::Bam.write_mode(),
+            format => format.write_mode(),
+        };
+        let mode = [mode, level.to_string().as_bytes()].concat();
+        Self::new(&path_as_bytes(path, false)?, &mode, header)
+    }
+
     /// Create a new SAM/BAM/CRAM file at STDOUT.
     ///
+    /// When piping into another tool (e.g. `samtools sort`), `Format::UncompressedBam` avoids
+    /// spending CPU on compressing data that is immediately decompressed again. STDOUT is
+    /// closed when the `Writer` is dropped, which signals EOF to the reading process.
+    ///
     /// # Arguments
     ///
     /// * `header` - header definition to use
@@ -1137,12 +1824,19 @@ impl Writer {
             f,
             header: Rc::new(HeaderView::new(header_record)),
             tpool: None,
+            sort_order: SortOrder::Unknown,
+            last_pos: None,
+            tid_translation: None,
+            validate: false,
         })
     }
 
     /// Activate multi-threaded BAM write support in htslib. This should permit faster
     /// writing of large BAM files.
     ///
+    /// The background threads are only used for BGZF compression. The writer itself
+    /// is still not `Sync`, so records have to be written from a single thread.
+    ///
     /// # Arguments
     ///
     /// * `n_threads` - number of extra background writer threads to use, must be `> 0`.
@@ -1163,25 +1857,175 @@ impl Writer {
         Ok(())
     }
 
+    /// Check that records passed to `write` are in the given sort order. If a record is
+    /// out of order, `write` returns `Error::BamUnsorted` without writing it. Only
+    /// `SortOrder::Coordinate` is checked, where unmapped records (tid -1) have to come last,
+    /// as with `samtools sort`. Any other order disables the check.
+    ///
+    /// To check that the records match what the header claims, use
+    /// `writer.assert_sorted(writer.header().sort_order())`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sort_order` - the expected sort order
+    pub fn assert_sorted(&mut self, sort_order: SortOrder) {
+        self.sort_order = sort_order;
+        self.last_pos = None;
+    }
+
+    /// Whether to check each record with `Record::check` before writing it (default: false).
+    /// Invalid records are then rejected with `Error::BamInvalidRecordFields`, instead of
+    /// producing a file that fails to be read later.
+    ///
+    /// # Arguments
+    ///
+    /// * `validate` - whether to check records
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate;
+    }
+
     /// Write record to BAM.
     ///
+    /// If the record has been read from a file whose header lists the contigs in a different
+    /// order than the header of this writer, its tid and mate tid are translated by contig
+    /// name. Returns `Error::BamWriteUnknownContig` if a contig is not in the header of this
+    /// writer, `Error::BamInvalidRecordFields` if the record is invalid and validation has
+    /// been enabled with `set_validate`, and `Error::BamWriteRecord` with the name of the
+    /// record if htslib fails to write it (e.g. because the disk is full). Since output is
+    /// buffered, such failures may only be detected by a later write, or by `flush` or
+    /// `close`.
+    ///
     /// # Arguments
     ///
     /// * `record` - the record to write
     pub fn write(&mut self, record: &record::Record) -> Result<()> {
-        if unsafe { htslib::sam_write1(self.f, self.header.inner(), record.inner_ptr()) } == -1 {
-            Err(Error::WriteRecord)
+        let translated;
+        let record = match self.translate_tids(record)? {
+            Some(copy) => {
+                translated = copy;
+                &translated
+            }
+            None => record,
+        };
+        if self.validate {
+            record
+                .check(&self.header)
+                .map_err(|errors| Error::BamInvalidRecordFields {
+                    qname: String::from_utf8_lossy(record.qname()).into_owned(),
+                    errors,
+                })?;
+        }
+        if self.sort_order == SortOrder::Coordinate {
+            // Casting to u32 sorts unmapped records (tid -1) last, like samtools does.
+            let pos = (record.tid() as u32, record.pos());
+            if matches!(self.last_pos, Some(last) if pos < last) {
+                return Err(Error::BamUnsorted);
+            }
+            self.last_pos = Some(pos);
+        }
+        if unsafe { htslib::sam_write1(self.f, self.header.inner(), record.inner_ptr()) } < 0 {
+            Err(Error::BamWriteRecord {
+                qname: String::from_utf8_lossy(record.qname()).into_owned(),
+            })
         } else {
             Ok(())
         }
     }
 
+    /// Write all given records (e.g. a filtered iterator over the records of a reader),
+    /// returning how many have been written. Writing stops at the first error, which is
+    /// returned as `Error::WriteRecordAt` with the 0-based index of the failing record.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - the records (or references to them) to write
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::{self, Read};
+    ///
+    /// let mut bam = bam::Reader::from_path("test/test.bam").unwrap();
+    /// let header = bam::Header::from_template(bam.header());
+    /// let mut writer = bam::Writer::from_path("/dev/null", &header, bam::Format::Sam).unwrap();
+    /// let primary = bam.records().map(|r| r.unwrap()).filter(|r| !r.is_supplementary());
+    /// assert_eq!(writer.write_all(primary).unwrap(), 5);
+    /// writer.close().unwrap();
+    /// ```
+    pub fn write_all<I>(&mut self, records: I) -> Result<usize>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<record::Record>,
+    {
+        let mut n = 0;
+        for record in records {
+            self.write(record.borrow())
+                .map_err(|e| Error::WriteRecordAt {
+                    index: n,
+                    source: Box::new(e),
+                })?;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Flush buffered records to the file. Returns `Error::FileFlush` if this fails.
+    pub fn flush(&mut self) -> Result<()> {
+        unsafe { utils::flush(self.f) }
+    }
+
+    /// Write the remaining buffered records (and e.g. the BGZF EOF marker) and close the file.
+    /// Returns `Error::FileClose` if this fails, e.g. because the disk is full. Dropping the
+    /// writer closes the file as well, but has to ignore such failures.
+    pub fn close(mut self) -> Result<()> {
+        let f = std::mem::replace(&mut self.f, std::ptr::null_mut());
+        unsafe { utils::close(f) }
+    }
+
+    /// Return a copy of the record with tids translated to the header of this writer, or
+    /// `None` if the tids do not need to be translated.
+    fn translate_tids(&mut self, record: &record::Record) -> Result<Option<record::Record>> {
+        let header = match record.header() {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if !matches!(&self.tid_translation, Some((last, _)) if Rc::ptr_eq(last, header)) {
+            let tids: Vec<i32> = header
+                .target_names()
+                .iter()
+                .map(|name| self.header.tid(name).map_or(-1, |tid| tid as i32))
+                .collect();
+            let identical = tids.iter().enumerate().all(|(i, &tid)| tid == i as i32);
+            self.tid_translation = Some((Rc::clone(header), Some(tids).filter(|_| !identical)));
+        }
+        let tids = match &self.tid_translation {
+            Some((_, Some(tids))) => tids,
+            _ => return Ok(None),
+        };
+        let translate = |tid: i32| match tid {
+            -1 => Ok(-1),
+            tid => match tids.get(tid as usize) {
+                Some(&new_tid) if new_tid >= 0 => Ok(new_tid),
+                _ => Err(Error::BamWriteUnknownContig {
+                    contig: header.try_tid2name(tid).map_or_else(
+                        |_| tid.to_string(),
+                        |name| String::from_utf8_lossy(name).into_owned(),
+                    ),
+                }),
+            },
+        };
+        let mut copy = record.clone();
+        copy.set_tid(translate(record.tid())?);
+        copy.set_mtid(translate(record.mtid())?);
+        Ok(Some(copy))
+    }
+
     /// Return the header.
     pub fn header(&self) -> &HeaderView {
         &self.header
     }
 
-    /// Set the reference path for reading CRAM files.
+    /// Set the reference path for writing CRAM files.
     ///
     /// # Arguments
     ///
@@ -1239,8 +2083,10 @@ impl CompressionLevel {
 
 impl Drop for Writer {
     fn drop(&mut self) {
-        unsafe {
-            htslib::hts_close(self.f);
+        if !self.f.is_null() {
+            unsafe {
+                htslib::hts_close(self.f);
+            }
         }
     }
 }
@@ -1294,9 +2140,120 @@ impl<'a, R: Read> Iterator for RcRecords<'a, R> {
     }
 }
 
-/// Iterator over the records of a BAM until the virtual offset is less than `end`
-pub struct ChunkIterator<'a, R: Read> {
-    reader: &'a mut R,
+/// Iterator over groups of records with the same query name.
+///
+/// See [record_groups](trait.Read.html#method.record_groups).
+#[derive(Debug)]
+pub struct RecordGroups<'a, R: Read> {
+    reader: &'a mut R,
+    next: Option<record::Record>,
+    checked_sort_order: bool,
+    seen_qnames: HashSet<Vec<u8>>,
+    done: bool,
+}
+
+impl<'a, R: Read> RecordGroups<'a, R> {
+    fn read_record(&mut self) -> Option<Result<record::Record>> {
+        let mut record = record::Record::new();
+        match self.reader.read(&mut record)? {
+            Ok(()) => Some(Ok(record)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<'a, R: Read> Iterator for RecordGroups<'a, R> {
+    type Item = Result<Vec<record::Record>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if !self.checked_sort_order {
+            self.checked_sort_order = true;
+            if self.reader.header().sort_order() == SortOrder::Coordinate {
+                self.done = true;
+                return Some(Err(Error::BamCoordinateSorted));
+            }
+        }
+        let first = match self.next.take() {
+            Some(record) => record,
+            None => match self.read_record()? {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        // The header does not need to declare the sort order, so also detect records that are
+        // not grouped, e.g. in a coordinate sorted file, by a query name seen before.
+        if !self.seen_qnames.insert(first.qname().to_owned()) {
+            self.done = true;
+            return Some(Err(Error::BamNotQueryGrouped {
+                qname: String::from_utf8_lossy(first.qname()).into_owned(),
+            }));
+        }
+        let mut group = vec![first];
+        while let Some(result) = self.read_record() {
+            let record = match result {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            };
+            if record.qname() == group[0].qname() {
+                group.push(record);
+            } else {
+                self.next = Some(record);
+                break;
+            }
+        }
+        Some(Ok(group))
+    }
+}
+
+/// Iterator over read pairs.
+///
+/// See [read_pairs](trait.Read.html#method.read_pairs).
+#[derive(Debug)]
+pub struct ReadPairs<'a, R: Read> {
+    groups: RecordGroups<'a, R>,
+}
+
+impl<'a, R: Read> Iterator for ReadPairs<'a, R> {
+    type Item = Result<(record::Record, Option<record::Record>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let group = match self.groups.next()? {
+                Ok(group) => group,
+                Err(e) => return Some(Err(e)),
+            };
+            let not_grouped = |record: &record::Record| Error::BamNotQueryGrouped {
+                qname: String::from_utf8_lossy(record.qname()).into_owned(),
+            };
+            let mut primary = group
+                .into_iter()
+                .filter(|record| !record.is_secondary() && !record.is_supplementary());
+            let pair = match (primary.next(), primary.next(), primary.next()) {
+                // only secondary or supplementary alignments
+                (None, _, _) => continue,
+                (Some(single), None, _) if !single.is_paired() => Ok((single, None)),
+                (Some(first), Some(second), None) => {
+                    if first.is_first_in_template() && second.is_last_in_template() {
+                        Ok((first, Some(second)))
+                    } else if first.is_last_in_template() && second.is_first_in_template() {
+                        Ok((second, Some(first)))
+                    } else {
+                        Err(not_grouped(&first))
+                    }
+                }
+                (Some(first), _, _) => Err(not_grouped(&first)),
+            };
+            return Some(pair);
+        }
+    }
+}
+
+/// Iterator over the records of a BAM until the virtual offset is less than `end`
+pub struct ChunkIterator<'a, R: Read> {
+    reader: &'a mut R,
     end: Option<i64>,
 }
 
@@ -1324,8 +2281,17 @@ fn hts_open(path: &[u8], mode: &[u8]) -> Result<*mut htslib::htsFile> {
     let c_str = ffi::CString::new(mode).unwrap();
     let ret = unsafe { htslib::hts_open(cpath.as_ptr(), c_str.as_ptr()) };
     if ret.is_null() {
-        Err(Error::BamOpen {
-            target: path.to_owned(),
+        let errno = std::io::Error::last_os_error().raw_os_error();
+        Err(match errno {
+            Some(libc::ENOENT) if !mode.contains(&b'w') => {
+                Error::FileNotFound { path: path.into() }
+            }
+            errno if is_network_errno(errno) => Error::Network {
+                target: path.to_owned(),
+            },
+            _ => Error::BamOpen {
+                target: path.to_owned(),
+            },
         })
     } else {
         if !mode.contains(&b'w') {
@@ -1346,6 +2312,17 @@ fn hts_open(path: &[u8], mode: &[u8]) -> Result<*mut htslib::htsFile> {
     }
 }
 
+/// Error for a failed attempt to load the index of the file at `path`.
+fn index_error(path: &[u8]) -> Error {
+    let errno = std::io::Error::last_os_error().raw_os_error();
+    let target = str::from_utf8(path).unwrap().to_owned();
+    match errno {
+        Some(libc::ENOENT) => Error::BamIndexNotFound { target },
+        errno if is_network_errno(errno) => Error::Network { target },
+        _ => Error::BamInvalidIndex { target },
+    }
+}
+
 /// Wrapper for iterating an indexed BAM file.
 fn itr_next(
     htsfile: *mut htslib::htsFile,
@@ -1353,6 +2330,9 @@ fn itr_next(
     record: *mut htslib::bam1_t,
 ) -> i32 {
     unsafe {
+        if (*itr).multi() != 0 {
+            return htslib::hts_itr_multi_next(htsfile, itr, record as *mut ::std::os::raw::c_void);
+        }
         htslib::hts_itr_next(
             (*htsfile).fp.bgzf,
             itr,
@@ -1362,6 +2342,27 @@ fn itr_next(
     }
 }
 
+/// Sort order of a SAM/BAM/CRAM file, as given by the `SO` tag of the `@HD` header line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Unsorted,
+    Queryname,
+    Coordinate,
+    /// The `SO` tag is missing, set to `unknown`, or has an unrecognized value.
+    Unknown,
+}
+
+impl SortOrder {
+    fn from_tag(value: &[u8]) -> Self {
+        match value {
+            b"unsorted" => SortOrder::Unsorted,
+            b"queryname" => SortOrder::Queryname,
+            b"coordinate" => SortOrder::Coordinate,
+            _ => SortOrder::Unknown,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct HeaderView {
     inner: *mut htslib::bam_hdr_t,
@@ -1436,8 +2437,35 @@ impl HeaderView {
         }
     }
 
+    /// Return the target id of the given target name, or `Error::UnknownSequence` naming
+    /// the target if it is not in the header.
+    pub fn name2tid(&self, name: &[u8]) -> Result<u32> {
+        let unknown = || Error::UnknownSequence {
+            sequence: String::from_utf8_lossy(name).into_owned(),
+        };
+        let c_str = ffi::CString::new(name).map_err(|_| unknown())?;
+        let tid = unsafe { htslib::sam_hdr_name2tid(self.inner, c_str.as_ptr()) };
+        if tid < 0 {
+            Err(unknown())
+        } else {
+            Ok(tid as u32)
+        }
+    }
+
+    /// Return the name of the given target. Panics if the target id is not in the header,
+    /// use `try_tid2name` to handle that case.
     pub fn tid2name(&self, tid: u32) -> &[u8] {
-        unsafe { ffi::CStr::from_ptr(htslib::sam_hdr_tid2name(self.inner, tid as i32)).to_bytes() }
+        self.try_tid2name(tid as i32)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Return the name of the given target, or `Error::InvalidTid` if it is not in the
+    /// header (e.g. -1 for unmapped records).
+    pub fn try_tid2name(&self, tid: i32) -> Result<&[u8]> {
+        if tid < 0 || tid as i64 >= self.target_count() as i64 {
+            return Err(Error::InvalidTid { tid });
+        }
+        Ok(unsafe { ffi::CStr::from_ptr(htslib::sam_hdr_tid2name(self.inner, tid)).to_bytes() })
     }
 
     pub fn target_count(&self) -> u32 {
@@ -1454,18 +2482,33 @@ impl HeaderView {
             .collect()
     }
 
+    /// Length of the given target. Lengths beyond `u32::MAX` (which do not fit the BAM header
+    /// structure) are taken from the `LN` tag of the textual header.
     pub fn target_len(&self, tid: u32) -> Option<u64> {
         let inner = unsafe { *self.inner };
         if (tid as i32) < inner.n_targets {
-            let l: &[u32] =
-                unsafe { slice::from_raw_parts(inner.target_len, inner.n_targets as usize) };
-            Some(l[tid as usize] as u64)
+            let len = unsafe { htslib::sam_hdr_tid2len(self.inner, tid as i32) };
+            Some(len as u64)
         } else {
             None
         }
     }
 
-    /// Retrieve the textual SAM header as bytes
+    /// Return the sort order declared in the `@HD` line of the header. Note that this is
+    /// only what the header claims, the records themselves are not inspected.
+    pub fn sort_order(&self) -> SortOrder {
+        self.as_bytes()
+            .split(|&c| c == b'\n')
+            .find(|line| line.starts_with(b"@HD\t"))
+            .and_then(|line| {
+                line.split(|&c| c == b'\t')
+                    .find_map(|field| field.strip_prefix(b"SO:"))
+            })
+            .map_or(SortOrder::Unknown, SortOrder::from_tag)
+    }
+
+    /// Retrieve the textual SAM header as bytes, exactly as htslib holds it (including the
+    /// final newline). This is empty if the file has no header.
     pub fn as_bytes(&self) -> &[u8] {
         unsafe {
             let rebuilt_hdr = htslib::sam_hdr_str(self.inner);
@@ -1664,6 +2707,125 @@ CCCCCCCCCCCCCCCCCCC"[..],
         assert_eq!(header_text, true_header);
     }
 
+    #[test]
+    fn test_sort_order() {
+        let bam = Reader::from_path("test/test.bam").expect("Error opening file.");
+        assert_eq!(bam.header().sort_order(), SortOrder::Unknown);
+        for (text, order) in [
+            (&b"@HD\tVN:1.6\tSO:coordinate\n"[..], SortOrder::Coordinate),
+            (b"@HD\tSO:queryname\tVN:1.6\n", SortOrder::Queryname),
+            (b"@HD\tVN:1.6\tSO:unsorted\n", SortOrder::Unsorted),
+            (b"@HD\tVN:1.6\tSO:unknown\n", SortOrder::Unknown),
+            (b"@HD\tVN:1.6\n@CO\tSO:coordinate\n", SortOrder::Unknown),
+        ] {
+            assert_eq!(HeaderView::from_bytes(text).sort_order(), order);
+        }
+    }
+
+    #[test]
+    fn test_write_assert_sorted() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("sorted.bam");
+
+        let mut header = Header::new();
+        header.push_record(
+            HeaderRecord::new(b"HD")
+                .push_tag(b"VN", "1.6")
+                .push_tag(b"SO", "coordinate"),
+        );
+        for name in ["chr1", "chr2"] {
+            header.push_record(
+                HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", name)
+                    .push_tag(b"LN", 1000),
+            );
+        }
+        let record_at = |tid, pos| {
+            let mut record = Record::new();
+            record.set(b"read", None, b"ACGT", &[30; 4]);
+            record.set_tid(tid);
+            record.set_pos(pos);
+            if tid < 0 {
+                record.set_unmapped();
+            }
+            record
+        };
+
+        let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+        let sort_order = writer.header().sort_order();
+        writer.assert_sorted(sort_order);
+        writer.write(&record_at(0, 10)).unwrap();
+        writer.write(&record_at(0, 10)).unwrap();
+        writer.write(&record_at(1, 5)).unwrap();
+        assert_eq!(writer.write(&record_at(1, 4)), Err(Error::BamUnsorted));
+        assert_eq!(writer.write(&record_at(0, 20)), Err(Error::BamUnsorted));
+        writer.write(&record_at(-1, -1)).unwrap();
+        assert_eq!(writer.write(&record_at(1, 500)), Err(Error::BamUnsorted));
+        writer.write(&record_at(-1, -1)).unwrap();
+
+        // without the check, anything goes
+        writer.assert_sorted(SortOrder::Unsorted);
+        writer.write(&record_at(0, 0)).unwrap();
+        drop(writer);
+
+        let mut reader = Reader::from_path(&bampath).unwrap();
+        let positions: Vec<_> = reader
+            .records()
+            .map(|r| {
+                let r = r.unwrap();
+                (r.tid(), r.pos())
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![(0, 10), (0, 10), (1, 5), (-1, -1), (-1, -1), (0, 0)]
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_header_name_lookups() {
+        let mut bam = Reader::from_path("test/test.bam").expect("Error opening file.");
+        let header = bam.header().clone();
+        assert_eq!(header.name2tid(b"CHROMOSOME_III"), Ok(2));
+        assert_eq!(
+            header.name2tid(b"chrUn"),
+            Err(Error::UnknownSequence {
+                sequence: "chrUn".to_owned()
+            })
+        );
+        assert_eq!(header.try_tid2name(4), Ok(&b"CHROMOSOME_V"[..]));
+        assert_eq!(header.tid2name(0), b"CHROMOSOME_I");
+        assert_eq!(header.try_tid2name(5), Err(Error::InvalidTid { tid: 5 }));
+        assert_eq!(header.try_tid2name(-1), Err(Error::InvalidTid { tid: -1 }));
+        assert_eq!(header.target_len(5), None);
+
+        let mut record = bam.records().next().unwrap().unwrap();
+        assert_eq!(record.contig_name(), Ok("CHROMOSOME_I"));
+        record.set_tid(-1);
+        assert_eq!(record.contig_name(), Err(Error::InvalidTid { tid: -1 }));
+        assert_eq!(
+            Record::new().contig_name(),
+            Err(Error::BamRecordWithoutHeader)
+        );
+
+        let header = Rc::new(HeaderView::from_bytes(b"@SQ\tSN:chr\xff\tLN:100\n"));
+        let mut record =
+            Record::from_sam(&header, b"r\t0\tchr\xff\t1\t60\t4M\t*\t0\t0\tACGT\t*").unwrap();
+        record.set_header(Rc::clone(&header));
+        let not_utf8 = Err(Error::BamContigNameNotUtf8 {
+            tid: 0,
+            name: "chr\u{fffd}".to_owned(),
+        });
+        assert_eq!(record.contig_name(), not_utf8);
+        let reference = faidx::Reader::from_path("test/test_cram.fa").unwrap();
+        assert_eq!(record.cal_md_nm(&reference), not_utf8.map(|_| ()));
+    }
+
     #[test]
     fn test_read_against_sam() {
         let mut bam = Reader::from_path("./test/bam2sam_out.sam").unwrap();
@@ -1806,6 +2968,121 @@ CCCCCCCCCCCCCCCCCCC"[..],
         _test_read_indexed_common(bam);
     }
 
+    #[test]
+    fn test_fetch_large_coordinates_csi() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("large.bam");
+        let idxdir = tmp.path().join("indices");
+        fs::create_dir(&idxdir).unwrap();
+        let idxpath = idxdir.join("custom_name.csi");
+
+        // Positions beyond the 2^29 limit of BAI, which need a CSI index.
+        let starts: Vec<i64> = vec![100, 2_100_000_000, 2_100_000_010, 2_140_000_000];
+        {
+            let mut header = Header::new();
+            header.push_record(
+                HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", "chrBig")
+                    .push_tag(b"LN", 3_000_000_000u64),
+            );
+            let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            for (i, start) in starts.iter().enumerate() {
+                let mut record = Record::new();
+                let cigar = CigarString(vec![Cigar::Match(4)]);
+                record.set(
+                    format!("read{}", i).as_bytes(),
+                    Some(&cigar),
+                    b"ACGT",
+                    &[30; 4],
+                );
+                record.set_tid(0);
+                record.set_pos(*start);
+                record.set_mtid(-1);
+                record.set_mpos(-1);
+                writer.write(&record).unwrap();
+            }
+        }
+        assert!(index::build(&bampath, Some(&idxpath), index::Type::Bai, 1).is_err());
+        index::build(&bampath, Some(&idxpath), index::Type::Csi(14), 1).unwrap();
+
+        let mut reader = IndexedReader::from_path_and_index(&bampath, &idxpath).unwrap();
+        assert_eq!(reader.header().target_len(0), Some(3_000_000_000));
+
+        reader
+            .fetch(("chrBig", 2_099_999_990u64, 2_100_000_020u64))
+            .unwrap();
+        let positions: Vec<i64> = reader.records().map(|r| r.unwrap().pos()).collect();
+        assert_eq!(positions, vec![2_100_000_000, 2_100_000_010]);
+
+        reader.fetch("chrBig:2,139,999,999-2,140,000,001").unwrap();
+        let pileup_positions: Vec<u64> = reader.pileup().map(|p| p.unwrap().pos()).collect();
+        assert_eq!(
+            pileup_positions,
+            vec![2_140_000_000, 2_140_000_001, 2_140_000_002, 2_140_000_003]
+        );
+
+        reader.fetch(FetchDefinition::CompleteTid(0)).unwrap();
+        assert_eq!(reader.records().count(), starts.len());
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_target_len_beyond_u32() {
+        let header = HeaderView::from_bytes(b"@SQ\tSN:chrHuge\tLN:5000000000\n");
+        assert_eq!(header.target_len(0), Some(5_000_000_000));
+        assert_eq!(header.target_len(1), None);
+    }
+
+    #[test]
+    fn test_fetch_region_string() {
+        let mut bam = IndexedReader::from_path("test/test.bam").expect("Expected valid index.");
+
+        bam.fetch("CHROMOSOME_I:1-2")
+            .expect("Expected successful fetch.");
+        assert_eq!(bam.records().count(), 6);
+        bam.fetch("CHROMOSOME_I:1")
+            .expect("Expected successful fetch.");
+        assert_eq!(bam.records().count(), 6);
+        bam.fetch("CHROMOSOME_I:1,000,000-2,000,000")
+            .expect("Expected successful fetch.");
+        assert_eq!(bam.records().count(), 0);
+
+        assert_eq!(
+            bam.fetch("CHROMOSOME_X:1-100"),
+            Err(Error::UnknownSequence {
+                sequence: "CHROMOSOME_X".to_owned()
+            })
+        );
+        assert_eq!(
+            bam.fetch("CHROMOSOME_X"),
+            Err(Error::UnknownSequence {
+                sequence: "CHROMOSOME_X".to_owned()
+            })
+        );
+        assert_eq!(
+            bam.fetch(("CHROMOSOME_X", 0, 100)),
+            Err(Error::UnknownSequence {
+                sequence: "CHROMOSOME_X".to_owned()
+            })
+        );
+        assert_eq!(
+            bam.fetch("CHROMOSOME_I:abc"),
+            Err(Error::InvalidRegion {
+                region: "CHROMOSOME_I:abc".to_owned()
+            })
+        );
+        assert_eq!(
+            bam.fetch("CHROMOSOME_I:200-100"),
+            Err(Error::InvalidRegion {
+                region: "CHROMOSOME_I:200-100".to_owned()
+            })
+        );
+    }
+
     #[test]
     fn test_set_record() {
         let (names, _, seqs, quals, cigars) = gold();
@@ -1918,6 +3195,65 @@ CCCCCCCCCCCCCCCCCCC"[..],
         }
     }
 
+    #[test]
+    fn test_set_qname_write_read_back() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("renamed.bam");
+
+        let mut reader = Reader::from_path("test/test.bam").unwrap();
+        let header = Header::from_template(reader.header());
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        let new_names: Vec<Vec<u8>> = (0..records.len())
+            .map(|i| match i % 3 {
+                0 => vec![b'L'; 254],
+                1 => b"s".to_vec(),
+                _ => format!("{}/1", str::from_utf8(records[i].qname()).unwrap()).into_bytes(),
+            })
+            .collect();
+        {
+            let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            for (record, name) in records.iter().zip(new_names.iter()) {
+                let mut renamed = record.clone();
+                renamed.set_qname(name);
+                writer.write(&renamed).unwrap();
+            }
+        }
+
+        let mut reader = Reader::from_path(&bampath).unwrap();
+        let read_back: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(read_back.len(), records.len());
+        for ((old, new), name) in records.iter().zip(read_back.iter()).zip(new_names.iter()) {
+            assert_eq!(new.qname(), name.as_slice());
+            assert_eq!(new.inner().core.l_extranul, extranul(name));
+            assert_eq!(new.flags(), old.flags());
+            assert_eq!(new.tid(), old.tid());
+            assert_eq!(new.pos(), old.pos());
+            assert_eq!(new.mapq(), old.mapq());
+            assert_eq!(new.raw_cigar(), old.raw_cigar());
+            assert_eq!(new.seq().encoded, old.seq().encoded);
+            assert_eq!(new.qual(), old.qual());
+            let old_aux: Vec<_> = old.aux_iter().map(|a| a.unwrap()).collect();
+            let new_aux: Vec<_> = new.aux_iter().map(|a| a.unwrap()).collect();
+            assert_eq!(new_aux, old_aux);
+        }
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    fn extranul(qname: &[u8]) -> u8 {
+        ((4 - (qname.len() + 1) % 4) % 4) as u8
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_qname_too_long() {
+        let mut rec = Record::new();
+        rec.set_qname(&[b'L'; 255]);
+    }
+
     #[test]
     fn test_set_qname2() {
         let mut _header = Header::new();
@@ -2012,73 +3348,542 @@ CCCCCCCCCCCCCCCCCCC"[..],
     }
 
     #[test]
-    fn test_write_threaded() {
-        let (names, _, seqs, quals, cigars) = gold();
+    fn test_read_from_stdin_pipe() {
+        // Redirecting STDIN in-process would race with the other tests opening files, so the
+        // test re-runs itself in a child process with the BAM piped into its STDIN, like in
+        // `samtools view -u ... | mytool`.
+        if std::env::var_os("RUST_HTSLIB_TEST_STDIN").is_some() {
+            let mut reader = Reader::from_stdin().unwrap();
+            let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+            let (names, flags, seqs, _, cigars) = gold();
+            assert_eq!(records.len(), names.len());
+            for (i, rec) in records.iter().enumerate() {
+                assert_eq!(rec.qname(), names[i]);
+                assert_eq!(rec.flags(), flags[i]);
+                assert_eq!(rec.seq().as_bytes(), seqs[i]);
+                assert_eq!(*rec.cigar(), cigars[i]);
+            }
+            return;
+        }
+
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "bam::tests::test_read_from_stdin_pipe"])
+            .env("RUST_HTSLIB_TEST_STDIN", "1")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        {
+            use std::io::Write;
+            let mut stdin = child.stdin.take().unwrap();
+            stdin
+                .write_all(&fs::read("test/test.bam").unwrap())
+                .unwrap();
+        }
+        let output = child.wait_with_output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(output.status.success(), "{}", stdout);
+        // The child has to have run this test, and not e.g. no test at all, which also succeeds.
+        assert!(
+            stdout.contains("test result: ok. 1 passed; 0 failed"),
+            "{}",
+            stdout
+        );
+    }
 
+    #[test]
+    fn test_sam_output_round_trip() {
         let tmp = tempfile::Builder::new()
             .prefix("rust-htslib")
             .tempdir()
             .expect("Cannot create temp dir");
         let bampath = tmp.path().join("test.bam");
-        println!("{:?}", bampath);
-        {
-            let mut bam = Writer::from_path(
-                &bampath,
-                Header::new().push_record(
-                    HeaderRecord::new(b"SQ")
-                        .push_tag(b"SN", &"chr1")
-                        .push_tag(b"LN", &15072423),
-                ),
-                Format::Bam,
-            )
-            .expect("Error opening file.");
-            bam.set_threads(4).unwrap();
+        let sampath = tmp.path().join("test.sam");
+        let sampath2 = tmp.path().join("test2.sam");
+
+        let mut header = Header::new();
+        header.push_record(
+            HeaderRecord::new(b"HD")
+                .push_tag(b"VN", "1.6")
+                .push_tag(b"SO", "coordinate"),
+        );
+        header.push_record(
+            HeaderRecord::new(b"SQ")
+                .push_tag(b"SN", "chr1")
+                .push_tag(b"LN", 1000),
+        );
+        header.push_record(
+            HeaderRecord::new(b"RG")
+                .push_tag(b"ID", "rg1")
+                .push_tag(b"SM", "sample1"),
+        );
+        header.push_comment(b"round trip test");
 
-            for i in 0..10000 {
-                let mut rec = record::Record::new();
-                let idx = i % names.len();
-                rec.set(names[idx], Some(&cigars[idx]), seqs[idx], quals[idx]);
-                rec.push_aux(b"NM", Aux::I32(15)).unwrap();
-                rec.set_pos(i as i64);
+        {
+            let mut bam = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            let mut rec = Record::new();
+            let cigar = CigarString(vec![Cigar::Match(4)]);
+            rec.set(b"read1", Some(&cigar), b"ACGT", &[10, 20, 30, 40]);
+            rec.set_tid(0);
+            rec.set_pos(99);
+            rec.set_mapq(60);
+            rec.set_mtid(-1);
+            rec.set_mpos(-1);
+            rec.push_aux(b"XA", Aux::Char(b'x')).unwrap();
+            rec.push_aux(b"XB", Aux::I8(-5)).unwrap();
+            rec.push_aux(b"XC", Aux::U16(1000)).unwrap();
+            rec.push_aux(b"XD", Aux::I32(-100_000)).unwrap();
+            rec.push_aux(b"XE", Aux::Float(0.5)).unwrap();
+            rec.push_aux(b"XF", Aux::String("a string")).unwrap();
+            rec.push_aux(b"XG", Aux::HexByteArray("1AE301")).unwrap();
+            rec.push_aux(b"XH", Aux::ArrayI8((&[-1i8, 2][..]).into()))
+                .unwrap();
+            rec.push_aux(b"XI", Aux::ArrayU16((&[1u16, 65535][..]).into()))
+                .unwrap();
+            rec.push_aux(b"XJ", Aux::ArrayI32((&[-70_000i32, 70_000][..]).into()))
+                .unwrap();
+            rec.push_aux(b"XK", Aux::ArrayFloat((&[1.5f32, -2.5][..]).into()))
+                .unwrap();
+            rec.push_aux(b"RG", Aux::String("rg1")).unwrap();
+            bam.write(&rec).unwrap();
+        }
 
-                bam.write(&rec).expect("Failed to write record.");
+        // BAM -> SAM -> SAM, the SAM text must be stable.
+        for (src, dst) in &[(&bampath, &sampath), (&sampath, &sampath2)] {
+            let mut reader = Reader::from_path(src).unwrap();
+            let header = Header::from_template(reader.header());
+            let mut writer = Writer::from_path(dst, &header, Format::Sam).unwrap();
+            for rec in reader.records() {
+                writer.write(&rec.unwrap()).unwrap();
             }
         }
+        let sam = fs::read_to_string(&sampath).unwrap();
+        assert_eq!(sam, fs::read_to_string(&sampath2).unwrap());
+
+        let lines: Vec<&str> = sam.lines().collect();
+        assert_eq!(lines[0], "@HD\tVN:1.6\tSO:coordinate");
+        assert!(lines.contains(&"@SQ\tSN:chr1\tLN:1000"));
+        assert!(lines.contains(&"@RG\tID:rg1\tSM:sample1"));
+        assert!(lines.contains(&"@CO\tround trip test"));
+        assert_eq!(
+            lines.last().unwrap(),
+            &"read1\t0\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\t+5?I\t\
+              XA:A:x\tXB:i:-5\tXC:i:1000\tXD:i:-100000\tXE:f:0.5\tXF:Z:a string\t\
+              XG:H:1AE301\tXH:B:c,-1,2\tXI:B:S,1,65535\tXJ:B:i,-70000,70000\t\
+              XK:B:f,1.5,-2.5\tRG:Z:rg1"
+        );
 
-        {
-            let mut bam = Reader::from_path(&bampath).expect("Error opening file.");
-
-            for (i, _rec) in bam.records().enumerate() {
-                let idx = i % names.len();
+        tmp.close().expect("Failed to delete temp dir");
+    }
 
-                let rec = _rec.expect("Failed to read record.");
+    #[test]
+    fn test_write_validate() {
+        let mut header = Header::new();
+        header.push_record(
+            HeaderRecord::new(b"SQ")
+                .push_tag(b"SN", "chr1")
+                .push_tag(b"LN", 1000),
+        );
+        let mut record = Record::new();
+        let cigar = CigarString(vec![Cigar::Match(3)]);
+        record.set(b"bad", Some(&cigar), b"ACGT", &[30; 4]);
+        record.set_pos(10);
 
-                assert_eq!(rec.pos(), i as i64);
-                assert_eq!(rec.qname(), names[idx]);
-                assert_eq!(*rec.cigar(), cigars[idx]);
-                assert_eq!(rec.seq().as_bytes(), seqs[idx]);
-                assert_eq!(rec.qual(), quals[idx]);
-                assert_eq!(rec.aux(b"NM").unwrap(), Aux::I32(15));
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.bam");
+        let mut writer = Writer::from_path(&path, &header, Format::Bam).unwrap();
+        // htslib happily writes the record
+        writer.write(&record).unwrap();
+        writer.set_validate(true);
+        let err = writer.write(&record).unwrap_err();
+        assert_eq!(
+            err,
+            Error::BamInvalidRecordFields {
+                qname: "bad".to_owned(),
+                errors: vec![record::ValidationError::CigarSeqLengthMismatch {
+                    cigar_len: 3,
+                    seq_len: 4
+                }],
             }
-        }
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid record bad: CIGAR covers 3 query bases, but the sequence has 4"
+        );
 
+        record.set(b"good", Some(&cigar), b"ACG", &[30; 3]);
+        writer.write(&record).unwrap();
+        writer.close().unwrap();
+        assert_eq!(Reader::from_path(&path).unwrap().records().count(), 2);
         tmp.close().expect("Failed to delete temp dir");
     }
 
     #[test]
-    fn test_write_shared_tpool() {
-        let (names, _, seqs, quals, cigars) = gold();
+    fn test_write_errors() {
+        let mut bam = Reader::from_path("test/test.bam").unwrap();
+        let header = Header::from_template(bam.header());
+        let records: Vec<Record> = bam.records().map(|r| r.unwrap()).collect();
 
         let tmp = tempfile::Builder::new()
             .prefix("rust-htslib")
             .tempdir()
             .expect("Cannot create temp dir");
-        let bampath1 = tmp.path().join("test1.bam");
-        let bampath2 = tmp.path().join("test2.bam");
-
-        {
-            let (mut bam1, mut bam2) = {
-                let pool = crate::tpool::ThreadPool::new(4).unwrap();
+        let path = tmp.path().join("out.bam");
+        let mut writer = Writer::from_path(&path, &header, Format::Bam).unwrap();
+        assert_eq!(writer.write_all(&records).unwrap(), records.len());
+        writer.close().unwrap();
+        let mut bam = Reader::from_path(&path).unwrap();
+        assert_eq!(bam.records().count(), records.len());
+        tmp.close().expect("Failed to delete temp dir");
+
+        // /dev/full is not available on e.g. macOS
+        if !std::path::Path::new("/dev/full").exists() {
+            return;
+        }
+        // output is buffered, so that writing to a full disk first fails when flushing
+        let mut writer = Writer::from_path("/dev/full", &header, Format::Sam).unwrap();
+        writer.write(&records[0]).unwrap();
+        assert_eq!(writer.flush(), Err(Error::FileFlush));
+        let mut writer = Writer::from_path("/dev/full", &header, Format::Sam).unwrap();
+        writer.write(&records[0]).unwrap();
+        assert_eq!(writer.close(), Err(Error::FileClose));
+
+        // once the buffer is full, the failing record is reported
+        let mut writer = Writer::from_path("/dev/full", &header, Format::Sam).unwrap();
+        match writer.write_all(records.iter().cycle().take(100_000)) {
+            Err(Error::WriteRecordAt { index, source }) => {
+                assert!(index > 0);
+                let qname = records[index % records.len()].qname();
+                assert_eq!(
+                    *source,
+                    Error::BamWriteRecord {
+                        qname: String::from_utf8_lossy(qname).into_owned()
+                    }
+                );
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_write_format_from_extension() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let mut header = Header::new();
+        header.push_record(
+            HeaderRecord::new(b"SQ")
+                .push_tag(b"SN", "chr1")
+                .push_tag(b"LN", 1000),
+        );
+
+        let decompressed = |path: &Path| {
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(
+                &mut crate::bgzf::Reader::from_path(path).unwrap(),
+                &mut content,
+            )
+            .unwrap();
+            content
+        };
+
+        // the given format is overridden by the extension, unless it is compatible
+        for (name, format, bgzf, magic) in [
+            ("out.sam", Format::Bam, false, &b"@SQ\tSN:chr1"[..]),
+            ("out.SAM", Format::UncompressedBam, false, b"@SQ\tSN:chr1"),
+            ("out.bam", Format::Sam, true, b"BAM\x01"),
+            ("out.bam", Format::UncompressedBam, true, b"BAM\x01"),
+            ("out.cram", Format::Bam, false, b"CRAM"),
+            ("out.txt", Format::Sam, false, b"@SQ\tSN:chr1"),
+            ("out", Format::Bam, true, b"BAM\x01"),
+        ] {
+            let path = tmp.path().join(name);
+            Writer::from_path(&path, &header, format).unwrap();
+            assert_eq!(crate::bgzf::is_bgzip(&path).unwrap(), bgzf, "{}", name);
+            let content = if bgzf {
+                decompressed(&path)
+            } else {
+                std::fs::read(&path).unwrap()
+            };
+            assert!(content.starts_with(magic), "{}", name);
+            let reader = Reader::from_path(&path).unwrap();
+            assert_eq!(reader.header().target_names(), vec![b"chr1"], "{}", name);
+        }
+
+        // an explicit format is written regardless of the extension
+        let path = tmp.path().join("explicit.sam");
+        Writer::from_path_with_format(&path, &header, Format::Bam).unwrap();
+        assert!(decompressed(&path).starts_with(b"BAM\x01"));
+
+        assert!(matches!(
+            Format::from_extension("a/b.Cram"),
+            Some(Format::Cram)
+        ));
+        assert!(Format::from_extension("a/b.sam.gz").is_none());
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_write_uncompressed_bam() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let mut reader = Reader::from_path("test/test_spliced_reads.bam").unwrap();
+        let header = Header::from_template(reader.header());
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+
+        let mut sizes = Vec::new();
+        for (name, format) in &[("c.bam", Format::Bam), ("u.bam", Format::UncompressedBam)] {
+            let path = tmp.path().join(name);
+            {
+                let mut writer = Writer::from_path(&path, &header, *format).unwrap();
+                for record in &records {
+                    writer.write(record).unwrap();
+                }
+            }
+            let mut reader = Reader::from_path(&path).unwrap();
+            let read_back: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+            assert_eq!(read_back.len(), records.len());
+            for (a, b) in read_back.iter().zip(records.iter()) {
+                assert_eq!(a.qname(), b.qname());
+                assert_eq!(a.seq().as_bytes(), b.seq().as_bytes());
+            }
+            sizes.push(fs::metadata(&path).unwrap().len());
+        }
+        assert!(sizes[1] > sizes[0]);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_long_cigar_round_trip() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("long_cigar.bam");
+
+        // More operations than fit into the 16 bit CIGAR length of BAM records,
+        // so that htslib has to store the CIGAR in the CG tag
+        let n_ops = 70_000;
+        let cigar = CigarString(
+            (0..n_ops)
+                .map(|i| {
+                    if i % 2 == 0 {
+                        Cigar::Match(2)
+                    } else {
+                        Cigar::Del(1)
+                    }
+                })
+                .collect(),
+        );
+        let seq_len = n_ops;
+        let seq: Vec<u8> = b"ACGT".iter().cycle().take(seq_len).copied().collect();
+        let qual = vec![30; seq_len];
+
+        let mut record = Record::new();
+        record.set(b"long", Some(&cigar), &seq, &qual);
+        record.set_tid(0);
+        record.set_pos(1000);
+        record.set_mtid(-1);
+        record.set_mpos(-1);
+        record.push_aux(b"NM", Aux::I32(35_000)).unwrap();
+        assert_eq!(record.cigar().end_pos(), 1000 + 105_000);
+
+        let mut header = Header::new();
+        header.push_record(
+            HeaderRecord::new(b"SQ")
+                .push_tag(b"SN", "chr1")
+                .push_tag(b"LN", 1_000_000),
+        );
+        {
+            let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            writer.write(&record).unwrap();
+        }
+
+        let mut reader = Reader::from_path(&bampath).unwrap();
+        let read_back = reader.records().next().unwrap().unwrap();
+        assert_eq!(read_back.cigar_len(), n_ops);
+        assert_eq!(read_back.cigar(), record.cigar());
+        assert_eq!(read_back.cigar().end_pos(), 1000 + 105_000);
+        // The CG tag is consumed by htslib when reading
+        assert_eq!(read_back.aux(b"CG"), Err(Error::BamAuxTagNotFound));
+        assert_eq!(read_back.aux(b"NM"), Ok(Aux::I32(35_000)));
+        assert_eq!(read_back.seq().as_bytes(), seq);
+
+        // The pileup sees the expanded CIGAR
+        let mut reader = Reader::from_path(&bampath).unwrap();
+        let mut n_columns = 0;
+        let mut n_dels = 0;
+        let mut last_pos = 0;
+        for pileup in reader.pileup() {
+            let pileup = pileup.unwrap();
+            n_columns += 1;
+            last_pos = pileup.pos();
+            if pileup.alignments().next().unwrap().is_del() {
+                n_dels += 1;
+            }
+        }
+        assert_eq!(n_columns, 105_000);
+        assert_eq!(n_dels, 35_000);
+        assert_eq!(last_pos, 1000 + 105_000 - 1);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_from_sam_cg_tag() {
+        // A SAM line with a placeholder CIGAR and the real CIGAR in the CG tag
+        let header = {
+            let mut header = Header::new();
+            header.push_record(
+                HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", "chr1")
+                    .push_tag(b"LN", 1000),
+            );
+            HeaderView::from_header(&header)
+        };
+        let record = Record::from_sam(
+            &header,
+            b"r1\t0\tchr1\t11\t60\t4S3N\t*\t0\t0\tACGT\tIIII\tCG:B:I,32,17,48",
+        )
+        .unwrap();
+        assert_eq!(format!("{}", record.cigar()), "2M1I3M");
+        assert_eq!(record.cigar().end_pos(), 15);
+        assert_eq!(record.aux(b"CG"), Err(Error::BamAuxTagNotFound));
+    }
+
+    #[test]
+    fn test_from_sam_to_sam() {
+        let header = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:500\n");
+        let lines: &[&[u8]] = &[
+            b"r1\t0\tchr1\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\tXA:A:x\tXI:i:-70000\t\
+              XF:f:0.5\tXZ:Z:some text\tXH:H:1AE301",
+            b"r2\t16\tchr2\t20\t30\t2S2M\tchr1\t10\t0\t*\t*\tXC:B:c,-1,2\tXS:B:S,1,65535\t\
+              XB:B:i,-70000,70000\tXE:B:f,1.5,-2.5",
+            b"r3\t4\t*\t0\t0\t*\t*\t0\t0\tACGTN\t#####",
+            b"r4\t99\tchr1\t100\t60\t3M\t=\t200\t103\tACG\tIII",
+        ];
+        for line in lines {
+            let record = Record::from_sam(&header, line).unwrap();
+            assert_eq!(&record.to_sam(&header).unwrap(), line);
+        }
+        let record = Record::from_sam(&header, lines[1]).unwrap();
+        assert_eq!(record.seq_len(), 0);
+        assert_eq!(record.mtid(), 0);
+        assert_eq!(
+            record.aux(b"XB").unwrap(),
+            Aux::ArrayI32((&[-70000, 70000][..]).into())
+        );
+        // a trailing newline is ignored
+        let record =
+            Record::from_sam(&header, b"r5\t0\tchr2\t1\t0\t1M\t*\t0\t0\tA\tI\r\n").unwrap();
+        assert_eq!(record.tid(), 1);
+
+        let unknown_contig = |contig: &str| Error::ContigNotFound {
+            contig: contig.to_owned(),
+            known: "chr1, chr2".to_owned(),
+        };
+        assert_eq!(
+            Record::from_sam(&header, b"r6\t0\tchr3\t1\t0\t1M\t*\t0\t0\tA\tI"),
+            Err(unknown_contig("chr3"))
+        );
+        assert_eq!(
+            Record::from_sam(&header, b"r6\t1\tchr1\t1\t0\t1M\tchrM\t1\t0\tA\tI"),
+            Err(unknown_contig("chrM"))
+        );
+        let malformed = b"r7\t0\tchr1\tnot_a_position\t0\t1M\t*\t0\t0\tA\tI";
+        assert_eq!(
+            Record::from_sam(&header, malformed),
+            Err(Error::BamParseSAM {
+                rec: String::from_utf8_lossy(malformed).into_owned()
+            })
+        );
+        assert!(Record::from_sam(&header, b"r8\t0\tchr1").is_err());
+
+        // the contig of the record has to be in the header
+        let record = Record::from_sam(&header, lines[1]).unwrap();
+        let other = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n");
+        assert_eq!(record.to_sam(&other), Err(Error::BamFormatSAM));
+    }
+
+    #[test]
+    fn test_write_threaded() {
+        let (names, _, seqs, quals, cigars) = gold();
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("test.bam");
+        println!("{:?}", bampath);
+        {
+            let mut bam = Writer::from_path(
+                &bampath,
+                Header::new().push_record(
+                    HeaderRecord::new(b"SQ")
+                        .push_tag(b"SN", &"chr1")
+                        .push_tag(b"LN", &15072423),
+                ),
+                Format::Bam,
+            )
+            .expect("Error opening file.");
+            bam.set_threads(4).unwrap();
+
+            for i in 0..10000 {
+                let mut rec = record::Record::new();
+                let idx = i % names.len();
+                rec.set(names[idx], Some(&cigars[idx]), seqs[idx], quals[idx]);
+                rec.push_aux(b"NM", Aux::I32(15)).unwrap();
+                rec.set_pos(i as i64);
+
+                bam.write(&rec).expect("Failed to write record.");
+            }
+        }
+
+        {
+            let mut bam = Reader::from_path(&bampath).expect("Error opening file.");
+            bam.set_threads(4).unwrap();
+
+            let mut n = 0;
+            for (i, _rec) in bam.records().enumerate() {
+                let idx = i % names.len();
+                n += 1;
+
+                let rec = _rec.expect("Failed to read record.");
+
+                assert_eq!(rec.pos(), i as i64);
+                assert_eq!(rec.qname(), names[idx]);
+                assert_eq!(*rec.cigar(), cigars[idx]);
+                assert_eq!(rec.seq().as_bytes(), seqs[idx]);
+                assert_eq!(rec.qual(), quals[idx]);
+                assert_eq!(rec.aux(b"NM").unwrap(), Aux::I32(15));
+            }
+            assert_eq!(n, 10000);
+        }
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_write_shared_tpool() {
+        let (names, _, seqs, quals, cigars) = gold();
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath1 = tmp.path().join("test1.bam");
+        let bampath2 = tmp.path().join("test2.bam");
+
+        {
+            let (mut bam1, mut bam2) = {
+                let pool = crate::tpool::ThreadPool::new(4).unwrap();
 
                 let mut bam1 = Writer::from_path(
                     &bampath1,
@@ -2182,24 +3987,855 @@ CCCCCCCCCCCCCCCCCCC"[..],
     }
 
     #[test]
-    fn test_pileup() {
-        let (_, _, seqs, quals, _) = gold();
-
-        let mut bam = Reader::from_path(&"test/test.bam").expect("Error opening file.");
-        let pileups = bam.pileup();
-        for pileup in pileups.take(26) {
-            let _pileup = pileup.expect("Expected successful pileup.");
-            let pos = _pileup.pos() as usize;
-            assert_eq!(_pileup.depth(), 6);
-            assert!(_pileup.tid() == 0);
-            for (i, a) in _pileup.alignments().enumerate() {
-                assert_eq!(a.indel(), pileup::Indel::None);
-                let qpos = a.qpos().unwrap();
-                assert_eq!(qpos, pos - 1);
-                assert_eq!(a.record().seq()[qpos], seqs[i][qpos]);
-                assert_eq!(a.record().qual()[qpos], quals[i][qpos] - 33);
+    fn test_pileup() {
+        let (_, _, seqs, quals, _) = gold();
+
+        let mut bam = Reader::from_path(&"test/test.bam").expect("Error opening file.");
+        let pileups = bam.pileup();
+        for pileup in pileups.take(26) {
+            let _pileup = pileup.expect("Expected successful pileup.");
+            let pos = _pileup.pos() as usize;
+            assert_eq!(_pileup.depth(), 6);
+            assert!(_pileup.tid() == 0);
+            for (i, a) in _pileup.alignments().enumerate() {
+                assert_eq!(a.indel(), pileup::Indel::None);
+                let qpos = a.qpos().unwrap();
+                assert_eq!(qpos, pos - 1);
+                assert_eq!(a.record().seq()[qpos], seqs[i][qpos]);
+                assert_eq!(a.record().qual()[qpos], quals[i][qpos] - 33);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fetch_error_context() {
+        fn open_and_fetch(
+            tid: i32,
+        ) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let mut bam = IndexedReader::from_path("test/test.bam")?;
+            bam.fetch(tid)?;
+            Ok(())
+        }
+        assert!(open_and_fetch(0).is_ok());
+        assert_eq!(
+            open_and_fetch(99).unwrap_err().to_string(),
+            "invalid tid 99"
+        );
+
+        let mut bam = IndexedReader::from_path("test/test.bam").unwrap();
+        assert_eq!(
+            bam.fetch("CHROMOSOME_I:x-10").unwrap_err(),
+            Error::InvalidRegion {
+                region: "CHROMOSOME_I:x-10".to_owned()
+            }
+        );
+        let err = Error::Fetch {
+            region: "CHROMOSOME_I:1-10".to_owned(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "failed to fetch region \"CHROMOSOME_I:1-10\""
+        );
+    }
+
+    #[test]
+    fn test_fetch_by_name() {
+        let mut bam = IndexedReader::from_path("test/test.bam").unwrap();
+        bam.fetch_by_name(b"CHROMOSOME_I", 0, u64::MAX).unwrap();
+        assert_eq!(bam.records().count(), 6);
+        bam.fetch_by_name(b"CHROMOSOME_I", 0, 1).unwrap();
+        assert_eq!(bam.records().count(), 0);
+
+        match bam.fetch_by_name(b"chrI", 0, 100).unwrap_err() {
+            Error::ContigNotFound { contig, known } => {
+                assert_eq!(contig, "chrI");
+                assert!(known.starts_with("CHROMOSOME_I, CHROMOSOME_II, "));
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn test_coverage() {
+        // test/test_depth.txt lists the expected depth (contig, 1-based position, depth) of every
+        // position of the regions in test/test_depth.bed. It was derived by hand from the
+        // alignments of test/test.bam and checked against the pileup: all six reads on
+        // CHROMOSOME_I start at position 2 with 27M1D73M, except for the supplementary
+        // alignment with mapq 1, which has a deletion of 100000 bases instead. The regions
+        // cover the left edge of the reads, the shorter deletions, and the end of the long
+        // deletion and of the reads.
+        let regions = fs::read_to_string("test/test_depth.bed").unwrap();
+        let expected_depth = fs::read_to_string("test/test_depth.txt").unwrap();
+        let mut expected = expected_depth.lines().map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            (
+                fields[0].to_owned(),
+                fields[1].parse::<u64>().unwrap(),
+                fields[2].parse::<u32>().unwrap(),
+            )
+        });
+        let mut bam = IndexedReader::from_path("test/test.bam").unwrap();
+        for region in regions.lines() {
+            let fields: Vec<&str> = region.split('\t').collect();
+            let tid = bam.header().tid(fields[0].as_bytes()).unwrap();
+            let (beg, end) = (fields[1].parse().unwrap(), fields[2].parse().unwrap());
+            let depth = bam.coverage(tid, beg, end, 0, 0).unwrap();
+            assert_eq!(depth.len() as u64, end - beg);
+            for (pos, &d) in (beg..end).zip(&depth) {
+                assert_eq!(
+                    Some((fields[0].to_owned(), pos + 1, d)),
+                    expected.next(),
+                    "{}",
+                    region
+                );
+            }
+            let total: u64 = depth.iter().map(|&d| u64::from(d)).sum();
+            assert_eq!(
+                bam.mean_coverage(tid, beg, end, 0, 0).unwrap(),
+                total as f64 / (end - beg) as f64
+            );
+        }
+        assert_eq!(expected.next(), None);
+
+        let supplementary = htslib::BAM_FSUPPLEMENTARY as u16;
+        let depth = bam.coverage(0, 0, 105, 0, supplementary).unwrap();
+        assert_eq!(&depth[1..102], &[5; 101][..]);
+        assert_eq!(&depth[102..], &[0; 3]);
+        let depth = bam.coverage(0, 20, 40, 40, 0).unwrap();
+        assert_eq!(depth, vec![1; 20]);
+
+        assert!(bam.coverage(0, 10, 10, 0, 0).unwrap().is_empty());
+        assert!(bam.mean_coverage(0, 10, 10, 0, 0).unwrap().is_nan());
+        assert!(matches!(
+            bam.coverage(0, 10, 5, 0, 0),
+            Err(Error::InvalidRegion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_coverage_spliced_matches_pileup() {
+        let path = "test/test_spliced_reads.bam";
+        let (tid, beg, end) = (21, 17_092_700, 17_095_100);
+        let mut bam = IndexedReader::from_path(path).unwrap();
+        let depth = bam.coverage(tid, beg, end, 0, 0).unwrap();
+
+        // reference skips are part of the pileup, but do not count towards the depth
+        let mut expected = vec![0; (end - beg) as usize];
+        let mut n_refskip = 0;
+        bam.fetch((tid, beg, end)).unwrap();
+        let mut pileups = bam.pileup();
+        pileups.set_region(tid, beg, end);
+        for pileup in pileups {
+            let pileup = pileup.unwrap();
+            let n = pileup.alignments().filter(|a| !a.is_refskip()).count() as u32;
+            n_refskip += pileup.depth() - n;
+            expected[(pileup.pos() - beg) as usize] = n;
+        }
+        assert!(n_refskip > 0);
+        assert!(expected.iter().any(|&d| d > 0));
+        assert_eq!(depth, expected);
+    }
+
+    #[test]
+    fn test_fill_md() {
+        // test/test.bam has no MD tags and its reference is not part of the repository
+        let mut bam = Reader::from_path("test/test_cram.bam").unwrap();
+        let records: Vec<_> = bam.records().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            bam.fill_md(&mut records[0].clone()),
+            Err(Error::BamReferenceNotSet)
+        );
+        bam.set_reference("test/test_cram.fa").unwrap();
+        for record in &records {
+            let mut regenerated = record.clone();
+            regenerated.remove_aux(b"MD").unwrap();
+            bam.fill_md(&mut regenerated).unwrap();
+            assert_eq!(regenerated.aux(b"MD").unwrap(), record.aux(b"MD").unwrap());
+            let nm = regenerated.aux(b"NM").unwrap();
+            // the deletion of chr2.2 and the insertion of chr3.2
+            let expected = match record.qname() {
+                b"chr2.2" | b"chr3.2" => 1,
+                _ => 0,
+            };
+            assert!(matches!(nm, Aux::I32(n) if n == expected), "{:?}", nm);
+        }
+
+        let reference = faidx::Reader::from_path("test/test_cram.fa").unwrap();
+        let header = Rc::new(HeaderView::from_bytes(
+            b"@SQ\tSN:chr1\tLN:120\n@SQ\tSN:chrX\tLN:100\n",
+        ));
+        let sam = |line: &[u8]| {
+            let mut record = Record::from_sam(&header, line).unwrap();
+            record.set_header(Rc::clone(&header));
+            record
+        };
+        // mismatch, insertion, soft clip, deletion and N, replacing existing tags
+        let mut record =
+            sam(b"r\t0\tchr1\t5\t60\t2S3M1I4M2D3M\t*\t0\t0\tTTAGAGGCCTCCN\t*\tMD:Z:13\tNM:i:0");
+        record.cal_md_nm(&reference).unwrap();
+        assert_eq!(record.aux(b"MD").unwrap(), Aux::String("1C5^CA2C0"));
+        assert_eq!(record.aux(b"NM").unwrap(), Aux::I32(5));
+
+        let mut unmapped = sam(b"u\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*");
+        assert_eq!(
+            unmapped.cal_md_nm(&reference),
+            Err(Error::BamUnmappedRecord {
+                qname: "u".to_owned()
+            })
+        );
+        let mut missing = sam(b"m\t0\tchrX\t1\t60\t4M\t*\t0\t0\tACGT\t*");
+        assert_eq!(
+            missing.cal_md_nm(&reference),
+            Err(Error::UnknownSequence {
+                sequence: "chrX".to_owned()
+            })
+        );
+        let mut beyond = sam(b"b\t0\tchr1\t118\t60\t4M\t*\t0\t0\tACGT\t*");
+        assert!(matches!(
+            beyond.cal_md_nm(&reference),
+            Err(Error::InvalidRegion { .. })
+        ));
+        let mut without_header =
+            Record::from_sam(&header, b"r\t0\tchr1\t5\t60\t4M\t*\t0\t0\tACAG\t*").unwrap();
+        assert_eq!(
+            without_header.cal_md_nm(&reference),
+            Err(Error::BamRecordWithoutHeader)
+        );
+    }
+
+    #[test]
+    fn test_calculate_baq() {
+        use super::record::BaqFlags;
+
+        let reference = faidx::Reader::from_path("test/test_cram.fa").unwrap();
+        let mut bam = Reader::from_path("test/test_cram.bam").unwrap();
+        let records: Vec<_> = bam.records().map(|r| r.unwrap()).collect();
+        let ref_seq = |record: &Record| {
+            reference
+                .fetch_seq_range(record.contig_name().unwrap(), 0, 120)
+                .unwrap()
+        };
+
+        // Reads with a deletion (chr2.2) and an insertion (chr3.2). The expected qualities are
+        // regression values, recorded from the output of htslib's sam_prob_realn as called by
+        // calculate_baq, and have not been checked against samtools. The qualities of these
+        // reads in the output of `samtools calmd -Ar test/test_cram.bam test/test_cram.fa`
+        // should be the same.
+        let expected: [&[u8]; 2] = [
+            &[
+                28, 29, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 36, 37, 37, 25, 25,
+            ],
+            &[
+                32, 33, 36, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 25, 25,
+            ],
+        ];
+        for (record, expected) in [&records[3], &records[5]].iter().zip(expected.iter()) {
+            let original = record.qual().to_vec();
+            let ref_seq = ref_seq(record);
+
+            let mut applied = (*record).clone();
+            assert!(applied.calculate_baq(&ref_seq, BaqFlags::APPLY).unwrap());
+            assert_eq!(applied.qual(), *expected);
+            let zq: Vec<u8> = original
+                .iter()
+                .zip(expected.iter())
+                .map(|(q, baq)| q - baq + 64)
+                .collect();
+            assert_eq!(
+                applied.aux(b"ZQ").unwrap(),
+                Aux::String(str::from_utf8(&zq).unwrap())
+            );
+            // already applied
+            assert!(!applied.calculate_baq(&ref_seq, BaqFlags::APPLY).unwrap());
+            assert!(!applied
+                .calculate_baq(&ref_seq, BaqFlags::APPLY | BaqFlags::REDO)
+                .unwrap());
+
+            // computing the BQ tag only keeps the qualities, and applying it later is the same
+            let mut computed = (*record).clone();
+            assert!(computed
+                .calculate_baq(&ref_seq, BaqFlags::default())
+                .unwrap());
+            assert_eq!(computed.qual(), &original[..]);
+            assert!(computed.aux(b"BQ").is_ok());
+            assert!(computed.calculate_baq(&ref_seq, BaqFlags::APPLY).unwrap());
+            assert_eq!(computed.qual(), *expected);
+            assert!(computed.aux(b"BQ").is_err());
+
+            // the original qualities are restored from the ZQ tag
+            assert!(applied
+                .calculate_baq(&ref_seq, BaqFlags::default())
+                .unwrap());
+            assert_eq!(applied.qual(), &original[..]);
+            assert!(applied.aux(b"ZQ").is_err());
+            assert!(applied.aux(b"BQ").is_ok());
+        }
+
+        // deletion in a homopolymer, with the whole contig given as reference
+        let header = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:120\n");
+        let chr1 = reference.fetch_seq_range("chr1", 0, 120).unwrap();
+        let mut record = Record::from_sam(
+            &header,
+            b"del\t0\tchr1\t11\t60\t20M1D19M\t*\t0\t0\t\
+              TCACCCAGGAAAGCAGCTGGGGTCCACTGGGCTCAGGGA\tIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII",
+        )
+        .unwrap();
+        assert!(record.calculate_baq(&chr1, BaqFlags::APPLY).unwrap());
+        let mut expected = [40; 39];
+        expected[0] = 36;
+        expected[18..22].copy_from_slice(&[7, 4, 4, 7]);
+        expected[38] = 29;
+        assert_eq!(record.qual(), &expected[..]);
+
+        // skipped records are left unchanged
+        for line in [
+            &b"unmapped\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII"[..],
+            b"secondary\t256\tchr1\t11\t60\t4M\t*\t0\t0\tTCAC\tIIII",
+            b"noseq\t0\tchr1\t11\t60\t4M\t*\t0\t0\t*\t*",
+            b"noqual\t0\tchr1\t11\t60\t4M\t*\t0\t0\tTCAC\t*",
+            b"insertion\t0\tchr1\t11\t60\t4I\t*\t0\t0\tTCAC\tIIII",
+            b"spliced\t0\tchr1\t11\t60\t2M10N2M\t*\t0\t0\tTCAC\tIIII",
+        ] {
+            let mut record = Record::from_sam(&header, line).unwrap();
+            let qual = record.qual().to_vec();
+            assert!(!record.calculate_baq(&chr1, BaqFlags::APPLY).unwrap());
+            assert_eq!(record.qual(), &qual[..]);
+            assert!(record.aux(b"ZQ").is_err());
+        }
+    }
+
+    #[test]
+    fn test_try_clone() {
+        let path = "test/test_cram.bam";
+        let mut expected = HashMap::new();
+        let mut reader = Reader::from_path(path).unwrap();
+        for record in reader.records() {
+            *expected.entry(record.unwrap().tid()).or_insert(0) += 1;
+        }
+        let n_targets = reader.header().target_count();
+
+        let bam = IndexedReader::from_path(path).unwrap();
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let mut reader = bam.try_clone().unwrap();
+                std::thread::spawn(move || {
+                    let mut counts = HashMap::new();
+                    for tid in (i..n_targets).step_by(2) {
+                        reader.fetch(tid).unwrap();
+                        for record in reader.records() {
+                            assert_eq!(record.unwrap().tid(), tid as i32);
+                            *counts.entry(tid as i32).or_insert(0) += 1;
+                        }
+                    }
+                    counts
+                })
+            })
+            .collect();
+        let mut counts = HashMap::new();
+        for handle in handles {
+            counts.extend(handle.join().unwrap());
+        }
+        expected.remove(&-1);
+        assert_eq!(counts, expected);
+        assert!(counts.len() > 1);
+
+        // options are applied to the clone
+        let mut cram = IndexedReader::from_path("test/test_cram.cram").unwrap();
+        cram.set_reference("test/test_cram.fa").unwrap();
+        cram.set_cram_decode_options(Fields::FLAG | Fields::RNAME | Fields::POS, false)
+            .unwrap();
+        let mut clone = cram.try_clone().unwrap();
+        clone.fetch("chr1").unwrap();
+        let record = clone.records().next().unwrap().unwrap();
+        assert_eq!(record.qname(), b"?");
+        assert!(record.seq().is_empty());
+    }
+
+    #[test]
+    fn test_fetch_and_pileup() {
+        let summary = |p: pileup::Pileup| {
+            let qnames: Vec<_> = p
+                .alignments()
+                .map(|a| (a.record().qname().to_owned(), a.qpos(), a.is_refskip()))
+                .collect();
+            (p.tid(), p.pos(), p.depth(), qnames)
+        };
+        for (path, tid, beg, end) in [
+            ("test/test.bam", 0, 0, 40),
+            ("test/test.bam", 0, 100_000, 100_110),
+            ("test/test_spliced_reads.bam", 21, 17_092_780, 17_094_990),
+        ] {
+            let expected: Vec<_> = Reader::from_path(path)
+                .unwrap()
+                .pileup()
+                .map(|p| summary(p.unwrap()))
+                .filter(|(t, pos, _, _)| *t == tid && (beg..end).contains(pos))
+                .collect();
+            assert!(!expected.is_empty());
+
+            let mut bam = IndexedReader::from_path(path).unwrap();
+            let pileups: Vec<_> = bam
+                .fetch_and_pileup(tid, beg, end)
+                .unwrap()
+                .map(|p| summary(p.unwrap()))
+                .collect();
+            assert_eq!(pileups, expected, "{}", path);
+        }
+
+        let mut bam = IndexedReader::from_path("test/test.bam").unwrap();
+        {
+            let mut pileups = bam.fetch_and_pileup(0, 0, 40).unwrap();
+            pileups.set_filter(htslib::BAM_FSUPPLEMENTARY as u16);
+            assert!(pileups.all(|p| p.unwrap().depth() == 5));
+        }
+    }
+
+    #[test]
+    fn test_fetch_multiple() {
+        let mut bam = IndexedReader::from_path("test/test_cram.bam").unwrap();
+        let chr1 = bam.header().tid(b"chr1").unwrap();
+        let chr2 = bam.header().tid(b"chr2").unwrap();
+        // overlapping, adjacent and unordered regions
+        let regions = [
+            (chr2, 10, 30),
+            (chr1, 0, 50),
+            (chr1, 40, 80),
+            (chr1, 80, 100),
+            (chr1, 200, 300),
+        ];
+        let key = |r: Record| (r.tid(), r.pos(), r.qname().to_owned(), r.flags());
+
+        let mut expected = Vec::new();
+        for &(tid, beg, end) in &regions {
+            bam.fetch((tid, beg, end)).unwrap();
+            expected.extend(bam.records().map(|r| key(r.unwrap())));
+        }
+        let n_fetched = expected.len();
+        expected.sort();
+        expected.dedup();
+        assert!(expected.len() < n_fetched);
+
+        bam.fetch_multiple(&regions).unwrap();
+        let records: Vec<_> = bam.records().map(|r| key(r.unwrap())).collect();
+        assert_eq!(records, expected);
+
+        bam.fetch_multiple(&[]).unwrap();
+        assert_eq!(bam.records().count(), 0);
+
+        assert_eq!(
+            bam.fetch_multiple(&[(chr1, 0, 10), (99, 0, 10)]),
+            Err(Error::InvalidTid { tid: 99 })
+        );
+    }
+
+    #[test]
+    fn test_seek_tell() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("many.bam");
+        let sampath = tmp.path().join("many.sam");
+        {
+            let mut header = Header::new();
+            header.push_record(
+                HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", "chr1")
+                    .push_tag(b"LN", 100_000),
+            );
+            let mut bam_writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            let mut sam_writer = Writer::from_path(&sampath, &header, Format::Sam).unwrap();
+            let mut record = Record::new();
+            for i in 0..2000 {
+                record.set(
+                    format!("read{}", i).as_bytes(),
+                    None,
+                    &[b'G'; 100],
+                    &[30; 100],
+                );
+                record.set_tid(0);
+                record.set_pos(i * 10);
+                bam_writer.write(&record).unwrap();
+                sam_writer.write(&record).unwrap();
+            }
+        }
+
+        for n_threads in [0, 2] {
+            let mut bam = Reader::from_path(&bampath).unwrap();
+            if n_threads > 0 {
+                bam.set_threads(n_threads).unwrap();
+            }
+            let mut offsets = Vec::new();
+            let mut qnames = Vec::new();
+            let mut record = Record::new();
+            loop {
+                let offset = bam.tell();
+                match bam.read(&mut record) {
+                    Some(r) => r.unwrap(),
+                    None => break,
+                }
+                offsets.push(offset);
+                qnames.push(record.qname().to_owned());
+            }
+            assert_eq!(qnames.len(), 2000);
+            // more than one BGZF block
+            assert_ne!(offsets[0] >> 16, offsets[1999] >> 16);
+
+            for i in [100, 10, 1999, 0] {
+                bam.seek(offsets[i]).unwrap();
+                bam.read(&mut record).unwrap().unwrap();
+                assert_eq!(record.qname(), &qnames[i][..]);
+                if i + 1 < offsets.len() {
+                    assert_eq!(bam.tell(), offsets[i + 1]);
+                }
+            }
+        }
+
+        let mut sam = Reader::from_path(&sampath).unwrap();
+        sam.read(&mut Record::new()).unwrap().unwrap();
+        assert_eq!(sam.tell(), -1);
+        assert_eq!(sam.seek(0), Err(Error::FileSeek));
+        assert_eq!(sam.records().count(), 1999);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_record_groups_and_read_pairs() {
+        let mut bam = Reader::from_path("test/test_name_sorted.sam").unwrap();
+        let groups: Vec<Vec<(Vec<u8>, u16)>> = bam
+            .record_groups()
+            .map(|group| {
+                group
+                    .unwrap()
+                    .iter()
+                    .map(|r| (r.qname().to_owned(), r.flags()))
+                    .collect()
+            })
+            .collect();
+        let sizes: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+        assert_eq!(sizes, vec![2, 4, 1, 2]);
+        assert_eq!(groups[1][1], (b"pair2".to_vec(), 2129));
+
+        let mut bam = Reader::from_path("test/test_name_sorted.sam").unwrap();
+        let pairs: Vec<_> = bam
+            .read_pairs()
+            .map(|pair| {
+                let (first, second) = pair.unwrap();
+                (
+                    String::from_utf8(first.qname().to_owned()).unwrap(),
+                    first.flags(),
+                    second.map(|r| r.flags()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("pair1".to_owned(), 99, Some(147)),
+                ("pair2".to_owned(), 83, Some(163)),
+                ("single".to_owned(), 0, None),
+                ("unmapped_pair".to_owned(), 77, Some(141)),
+            ]
+        );
+
+        // mates are not next to each other
+        let mut bam = Reader::from_path("test/test_paired.sam").unwrap();
+        assert_eq!(
+            bam.read_pairs().next().unwrap().unwrap_err(),
+            Error::BamNotQueryGrouped {
+                qname: "a1".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_record_groups_coordinate_sorted() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let sampath = tmp.path().join("sorted.sam");
+        std::fs::write(
+            &sampath,
+            "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:chr1\tLN:1000\n\
+             r1\t0\tchr1\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\n",
+        )
+        .unwrap();
+        let mut bam = Reader::from_path(&sampath).unwrap();
+        let mut groups = bam.record_groups();
+        assert_eq!(groups.next().unwrap(), Err(Error::BamCoordinateSorted));
+        assert!(groups.next().is_none());
+
+        // no sort order in the header, but r1 reappears after r2
+        std::fs::write(
+            &sampath,
+            "@SQ\tSN:chr1\tLN:1000\n\
+             r1\t0\tchr1\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\n\
+             r2\t0\tchr1\t20\t60\t4M\t*\t0\t0\tACGT\tIIII\n\
+             r1\t256\tchr1\t30\t60\t4M\t*\t0\t0\tACGT\tIIII\n",
+        )
+        .unwrap();
+        let mut bam = Reader::from_path(&sampath).unwrap();
+        let groups: Vec<_> = bam
+            .record_groups()
+            .map(|group| group.map(|g| g.len()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                Ok(1),
+                Ok(1),
+                Err(Error::BamNotQueryGrouped {
+                    qname: "r1".to_owned()
+                })
+            ]
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_indexed_reader_from_url() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("test.bam");
+        std::fs::copy("test/test.bam", &bampath).unwrap();
+        let url = Url::from_file_path(&bampath).unwrap();
+
+        assert_eq!(
+            IndexedReader::from_url(&url).unwrap_err(),
+            Error::BamIndexNotFound {
+                target: url.to_string()
+            }
+        );
+        let missing = Url::from_file_path(tmp.path().join("missing.bam")).unwrap();
+        assert_eq!(
+            IndexedReader::from_url(&missing).unwrap_err(),
+            Error::FileNotFound {
+                path: missing.as_str().into()
+            }
+        );
+
+        let idxpath = tmp.path().join("other.bai");
+        index::build(&bampath, Some(&idxpath), index::Type::Bai, 1).unwrap();
+        let idx_url = Url::from_file_path(&idxpath).unwrap();
+        let mut bam = IndexedReader::from_url_and_index(&url, &idx_url).unwrap();
+        bam.fetch((0, 0, 10)).unwrap();
+        assert_eq!(bam.records().count(), 6);
+
+        std::fs::rename(&idxpath, tmp.path().join("test.bam.bai")).unwrap();
+        let mut bam = IndexedReader::from_url(&url).unwrap();
+        bam.fetch((0, 0, 10)).unwrap();
+        assert_eq!(bam.records().count(), 6);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[cfg(feature = "curl")]
+    #[test]
+    fn test_indexed_reader_from_url_network_error() {
+        // nothing listens on port 1
+        let url = Url::parse("http://127.0.0.1:1/test.bam").unwrap();
+        assert_eq!(
+            IndexedReader::from_url(&url).unwrap_err(),
+            Error::Network {
+                target: url.to_string()
+            }
+        );
+    }
+
+    #[cfg(feature = "curl")]
+    #[test]
+    #[ignore = "requires network access"]
+    fn test_indexed_reader_from_remote_url() {
+        let url = Url::parse(
+            "https://ftp.1000genomes.ebi.ac.uk/vol1/ftp/phase3/data/HG00096/alignment/\
+             HG00096.mapped.ILLUMINA.bwa.GBR.low_coverage.20120522.bam",
+        )
+        .unwrap();
+        let mut bam = IndexedReader::from_url(&url).unwrap();
+        bam.fetch(("20", 1_000_000, 1_001_000)).unwrap();
+        assert!(bam.records().next().is_some());
+    }
+
+    #[test]
+    fn test_check_eof() {
+        let mut bam = Reader::from_path("test/test.bam").unwrap();
+        assert_eq!(bam.check_eof(), Ok(true));
+        assert_eq!(bam.records().count(), 6);
+        assert_eq!(bam.check_eof(), Ok(true));
+        let bam = IndexedReader::from_path("test/test.bam").unwrap();
+        assert_eq!(bam.check_eof(), Ok(true));
+        let cram = Reader::from_path("test/test_cram.cram").unwrap();
+        assert_eq!(cram.check_eof(), Ok(true));
+
+        // test.bam without the 28 byte EOF block
+        let mut bam = Reader::from_path("test/test_truncated.bam").unwrap();
+        assert_eq!(bam.check_eof(), Ok(false));
+        assert_eq!(bam.records().count(), 6);
+
+        let sam = Reader::from_path("test/test_paired.sam").unwrap();
+        assert_eq!(sam.check_eof(), Err(Error::CheckEof));
+    }
+
+    #[test]
+    fn test_write_translates_tids() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let sampath = tmp.path().join("in.sam");
+        std::fs::write(
+            &sampath,
+            "@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:1000\n\
+             a\t97\tchr1\t10\t60\t4M\tchr2\t20\t0\tACGT\tIIII\n\
+             b\t0\tchr2\t30\t60\t4M\t*\t0\t0\tACGT\tIIII\n\
+             c\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tIIII\n",
+        )
+        .unwrap();
+        let records: Vec<Record> = Reader::from_path(&sampath)
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(records[0].contig_name(), Ok("chr1"));
+        assert_eq!(records[0].mate_contig_name(), Ok("chr2"));
+        assert_eq!(records[1].clone().contig_name(), Ok("chr2"));
+        assert_eq!(
+            Record::new().mate_contig_name(),
+            Err(Error::BamRecordWithoutHeader)
+        );
+
+        // same contigs in a different order
+        let mut header = Header::new();
+        for name in ["chr3", "chr2", "chr1"] {
+            header.push_record(
+                HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", name)
+                    .push_tag(b"LN", 1000),
+            );
+        }
+        let bampath = tmp.path().join("out.bam");
+        {
+            let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+        }
+        let written: Vec<Record> = Reader::from_path(&bampath)
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!((written[0].tid(), written[0].mtid()), (2, 1));
+        assert_eq!(written[0].contig_name(), Ok("chr1"));
+        assert_eq!(written[0].mate_contig_name(), Ok("chr2"));
+        assert_eq!(written[1].contig_name(), Ok("chr2"));
+        assert_eq!(written[2].tid(), -1);
+
+        // a contig that is missing in the header of the writer
+        let mut header = Header::new();
+        header.push_record(
+            HeaderRecord::new(b"SQ")
+                .push_tag(b"SN", "chr1")
+                .push_tag(b"LN", 1000),
+        );
+        let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+        assert_eq!(
+            writer.write(&records[1]),
+            Err(Error::BamWriteUnknownContig {
+                contig: "chr2".to_owned()
+            })
+        );
+        drop(writer);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_find_mate() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("pairs.bam");
+        {
+            let header = Header::from_template(
+                Reader::from_path("test/test_name_sorted.sam")
+                    .unwrap()
+                    .header(),
+            );
+            let header_view = HeaderView::from_header(&header);
+            let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            let mut records = Vec::new();
+            for (i, pos) in (0..200).step_by(2).enumerate() {
+                let name = format!("p{}", i);
+                // the mate of every fifth pair is unmapped, but placed
+                let (flags1, flags2, pos2) = if i % 5 == 0 {
+                    (73, 133, pos)
+                } else {
+                    (99, 147, pos + 50)
+                };
+                for (flags, pos, mpos) in [(flags1, pos, pos2), (flags2, pos2, pos)] {
+                    let cigar = if flags & 4 != 0 { "*" } else { "10M" };
+                    let line = format!(
+                        "{}\t{}\tchr1\t{}\t60\t{}\t=\t{}\t0\tACGTACGTAC\tIIIIIIIIII",
+                        name,
+                        flags,
+                        pos + 1,
+                        cigar,
+                        mpos + 1
+                    );
+                    records.push(Record::from_sam(&header_view, line.as_bytes()).unwrap());
+                }
+            }
+            // a decoy with the same name, but as secondary alignment at the mate position
+            let decoy = "p1\t403\tchr1\t53\t0\t10M\t=\t3\t0\tACGTACGTAC\tIIIIIIIIII";
+            records.push(Record::from_sam(&header_view, decoy.as_bytes()).unwrap());
+            // a pair whose mate was filtered out
+            let lonely = "lonely\t99\tchr1\t301\t60\t10M\t=\t351\t0\tACGTACGTAC\tIIIIIIIIII";
+            records.push(Record::from_sam(&header_view, lonely.as_bytes()).unwrap());
+            records.sort_by_key(|r| (r.pos(), r.flags()));
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+        }
+        index::build(&bampath, None, index::Type::Bai, 1).unwrap();
+
+        let mut bam = IndexedReader::from_path(&bampath).unwrap();
+        bam.fetch((0, 0, 1000)).unwrap();
+        let mut record = Record::new();
+        let mut n_records = 0;
+        let mut n_mates = 0;
+        while let Some(r) = bam.read(&mut record) {
+            r.unwrap();
+            n_records += 1;
+            if record.is_secondary() {
+                continue;
+            }
+            match bam.find_mate(&record).unwrap() {
+                Some(mate) => {
+                    n_mates += 1;
+                    assert_eq!(mate.qname(), record.qname());
+                    assert_eq!(mate.pos(), record.mpos());
+                    assert_eq!(mate.is_first_in_template(), record.is_last_in_template());
+                    assert!(!mate.is_secondary());
+                }
+                None => assert_eq!(record.qname(), b"lonely"),
             }
         }
+        // the fetch was not disturbed by looking up the mates
+        assert_eq!(n_records, 202);
+        assert_eq!(n_mates, 200);
+
+        let mut unpaired = Record::new();
+        unpaired.set(b"unpaired", None, b"ACGT", &[30; 4]);
+        assert_eq!(bam.find_mate(&unpaired), Ok(None));
+
+        tmp.close().expect("Failed to delete temp dir");
     }
 
     #[test]
@@ -2358,6 +4994,123 @@ CCCCCCCCCCCCCCCCCCC"[..],
         tmp.close().expect("Failed to delete temp dir");
     }
 
+    #[test]
+    fn test_cram_round_trip() {
+        let bam_path = "./test/test_cram.bam";
+        let ref_path = "./test/test_cram.fa";
+        let mut bam_reader = Reader::from_path(bam_path).unwrap();
+        let header = Header::from_template(bam_reader.header());
+        let bam_records: Vec<Record> = bam_reader.records().map(|v| v.unwrap()).collect();
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let cram_path = tmp.path().join("test.cram");
+        let bam_copy_path = tmp.path().join("test.bam");
+
+        // BAM -> CRAM
+        {
+            let mut cram_writer = Writer::from_path(&cram_path, &header, Format::Cram)
+                .expect("Error opening CRAM file.");
+            cram_writer.set_reference(ref_path).unwrap();
+            for rec in bam_records.iter() {
+                cram_writer
+                    .write(rec)
+                    .expect("Failed to write record to CRAM.");
+            }
+        }
+
+        // CRAM -> BAM
+        {
+            let mut cram_reader = Reader::from_path(&cram_path).unwrap();
+            cram_reader.set_reference(ref_path).unwrap();
+            let header = Header::from_template(cram_reader.header());
+            let mut bam_writer = Writer::from_path(&bam_copy_path, &header, Format::Bam)
+                .expect("Error opening BAM file.");
+            for rec in cram_reader.records() {
+                bam_writer
+                    .write(&rec.unwrap())
+                    .expect("Failed to write record to BAM.");
+            }
+        }
+
+        let mut bam_copy_reader = Reader::from_path(&bam_copy_path).unwrap();
+        let copied_records: Vec<Record> = bam_copy_reader.records().map(|v| v.unwrap()).collect();
+        assert_eq!(copied_records.len(), bam_records.len());
+        for (copied, original) in copied_records.iter().zip(bam_records.iter()) {
+            assert_eq!(copied.qname(), original.qname());
+            assert_eq!(copied.seq().as_bytes(), original.seq().as_bytes());
+            assert_eq!(copied.qual(), original.qual());
+        }
+        compare_inner_bam_cram_records(&copied_records, &bam_records);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_cram_decode_options() {
+        let mut full = Reader::from_path("test/test_cram.cram").unwrap();
+        full.set_reference("test/test_cram.fa").unwrap();
+        let full: Vec<Record> = full.records().map(|r| r.unwrap()).collect();
+
+        let mut cram = Reader::from_path("test/test_cram.cram").unwrap();
+        cram.set_reference("test/test_cram.fa").unwrap();
+        cram.set_cram_decode_options(Fields::FLAG | Fields::RNAME | Fields::POS, false)
+            .unwrap();
+        let reduced: Vec<Record> = cram.records().map(|r| r.unwrap()).collect();
+        assert_eq!(reduced.len(), full.len());
+        for (reduced, full) in reduced.iter().zip(&full) {
+            assert_eq!(reduced.flags(), full.flags());
+            assert_eq!(reduced.tid(), full.tid());
+            assert_eq!(reduced.pos(), full.pos());
+            // placeholders instead of the skipped fields
+            assert_ne!(full.qname(), b"?");
+            assert_eq!(reduced.qname(), b"?");
+            assert!(!full.seq().is_empty());
+            assert!(reduced.seq().is_empty());
+            assert!(reduced.qual().is_empty());
+            assert_eq!(reduced.cigar_len(), 0);
+            assert_eq!(reduced.aux_iter().count(), 0);
+            assert_eq!(reduced.mtid(), -1);
+        }
+
+        // BAM files are always fully decoded
+        let mut bam = Reader::from_path("test/test.bam").unwrap();
+        bam.set_cram_decode_options(Fields::FLAG, false).unwrap();
+        let record = bam.records().next().unwrap().unwrap();
+        assert_eq!(record.qname(), b"I");
+        assert!(!record.seq().is_empty());
+
+        let mut fields = Fields::FLAG | Fields::POS;
+        assert!(fields.contains(Fields::POS));
+        assert!(!fields.contains(Fields::POS | Fields::SEQ));
+        fields |= Fields::SEQ;
+        assert!(fields.contains(Fields::POS | Fields::SEQ));
+        assert!(Fields::all().contains(fields | Fields::RGAUX));
+    }
+
+    #[test]
+    fn test_indexed_cram() {
+        let mut cram = IndexedReader::from_path("./test/test_cram.cram").unwrap();
+        cram.set_reference("./test/test_cram.fa").unwrap();
+        let mut bam = IndexedReader::from_path("./test/test_cram.bam").unwrap();
+
+        cram.fetch(("chr1", 0, 120)).unwrap();
+        bam.fetch(("chr1", 0, 120)).unwrap();
+        let cram_records: Vec<Record> = cram.records().map(|v| v.unwrap()).collect();
+        let bam_records: Vec<Record> = bam.records().map(|v| v.unwrap()).collect();
+        assert!(!cram_records.is_empty());
+        assert_eq!(cram_records.len(), bam_records.len());
+        compare_inner_bam_cram_records(&cram_records, &bam_records);
+
+        cram.fetch(("chr2", 0, 120)).unwrap();
+        bam.fetch(("chr2", 0, 120)).unwrap();
+        let cram_depths: Vec<_> = cram.pileup().map(|p| p.unwrap().depth()).collect();
+        let bam_depths: Vec<_> = bam.pileup().map(|p| p.unwrap().depth()).collect();
+        assert_eq!(cram_depths, bam_depths);
+    }
+
     #[test]
     fn test_compression_level_conversion() {
         // predefined compression levels
@@ -2422,6 +5175,75 @@ CCCCCCCCCCCCCCCCCCC"[..],
         tmp.close().expect("Failed to delete temp dir");
     }
 
+    #[test]
+    fn test_write_with_level() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let mut reader = Reader::from_path("test/test_spliced_reads.bam").unwrap();
+        let header = Header::from_template(reader.header());
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+
+        let mut file_sizes = Vec::new();
+        for (name, level) in &[
+            ("fastest.bam", CompressionLevel::Fastest),
+            ("maximum.bam", CompressionLevel::Maximum),
+        ] {
+            let path = tmp.path().join(name);
+            {
+                let mut writer =
+                    Writer::from_path_with_level(&path, &header, Format::Bam, *level).unwrap();
+                for record in &records {
+                    writer.write(record).unwrap();
+                }
+            }
+            let read_back: Vec<Record> = Reader::from_path(&path)
+                .unwrap()
+                .records()
+                .map(|r| r.unwrap())
+                .collect();
+            assert_eq!(read_back.len(), records.len());
+            for (a, b) in read_back.iter().zip(records.iter()) {
+                assert_eq!(a.qname(), b.qname());
+                assert_eq!(a.pos(), b.pos());
+                assert_eq!(a.cigar(), b.cigar());
+                assert_eq!(a.seq().as_bytes(), b.seq().as_bytes());
+                assert_eq!(a.qual(), b.qual());
+            }
+            file_sizes.push(fs::metadata(&path).unwrap().len());
+        }
+        assert!(file_sizes[1] < file_sizes[0]);
+
+        // a compression level turns SAM into BGZF-compressed SAM
+        let sampath = tmp.path().join("test.sam.gz");
+        {
+            let mut writer = Writer::from_path_with_level(
+                &sampath,
+                &header,
+                Format::Sam,
+                CompressionLevel::Level(6),
+            )
+            .unwrap();
+            writer.write(&records[0]).unwrap();
+        }
+        assert!(crate::bgzf::is_bgzip(&sampath).unwrap());
+        assert_eq!(Reader::from_path(&sampath).unwrap().records().count(), 1);
+
+        assert_eq!(
+            Writer::from_path_with_level(
+                tmp.path().join("invalid.bam"),
+                &header,
+                Format::Bam,
+                CompressionLevel::Level(10)
+            )
+            .err(),
+            Some(Error::BamInvalidCompressionLevel { level: 10 })
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
     #[test]
     fn test_bam_fails_on_vcf() {
         let bam_path = "./test/test_left.vcf";
@@ -2503,6 +5325,23 @@ CCCCCCCCCCCCCCCCCCC"[..],
     //     assert_eq!(r.header().target_names()[0], b"chr1");
     // }
 
+    #[test]
+    fn test_rc_records_reuse() {
+        let mut bam = Reader::from_path("test/test.bam").unwrap();
+        let mut records = bam.rc_records();
+        let first = records.next().unwrap().unwrap();
+        let first_ptr = Rc::as_ptr(&first);
+        drop(first);
+        // the dropped record is recycled
+        let second = records.next().unwrap().unwrap();
+        assert_eq!(Rc::as_ptr(&second), first_ptr);
+        // a record that is still held is never overwritten
+        let third = records.next().unwrap().unwrap();
+        assert_ne!(Rc::as_ptr(&third), Rc::as_ptr(&second));
+        assert_eq!(second.qname(), b"II.14978392");
+        assert_eq!(third.qname(), b"III");
+    }
+
     #[test]
     fn test_rc_records() {
         let (names, flags, seqs, quals, cigars) = gold();
@@ -2874,6 +5713,309 @@ CCCCCCCCCCCCCCCCCCC"[..],
         );
     }
 
+    #[test]
+    fn test_aux_arrays_empty_and_large() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("arrays.bam");
+        let sampath = tmp.path().join("arrays.sam");
+
+        let n = 5000;
+        let array_i8: Vec<i8> = (0..n).map(|i| (i % 256 - 128) as i8).collect();
+        let array_u8: Vec<u8> = (0..n).map(|i| (i % 256) as u8).collect();
+        let array_i16: Vec<i16> = (0..n).map(|i| (i * 13 - 30000) as i16).collect();
+        let array_u16: Vec<u16> = (0..n).map(|i| (i * 13) as u16).collect();
+        let array_i32: Vec<i32> = (0..n).map(|i| i * -100_000).collect();
+        let array_u32: Vec<u32> = (0..n).map(|i| i as u32 * 100_000).collect();
+        let array_f32: Vec<f32> = (0..n).map(|i| i as f32 / 4.0).collect();
+
+        let mut record = Record::new();
+        record.set(b"ali1", None, b"ACGT", b"FFFF");
+        record.set_unmapped();
+        record.set_tid(-1);
+        record.set_pos(-1);
+        record.set_mtid(-1);
+        record.set_mpos(-1);
+        record
+            .push_aux(b"XA", Aux::ArrayI8((&array_i8).into()))
+            .unwrap();
+        record
+            .push_aux(b"XB", Aux::ArrayU8((&array_u8).into()))
+            .unwrap();
+        record
+            .push_aux(b"XC", Aux::ArrayI16((&array_i16).into()))
+            .unwrap();
+        record
+            .push_aux(b"XD", Aux::ArrayU16((&array_u16).into()))
+            .unwrap();
+        record
+            .push_aux(b"XE", Aux::ArrayI32((&array_i32).into()))
+            .unwrap();
+        record
+            .push_aux(b"XF", Aux::ArrayU32((&array_u32).into()))
+            .unwrap();
+        record
+            .push_aux(b"XG", Aux::ArrayFloat((&array_f32).into()))
+            .unwrap();
+        record
+            .push_aux(b"YA", Aux::ArrayI8((&[][..]).into()))
+            .unwrap();
+        record
+            .push_aux(b"YB", Aux::ArrayU32((&[][..]).into()))
+            .unwrap();
+        record
+            .push_aux(b"YC", Aux::ArrayFloat((&[][..]).into()))
+            .unwrap();
+
+        let check = |record: &Record| {
+            match record.aux(b"XA").unwrap() {
+                Aux::ArrayI8(array) => assert_eq!(array.iter().collect::<Vec<_>>(), array_i8),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"XB").unwrap() {
+                Aux::ArrayU8(array) => assert_eq!(array.iter().collect::<Vec<_>>(), array_u8),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"XC").unwrap() {
+                Aux::ArrayI16(array) => assert_eq!(array.iter().collect::<Vec<_>>(), array_i16),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"XD").unwrap() {
+                Aux::ArrayU16(array) => assert_eq!(array.iter().collect::<Vec<_>>(), array_u16),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"XE").unwrap() {
+                Aux::ArrayI32(array) => assert_eq!(array.iter().collect::<Vec<_>>(), array_i32),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"XF").unwrap() {
+                Aux::ArrayU32(array) => assert_eq!(array.iter().collect::<Vec<_>>(), array_u32),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"XG").unwrap() {
+                Aux::ArrayFloat(array) => assert_eq!(array.iter().collect::<Vec<_>>(), array_f32),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"YA").unwrap() {
+                Aux::ArrayI8(array) => assert!(array.is_empty()),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"YB").unwrap() {
+                Aux::ArrayU32(array) => assert!(array.is_empty()),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+            match record.aux(b"YC").unwrap() {
+                Aux::ArrayFloat(array) => assert!(array.is_empty()),
+                other => panic!("unexpected aux type {:?}", other),
+            }
+        };
+        check(&record);
+
+        let header = Header::new();
+        for (path, format) in [(&bampath, Format::Bam), (&sampath, Format::Sam)] {
+            let mut writer = Writer::from_path(path, &header, format).unwrap();
+            writer.write(&record).unwrap();
+        }
+
+        let mut reader = Reader::from_path(&bampath).unwrap();
+        let read_back = reader.records().next().unwrap().unwrap();
+        check(&read_back);
+
+        let sam = std::fs::read_to_string(&sampath).unwrap();
+        let fields: Vec<&str> = sam.trim_end().split('\t').collect();
+        let expected_prefix = |tag: &str, subtype: char, first: &str| {
+            let field = fields.iter().find(|f| f.starts_with(tag)).unwrap();
+            assert!(field.starts_with(&format!("{}:B:{},{},", tag, subtype, first)));
+            assert_eq!(field.matches(',').count(), n as usize);
+        };
+        expected_prefix("XA", 'c', "-128");
+        expected_prefix("XB", 'C', "0");
+        expected_prefix("XC", 's', "-30000");
+        expected_prefix("XD", 'S', "0");
+        expected_prefix("XE", 'i', "0");
+        expected_prefix("XF", 'I', "0");
+        expected_prefix("XG", 'f', "0");
+        assert!(fields.contains(&"YA:B:c"));
+        assert!(fields.contains(&"YB:B:I"));
+        assert!(fields.contains(&"YC:B:f"));
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_remove_aux_positions() {
+        let mut template = Record::new();
+        template.set(b"ali1", None, b"ACGT", b"FFFF");
+        template.push_aux(b"XA", Aux::I32(1)).unwrap();
+        template.push_aux(b"XB", Aux::String("middle")).unwrap();
+        template
+            .push_aux(b"XC", Aux::ArrayU16((&[1u16, 2, 3][..]).into()))
+            .unwrap();
+        let full_len = template.data().len();
+        let tags = |record: &Record| -> Vec<Vec<u8>> {
+            record
+                .aux_iter()
+                .map(|field| field.unwrap().0.to_owned())
+                .collect()
+        };
+
+        // Field sizes: tag + type + value
+        for (tag, size, remaining) in [
+            (b"XA", 2 + 1 + 4, [b"XB", b"XC"]),
+            (b"XB", 2 + 1 + 7, [b"XA", b"XC"]),
+            (b"XC", 2 + 1 + 1 + 4 + 6, [b"XA", b"XB"]),
+        ] {
+            let mut record = template.clone();
+            record.remove_aux(tag).unwrap();
+            assert_eq!(record.data().len(), full_len - size);
+            assert_eq!(tags(&record), remaining);
+            assert_eq!(record.aux(tag), Err(Error::BamAuxTagNotFound));
+            assert_eq!(record.remove_aux(tag), Err(Error::BamAuxTagNotFound));
+            for remaining_tag in remaining {
+                assert_eq!(
+                    record.aux(remaining_tag).unwrap(),
+                    template.aux(remaining_tag).unwrap()
+                );
+            }
+        }
+
+        // Remove a field right after pushing it
+        let mut record = template.clone();
+        record.push_aux(b"XD", Aux::Float(0.5)).unwrap();
+        record.remove_aux(b"XD").unwrap();
+        assert_eq!(record.data(), template.data());
+        record.push_aux(b"XD", Aux::I8(-1)).unwrap();
+        assert_eq!(record.aux(b"XD").unwrap(), Aux::I8(-1));
+
+        // Remove everything
+        for tag in [b"XC", b"XA", b"XD", b"XB"] {
+            record.remove_aux(tag).unwrap();
+        }
+        assert!(record.aux_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_aux_int_and_float() {
+        let mut record = Record::new();
+        record.set(b"ali1", None, b"ACGT", b"FFFF");
+        record.push_aux(b"XA", Aux::I8(-3)).unwrap();
+        record.push_aux(b"XB", Aux::U8(200)).unwrap();
+        record.push_aux(b"XC", Aux::I16(-300)).unwrap();
+        record.push_aux(b"XD", Aux::U16(60000)).unwrap();
+        record.push_aux(b"XE", Aux::I32(i32::MIN)).unwrap();
+        record.push_aux(b"XF", Aux::U32(u32::MAX)).unwrap();
+        record.push_aux(b"XG", Aux::Float(0.5)).unwrap();
+        record.push_aux(b"XH", Aux::Double(0.25)).unwrap();
+        record.push_aux(b"XI", Aux::String("12")).unwrap();
+
+        assert_eq!(record.aux_int(b"XA"), Ok(-3));
+        assert_eq!(record.aux_int(b"XB"), Ok(200));
+        assert_eq!(record.aux_int(b"XC"), Ok(-300));
+        assert_eq!(record.aux_int(b"XD"), Ok(60000));
+        assert_eq!(record.aux_int(b"XE"), Ok(i32::MIN as i64));
+        assert_eq!(record.aux_int(b"XF"), Ok(u32::MAX as i64));
+        assert_eq!(record.aux_float(b"XG"), Ok(0.5));
+        assert_eq!(record.aux_float(b"XH"), Ok(0.25));
+
+        let mismatch = || Error::BamAuxTypeMismatch {
+            tag: "XI".to_owned(),
+        };
+        assert_eq!(record.aux_int(b"XI"), Err(mismatch()));
+        assert_eq!(record.aux_float(b"XI"), Err(mismatch()));
+        assert_eq!(
+            record.aux_int(b"XG"),
+            Err(Error::BamAuxTypeMismatch {
+                tag: "XG".to_owned()
+            })
+        );
+        assert_eq!(record.aux_int(b"NM"), Err(Error::BamAuxTagNotFound));
+        assert_eq!(record.aux_float(b"NM"), Err(Error::BamAuxTagNotFound));
+        assert_eq!(record.aux(b""), Err(Error::BamAuxTagNotFound));
+        assert_eq!(record.aux(b"X"), Err(Error::BamAuxTagNotFound));
+
+        // htslib stores SAM integers with the smallest fitting type
+        let header = HeaderView::from_header(&Header::new());
+        let record = Record::from_sam(
+            &header,
+            b"r1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tFFFF\tNM:i:2\tXM:i:-70000",
+        )
+        .unwrap();
+        assert_eq!(record.aux(b"NM"), Ok(Aux::U8(2)));
+        assert_eq!(record.aux_int(b"NM"), Ok(2));
+        assert_eq!(record.aux(b"XM"), Ok(Aux::I32(-70000)));
+        assert_eq!(record.aux_int(b"XM"), Ok(-70000));
+    }
+
+    #[test]
+    fn test_reference_seq_from_md() {
+        let fasta = crate::faidx::Reader::from_path("test/test_cram.fa").unwrap();
+        let mut reader = Reader::from_path("test/test_cram.sam").unwrap();
+        let header = reader.header().clone();
+        let mut n = 0;
+        for record in reader.records() {
+            let record = record.unwrap();
+            let contig = str::from_utf8(header.tid2name(record.tid() as u32)).unwrap();
+            let expected = fasta
+                .fetch_seq_range(
+                    contig,
+                    record.pos() as usize,
+                    record.cigar().end_pos() as usize,
+                )
+                .unwrap()
+                .to_ascii_uppercase();
+            assert_eq!(record.reference_seq_from_md().unwrap(), expected);
+            n += 1;
+        }
+        assert_eq!(n, 6);
+    }
+
+    #[test]
+    fn test_reference_seq_from_md_clips_mismatches_and_indels() {
+        // ref:  GGGCACAG  CCTCAC
+        // read: ggGGTCAcAG--TCACn (lower case: clipped or inserted)
+        let header = HeaderView::from_header(&Header::new());
+        let sam = |md: &str| {
+            let line = format!(
+                "r1\t4\t*\t1\t0\t2S5M1I3M2D4M1S\t*\t0\t0\tTTGGTCAACAGTCACN\t*{}",
+                md
+            );
+            Record::from_sam(&header, line.as_bytes()).unwrap()
+        };
+
+        let record = sam("\tMD:Z:2G5^CC4");
+        assert_eq!(record.reference_seq_from_md().unwrap(), b"GGGCACAGCCTCAC");
+        // Zero-length matches are used between adjacent mismatches and deletions
+        let record = sam("\tMD:Z:2g0A4^cc0T3");
+        assert_eq!(record.reference_seq_from_md().unwrap(), b"GGGAACAGCCTCAC");
+
+        assert_eq!(
+            sam("").reference_seq_from_md(),
+            Err(Error::BamAuxTagNotFound)
+        );
+        assert_eq!(
+            sam("\tMD:i:12").reference_seq_from_md(),
+            Err(Error::BamParseMd {
+                msg: "MD tag is not a string".to_owned()
+            })
+        );
+        for md in ["2G5^^CC4", "2G5^", "2G-4", "99999999999"] {
+            assert!(matches!(
+                sam(&format!("\tMD:Z:{}", md)).reference_seq_from_md(),
+                Err(Error::BamParseMd { .. })
+            ));
+        }
+        for md in [
+            "2G5^CC3", "2G5^CC5", "2G5^C4", "2G5^CCC4", "2G4^CC5", "2G5^CC4A",
+        ] {
+            assert!(matches!(
+                sam(&format!("\tMD:Z:{}", md)).reference_seq_from_md(),
+                Err(Error::BamMdCigarMismatch { .. })
+            ));
+        }
+    }
+
     #[test]
     fn test_aux_scalars() {
         let bam_header = Header::new();
@@ -2930,6 +6072,108 @@ CCCCCCCCCCCCCCCCCCC"[..],
         );
     }
 
+    #[test]
+    fn test_aux_iter_all_types() {
+        let bam_header = Header::new();
+        let mut test_record = Record::from_sam(
+            &HeaderView::from_header(&bam_header),
+            "ali1\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\tFFFF".as_bytes(),
+        )
+        .unwrap();
+
+        let fields = || {
+            vec![
+                (&b"XA"[..], Aux::Char(b'x')),
+                (&b"XB"[..], Aux::I8(-1)),
+                (&b"XC"[..], Aux::U8(1)),
+                (&b"XD"[..], Aux::I16(-300)),
+                (&b"XE"[..], Aux::U16(300)),
+                (&b"XF"[..], Aux::I32(-70000)),
+                (&b"XG"[..], Aux::U32(70000)),
+                (&b"XH"[..], Aux::Float(0.5)),
+                (&b"XI"[..], Aux::Double(0.25)),
+                (&b"XJ"[..], Aux::String("text")),
+                (&b"XK"[..], Aux::HexByteArray("1AE301")),
+                (&b"XL"[..], Aux::ArrayI8((&[-1i8, 2][..]).into())),
+                (&b"XM"[..], Aux::ArrayU8((&[1u8, 255][..]).into())),
+                (&b"XN"[..], Aux::ArrayI16((&[-300i16, 300][..]).into())),
+                (&b"XO"[..], Aux::ArrayU16((&[1u16, 65535][..]).into())),
+                (&b"XP"[..], Aux::ArrayI32((&[-70000i32, 70000][..]).into())),
+                (&b"XQ"[..], Aux::ArrayU32((&[1u32, u32::MAX][..]).into())),
+                (&b"XR"[..], Aux::ArrayFloat((&[1.5f32, -2.5][..]).into())),
+            ]
+        };
+        for (tag, value) in fields() {
+            test_record.push_aux(tag, value).unwrap();
+        }
+
+        let collected_aux_fields = test_record.aux_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(collected_aux_fields, fields());
+
+        // A record without aux data yields nothing
+        let mut empty_record = Record::new();
+        empty_record.set(b"ali1", None, b"ACGT", b"FFFF");
+        assert!(empty_record.aux_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_aux_iter_corrupt() {
+        let mut test_record = Record::new();
+        test_record.set(b"ali1", None, b"ACGT", b"FFFF");
+        let aux_offset = test_record.data().len();
+        test_record.push_aux(b"XA", Aux::I32(1)).unwrap();
+        test_record.push_aux(b"XB", Aux::String("text")).unwrap();
+        let valid = test_record.data().to_vec();
+        test_record
+            .push_aux(b"XC", Aux::ArrayI32((&[1i32, 2, 3][..]).into()))
+            .unwrap();
+        let with_array = test_record.data().to_vec();
+
+        let check = |data: &[u8], expected: Error| {
+            let mut record = test_record.clone();
+            record.set_data(data);
+            let items: Vec<_> = record.aux_iter().collect();
+            assert_eq!(items.len(), 2);
+            assert_eq!(items[0], Ok((&b"XA"[..], Aux::I32(1))));
+            assert_eq!(items[1], Err(expected));
+        };
+
+        // String without its terminating NUL
+        check(&valid[..valid.len() - 1], Error::BamAuxParsingError);
+        // Truncated integer
+        let mut record = test_record.clone();
+        record.set_data(&valid[..aux_offset + 5]);
+        let items: Vec<_> = record.aux_iter().collect();
+        assert_eq!(items, vec![Err(Error::BamAuxParsingError)]);
+        // Unknown type
+        let mut unknown_type = valid.clone();
+        unknown_type[aux_offset + 9] = b'X';
+        check(&unknown_type, Error::BamAuxUnknownType);
+
+        // Array data shorter than its element count
+        let check_array = |data: &[u8], expected: Error| {
+            let mut record = test_record.clone();
+            record.set_data(data);
+            let items: Vec<_> = record.aux_iter().collect();
+            assert_eq!(items.len(), 3);
+            assert!(items[1].is_ok());
+            assert_eq!(items[2], Err(expected));
+        };
+        check_array(
+            &with_array[..with_array.len() - 1],
+            Error::BamAuxParsingError,
+        );
+        // Array element count beyond the end of the data
+        let mut huge_count = with_array.clone();
+        let count_offset = valid.len() + 4;
+        huge_count[count_offset..count_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        check_array(&huge_count, Error::BamAuxParsingError);
+        // Unknown array element type
+        let mut unknown_subtype = with_array;
+        unknown_subtype[valid.len() + 3] = b'd';
+        check_array(&unknown_subtype, Error::BamAuxUnknownType);
+    }
+
     #[test]
     fn test_aux_array_partial_eq() {
         use record::AuxArray;
@@ -2990,6 +6234,45 @@ CCCCCCCCCCCCCCCCCCC"[..],
         );
     }
 
+    #[test]
+    fn test_write_header_comments() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("comments.bam");
+
+        let template = HeaderView::from_bytes(
+            b"@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:chr1\tLN:1000\n@CO\toriginal comment\n",
+        );
+        let mut header = Header::from_template(&template);
+        header.push_comment(b"mytool --input in.bam");
+        header.push_comment(b"mytool version 1.0");
+        Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+
+        let reader = Reader::from_path(&bampath).unwrap();
+        assert_eq!(
+            str::from_utf8(reader.header().as_bytes()).unwrap(),
+            "@HD\tVN:1.6\tSO:unsorted\n\
+             @SQ\tSN:chr1\tLN:1000\n\
+             @CO\toriginal comment\n\
+             @CO\tmytool --input in.bam\n\
+             @CO\tmytool version 1.0\n"
+        );
+        assert_eq!(
+            Header::from_template(reader.header())
+                .comments()
+                .collect::<Vec<_>>(),
+            vec![
+                "original comment",
+                "mytool --input in.bam",
+                "mytool version 1.0"
+            ]
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
     /// Test if both text and binary representations of a BAM header are in sync (#156)
     #[test]
     fn test_bam_header_sync() {
@@ -3015,6 +6298,59 @@ CCCCCCCCCCCCCCCCCCC"[..],
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_idxstats_unmapped_bai_csi_cram() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+
+        // count by reading all records
+        let mut bam = Reader::from_path("test/test_unmapped.bam").unwrap();
+        let n_targets = bam.header().target_count();
+        let mut expected: Vec<_> = (0..n_targets)
+            .map(|tid| (tid as i64, bam.header().target_len(tid).unwrap(), 0, 0))
+            .chain([(-1, 0, 0, 0)])
+            .collect();
+        for record in bam.records() {
+            let record = record.unwrap();
+            let i = if record.tid() < 0 {
+                n_targets as usize
+            } else {
+                record.tid() as usize
+            };
+            if record.is_unmapped() {
+                expected[i].3 += 1;
+            } else {
+                expected[i].2 += 1;
+            }
+        }
+        assert_eq!(expected.last(), Some(&(-1, 0, 0, 8)));
+
+        let mut bai = IndexedReader::from_path("test/test_unmapped.bam").unwrap();
+        assert_eq!(bai.index_stats().unwrap(), expected);
+
+        let bampath = tmp.path().join("unmapped.bam");
+        let csipath = tmp.path().join("unmapped.bam.csi");
+        std::fs::copy("test/test_unmapped.bam", &bampath).unwrap();
+        index::build(&bampath, None, index::Type::Csi(14), 1).unwrap();
+        let mut csi = IndexedReader::from_path_and_index(&bampath, &csipath).unwrap();
+        assert_eq!(csi.index_stats().unwrap(), expected);
+
+        let mut cram = IndexedReader::from_path("test/test_unmapped.cram").unwrap();
+        assert_eq!(cram.index_stats().unwrap(), expected);
+
+        let bampath = tmp.path().join("test.bam");
+        let csipath = tmp.path().join("test.bam.csi");
+        std::fs::copy("test/test.bam", &bampath).unwrap();
+        index::build(&bampath, None, index::Type::Csi(14), 1).unwrap();
+        let mut csi = IndexedReader::from_path_and_index(&bampath, &csipath).unwrap();
+        let mut bai = IndexedReader::from_path("test/test.bam").unwrap();
+        assert_eq!(csi.index_stats().unwrap(), bai.index_stats().unwrap());
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
     #[test]
     fn test_number_mapped_and_unmapped_bam() {
         let reader = IndexedReader::from_path("test/test.bam").unwrap();
@@ -3031,6 +6367,30 @@ CCCCCCCCCCCCCCCCCCC"[..],
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_fetch_unmapped_and_all() {
+        let mut reader = IndexedReader::from_path("test/test_unmapped.bam").unwrap();
+
+        reader.fetch(FetchDefinition::Unmapped).unwrap();
+        let unmapped: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(unmapped.len() as u64, reader.index().number_unmapped());
+        assert!(unmapped.iter().all(|r| r.tid() == -1 && r.is_unmapped()));
+
+        let mut reader = IndexedReader::from_path("test/test_spliced_reads.bam").unwrap();
+        reader.fetch(FetchDefinition::All).unwrap();
+        let all = reader
+            .records()
+            .map(|r| r.unwrap().tid())
+            .collect::<Vec<_>>();
+        let expected = Reader::from_path("test/test_spliced_reads.bam")
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap().tid())
+            .collect::<Vec<_>>();
+        assert!(!all.is_empty());
+        assert_eq!(all, expected);
+    }
+
     #[test]
     fn test_idxstats_cram() {
         let mut reader = IndexedReader::from_path("test/test_cram.cram").unwrap();
@@ -3,8 +3,11 @@
 // This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::ffi;
 use std::fmt;
 use std::iter;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
 use std::slice;
 
 use crate::htslib;
@@ -25,7 +28,7 @@ pub struct Pileup {
     inner: *const htslib::bam_pileup1_t,
     depth: u32,
     tid: u32,
-    pos: u32,
+    pos: u64,
 }
 
 impl Pileup {
@@ -33,7 +36,7 @@ impl Pileup {
         self.tid
     }
 
-    pub fn pos(&self) -> u32 {
+    pub fn pos(&self) -> u64 {
         self.pos
     }
 
@@ -114,6 +117,116 @@ impl<'a> Alignment<'a> {
     pub fn record(&self) -> record::Record {
         record::Record::from_inner(self.inner.b)
     }
+
+    /// The base at this position, as stored in the record (i.e. already reverse complemented
+    /// for reads on the reverse strand). None if either `is_del` or `is_refskip`, or if the
+    /// record has no sequence. In contrast to `record().seq()`, this does not copy the record.
+    pub fn base(&self) -> Option<u8> {
+        let qpos = self.stored_qpos()?;
+        let b = self.bam();
+        let seq = unsafe {
+            slice::from_raw_parts(
+                b.data
+                    .add(b.core.l_qname as usize + b.core.n_cigar as usize * 4),
+                (b.core.l_qseq as usize).div_ceil(2),
+            )
+        };
+        Some(record::DECODE_BASE[record::encoded_base(seq, qpos) as usize])
+    }
+
+    /// The base quality at this position. None if either `is_del` or `is_refskip`, or if the
+    /// record has no base qualities.
+    pub fn base_qual(&self) -> Option<u8> {
+        let qpos = self.stored_qpos()?;
+        let b = self.bam();
+        let qual = unsafe {
+            *b.data.add(
+                b.core.l_qname as usize
+                    + b.core.n_cigar as usize * 4
+                    + (b.core.l_qseq as usize).div_ceil(2)
+                    + qpos,
+            )
+        };
+        // a missing quality string is stored as 0xff
+        if qual == 0xff {
+            None
+        } else {
+            Some(qual)
+        }
+    }
+
+    /// Whether the read is aligned to the reverse strand.
+    pub fn is_reverse(&self) -> bool {
+        u32::from(self.bam().core.flag) & htslib::BAM_FREVERSE != 0
+    }
+
+    /// Distance of this position from the nearer end of the read, e.g. 0 for its first and last
+    /// base. Soft clipped bases count towards the distance. None if either `is_del` or
+    /// `is_refskip`, or if the record has no sequence.
+    pub fn dist_from_read_end(&self) -> Option<u32> {
+        let qpos = self.stored_qpos()?;
+        let last = self.bam().core.l_qseq as usize - 1;
+        Some(qpos.min(last - qpos) as u32)
+    }
+
+    fn bam(&self) -> &htslib::bam1_t {
+        unsafe { &*self.inner.b }
+    }
+
+    /// Like `qpos`, but None if the record does not store a base at this position.
+    fn stored_qpos(&self) -> Option<usize> {
+        self.qpos()
+            .filter(|&qpos| qpos < self.bam().core.l_qseq as usize)
+    }
+}
+
+/// An owned copy of a pileup, with the contig name resolved, see `Pileups::owned`. Since it
+/// does not borrow from the reader, it can be sent to other threads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedPileup {
+    pub contig: String,
+    pub tid: u32,
+    /// 0-based position.
+    pub pos: u64,
+    pub depth: u32,
+    /// One observation per read, in the order of `Pileup::alignments`.
+    pub observations: Vec<Observation>,
+}
+
+/// The observation of a read at a position of an `OwnedPileup`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    pub qname: Vec<u8>,
+    /// See `Alignment::qpos`.
+    pub qpos: Option<usize>,
+    /// See `Alignment::base`.
+    pub base: Option<u8>,
+    /// See `Alignment::base_qual`.
+    pub base_qual: Option<u8>,
+    pub mapq: u8,
+    pub is_reverse: bool,
+    pub is_del: bool,
+    pub is_refskip: bool,
+    /// See `Alignment::indel`.
+    pub indel: Indel,
+}
+
+impl<'a> From<Alignment<'a>> for Observation {
+    fn from(alignment: Alignment<'a>) -> Self {
+        let b = alignment.bam();
+        let qname = unsafe { ffi::CStr::from_ptr(b.data as *const c_char) };
+        Observation {
+            qname: qname.to_bytes().to_owned(),
+            qpos: alignment.qpos(),
+            base: alignment.base(),
+            base_qual: alignment.base_qual(),
+            mapq: b.core.qual,
+            is_reverse: alignment.is_reverse(),
+            is_del: alignment.is_del(),
+            is_refskip: alignment.is_refskip(),
+            indel: alignment.indel(),
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
@@ -126,16 +239,193 @@ pub enum Indel {
 /// Iterator over pileups.
 #[derive(Debug)]
 pub struct Pileups<'a, R: bam::Read> {
-    #[allow(dead_code)]
     reader: &'a mut R,
     itr: htslib::bam_plp_t,
+    exclude_flags: u16,
+    min_mapq: u8,
+    region: Option<(u32, u64, u64)>,
+    max_depth: Option<i32>,
+    mplp: Option<(htslib::bam_mplp_t, Box<MplpReader<R>>)>,
+    record: record::Record,
+    eof: bool,
 }
 
 impl<'a, R: bam::Read> Pileups<'a, R> {
     pub fn new(reader: &'a mut R, itr: htslib::bam_plp_t) -> Self {
-        Pileups { reader, itr }
+        Pileups {
+            reader,
+            itr,
+            exclude_flags: 0,
+            min_mapq: 0,
+            region: None,
+            max_depth: None,
+            mplp: None,
+            record: record::Record::new(),
+            eof: false,
+        }
+    }
+
+    /// Only yield pileups in the given region, like `samtools mpileup -r`. Without this,
+    /// a pileup after `IndexedReader::fetch` also yields positions outside of the fetched
+    /// region, because the fetched reads can extend beyond it. Since the fetch includes all
+    /// reads overlapping the region, the depths at its edges are the same as for a pileup of
+    /// the whole file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - the target id of the region
+    /// * `beg` - 0-based start of the region
+    /// * `end` - 0-based, exclusive end of the region
+    pub fn set_region(&mut self, tid: u32, beg: u64, end: u64) {
+        self.region = Some((tid, beg, end));
+    }
+
+    /// Avoid counting the evidence of overlapping mates twice, like `samtools mpileup` does by
+    /// default, using htslib's `bam_mplp_init_overlaps`. Where the mates of a proper pair
+    /// overlap, the base quality of one of them is set to 0 (visible via `Alignment::record`).
+    /// If both mates agree on the base, the other one gets the sum of both qualities (capped at
+    /// 200). Otherwise, the base with the higher quality is kept with 80% of its quality.
+    /// Overlapping mates still count towards `Pileup::depth`. Has to be called before the first
+    /// pileup is retrieved from the iterator.
+    pub fn ignore_overlaps(&mut self) -> Result<()> {
+        if self.mplp.is_some() {
+            return Ok(());
+        }
+        // htslib only offers overlap detection for multi-pileups, hence one is used over the
+        // single reader.
+        let mut state = Box::new(MplpReader {
+            reader: &mut *self.reader as *mut R,
+            record: record::Record::new(),
+            exclude_flags: self.exclude_flags,
+            min_mapq: self.min_mapq,
+            error: None,
+        });
+        let mut data = [&mut *state as *mut MplpReader<R> as *mut c_void];
+        let itr = unsafe { htslib::bam_mplp_init(1, Some(mplp_read::<R>), data.as_mut_ptr()) };
+        if itr.is_null() {
+            return Err(Error::BamPileup);
+        }
+        if unsafe { htslib::bam_mplp_init_overlaps(itr) } < 0 {
+            unsafe { htslib::bam_mplp_destroy(itr) };
+            return Err(Error::BamPileup);
+        }
+        if let Some(depth) = self.max_depth {
+            unsafe { htslib::bam_mplp_set_maxcnt(itr, depth) };
+        }
+        self.mplp = Some((itr, state));
+        Ok(())
     }
 
+    /// Skip reads having any of the given flags (e.g. `htslib::BAM_FDUP`), like
+    /// `samtools mpileup --ff`. Skipped reads neither count towards `Pileup::depth` nor
+    /// appear in `Pileup::alignments`. By default, no reads are skipped (note that
+    /// unmapped reads are always skipped by htslib). Has to be set before the first pileup
+    /// is retrieved from the iterator.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - bitwise or of the flags to exclude
+    pub fn set_filter(&mut self, flags: u16) {
+        self.exclude_flags = flags;
+        if let Some((_, state)) = &mut self.mplp {
+            state.exclude_flags = flags;
+        }
+    }
+
+    /// Skip reads with a mapping quality below the given value, like `samtools mpileup -q`.
+    /// As with `set_filter`, skipped reads do not count towards `Pileup::depth`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mapq` - minimum mapping quality of reads to include
+    pub fn set_min_mapq(&mut self, mapq: u8) {
+        self.min_mapq = mapq;
+        if let Some((_, state)) = &mut self.mplp {
+            state.min_mapq = mapq;
+        }
+    }
+
+    #[allow(clippy::match_bool)]
+    fn next_unrestricted(&mut self) -> Option<Result<Pileup>> {
+        if let Some((itr, state)) = &mut self.mplp {
+            let (mut tid, mut pos, mut depth) = (0i32, 0i64, 0i32);
+            let mut inner = ptr::null();
+            let ret = unsafe {
+                htslib::bam_mplp64_auto(*itr, &mut tid, &mut pos, &mut depth, &mut inner)
+            };
+            return match ret {
+                0 => None,
+                ret if ret < 0 => Some(Err(state.error.take().unwrap_or(Error::BamPileup))),
+                _ => Some(Ok(Pileup {
+                    inner,
+                    depth: depth as u32,
+                    tid: tid as u32,
+                    pos: pos as u64,
+                })),
+            };
+        }
+        if self.exclude_flags != 0 || self.min_mapq > 0 {
+            return self.next_filtered();
+        }
+        let (mut tid, mut pos, mut depth) = (0i32, 0i64, 0i32);
+        let inner = unsafe { htslib::bam_plp64_auto(self.itr, &mut tid, &mut pos, &mut depth) };
+
+        match inner.is_null() {
+            true if depth == -1 => Some(Err(Error::BamPileup)),
+            true => None,
+            false => Some(Ok(Pileup {
+                inner,
+                depth: depth as u32,
+                tid: tid as u32,
+                pos: pos as u64,
+            })),
+        }
+    }
+
+    // Like bam_plp64_auto, but reads records via the reader in order to filter them before
+    // they are pushed into the pileup buffer.
+    fn next_filtered(&mut self) -> Option<Result<Pileup>> {
+        let (mut tid, mut pos, mut depth) = (0i32, 0i64, 0i32);
+        loop {
+            let inner = unsafe { htslib::bam_plp64_next(self.itr, &mut tid, &mut pos, &mut depth) };
+            if !inner.is_null() {
+                return Some(Ok(Pileup {
+                    inner,
+                    depth: depth as u32,
+                    tid: tid as u32,
+                    pos: pos as u64,
+                }));
+            } else if depth == -1 {
+                return Some(Err(Error::BamPileup));
+            } else if self.eof {
+                return None;
+            }
+
+            match self.reader.read(&mut self.record) {
+                Some(Ok(())) => {
+                    if self.record.flags() & self.exclude_flags != 0
+                        || self.record.mapq() < self.min_mapq
+                    {
+                        continue;
+                    }
+                    if unsafe { htslib::bam_plp_push(self.itr, self.record.inner_ptr()) } < 0 {
+                        return Some(Err(Error::BamPileup));
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.eof = true;
+                    unsafe { htslib::bam_plp_push(self.itr, ptr::null()) };
+                }
+            }
+        }
+    }
+
+    /// Set the maximum depth of the pileup (htslib's default is 8000, like `samtools mpileup
+    /// -d`). Once more than `depth` reads overlap the current position, reads starting at that
+    /// position are skipped, so `Pileup::depth` may slightly exceed the limit. The limit has to
+    /// be set before the first pileup is retrieved from the iterator.
+    ///
     /// Warning: because htslib internally uses signed integer for depth this method
     /// will panic if `depth` exceeds `i32::max_value()`.
     pub fn set_max_depth(&mut self, depth: u32) {
@@ -147,8 +437,45 @@ impl<'a, R: bam::Read> Pileups<'a, R> {
             )
         }
         let intdepth = depth as i32;
+        self.max_depth = Some(intdepth);
         unsafe {
             htslib::bam_plp_set_maxcnt(self.itr, intdepth);
+            if let Some((itr, _)) = self.mplp {
+                htslib::bam_mplp_set_maxcnt(itr, intdepth);
+            }
+        }
+    }
+}
+
+impl<'a, R: bam::Read> Pileups<'a, R> {
+    /// Turn this into an iterator over owned pileups, e.g. in order to process positions in
+    /// parallel. Settings like `set_region` or `set_filter` are retained.
+    ///
+    /// This comes at the cost of copying the observation of each read (including its name)
+    /// at every position, i.e. memory and time proportional to the depth, while `Pileup`
+    /// merely points to htslib's buffers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::{self, Read};
+    ///
+    /// let mut bam = bam::Reader::from_path("test/test.bam").unwrap();
+    /// let pileups: Vec<_> = bam.pileup().owned().map(|p| p.unwrap()).collect();
+    /// let handle = std::thread::spawn(move || pileups.iter().all(|p| p.contig == "CHROMOSOME_I"));
+    /// assert!(handle.join().unwrap());
+    /// ```
+    pub fn owned(self) -> OwnedPileups<'a, R> {
+        let contigs = self
+            .reader
+            .header()
+            .target_names()
+            .into_iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        OwnedPileups {
+            pileups: self,
+            contigs,
         }
     }
 }
@@ -156,29 +483,94 @@ impl<'a, R: bam::Read> Pileups<'a, R> {
 impl<'a, R: bam::Read> Iterator for Pileups<'a, R> {
     type Item = Result<Pileup>;
 
-    #[allow(clippy::match_bool)]
     fn next(&mut self) -> Option<Result<Pileup>> {
-        let (mut tid, mut pos, mut depth) = (0i32, 0i32, 0i32);
-        let inner = unsafe { htslib::bam_plp_auto(self.itr, &mut tid, &mut pos, &mut depth) };
+        loop {
+            let pileup = match self.next_unrestricted()? {
+                Ok(pileup) => pileup,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some((tid, beg, end)) = self.region {
+                if (pileup.tid, pileup.pos) < (tid, beg) {
+                    continue;
+                } else if (pileup.tid, pileup.pos) >= (tid, end) {
+                    return None;
+                }
+            }
+            return Some(Ok(pileup));
+        }
+    }
+}
 
-        match inner.is_null() {
-            true if depth == -1 => Some(Err(Error::BamPileup)),
-            true => None,
-            false => Some(Ok(Pileup {
-                inner,
-                depth: depth as u32,
-                tid: tid as u32,
-                pos: pos as u32,
-            })),
+/// State of the read function of a multi-pileup iterator over a single reader, which is used
+/// for `Pileups::ignore_overlaps`.
+#[derive(Debug)]
+struct MplpReader<R: bam::Read> {
+    reader: *mut R,
+    record: record::Record,
+    exclude_flags: u16,
+    min_mapq: u8,
+    error: Option<Error>,
+}
+
+// Read the next record passing the filters into the pileup buffer of htslib.
+unsafe extern "C" fn mplp_read<R: bam::Read>(data: *mut c_void, b: *mut htslib::bam1_t) -> c_int {
+    let state = &mut *(data as *mut MplpReader<R>);
+    loop {
+        match (*state.reader).read(&mut state.record) {
+            Some(Ok(())) => {
+                if state.record.flags() & state.exclude_flags != 0
+                    || state.record.mapq() < state.min_mapq
+                {
+                    continue;
+                }
+                return if htslib::bam_copy1(b, state.record.inner_ptr()).is_null() {
+                    -2
+                } else {
+                    0
+                };
+            }
+            Some(Err(e)) => {
+                state.error = Some(e);
+                return -2;
+            }
+            None => return -1,
         }
     }
 }
 
+/// Iterator over owned pileups, see `Pileups::owned`.
+#[derive(Debug)]
+pub struct OwnedPileups<'a, R: bam::Read> {
+    pileups: Pileups<'a, R>,
+    contigs: Vec<String>,
+}
+
+impl<'a, R: bam::Read> Iterator for OwnedPileups<'a, R> {
+    type Item = Result<OwnedPileup>;
+
+    fn next(&mut self) -> Option<Result<OwnedPileup>> {
+        let pileup = match self.pileups.next()? {
+            Ok(pileup) => pileup,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Ok(OwnedPileup {
+            contig: self.contigs[pileup.tid() as usize].clone(),
+            tid: pileup.tid(),
+            pos: pileup.pos(),
+            depth: pileup.depth(),
+            observations: pileup.alignments().map(Observation::from).collect(),
+        }))
+    }
+}
+
 impl<'a, R: bam::Read> Drop for Pileups<'a, R> {
     fn drop(&mut self) {
         unsafe {
             htslib::bam_plp_reset(self.itr);
             htslib::bam_plp_destroy(self.itr);
+            if let Some((itr, _)) = self.mplp {
+                htslib::bam_mplp_destroy(itr);
+            }
         }
     }
 }
@@ -188,6 +580,105 @@ mod tests {
 
     use crate::bam;
     use crate::bam::Read;
+    use crate::htslib;
+
+    #[test]
+    fn test_alignment_base() {
+        let mut bam = bam::Reader::from_path("test/test.bam").unwrap();
+        let mut n = 0;
+        for pileup in bam.pileup() {
+            let pileup = pileup.unwrap();
+            for alignment in pileup.alignments() {
+                let record = alignment.record();
+                assert_eq!(alignment.is_reverse(), record.is_reverse());
+                match alignment.qpos() {
+                    Some(qpos) => {
+                        assert_eq!(alignment.base(), Some(record.seq()[qpos]));
+                        assert_eq!(alignment.base_qual(), Some(record.qual()[qpos]));
+                        let dist = alignment.dist_from_read_end().unwrap() as usize;
+                        assert_eq!(dist, qpos.min(record.seq_len() - 1 - qpos));
+                        n += 1;
+                    }
+                    None => {
+                        assert_eq!(alignment.base(), None);
+                        assert_eq!(alignment.base_qual(), None);
+                        assert_eq!(alignment.dist_from_read_end(), None);
+                    }
+                }
+            }
+        }
+        assert!(n > 0);
+    }
+
+    #[test]
+    fn test_alignment_base_missing_seq_and_qual() {
+        let header = bam::HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n");
+        let record = bam::Record::from_sam(
+            &header,
+            b"noqual\t16\tchr1\t10\t60\t2S3M\t*\t0\t0\tACGTA\t*",
+        )
+        .unwrap();
+        let mut pileup1: htslib::bam_pileup1_t = unsafe { std::mem::zeroed() };
+        pileup1.b = record.inner() as *const htslib::bam1_t as *mut htslib::bam1_t;
+        pileup1.qpos = 2;
+        let alignment = super::Alignment::new(&pileup1);
+        assert_eq!(alignment.base(), Some(b'G'));
+        assert_eq!(alignment.base_qual(), None);
+        assert_eq!(alignment.dist_from_read_end(), Some(2));
+        assert!(alignment.is_reverse());
+        pileup1.qpos = 4;
+        let alignment = super::Alignment::new(&pileup1);
+        assert_eq!(alignment.dist_from_read_end(), Some(0));
+    }
+
+    #[test]
+    fn test_pileup_owned_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<super::OwnedPileup>();
+
+        let mut bam = bam::IndexedReader::from_path("test/test.bam").unwrap();
+        let region = (0, 1000, 1100);
+        bam.fetch(region).unwrap();
+        let mut pileups = bam.pileup();
+        pileups.set_region(region.0, region.1, region.2);
+        let expected: Vec<_> = pileups
+            .map(|p| {
+                let p = p.unwrap();
+                let qnames: Vec<_> = p
+                    .alignments()
+                    .map(|a| a.record().qname().to_owned())
+                    .collect();
+                (p.pos(), p.depth(), qnames)
+            })
+            .collect();
+        assert_eq!(expected.len(), 100);
+
+        bam.fetch(region).unwrap();
+        let mut pileups = bam.pileup();
+        pileups.set_region(region.0, region.1, region.2);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let summer = std::thread::spawn(move || {
+            receiver
+                .iter()
+                .map(|p: super::OwnedPileup| {
+                    assert_eq!(p.contig, "CHROMOSOME_I");
+                    assert_eq!(p.observations.len(), p.depth as usize);
+                    p.depth as u64
+                })
+                .sum::<u64>()
+        });
+        for (p, (pos, depth, qnames)) in pileups.owned().zip(&expected) {
+            let p = p.unwrap();
+            assert_eq!((p.pos, p.depth), (*pos, *depth));
+            let names: Vec<_> = p.observations.iter().map(|o| o.qname.clone()).collect();
+            assert_eq!(&names, qnames);
+            sender.send(p).unwrap();
+        }
+        drop(sender);
+        let depth_sum: u64 = expected.iter().map(|(_, depth, _)| *depth as u64).sum();
+        assert!(depth_sum > 0);
+        assert_eq!(summer.join().unwrap(), depth_sum);
+    }
 
     #[test]
     fn test_max_pileup() {
@@ -197,6 +688,389 @@ mod tests {
         p.set_max_depth(800u32);
     }
 
+    #[test]
+    fn test_max_pileup_high_depth() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("deep.bam");
+        let n_reads = 10_000;
+        {
+            let mut header = bam::Header::new();
+            header.push_record(
+                bam::header::HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", "chrM")
+                    .push_tag(b"LN", 16569),
+            );
+            let mut writer = bam::Writer::from_path(&bampath, &header, bam::Format::Bam).unwrap();
+            let cigar = bam::record::CigarString(vec![bam::record::Cigar::Match(4)]);
+            let mut record = bam::Record::new();
+            for i in 0..n_reads {
+                record.set(
+                    format!("r{}", i).as_bytes(),
+                    Some(&cigar),
+                    b"ACGT",
+                    &[30; 4],
+                );
+                record.set_tid(0);
+                record.set_pos(100);
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let max_depth = |limit: Option<u32>| {
+            let mut bam = bam::Reader::from_path(&bampath).unwrap();
+            let mut pileups = bam.pileup();
+            if let Some(limit) = limit {
+                pileups.set_max_depth(limit);
+            }
+            pileups.map(|p| p.unwrap().depth()).max().unwrap()
+        };
+        // htslib's default caps the depth at ~8000
+        assert!(max_depth(None) <= 8001);
+        assert_eq!(max_depth(Some(200_000)), n_reads);
+        assert!(max_depth(Some(100)) <= 101);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_pileup_filter() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let exclude = (htslib::BAM_FSUPPLEMENTARY | htslib::BAM_FDUP) as u16;
+        let min_mapq = 2;
+
+        let depths = |path: &std::path::Path, filter: bool| {
+            let mut bam = bam::Reader::from_path(path).unwrap();
+            let mut pileups = bam.pileup();
+            if filter {
+                pileups.set_filter(exclude);
+                pileups.set_min_mapq(min_mapq);
+            }
+            pileups
+                .map(|p| {
+                    let p = p.unwrap();
+                    assert_eq!(p.alignments().count(), p.depth() as usize);
+                    for alignment in p.alignments() {
+                        let record = alignment.record();
+                        assert!(!filter || record.flags() & exclude == 0);
+                        assert!(!filter || record.mapq() >= min_mapq);
+                    }
+                    (p.tid(), p.pos(), p.depth())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the same filter applied while writing a copy of the file
+        let filtered_path = tmp.path().join("filtered.bam");
+        {
+            let mut bam = bam::Reader::from_path("test/test.bam").unwrap();
+            let header = bam::Header::from_template(bam.header());
+            let mut writer =
+                bam::Writer::from_path(&filtered_path, &header, bam::Format::Bam).unwrap();
+            for record in bam.records() {
+                let record = record.unwrap();
+                if record.flags() & exclude == 0 && record.mapq() >= min_mapq {
+                    writer.write(&record).unwrap();
+                }
+            }
+        }
+
+        let test_bam = std::path::Path::new("test/test.bam");
+        let filtered = depths(test_bam, true);
+        assert_eq!(filtered, depths(&filtered_path, false));
+        assert_ne!(filtered, depths(test_bam, false));
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_pileup_region() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("staggered.bam");
+        {
+            let mut header = bam::Header::new();
+            for name in ["chr1", "chr2"] {
+                header.push_record(
+                    bam::header::HeaderRecord::new(b"SQ")
+                        .push_tag(b"SN", name)
+                        .push_tag(b"LN", 1000),
+                );
+            }
+            let mut writer = bam::Writer::from_path(&bampath, &header, bam::Format::Bam).unwrap();
+            let cigar = bam::record::CigarString(vec![bam::record::Cigar::Match(50)]);
+            let mut record = bam::Record::new();
+            for tid in 0..2 {
+                for pos in (0..500).step_by(7) {
+                    record.set(b"read", Some(&cigar), &[b'A'; 50], &[30; 50]);
+                    record.set_tid(tid);
+                    record.set_pos(pos);
+                    writer.write(&record).unwrap();
+                }
+            }
+        }
+        bam::index::build(&bampath, None, bam::index::Type::Bai, 1).unwrap();
+
+        let full: Vec<_> = bam::Reader::from_path(&bampath)
+            .unwrap()
+            .pileup()
+            .map(|p| {
+                let p = p.unwrap();
+                (p.tid(), p.pos(), p.depth())
+            })
+            .filter(|&(tid, pos, _)| tid == 0 && (100..200).contains(&pos))
+            .collect();
+        assert_eq!(full.len(), 100);
+
+        let mut bam = bam::IndexedReader::from_path(&bampath).unwrap();
+        bam.fetch((0, 100, 200)).unwrap();
+        let unrestricted: Vec<_> = bam.pileup().map(|p| p.unwrap().pos()).collect();
+        assert!(unrestricted.first().unwrap() < &100);
+        assert!(unrestricted.last().unwrap() >= &200);
+
+        bam.fetch((0, 100, 200)).unwrap();
+        let mut pileups = bam.pileup();
+        pileups.set_region(0, 100, 200);
+        let restricted: Vec<_> = pileups
+            .map(|p| {
+                let p = p.unwrap();
+                (p.tid(), p.pos(), p.depth())
+            })
+            .collect();
+        assert_eq!(restricted, full);
+
+        // also works without fetching
+        let mut bam = bam::Reader::from_path(&bampath).unwrap();
+        let mut pileups = bam.pileup();
+        pileups.set_region(0, 100, 200);
+        let positions: Vec<_> = pileups.map(|p| p.unwrap().pos()).collect();
+        assert_eq!(positions, (100..200).collect::<Vec<_>>());
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_pileup_ignore_overlaps() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("pair.bam");
+        {
+            let mut header = bam::Header::new();
+            header.push_record(
+                bam::header::HeaderRecord::new(b"SQ")
+                    .push_tag(b"SN", "chr1")
+                    .push_tag(b"LN", 1000),
+            );
+            let mut writer = bam::Writer::from_path(&bampath, &header, bam::Format::Bam).unwrap();
+            let cigar = bam::record::CigarString(vec![bam::record::Cigar::Match(50)]);
+            let mut mate1 = bam::Record::new();
+            mate1.set(b"pair", Some(&cigar), &[b'A'; 50], &[30; 50]);
+            mate1.set_flags(99);
+            mate1.set_pos(100);
+            let mut seq2 = [b'A'; 50];
+            seq2[5] = b'C'; // ref position 125, disagreeing with mate1
+            let mut qual2 = [20; 50];
+            qual2[5] = 35;
+            let mut mate2 = bam::Record::new();
+            mate2.set(b"pair", Some(&cigar), &seq2, &qual2);
+            mate2.set_flags(147);
+            mate2.set_pos(120);
+            for (record, mpos) in [(&mut mate1, 120), (&mut mate2, 100)] {
+                record.set_tid(0);
+                record.set_mtid(0);
+                record.set_mpos(mpos);
+                record.set_insert_size(if mpos == 120 { 70 } else { -70 });
+                writer.write(record).unwrap();
+            }
+        }
+
+        // base qualities of both mates at each position where they overlap
+        let overlap_quals = |ignore_overlaps: bool| {
+            let mut bam = bam::Reader::from_path(&bampath).unwrap();
+            let mut pileups = bam.pileup();
+            if ignore_overlaps {
+                pileups.ignore_overlaps().unwrap();
+            }
+            pileups
+                .map(|p| p.unwrap())
+                .filter(|p| p.depth() == 2)
+                .map(|p| {
+                    let quals: Vec<u8> = p
+                        .alignments()
+                        .map(|a| a.record().qual()[a.qpos().unwrap()])
+                        .collect();
+                    (p.pos(), quals)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let quals = overlap_quals(false);
+        assert_eq!(quals.len(), 30);
+        assert!(quals.iter().all(|(_, q)| q.iter().all(|&q| q > 0)));
+
+        let quals = overlap_quals(true);
+        assert_eq!(quals.len(), 30);
+        for (pos, q) in quals {
+            assert_eq!(q.iter().filter(|&&q| q > 0).count(), 1);
+            if pos == 125 {
+                // mismatch: the higher quality base of mate2 is kept
+                assert_eq!(q, vec![0, 28]);
+            } else {
+                assert_eq!(q.iter().sum::<u8>(), 50);
+            }
+        }
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_pileup_ignore_overlaps_like_htslib() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("pairs.bam");
+        let header = bam::HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n");
+        // pairs with indels, clips, skips, mismatches and equal qualities
+        let pairs = [
+            ("a", 100, "30M", 110, "30M"),
+            ("b", 105, "10M2D20M", 115, "3S12M1I14M"),
+            ("c", 120, "25M", 120, "25M"),
+            ("d", 130, "5S25M", 140, "10M3N10M"),
+            ("e", 150, "20M", 300, "20M"),
+            // same qualities in both mates
+            ("h", 160, "30M", 160, "30M"),
+        ];
+        let mut records = Vec::new();
+        for (i, &(name, pos1, cigar1, pos2, cigar2)) in pairs.iter().enumerate() {
+            let len = |cigar: &str| {
+                <bam::record::CigarString as std::convert::TryFrom<&str>>::try_from(cigar)
+                    .unwrap()
+                    .into_view(0)
+                    .iter()
+                    .map(|c| match c {
+                        bam::record::Cigar::Match(l)
+                        | bam::record::Cigar::Ins(l)
+                        | bam::record::Cigar::SoftClip(l) => *l as usize,
+                        _ => 0,
+                    })
+                    .sum::<usize>()
+            };
+            let isize = pos2 + 30 - pos1;
+            for (mate, (flag, pos, cigar, mpos)) in
+                [(99, pos1, cigar1, pos2), (147, pos2, cigar2, pos1)]
+                    .iter()
+                    .enumerate()
+            {
+                let n = len(cigar);
+                let seq: String = (0..n)
+                    .map(|j| match (j + i + mate * (j % 7 / 6)) % 4 {
+                        0 => 'A',
+                        1 => 'C',
+                        2 => 'G',
+                        _ => 'T',
+                    })
+                    .collect();
+                let qual: String = (0..n)
+                    .map(|j| {
+                        let offset = if name == "h" { 0 } else { mate * 11 };
+                        (b'!' + ((j * 7 + i * 3 + offset) % 40) as u8) as char
+                    })
+                    .collect();
+                let tlen = if mate == 0 { isize } else { -isize };
+                let line = format!(
+                    "{}\t{}\tchr1\t{}\t60\t{}\t=\t{}\t{}\t{}\t{}",
+                    name, flag, pos, cigar, mpos, tlen, seq, qual
+                );
+                records.push(bam::Record::from_sam(&header, line.as_bytes()).unwrap());
+            }
+        }
+        records.sort_by_key(|r| r.pos());
+        {
+            let header = bam::Header::from_template(&header);
+            let mut writer = bam::Writer::from_path(&bampath, &header, bam::Format::Bam).unwrap();
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+        }
+
+        type Observations = Vec<(i64, Vec<(Vec<u8>, Option<usize>, Option<u8>)>)>;
+        let mut expected: Observations = Vec::new();
+        {
+            extern "C" fn read(data: *mut std::os::raw::c_void, b: *mut htslib::bam1_t) -> i32 {
+                let reader = unsafe { &*(data as *const bam::Reader) };
+                unsafe {
+                    htslib::sam_read1(
+                        reader.htsfile(),
+                        reader.header().inner_ptr() as *mut htslib::sam_hdr_t,
+                        b,
+                    )
+                }
+            }
+            let bam = bam::Reader::from_path(&bampath).unwrap();
+            let mut data = [&bam as *const bam::Reader as *mut std::os::raw::c_void];
+            unsafe {
+                let itr = htslib::bam_mplp_init(1, Some(read), data.as_mut_ptr());
+                assert_eq!(htslib::bam_mplp_init_overlaps(itr), 0);
+                let (mut tid, mut pos, mut n) = (0, 0, 0);
+                let mut plp = std::ptr::null();
+                while htslib::bam_mplp64_auto(itr, &mut tid, &mut pos, &mut n, &mut plp) > 0 {
+                    let reads = std::slice::from_raw_parts(plp, n as usize)
+                        .iter()
+                        .map(|p| {
+                            let alignment = super::Alignment::new(p);
+                            let record = alignment.record();
+                            let qpos = alignment.qpos();
+                            (
+                                record.qname().to_owned(),
+                                qpos,
+                                qpos.map(|i| record.qual()[i]),
+                            )
+                        })
+                        .collect();
+                    expected.push((pos, reads));
+                }
+                htslib::bam_mplp_destroy(itr);
+            }
+        }
+
+        let mut bam = bam::Reader::from_path(&bampath).unwrap();
+        let mut pileups = bam.pileup();
+        pileups.ignore_overlaps().unwrap();
+        let observed: Observations = pileups
+            .map(|p| {
+                let p = p.unwrap();
+                let reads = p
+                    .alignments()
+                    .map(|a| {
+                        let record = a.record();
+                        let qpos = a.qpos();
+                        (
+                            record.qname().to_owned(),
+                            qpos,
+                            qpos.map(|i| record.qual()[i]),
+                        )
+                    })
+                    .collect();
+                (p.pos() as i64, reads)
+            })
+            .collect();
+        assert_eq!(observed, expected);
+        // quality of one of the mates is removed in overlaps
+        assert!(observed
+            .iter()
+            .any(|(_, reads)| reads.iter().any(|(_, _, q)| *q == Some(0))));
+    }
+
     #[test]
     #[should_panic]
     fn test_max_pileup_to_high() {
@@ -7,6 +7,7 @@ use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::ffi;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::mem::{size_of, MaybeUninit};
 use std::ops;
@@ -18,9 +19,11 @@ use std::u32;
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
+use crate::bam::fastq;
 use crate::bam::Error;
 use crate::bam::HeaderView;
 use crate::errors::Result;
+use crate::faidx;
 use crate::htslib;
 use crate::utils;
 #[cfg(feature = "serde_feature")]
@@ -64,15 +67,19 @@ impl Clone for Record {
     fn clone(&self) -> Self {
         let mut copy = Record::new();
         unsafe { htslib::bam_copy1(copy.inner_ptr_mut(), self.inner_ptr()) };
+        copy.header = self.header.clone();
         copy
     }
 }
 
+/// Records are equal if their content is: the core fields and the variable length data
+/// (qname, cigar, seq, qual and aux fields). The bin, which is derived from the position
+/// and cigar, and unused capacity of the internal buffer are ignored, so that e.g. a record read
+/// from a file is equal to the same record built with `set`.
 impl PartialEq for Record {
     fn eq(&self, other: &Record) -> bool {
         self.tid() == other.tid()
             && self.pos() == other.pos()
-            && self.bin() == other.bin()
             && self.mapq() == other.mapq()
             && self.flags() == other.flags()
             && self.mtid() == other.mtid()
@@ -85,6 +92,20 @@ impl PartialEq for Record {
 
 impl Eq for Record {}
 
+impl Hash for Record {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.tid().hash(state);
+        self.pos().hash(state);
+        self.mapq().hash(state);
+        self.flags().hash(state);
+        self.mtid().hash(state);
+        self.mpos().hash(state);
+        self.insert_size().hash(state);
+        self.data().hash(state);
+        self.inner().core.l_extranul.hash(state);
+    }
+}
+
 impl fmt::Debug for Record {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         fmt.write_fmt(format_args!(
@@ -111,6 +132,16 @@ fn extranul_from_qname(qname: &[u8]) -> usize {
     }
 }
 
+/// The name of the contig with the given tid, or `Error::BamContigNameNotUtf8` if it is not
+/// valid UTF-8.
+fn contig_name_str(header: &HeaderView, tid: i32) -> Result<&str> {
+    let name = header.try_tid2name(tid)?;
+    str::from_utf8(name).map_err(|_| Error::BamContigNameNotUtf8 {
+        tid,
+        name: String::from_utf8_lossy(name).into_owned(),
+    })
+}
+
 impl Record {
     /// Create an empty BAM record.
     pub fn new() -> Self {
@@ -142,8 +173,41 @@ impl Record {
         }
     }
 
-    // Create a BAM record from a line SAM text. SAM slice need not be 0-terminated.
+    /// Create a BAM record from a line of SAM text, which need not be 0-terminated, and may
+    /// end with a newline.
+    ///
+    /// Returns `Error::ContigNotFound` if the reference (`RNAME`) or the mate reference
+    /// (`RNEXT`) of the line is not in the given header, and `Error::BamParseSAM` if the line
+    /// cannot be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::{HeaderView, Record};
+    ///
+    /// let header = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n");
+    /// let line = b"r1\t0\tchr1\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\tXB:B:s,-1,2";
+    /// let record = Record::from_sam(&header, line).unwrap();
+    /// assert_eq!(record.pos(), 9);
+    /// assert_eq!(record.to_sam(&header).unwrap(), line);
+    /// ```
     pub fn from_sam(header_view: &HeaderView, sam: &[u8]) -> Result<Record> {
+        let sam = sam.strip_suffix(b"\n").unwrap_or(sam);
+        let sam = sam.strip_suffix(b"\r").unwrap_or(sam);
+        // htslib treats unknown references as unmapped, with a warning only
+        let fields: Vec<&[u8]> = sam.splitn(8, |&c| c == b'\t').collect();
+        for (i, skip) in [(2, &b"*"[..]), (6, &b"="[..])] {
+            if let Some(&name) = fields.get(i) {
+                if name != b"*"
+                    && name != skip
+                    && !name.contains(&0)
+                    && header_view.tid(name).is_none()
+                {
+                    return Err(utils::contig_not_found(name, header_view.target_names()));
+                }
+            }
+        }
+
         let mut record = Self::new();
 
         let mut sam_copy = Vec::with_capacity(sam.len() + 1);
@@ -152,7 +216,7 @@ impl Record {
 
         let mut sam_string = htslib::kstring_t {
             s: sam_copy.as_ptr() as *mut c_char,
-            l: sam_copy.len() as u64,
+            l: sam.len() as u64,
             m: sam_copy.len() as u64,
         };
 
@@ -168,26 +232,87 @@ impl Record {
             Ok(record)
         } else {
             Err(Error::BamParseSAM {
-                rec: str::from_utf8(&sam_copy).unwrap().to_owned(),
+                rec: String::from_utf8_lossy(sam).into_owned(),
             })
         }
     }
 
+    /// Format the record as a line of SAM text (without trailing newline), with the contig
+    /// names of the given header. This is the inverse of [`from_sam`](#method.from_sam).
+    ///
+    /// Returns `Error::BamFormatSAM` if htslib cannot format the record, e.g. because its
+    /// contig is not in the header.
+    pub fn to_sam(&self, header_view: &HeaderView) -> Result<Vec<u8>> {
+        // htslib does not check the contigs against the header
+        let n_targets = header_view.target_count() as i32;
+        if self.tid() >= n_targets || self.mtid() >= n_targets {
+            return Err(Error::BamFormatSAM);
+        }
+        let mut sam_string = htslib::kstring_t {
+            s: std::ptr::null_mut(),
+            l: 0,
+            m: 0,
+        };
+        let ret = unsafe {
+            htslib::sam_format1(header_view.inner_ptr(), self.inner_ptr(), &mut sam_string)
+        };
+        let sam = if ret < 0 {
+            Err(Error::BamFormatSAM)
+        } else {
+            Ok(
+                unsafe { slice::from_raw_parts(sam_string.s as *const u8, sam_string.l as usize) }
+                    .to_vec(),
+            )
+        };
+        unsafe { libc::free(sam_string.s as *mut libc::c_void) };
+        sam
+    }
+
     pub fn set_header(&mut self, header: Rc<HeaderView>) {
         self.header = Some(header);
     }
 
+    /// Return the name of the contig the record is aligned to.
+    ///
+    /// Unlike [`genome::AbstractInterval::contig`], this does not panic but returns
+    /// `Error::BamRecordWithoutHeader` if the record has not been read from a file (see
+    /// [`Record::set_header`]), `Error::InvalidTid` if it is unmapped, and
+    /// `Error::BamContigNameNotUtf8` if the name of the contig is not valid UTF-8.
+    pub fn contig_name(&self) -> Result<&str> {
+        self.tid_name(self.tid())
+    }
+
+    /// Return the name of the contig the mate is aligned to, with the same errors as
+    /// [`Record::contig_name`].
+    pub fn mate_contig_name(&self) -> Result<&str> {
+        self.tid_name(self.mtid())
+    }
+
+    fn tid_name(&self, tid: i32) -> Result<&str> {
+        let header = self.header.as_ref().ok_or(Error::BamRecordWithoutHeader)?;
+        contig_name_str(header, tid)
+    }
+
+    /// Return the header of the file the record has been read from, if any.
+    pub fn header(&self) -> Option<&Rc<HeaderView>> {
+        self.header.as_ref()
+    }
+
     pub(super) fn data(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.inner().data, self.inner().l_data as usize) }
     }
 
+    /// Mutable access to the underlying htslib record. Since the record can be modified in any
+    /// way through it, this drops the CIGAR cached by `cache_cigar`, as all modifying methods do.
     #[inline]
     pub fn inner_mut(&mut self) -> &mut htslib::bam1_t {
+        self.cigar = None;
         &mut self.inner
     }
 
     #[inline]
     pub(super) fn inner_ptr_mut(&mut self) -> *mut htslib::bam1_t {
+        self.cigar = None;
         &mut self.inner as *mut htslib::bam1_t
     }
 
@@ -413,10 +538,12 @@ impl Record {
         utils::copy_memory(qual, &mut data[i..]);
     }
 
-    /// Replace current qname with a new one.
+    /// Replace current qname with a new one, leaving cigar, sequence, qualities and aux data
+    /// untouched. Panics if the new qname is longer than the 254 bytes allowed by the SAM spec.
     pub fn set_qname(&mut self, new_qname: &[u8]) {
-        // 251 + 1NUL is the max 32-bit aligned value that fits in u8
-        assert!(new_qname.len() < 252);
+        // 254 + 1NUL is the max that fits into the u8 read name length of the BAM format.
+        // The extra NULs for 32-bit alignment are only kept in memory, hence the u16 l_qname.
+        assert!(new_qname.len() < 255);
 
         let old_q_len = self.qname_capacity();
         // We're going to add a terminal NUL
@@ -505,7 +632,13 @@ impl Record {
         }
     }
 
-    /// Return unpacked cigar string. This will create a fresh copy the Cigar data.
+    /// Return unpacked cigar string. This will create a fresh copy the Cigar data, i.e. a
+    /// snapshot that is not affected by later modifications of the record.
+    ///
+    /// CIGARs with more than 65535 operations are stored in the `CG` tag of BAM files, with a
+    /// placeholder in the CIGAR field. htslib moves them back into the CIGAR when reading
+    /// (from BAM as well as from SAM text), and into the `CG` tag when writing BAM, so records
+    /// always carry the real CIGAR in memory.
     pub fn cigar(&self) -> CigarStringView {
         match self.cigar {
             Some(ref c) => c.clone(),
@@ -513,12 +646,55 @@ impl Record {
         }
     }
 
-    // Return unpacked cigar string. This returns None unless you have first called `bam::Record::cache_cigar`.
+    /// Return the CIGAR of the mate, as stored in the `MC` tag, or `None` if the tag is absent.
+    ///
+    /// Returns `Error::BamParseCigar` if the tag is not a valid CIGAR string.
+    pub fn mate_cigar(&self) -> Result<Option<CigarString>> {
+        match self.aux(b"MC") {
+            Ok(Aux::String(mc)) => CigarString::try_from(mc).map(Some),
+            Ok(_) => Err(Error::BamAuxTypeMismatch {
+                tag: "MC".to_owned(),
+            }),
+            Err(Error::BamAuxTagNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Return the (exclusive) end position of the mate alignment, computed from `mpos` and
+    /// the `MC` tag. Returns `None` if the mate is unmapped or the tag is absent.
+    pub fn mate_end_pos(&self) -> Result<Option<i64>> {
+        if self.is_mate_unmapped() || self.mpos() < 0 {
+            return Ok(None);
+        }
+        Ok(self
+            .mate_cigar()?
+            .map(|cigar| cigar.into_view(self.mpos()).end_pos()))
+    }
+
+    /// Return unpacked cigar string. This returns None unless you have first called
+    /// `bam::Record::cache_cigar`, or if the record has been modified since then.
+    ///
+    /// The returned reference borrows the record, so that it cannot be modified while the
+    /// cached CIGAR is in use:
+    ///
+    /// ```compile_fail
+    /// use rust_htslib::bam::{Read, Reader};
+    ///
+    /// let mut bam = Reader::from_path("test/test.bam").unwrap();
+    /// let mut record = bam.records().next().unwrap().unwrap();
+    /// record.cache_cigar();
+    /// let cigar = record.cigar_cached().unwrap();
+    /// record.set_pos(100);
+    /// println!("{}", cigar.end_pos());
+    /// ```
     pub fn cigar_cached(&self) -> Option<&CigarStringView> {
         self.cigar.as_ref()
     }
 
-    /// Decode the cigar string and cache it inside the `Record`
+    /// Decode the cigar string and cache it inside the `Record`. The cache is dropped by all
+    /// methods that modify the record (e.g. `set`, `set_pos`, `set_qname`, `push_aux`, or
+    /// reading another record into it), so that it never describes an outdated alignment.
+    /// Only `set_seq` and `set_qual` keep it, since they cannot change the alignment.
     pub fn cache_cigar(&mut self) {
         self.cigar = Some(self.unpack_cigar())
     }
@@ -565,19 +741,190 @@ impl Record {
         }
     }
 
+    /// Replace the read sequence in place, leaving everything else untouched.
+    /// Returns `Error::BamReadLengthMismatch` if `seq` is not as long as the current sequence,
+    /// use [`set`](#method.set) to change the read length.
+    pub fn set_seq(&mut self, seq: &[u8]) -> Result<()> {
+        self.check_read_length(seq.len())?;
+        let offset = self.qname_capacity() + self.cigar_len() * 4;
+        let data =
+            unsafe { slice::from_raw_parts_mut(self.inner.data, self.inner().l_data as usize) };
+        for (encoded, bases) in data[offset..].iter_mut().zip(seq.chunks(2)) {
+            *encoded = ENCODE_BASE[bases[0] as usize] << 4
+                | bases.get(1).map_or(0, |&base| ENCODE_BASE[base as usize]);
+        }
+        Ok(())
+    }
+
+    /// Replace the base qualities in place (PHRED-scaled, without an offset of 33, see
+    /// `utils::ascii_to_phred`), leaving everything else untouched, e.g. for quality binning.
+    /// Returns
+    /// `Error::BamReadLengthMismatch` if `qual` is not as long as the sequence.
+    pub fn set_qual(&mut self, qual: &[u8]) -> Result<()> {
+        self.check_read_length(qual.len())?;
+        let offset = self.qname_capacity() + self.cigar_len() * 4 + self.seq_data().len();
+        let data =
+            unsafe { slice::from_raw_parts_mut(self.inner.data, self.inner().l_data as usize) };
+        data[offset..][..qual.len()].copy_from_slice(qual);
+        Ok(())
+    }
+
+    fn check_read_length(&self, len: usize) -> Result<()> {
+        if len == self.seq_len() {
+            Ok(())
+        } else {
+            Err(Error::BamReadLengthMismatch {
+                expected: self.seq_len(),
+                actual: len,
+            })
+        }
+    }
+
     /// Get base qualities (PHRED-scaled probability that base is wrong).
     /// This does not entail any offsets, hence the qualities can be used directly without
-    /// e.g. subtracting 33. Complexity: O(1).
+    /// e.g. subtracting 33. Use `utils::phred_to_ascii` to encode them like in SAM/FASTQ
+    /// files, and `utils::mean_qual` to average them. Missing qualities are stored as 0xff.
+    /// Complexity: O(1).
     pub fn qual(&self) -> &[u8] {
         &self.data()[self.qname_capacity() + self.cigar_len() * 4 + (self.seq_len() + 1) / 2..]
             [..self.seq_len()]
     }
 
+    /// Check the record for inconsistencies that htslib does not detect when writing it, but
+    /// that make the written file invalid (e.g. for samtools reading it). This is useful for
+    /// records that have been constructed or modified programmatically. See
+    /// `ValidationError` for the checks that are performed. All detected problems are
+    /// returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - the header the record is going to be written with
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::record::{Cigar, CigarString, Record, ValidationError};
+    /// use rust_htslib::bam::HeaderView;
+    ///
+    /// let header = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n");
+    /// let mut record = Record::new();
+    /// let cigar = CigarString(vec![Cigar::Match(3)]);
+    /// record.set(b"read", Some(&cigar), b"ACGT", &[30; 4]);
+    /// record.set_tid(0);
+    /// record.set_pos(100);
+    /// assert_eq!(
+    ///     record.check(&header),
+    ///     Err(vec![ValidationError::CigarSeqLengthMismatch {
+    ///         cigar_len: 3,
+    ///         seq_len: 4
+    ///     }])
+    /// );
+    /// ```
+    pub fn check(&self, header: &HeaderView) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let core = &self.inner().core;
+        let seq_len = self.seq_len();
+
+        let cigar_len: usize = self
+            .raw_cigar()
+            .iter()
+            .filter(|&&op| {
+                matches!(
+                    op & htslib::BAM_CIGAR_MASK,
+                    htslib::BAM_CMATCH
+                        | htslib::BAM_CINS
+                        | htslib::BAM_CSOFT_CLIP
+                        | htslib::BAM_CEQUAL
+                        | htslib::BAM_CDIFF
+                )
+            })
+            .map(|&op| (op >> htslib::BAM_CIGAR_SHIFT) as usize)
+            .sum();
+        if self.cigar_len() > 0 && seq_len > 0 && cigar_len != seq_len {
+            errors.push(ValidationError::CigarSeqLengthMismatch { cigar_len, seq_len });
+        }
+
+        let qual_offset = self.qname_capacity() + self.cigar_len() * 4 + seq_len.div_ceil(2);
+        let qual_len = (self.inner().l_data.max(0) as usize).saturating_sub(qual_offset);
+        if qual_len < seq_len {
+            errors.push(ValidationError::QualSeqLengthMismatch { qual_len, seq_len });
+        }
+
+        let n_targets = header.target_count();
+        // the length of the contig, or `Err` if the tid is out of range
+        let contig_len = |tid: i32| match tid {
+            -1 => Ok(None),
+            tid if tid < -1 || tid >= n_targets as i32 => Err(()),
+            tid => Ok(header.target_len(tid as u32)),
+        };
+        match contig_len(core.tid) {
+            Err(()) => errors.push(ValidationError::TidOutOfRange {
+                tid: core.tid,
+                n_targets,
+            }),
+            Ok(Some(len)) if core.pos < -1 || core.pos >= len as i64 => {
+                errors.push(ValidationError::PosOutOfRange {
+                    pos: core.pos,
+                    contig_len: len,
+                })
+            }
+            Ok(_) => (),
+        }
+        match contig_len(core.mtid) {
+            Err(()) => errors.push(ValidationError::MtidOutOfRange {
+                mtid: core.mtid,
+                n_targets,
+            }),
+            Ok(Some(len)) if core.mpos < -1 || core.mpos >= len as i64 => {
+                errors.push(ValidationError::MposOutOfRange {
+                    mpos: core.mpos,
+                    contig_len: len,
+                })
+            }
+            Ok(_) => (),
+        }
+
+        let flags = u32::from(core.flag);
+        let has = |flag: u32| flags & flag != 0;
+        if !has(htslib::BAM_FUNMAP) && (core.tid < 0 || core.pos < 0) {
+            errors.push(ValidationError::MappedWithoutPosition {
+                tid: core.tid,
+                pos: core.pos,
+            });
+        }
+        let mate_flags = htslib::BAM_FPROPER_PAIR
+            | htslib::BAM_FMUNMAP
+            | htslib::BAM_FMREVERSE
+            | htslib::BAM_FREAD1
+            | htslib::BAM_FREAD2;
+        if has(htslib::BAM_FPAIRED) {
+            if !has(htslib::BAM_FMUNMAP) && (core.mtid < 0 || core.mpos < 0) {
+                errors.push(ValidationError::MateMappedWithoutPosition {
+                    mtid: core.mtid,
+                    mpos: core.mpos,
+                });
+            }
+        } else if has(mate_flags) {
+            errors.push(ValidationError::MateFlagsWithoutPaired { flags: core.flag });
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     /// Look up an auxiliary field by its tag.
     ///
     /// Only the first two bytes of a given tag are used for the look-up of a field.
-    /// See [`Aux`] for more details.
+    /// The returned [`Aux`] variant reflects the type the field is stored with, see [`Aux`]
+    /// for more details. A missing field results in `Error::BamAuxTagNotFound`, a corrupt one
+    /// in `Error::BamAuxParsingError` or `Error::BamAuxUnknownType`.
     pub fn aux(&self, tag: &[u8]) -> Result<Aux<'_>> {
+        if tag.len() < 2 {
+            return Err(Error::BamAuxTagNotFound);
+        }
         let c_str = ffi::CString::new(tag).map_err(|_| Error::BamAuxStringError)?;
         let aux = unsafe {
             htslib::bam_aux_get(
@@ -588,6 +935,39 @@ impl Record {
         unsafe { Self::read_aux_field(aux).map(|(aux_field, _length)| aux_field) }
     }
 
+    /// Look up an integer auxiliary field by its tag, widening it to `i64` regardless of
+    /// the integer type it is stored with (`c`, `C`, `s`, `S`, `i` or `I`).
+    ///
+    /// Aligners differ in the integer types they use for the same tag (e.g. `NM`), so this
+    /// is usually preferable to matching on the [`Aux`] variant returned by [`Record::aux`].
+    /// Fields of other types result in `Error::BamAuxTypeMismatch`.
+    pub fn aux_int(&self, tag: &[u8]) -> Result<i64> {
+        match self.aux(tag)? {
+            Aux::I8(v) => Ok(v as i64),
+            Aux::U8(v) => Ok(v as i64),
+            Aux::I16(v) => Ok(v as i64),
+            Aux::U16(v) => Ok(v as i64),
+            Aux::I32(v) => Ok(v as i64),
+            Aux::U32(v) => Ok(v as i64),
+            _ => Err(Error::BamAuxTypeMismatch {
+                tag: String::from_utf8_lossy(&tag[..2]).into_owned(),
+            }),
+        }
+    }
+
+    /// Look up a floating point auxiliary field (`f` or `d`) by its tag, widening it to `f64`.
+    ///
+    /// Fields of other types result in `Error::BamAuxTypeMismatch`.
+    pub fn aux_float(&self, tag: &[u8]) -> Result<f64> {
+        match self.aux(tag)? {
+            Aux::Float(v) => Ok(v as f64),
+            Aux::Double(v) => Ok(v),
+            _ => Err(Error::BamAuxTypeMismatch {
+                tag: String::from_utf8_lossy(&tag[..2]).into_owned(),
+            }),
+        }
+    }
+
     unsafe fn read_aux_field<'a>(aux: *const u8) -> Result<(Aux<'a>, usize)> {
         const TAG_LEN: isize = 2;
         // Used for skipping type identifier
@@ -679,7 +1059,12 @@ impl Record {
             b'Z' | b'H' => {
                 let c_str = ffi::CStr::from_ptr(aux.offset(TYPE_ID_LEN).cast::<c_char>());
                 let rust_str = c_str.to_str().map_err(|_| Error::BamAuxParsingError)?;
-                (Aux::String(rust_str), c_str.to_bytes_with_nul().len())
+                let data = if *aux == b'Z' {
+                    Aux::String(rust_str)
+                } else {
+                    Aux::HexByteArray(rust_str)
+                };
+                (data, c_str.to_bytes_with_nul().len())
             }
             b'B' => {
                 const ARRAY_INNER_TYPE_LEN: isize = 1;
@@ -784,6 +1169,9 @@ impl Record {
     }
 
     /// Add auxiliary data.
+    ///
+    /// Arrays (`B` tags) are added with the `Aux::Array*` variants. They are stored with the
+    /// element type of the variant, so that [`Record::aux`] returns the same variant again.
     pub fn push_aux(&mut self, tag: &[u8], value: Aux<'_>) -> Result<()> {
         // Don't allow pushing aux data when the given tag is already present in the record.
         // `htslib` seems to allow this (for non-array values), which can lead to problems
@@ -1002,7 +1390,9 @@ impl Record {
         }
     }
 
-    // Delete auxiliary tag.
+    /// Delete an auxiliary field by its tag, shrinking the record data accordingly.
+    ///
+    /// Returns `Error::BamAuxTagNotFound` if the record has no such field.
     pub fn remove_aux(&mut self, tag: &[u8]) -> Result<()> {
         let c_str = ffi::CString::new(tag).map_err(|_| Error::BamAuxStringError)?;
         let aux = unsafe {
@@ -1014,14 +1404,22 @@ impl Record {
         unsafe {
             if aux.is_null() {
                 Err(Error::BamAuxTagNotFound)
+            } else if htslib::bam_aux_del(self.inner_ptr_mut(), aux) < 0 {
+                Err(Error::BamAux)
             } else {
-                htslib::bam_aux_del(self.inner_ptr_mut(), aux);
                 Ok(())
             }
         }
     }
 
     /// Access the base modifications associated with this Record through the MM tag.
+    ///
+    /// Each item holds the position within the stored sequence (positions of reverse strand
+    /// reads are converted from the original read orientation used by MM) and an
+    /// `hts_base_mod` with the modification code (an ASCII character, or the negated ChEBI
+    /// identifier), the canonical base, the strand and the ML probability (or `-1` without
+    /// an ML tag). Records without an MM tag yield no modifications.
+    ///
     /// Example:
     /// ```
     ///    use rust_htslib::bam::{Read, Reader, Record};
@@ -1063,6 +1461,246 @@ impl Record {
         BaseModificationsPositionIter::new(self)
     }
 
+    /// Reconstruct the reference sequence covered by the alignment from the `MD` tag and the
+    /// CIGAR, without access to the reference itself.
+    ///
+    /// Matching bases are taken from the read, mismatching and deleted bases from the `MD` tag.
+    /// Soft clips and insertions are not part of the reference and hence skipped. Reference
+    /// skips (`N`) are not described by `MD`, so the bases they cover are left out of the
+    /// returned sequence.
+    ///
+    /// Returns `Error::BamAuxTagNotFound` if the record has no `MD` tag, `Error::BamParseMd` if
+    /// it is malformed and `Error::BamMdCigarMismatch` if it does not fit the CIGAR.
+    pub fn reference_seq_from_md(&self) -> Result<Vec<u8>> {
+        let md = match self.aux(b"MD")? {
+            Aux::String(md) => MdString::try_from(md)?,
+            _ => {
+                return Err(Error::BamParseMd {
+                    msg: "MD tag is not a string".to_owned(),
+                })
+            }
+        };
+        let mismatch = |msg: &str| Error::BamMdCigarMismatch {
+            msg: msg.to_owned(),
+        };
+
+        let seq = self.seq();
+        let mut ops = md.0.iter().peekable();
+        let mut remaining_matches = 0;
+        let mut ref_seq = Vec::new();
+        let mut qpos = 0;
+        for c in &self.cigar() {
+            match *c {
+                Cigar::Match(l) | Cigar::Equal(l) | Cigar::Diff(l) => {
+                    for _ in 0..l {
+                        if qpos >= seq.len() {
+                            return Err(mismatch("CIGAR is longer than sequence"));
+                        }
+                        let base = loop {
+                            if remaining_matches > 0 {
+                                remaining_matches -= 1;
+                                break seq[qpos];
+                            }
+                            match ops.next() {
+                                Some(MdOp::Match(n)) => remaining_matches = *n,
+                                Some(MdOp::Mismatch(base)) => break *base,
+                                Some(MdOp::Deletion(_)) => {
+                                    return Err(mismatch("deletion in MD at aligned CIGAR base"))
+                                }
+                                None => return Err(mismatch("MD is shorter than CIGAR")),
+                            }
+                        };
+                        ref_seq.push(base);
+                        qpos += 1;
+                    }
+                }
+                Cigar::Ins(l) | Cigar::SoftClip(l) => qpos += l as usize,
+                Cigar::Del(l) => {
+                    if remaining_matches != 0 {
+                        return Err(mismatch("match in MD at CIGAR deletion"));
+                    }
+                    while let Some(MdOp::Match(0)) = ops.peek() {
+                        ops.next();
+                    }
+                    match ops.next() {
+                        Some(MdOp::Deletion(bases)) if bases.len() == l as usize => {
+                            ref_seq.extend_from_slice(bases)
+                        }
+                        _ => return Err(mismatch("CIGAR deletion not found in MD")),
+                    }
+                }
+                Cigar::RefSkip(_) | Cigar::HardClip(_) | Cigar::Pad(_) => (),
+            }
+        }
+        if remaining_matches != 0 || ops.any(|op| *op != MdOp::Match(0)) {
+            return Err(mismatch("MD is longer than CIGAR"));
+        }
+
+        Ok(ref_seq)
+    }
+
+    /// Compute the `MD` and `NM` tags of the record from the given reference, like
+    /// `samtools calmd`, replacing existing values. Read bases match the reference regardless
+    /// of case and `=` matches any base, while `N` never matches. `NM` counts mismatches as
+    /// well as inserted and deleted bases.
+    ///
+    /// Returns `Error::BamUnmappedRecord` for unmapped records, `Error::BamRecordWithoutHeader`
+    /// if the record has not been read from a file (use `bam::Reader::fill_md` instead),
+    /// `Error::UnknownSequence` if its contig is not in the reference and
+    /// `Error::InvalidRegion` if the alignment does not fit the contig.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::record::Aux;
+    /// use rust_htslib::bam::{Read, Reader};
+    /// use rust_htslib::faidx;
+    ///
+    /// let reference = faidx::Reader::from_path("test/test_cram.fa").unwrap();
+    /// let mut bam = Reader::from_path("test/test_cram.bam").unwrap();
+    /// let mut record = bam.records().nth(3).unwrap().unwrap();
+    /// record.remove_aux(b"MD").unwrap();
+    /// record.cal_md_nm(&reference).unwrap();
+    /// assert_eq!(record.aux(b"MD").unwrap(), Aux::String("15^T5"));
+    /// ```
+    pub fn cal_md_nm(&mut self, reference: &faidx::Reader) -> Result<()> {
+        let header = self.header.clone().ok_or(Error::BamRecordWithoutHeader)?;
+        let contig = self.mapped_contig_name(&header)?;
+        self.cal_md_nm_on_contig(contig, reference)
+    }
+
+    /// The name of the contig of the record in the given header, or
+    /// `Error::BamUnmappedRecord` if the record is unmapped.
+    pub(super) fn mapped_contig_name<'a>(&self, header: &'a HeaderView) -> Result<&'a str> {
+        if self.is_unmapped() || self.tid() < 0 {
+            return Err(Error::BamUnmappedRecord {
+                qname: String::from_utf8_lossy(self.qname()).into_owned(),
+            });
+        }
+        contig_name_str(header, self.tid())
+    }
+
+    pub(super) fn cal_md_nm_on_contig(
+        &mut self,
+        contig: &str,
+        reference: &faidx::Reader,
+    ) -> Result<()> {
+        let cigar = self.cigar();
+        let (beg, end) = (self.pos().max(0) as usize, cigar.end_pos() as usize);
+        let ref_seq = if end > beg {
+            reference.fetch_seq_range(contig, beg, end)?
+        } else {
+            Vec::new()
+        };
+        if ref_seq.len() < end - beg {
+            return Err(Error::InvalidRegion {
+                region: format!("{}:{}-{}", contig, beg + 1, end),
+            });
+        }
+
+        let seq = self.seq();
+        let (mut md, mut nm, mut matches) = (String::new(), 0, 0);
+        let (mut qpos, mut rpos) = (0, 0);
+        for c in &cigar {
+            match *c {
+                Cigar::Match(l) | Cigar::Equal(l) | Cigar::Diff(l) => {
+                    for _ in 0..l {
+                        let r = ref_seq[rpos].to_ascii_uppercase();
+                        let q = if qpos < seq.len() { seq[qpos] } else { b'N' };
+                        if (q == r || q == b'=') && r != b'N' {
+                            matches += 1;
+                        } else {
+                            md.push_str(&matches.to_string());
+                            md.push(r as char);
+                            matches = 0;
+                            nm += 1;
+                        }
+                        qpos += 1;
+                        rpos += 1;
+                    }
+                }
+                Cigar::Ins(l) => {
+                    nm += l;
+                    qpos += l as usize;
+                }
+                Cigar::SoftClip(l) => qpos += l as usize,
+                Cigar::Del(l) => {
+                    md.push_str(&matches.to_string());
+                    md.push('^');
+                    let deleted = &ref_seq[rpos..rpos + l as usize];
+                    md.extend(deleted.iter().map(|b| b.to_ascii_uppercase() as char));
+                    matches = 0;
+                    nm += l;
+                    rpos += l as usize;
+                }
+                Cigar::RefSkip(l) => rpos += l as usize,
+                Cigar::HardClip(_) | Cigar::Pad(_) => (),
+            }
+        }
+        md.push_str(&matches.to_string());
+        drop(cigar);
+
+        for tag in [b"MD", b"NM"] {
+            match self.remove_aux(tag) {
+                Ok(()) | Err(Error::BamAuxTagNotFound) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        self.push_aux(b"MD", Aux::String(&md))?;
+        self.push_aux(b"NM", Aux::I32(nm as i32))
+    }
+
+    /// Compute base alignment qualities (BAQ) by probabilistic realignment of the record to the
+    /// given reference, like `samtools calmd -r`. With `BaqFlags::APPLY`, the base qualities
+    /// are capped by the BAQ and the differences are stored in the `ZQ` tag, like
+    /// `samtools calmd -Ar`. Otherwise, the qualities are left unchanged and the BAQ is stored
+    /// in the `BQ` tag, and qualities previously adjusted with `APPLY` are reverted.
+    ///
+    /// `ref_seq` is the sequence of the contig of the record, starting at its first base (e.g.
+    /// from `faidx::Reader::fetch_seq_range`), such that `ref_seq[self.pos()]` is the
+    /// reference base at the start of the alignment. It may end anywhere after the end of the
+    /// alignment.
+    ///
+    /// Returns `Ok(false)` without modifying the record if it is unmapped or secondary, has
+    /// no sequence or qualities, has no `M`, `=` or `X` CIGAR operations or a reference skip,
+    /// or if BAQ has already been computed (or applied) and `BaqFlags::REDO` is not given.
+    /// Returns `Error::BamBaq` if the realignment fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bam::record::BaqFlags;
+    /// use rust_htslib::bam::{Read, Reader};
+    /// use rust_htslib::faidx;
+    ///
+    /// let reference = faidx::Reader::from_path("test/test_cram.fa").unwrap();
+    /// let mut bam = Reader::from_path("test/test_cram.bam").unwrap();
+    /// let mut record = bam.records().nth(3).unwrap().unwrap();
+    /// let ref_seq = reference.fetch_seq_range("chr2", 0, 120).unwrap();
+    /// assert!(record.calculate_baq(&ref_seq, BaqFlags::APPLY).unwrap());
+    /// assert!(record.aux(b"ZQ").is_ok());
+    /// ```
+    pub fn calculate_baq(&mut self, ref_seq: &[u8], flags: BaqFlags) -> Result<bool> {
+        if self.is_unmapped() || self.is_secondary() || self.seq_len() == 0 {
+            return Ok(false);
+        }
+        let ret = unsafe {
+            htslib::sam_prob_realn(
+                self.inner_ptr_mut(),
+                ref_seq.as_ptr() as *const c_char,
+                ref_seq.len() as i64,
+                flags.bits(),
+            )
+        };
+        match ret {
+            0 => Ok(true),
+            -1 | -3 => Ok(false),
+            _ => Err(Error::BamBaq {
+                qname: String::from_utf8_lossy(self.qname()).into_owned(),
+            }),
+        }
+    }
+
     /// Infer read pair orientation from record. Returns `SequenceReadPairOrientation::None` if record
     /// is not paired, mates are not mapping to the same contig, or mates start at the
     /// same position.
@@ -1144,6 +1782,16 @@ impl Record {
         unset_supplementary,
         2048u16
     );
+
+    /// Convert the record to a FASTQ entry, restoring the original read for reverse strand
+    /// alignments and appending `/1` or `/2` to the name of paired reads. Missing qualities
+    /// are written as Phred 1, like `samtools fastq`. Use `bam::fastq::Writer` for more
+    /// options.
+    pub fn to_fastq(&self) -> Vec<u8> {
+        let mut fastq = Vec::new();
+        fastq::format_record(self, fastq::Format::Fastq, &[], true, &mut fastq);
+        fastq
+    }
 }
 
 impl Drop for Record {
@@ -1209,6 +1857,78 @@ impl genome::AbstractInterval for Record {
     }
 }
 
+/// Options of `Record::calculate_baq`, combined with `|` (e.g.
+/// `BaqFlags::APPLY | BaqFlags::EXTEND`). The default computes the `BQ` tag with parameters
+/// chosen by read length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BaqFlags(i32);
+
+impl BaqFlags {
+    /// Cap the base qualities by the BAQ, like `samtools calmd -A`.
+    pub const APPLY: BaqFlags = BaqFlags(1);
+    /// Extended BAQ, which is more sensitive but less specific, like `samtools calmd -E`.
+    pub const EXTEND: BaqFlags = BaqFlags(2);
+    /// Recompute BAQ even if a `BQ` tag is present. Records with a `ZQ` tag are never
+    /// recomputed.
+    pub const REDO: BaqFlags = BaqFlags(4);
+    /// Parameters tuned for short Illumina reads.
+    pub const ILLUMINA: BaqFlags = BaqFlags(1 << 3);
+    /// Parameters tuned for long reads; they are also used for reads longer than 1000 bases.
+    pub const PACBIO_CCS: BaqFlags = BaqFlags(2 << 3);
+    pub const PACBIO: BaqFlags = BaqFlags(3 << 3);
+    pub const ONT: BaqFlags = BaqFlags(4 << 3);
+    pub const GENAPSYS: BaqFlags = BaqFlags(5 << 3);
+
+    /// The raw flag bits, as expected by `htslib::sam_prob_realn`.
+    pub fn bits(self) -> i32 {
+        self.0
+    }
+}
+
+impl std::ops::BitOr for BaqFlags {
+    type Output = BaqFlags;
+
+    fn bitor(self, rhs: BaqFlags) -> BaqFlags {
+        BaqFlags(self.0 | rhs.0)
+    }
+}
+
+/// An inconsistency of a record, as detected by `Record::check`.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The number of query bases consumed by the CIGAR (`M`, `I`, `S`, `=` and `X`) differs
+    /// from the length of the sequence. Records without a CIGAR or sequence are not checked.
+    #[error("CIGAR covers {cigar_len} query bases, but the sequence has {seq_len}")]
+    CigarSeqLengthMismatch { cigar_len: usize, seq_len: usize },
+    /// The data of the record is too short to hold one quality per base, e.g. because it has
+    /// been modified via `Record::inner_mut` or `Record::set_data`.
+    #[error("record holds {qual_len} base qualities, but the sequence has {seq_len} bases")]
+    QualSeqLengthMismatch { qual_len: usize, seq_len: usize },
+    /// The target id is neither -1 (unmapped) nor a target of the header.
+    #[error("tid {tid} is not a target of the header with {n_targets} targets")]
+    TidOutOfRange { tid: i32, n_targets: u32 },
+    /// The mate target id is neither -1 (unmapped) nor a target of the header.
+    #[error("mate tid {mtid} is not a target of the header with {n_targets} targets")]
+    MtidOutOfRange { mtid: i32, n_targets: u32 },
+    /// The position is not within the contig of the record.
+    #[error("position {pos} is outside of the contig of length {contig_len}")]
+    PosOutOfRange { pos: i64, contig_len: u64 },
+    /// The mate position is not within the contig of the mate.
+    #[error("mate position {mpos} is outside of the contig of length {contig_len}")]
+    MposOutOfRange { mpos: i64, contig_len: u64 },
+    /// The record is not flagged as unmapped, but has no target id or position.
+    #[error("record is mapped (flag 0x4 not set), but has tid {tid} and position {pos}")]
+    MappedWithoutPosition { tid: i32, pos: i64 },
+    /// The record is paired and its mate is not flagged as unmapped, but it has no mate
+    /// target id or position.
+    #[error("mate is mapped (flag 0x8 not set), but has tid {mtid} and position {mpos}")]
+    MateMappedWithoutPosition { mtid: i32, mpos: i64 },
+    /// The record is not flagged as paired, but has flags that only apply to paired reads
+    /// (proper pair, mate unmapped, mate reverse, first or last in template).
+    #[error("record is not paired (flag 0x1 not set), but has mate flags in {flags:#x}")]
+    MateFlagsWithoutPaired { flags: u16 },
+}
+
 /// Auxiliary record data
 ///
 /// The specification allows a wide range of types to be stored as an auxiliary data field of a BAM record.
@@ -1263,6 +1983,11 @@ impl genome::AbstractInterval for Record {
 ///     panic!("Could not read array data");
 /// }
 /// ```
+///
+/// With the `serde_feature` enabled, `Aux` values can be serialized (arrays as sequences of
+/// their elements). They can not be deserialized, because they borrow from the record they
+/// were read from; serialize the whole [`Record`] instead.
+#[cfg_attr(feature = "serde_feature", derive(Serialize))]
 #[derive(Debug, PartialEq)]
 pub enum Aux<'a> {
     Char(u8),
@@ -1393,6 +2118,19 @@ where
     }
 }
 
+#[cfg(feature = "serde_feature")]
+impl<T> Serialize for AuxArray<'_, T>
+where
+    T: AuxArrayElement + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
 /// Create AuxArrays from slices of allowed target types.
 impl<'a, I, T> From<&'a T> for AuxArray<'a, I>
 where
@@ -1547,6 +2285,19 @@ impl<'a> Iterator for AuxIter<'a> {
             return Some(Err(Error::BamAuxParsingError));
         }
         let tag = &self.aux[..2];
+        // Make sure the whole field lies within the aux data before decoding it,
+        // so that a corrupt field can not make us read out of bounds
+        match aux_field_len(&self.aux[2..]) {
+            Ok(len) if 2 + len <= self.aux.len() => (),
+            Ok(_) => {
+                self.aux = &[];
+                return Some(Err(Error::BamAuxParsingError));
+            }
+            Err(e) => {
+                self.aux = &[];
+                return Some(Err(e));
+            }
+        }
         Some(unsafe {
             let data_ptr = self.aux[2..].as_ptr();
             Record::read_aux_field(data_ptr)
@@ -1563,7 +2314,49 @@ impl<'a> Iterator for AuxIter<'a> {
     }
 }
 
-static DECODE_BASE: &[u8] = b"=ACMGRSVTWYHKDBN";
+/// Return the length in bytes of the aux field value (including its type identifier) at the
+/// start of `data`, without reading beyond the end of `data`.
+fn aux_field_len(data: &[u8]) -> Result<usize> {
+    fn type_size(type_id: u8) -> Option<usize> {
+        match type_id {
+            b'A' | b'c' | b'C' => Some(1),
+            b's' | b'S' => Some(2),
+            b'i' | b'I' | b'f' => Some(4),
+            b'd' => Some(8),
+            _ => None,
+        }
+    }
+
+    let type_id = *data.first().ok_or(Error::BamAuxParsingError)?;
+    match type_id {
+        b'Z' | b'H' => data[1..]
+            .iter()
+            .position(|&c| c == 0)
+            .map(|nul| 1 + nul + 1)
+            .ok_or(Error::BamAuxParsingError),
+        b'B' => {
+            let subtype_size = match data.get(1) {
+                Some(b'c' | b'C' | b's' | b'S' | b'i' | b'I' | b'f') => type_size(data[1]).unwrap(),
+                Some(_) => return Err(Error::BamAuxUnknownType),
+                None => return Err(Error::BamAuxParsingError),
+            };
+            let count = data
+                .get(2..6)
+                .ok_or(Error::BamAuxParsingError)?
+                .read_u32::<LittleEndian>()
+                .map_err(|_| Error::BamAuxParsingError)? as usize;
+            count
+                .checked_mul(subtype_size)
+                .and_then(|size| size.checked_add(6))
+                .ok_or(Error::BamAuxParsingError)
+        }
+        _ => type_size(type_id)
+            .map(|size| 1 + size)
+            .ok_or(Error::BamAuxUnknownType),
+    }
+}
+
+pub(super) static DECODE_BASE: &[u8] = b"=ACMGRSVTWYHKDBN";
 static ENCODE_BASE: [u8; 256] = [
     15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
     15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
@@ -1579,7 +2372,7 @@ static ENCODE_BASE: [u8; 256] = [
 ];
 
 #[inline]
-fn encoded_base(encoded_seq: &[u8], i: usize) -> u8 {
+pub(super) fn encoded_base(encoded_seq: &[u8], i: usize) -> u8 {
     (encoded_seq[i / 2] >> ((!i & 1) << 2)) & 0b1111
 }
 
@@ -1623,7 +2416,21 @@ impl<'a> Seq<'a> {
 
     /// Return decoded sequence. Complexity: O(m) with m being the read length.
     pub fn as_bytes(&self) -> Vec<u8> {
-        (0..self.len()).map(|i| self[i]).collect()
+        self.iter().collect()
+    }
+
+    /// Return an iterator over the decoded bases, without allocating. Complexity: O(1) per base.
+    pub fn iter(&self) -> SeqIter<'a> {
+        SeqIter {
+            seq: *self,
+            index: 0,
+        }
+    }
+
+    /// Return the raw 4-bit encoded sequence, two bases per byte with the first base in the
+    /// high nibble (see the SAM specification). Complexity: O(1).
+    pub fn encoded(&self) -> &'a [u8] {
+        self.encoded
     }
 
     /// Return length (in bases) of the sequence.
@@ -1645,9 +2452,46 @@ impl<'a> ops::Index<usize> for Seq<'a> {
     }
 }
 
+impl<'a> IntoIterator for Seq<'a> {
+    type Item = u8;
+    type IntoIter = SeqIter<'a>;
+
+    fn into_iter(self) -> SeqIter<'a> {
+        self.iter()
+    }
+}
+
 unsafe impl<'a> Send for Seq<'a> {}
 unsafe impl<'a> Sync for Seq<'a> {}
 
+/// Iterator over the decoded bases of a sequence.
+///
+/// This struct is created by the [`Seq::iter`] method.
+pub struct SeqIter<'a> {
+    seq: Seq<'a>,
+    index: usize,
+}
+
+impl<'a> Iterator for SeqIter<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.index >= self.seq.len() {
+            return None;
+        }
+        let base = self.seq[self.index];
+        self.index += 1;
+        Some(base)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.seq.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for SeqIter<'a> {}
+
 #[cfg_attr(feature = "serde_feature", derive(Serialize, Deserialize))]
 #[derive(PartialEq, PartialOrd, Eq, Debug, Clone, Copy, Hash)]
 pub enum Cigar {
@@ -1862,11 +2706,19 @@ impl TryFrom<&[u8]> for CigarString {
             let s = str::from_utf8(&bytes[i..j]).map_err(|_| Error::BamParseCigar {
                 msg: format!("Invalid utf-8 bytes '{:?}'.", &bytes[i..j]),
             })?;
-            let n = s.parse().map_err(|_| Error::BamParseCigar {
+            let n: u32 = s.parse().map_err(|_| Error::BamParseCigar {
                 msg: format!("Unable to parse &str '{:?}' to u32.", s),
             })?;
+            // BAM stores the length in the upper 28 bits of each operation
+            if n >= 1 << 28 {
+                return Err(Error::BamParseCigar {
+                    msg: format!("Length {} of cigar operation is too large.", n),
+                });
+            }
             // get the operation
-            let op = &bytes[j];
+            let op = bytes.get(j).ok_or_else(|| Error::BamParseCigar {
+                msg: "Expected cigar operation [MIDNSHP=X] after length".to_owned(),
+            })?;
             inner.push(match op {
                 b'M' => Cigar::Match(n),
                 b'I' => Cigar::Ins(n),
@@ -1992,19 +2844,38 @@ impl CigarStringView {
 
     /// Get (exclusive) end position of alignment.
     pub fn end_pos(&self) -> i64 {
-        let mut pos = self.pos;
-        for c in self {
-            match c {
+        self.pos + self.ref_len()
+    }
+
+    /// Get the number of reference bases covered by the alignment (`M`, `D`, `N`, `=`, `X`).
+    pub fn ref_len(&self) -> i64 {
+        self.iter()
+            .map(|c| match c {
                 Cigar::Match(l)
                 | Cigar::RefSkip(l)
                 | Cigar::Del(l)
                 | Cigar::Equal(l)
-                | Cigar::Diff(l) => pos += *l as i64,
-                // these don't add to end_pos on reference
-                Cigar::Ins(_) | Cigar::SoftClip(_) | Cigar::HardClip(_) | Cigar::Pad(_) => (),
-            }
-        }
-        pos
+                | Cigar::Diff(l) => *l as i64,
+                // these don't consume the reference
+                Cigar::Ins(_) | Cigar::SoftClip(_) | Cigar::HardClip(_) | Cigar::Pad(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Get the number of read bases consumed by the alignment (`M`, `I`, `S`, `=`, `X`),
+    /// i.e. the length of the stored sequence, which excludes hardclips.
+    pub fn read_len(&self) -> i64 {
+        self.iter()
+            .map(|c| match c {
+                Cigar::Match(l)
+                | Cigar::Ins(l)
+                | Cigar::SoftClip(l)
+                | Cigar::Equal(l)
+                | Cigar::Diff(l) => *l as i64,
+                // these don't consume the read
+                Cigar::Del(_) | Cigar::RefSkip(_) | Cigar::HardClip(_) | Cigar::Pad(_) => 0,
+            })
+            .sum()
     }
 
     /// Get the start position of the alignment (0-based).
@@ -2053,13 +2924,19 @@ impl CigarStringView {
     /// * `include_softclips` - if true, softclips will be considered as matches or mismatches
     /// * `include_dels` - if true, positions within deletions will be considered (first reference matching read position after deletion will be returned)
     ///
+    /// Positions within reference skips (N) are never considered. An error is returned for
+    /// CIGAR strings that violate the SAM specification, e.g. hard clips in between other
+    /// operations.
     pub fn read_pos(
         &self,
         ref_pos: u32,
         include_softclips: bool,
         include_dels: bool,
     ) -> Result<Option<u32>> {
-        let mut rpos = self.pos as u32; // reference position
+        // Reference positions are tracked as i64, since leading softclips can reach beyond the
+        // start of the reference when they are included.
+        let ref_pos = ref_pos as i64;
+        let mut rpos = self.pos; // reference position
         let mut qpos = 0u32; // position within read
         let mut j = 0; // index into cigar operation vector
 
@@ -2082,7 +2959,7 @@ impl CigarStringView {
                         // Alignment starts with softclip and we want to include it in the
                         // projection of the reference position. However, the POS field does not
                         // include the softclip. Hence we have to subtract its length.
-                        rpos = rpos.saturating_sub(*l);
+                        rpos -= *l as i64;
                     }
                     break;
                 },
@@ -2108,8 +2985,8 @@ impl CigarStringView {
             }
         }
 
-        let contains_ref_pos = |cigar_op_start: u32, cigar_op_length: u32| {
-            cigar_op_start <= ref_pos && cigar_op_start + cigar_op_length > ref_pos
+        let contains_ref_pos = |cigar_op_start: i64, cigar_op_length: u32| {
+            cigar_op_start <= ref_pos && cigar_op_start + cigar_op_length as i64 > ref_pos
         };
 
         while rpos <= ref_pos && j < self.len() {
@@ -2118,11 +2995,11 @@ impl CigarStringView {
                 Cigar::Match(l) | Cigar::Diff(l) | Cigar::Equal(l) if contains_ref_pos(rpos, l) => {
                     // difference between desired position and first position of current cigar
                     // operation
-                    qpos += ref_pos - rpos;
+                    qpos += (ref_pos - rpos) as u32;
                     return Ok(Some(qpos));
                 }
                 Cigar::SoftClip(l) if include_softclips && contains_ref_pos(rpos, l) => {
-                    qpos += ref_pos - rpos;
+                    qpos += (ref_pos - rpos) as u32;
                     return Ok(Some(qpos));
                 }
                 Cigar::Del(l) if include_dels && contains_ref_pos(rpos, l) => {
@@ -2131,7 +3008,7 @@ impl CigarStringView {
                 }
                 // for others, just increase pos and qpos as needed
                 Cigar::Match(l) | Cigar::Diff(l) | Cigar::Equal(l) => {
-                    rpos += l;
+                    rpos += l as i64;
                     qpos += l;
                     j += 1;
                 }
@@ -2139,7 +3016,7 @@ impl CigarStringView {
                     qpos += l;
                     j += 1;
                     if include_softclips {
-                        rpos += l;
+                        rpos += l as i64;
                     }
                 }
                 Cigar::Ins(l) => {
@@ -2147,7 +3024,7 @@ impl CigarStringView {
                     j += 1;
                 }
                 Cigar::RefSkip(l) | Cigar::Del(l) => {
-                    rpos += l;
+                    rpos += l as i64;
                     j += 1;
                 }
                 Cigar::Pad(_) => {
@@ -2214,6 +3091,97 @@ impl fmt::Display for CigarStringView {
     }
 }
 
+/// An operation of an `MD` tag, describing the reference bases of an alignment.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum MdOp {
+    /// A stretch of bases matching the reference; may be of length 0.
+    Match(u32),
+    /// A single mismatching base, with the base of the reference.
+    Mismatch(u8),
+    /// Reference bases deleted from the read (`^` followed by the bases).
+    Deletion(Vec<u8>),
+}
+
+/// A parsed `MD` tag.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct MdString(pub Vec<MdOp>);
+
+impl TryFrom<&[u8]> for MdString {
+    type Error = Error;
+
+    /// Parse an `MD` tag value, e.g. `10A5^AC6`.
+    /// # Example
+    /// ```
+    /// use rust_htslib::bam::record::{MdOp, MdString};
+    /// use std::convert::TryFrom;
+    ///
+    /// let md = MdString::try_from("10A5^AC6".as_bytes()).unwrap();
+    /// assert_eq!(
+    ///     md.0,
+    ///     vec![
+    ///         MdOp::Match(10),
+    ///         MdOp::Mismatch(b'A'),
+    ///         MdOp::Match(5),
+    ///         MdOp::Deletion(b"AC".to_vec()),
+    ///         MdOp::Match(6),
+    ///     ]
+    /// );
+    /// ```
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let mut inner = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let mut j = i;
+            match bytes[i] {
+                b'0'..=b'9' => {
+                    while j < bytes.len() && bytes[j].is_ascii_digit() {
+                        j += 1;
+                    }
+                    // only ASCII digits, hence valid utf-8
+                    let n = str::from_utf8(&bytes[i..j]).unwrap().parse().map_err(|_| {
+                        Error::BamParseMd {
+                            msg: format!("match length {:?} is too large", &bytes[i..j]),
+                        }
+                    })?;
+                    inner.push(MdOp::Match(n));
+                }
+                b'^' => {
+                    j += 1;
+                    while j < bytes.len() && bytes[j].is_ascii_alphabetic() {
+                        j += 1;
+                    }
+                    if j == i + 1 {
+                        return Err(Error::BamParseMd {
+                            msg: "expected bases after deletion marker '^'".to_owned(),
+                        });
+                    }
+                    inner.push(MdOp::Deletion(bytes[i + 1..j].to_ascii_uppercase()));
+                }
+                base if base.is_ascii_alphabetic() => {
+                    j += 1;
+                    inner.push(MdOp::Mismatch(base.to_ascii_uppercase()));
+                }
+                other => {
+                    return Err(Error::BamParseMd {
+                        msg: format!("unexpected character {:?}", other as char),
+                    })
+                }
+            }
+            i = j;
+        }
+        Ok(MdString(inner))
+    }
+}
+
+impl TryFrom<&str> for MdString {
+    type Error = Error;
+
+    /// Parse an `MD` tag value given as a string.
+    fn try_from(text: &str) -> Result<Self> {
+        MdString::try_from(text.as_bytes())
+    }
+}
+
 pub struct BaseModificationMetadata {
     pub strand: i32,
     pub implicit: i32,
@@ -2453,6 +3421,66 @@ impl<'a> Iterator for BaseModificationsIter<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Allocator counting the allocations made by the current thread.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|n| n.get())
+    }
+
+    #[test]
+    fn test_seq_iter() {
+        let mut record = Record::new();
+        let seq = b"ACGTNRYacgtn=ACGTA";
+        record.set(b"read", None, seq, &[30; 18]);
+
+        let before = allocations();
+        let mut gc = 0;
+        let mut len = 0;
+        for base in record.seq().iter() {
+            if base == b'G' || base == b'C' {
+                gc += 1;
+            }
+            len += 1;
+        }
+        let encoded_len = record.seq().encoded().len();
+        assert_eq!(allocations(), before);
+
+        assert_eq!(gc, 6);
+        assert_eq!(len, record.seq().len());
+        assert_eq!(encoded_len, 9);
+        assert_eq!(record.seq().encoded()[0], 0x12);
+        assert_eq!(record.seq().iter().len(), 18);
+        assert_eq!(
+            record.seq().iter().collect::<Vec<_>>(),
+            record.seq().as_bytes()
+        );
+        assert_eq!(
+            record.seq().into_iter().collect::<Vec<_>>(),
+            b"ACGTNRYACGTN=ACGTA"
+        );
+    }
 
     #[test]
     fn test_cigar_string() {
@@ -2465,6 +3493,148 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_check() {
+        let header = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:500\n");
+        let valid = || {
+            Record::from_sam(
+                &header,
+                b"read\t99\tchr1\t10\t60\t2S4M\tchr2\t20\t0\tACGTAC\tIIIIII",
+            )
+            .unwrap()
+        };
+        assert_eq!(valid().check(&header), Ok(()));
+        let unmapped = Record::from_sam(&header, b"read\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*");
+        assert_eq!(unmapped.unwrap().check(&header), Ok(()));
+
+        let check = |modify: &dyn Fn(&mut Record)| {
+            let mut record = valid();
+            modify(&mut record);
+            record.check(&header).unwrap_err()
+        };
+        let cigar = CigarString(vec![Cigar::SoftClip(2), Cigar::Match(3)]);
+        assert_eq!(
+            check(&|r| r.set(b"read", Some(&cigar), b"ACGTAC", &[30; 6])),
+            vec![ValidationError::CigarSeqLengthMismatch {
+                cigar_len: 5,
+                seq_len: 6
+            }]
+        );
+        assert_eq!(
+            check(&|r| r.inner_mut().l_data -= 2),
+            vec![ValidationError::QualSeqLengthMismatch {
+                qual_len: 4,
+                seq_len: 6
+            }]
+        );
+        assert_eq!(
+            check(&|r| r.set_tid(2)),
+            vec![ValidationError::TidOutOfRange {
+                tid: 2,
+                n_targets: 2
+            }]
+        );
+        assert_eq!(
+            check(&|r| r.set_mtid(7)),
+            vec![ValidationError::MtidOutOfRange {
+                mtid: 7,
+                n_targets: 2
+            }]
+        );
+        assert_eq!(
+            check(&|r| r.set_pos(1000)),
+            vec![ValidationError::PosOutOfRange {
+                pos: 1000,
+                contig_len: 1000
+            }]
+        );
+        assert_eq!(
+            check(&|r| r.set_mpos(500)),
+            vec![ValidationError::MposOutOfRange {
+                mpos: 500,
+                contig_len: 500
+            }]
+        );
+        assert_eq!(
+            check(&|r| r.set_tid(-1)),
+            vec![ValidationError::MappedWithoutPosition { tid: -1, pos: 9 }]
+        );
+        assert_eq!(
+            check(&|r| {
+                r.set_mtid(-1);
+                r.set_mpos(-1);
+            }),
+            vec![ValidationError::MateMappedWithoutPosition { mtid: -1, mpos: -1 }]
+        );
+        assert_eq!(
+            check(&|r| r.set_flags(0x40)),
+            vec![ValidationError::MateFlagsWithoutPaired { flags: 0x40 }]
+        );
+
+        // all problems are reported
+        let errors = check(&|r| {
+            r.set_flags(0x2);
+            r.set_pos(2000);
+        });
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].to_string(),
+            "position 2000 is outside of the contig of length 1000"
+        );
+    }
+
+    #[test]
+    fn test_cigar_cache_invalidated_by_modification() {
+        let mut record = Record::new();
+        let cigar = CigarString(vec![Cigar::Match(4)]);
+        record.set(b"read", Some(&cigar), b"ACGT", &[30; 4]);
+        record.set_pos(10);
+
+        record.cache_cigar();
+        let snapshot = record.cigar();
+        assert_eq!(record.cigar_cached().unwrap().end_pos(), 14);
+        record.set_pos(20);
+        assert!(record.cigar_cached().is_none());
+        assert_eq!(record.cigar().end_pos(), 24);
+        // the snapshot still describes the old alignment
+        assert_eq!(snapshot.end_pos(), 14);
+
+        record.cache_cigar();
+        let longer = CigarString(vec![Cigar::Match(2), Cigar::Del(3), Cigar::Match(2)]);
+        record.set(b"read", Some(&longer), b"ACGT", &[30; 4]);
+        assert!(record.cigar_cached().is_none());
+        assert_eq!(record.cigar().end_pos(), 27);
+
+        for modify in [
+            |r: &mut Record| r.set_qname(b"other"),
+            |r: &mut Record| r.push_aux(b"NM", Aux::I32(1)).unwrap(),
+            |r: &mut Record| r.remove_aux(b"NM").unwrap(),
+            |r: &mut Record| {
+                let data = r.data().to_vec();
+                r.set_data(&data)
+            },
+            |r: &mut Record| r.inner_mut().core.pos = 30,
+        ] {
+            record.cache_cigar();
+            modify(&mut record);
+            assert!(record.cigar_cached().is_none());
+        }
+        // changing bases or qualities does not affect the alignment
+        record.cache_cigar();
+        record.set_seq(b"TTTT").unwrap();
+        record.set_qual(&[20; 4]).unwrap();
+        assert_eq!(record.cigar_cached().unwrap().end_pos(), 37);
+        assert_eq!(record.cigar_cached().unwrap().to_string(), "2M3D2M");
+
+        // reading another record into it
+        let mut bam = crate::bam::Reader::from_path("test/test.bam").unwrap();
+        crate::bam::Read::read(&mut bam, &mut record)
+            .unwrap()
+            .unwrap();
+        assert!(record.cigar_cached().is_none());
+        assert_eq!(record.cigar().to_string(), "27M1D73M");
+    }
+
     #[test]
     fn test_cigar_string_view_pos() {
         let cigar = CigarString(vec![Cigar::Match(100), Cigar::SoftClip(10)]).into_view(5);
@@ -2497,6 +3667,128 @@ mod tests {
         assert_eq!(cigar2.trailing_softclips(), 10);
     }
 
+    #[test]
+    fn test_cigar_string_view_lengths() {
+        let cigar = CigarString::try_from("5H3S10M2I4D1N3=2X1P6S7H")
+            .unwrap()
+            .into_view(100);
+        assert_eq!(cigar.ref_len(), 10 + 4 + 1 + 3 + 2);
+        assert_eq!(cigar.read_len(), 3 + 10 + 2 + 3 + 2 + 6);
+        assert_eq!(cigar.end_pos(), 120);
+        assert_eq!(cigar.leading_softclips(), 3);
+        assert_eq!(cigar.trailing_softclips(), 6);
+        assert_eq!(cigar.leading_hardclips(), 5);
+        assert_eq!(cigar.trailing_hardclips(), 7);
+
+        // Missing CIGAR ("*")
+        let cigar = CigarString(vec![]).into_view(100);
+        assert_eq!(cigar.ref_len(), 0);
+        assert_eq!(cigar.read_len(), 0);
+        assert_eq!(cigar.end_pos(), 100);
+        assert_eq!(cigar.leading_softclips(), 0);
+        assert_eq!(cigar.trailing_hardclips(), 0);
+
+        // Pure insertion
+        let cigar = CigarString(vec![Cigar::Ins(8)]).into_view(100);
+        assert_eq!(cigar.ref_len(), 0);
+        assert_eq!(cigar.read_len(), 8);
+        assert_eq!(cigar.end_pos(), 100);
+
+        // Clipping only
+        let cigar = CigarString(vec![Cigar::HardClip(2), Cigar::SoftClip(4)]).into_view(100);
+        assert_eq!(cigar.leading_hardclips(), 2);
+        assert_eq!(cigar.leading_softclips(), 4);
+        assert_eq!(cigar.trailing_softclips(), 4);
+        assert_eq!(cigar.trailing_hardclips(), 0);
+        assert_eq!(cigar.read_len(), 4);
+    }
+
+    #[test]
+    fn test_cigar_string_view_lengths_match_htslib() {
+        use crate::bam::{Read, Reader};
+
+        for path in &["test/test.bam", "test/test_spliced_reads.bam"] {
+            let mut bam = Reader::from_path(path).unwrap();
+            for record in bam.records() {
+                let record = record.unwrap();
+                let cigar = record.cigar();
+                let raw_cigar = record.raw_cigar();
+                let (rlen, qlen) = unsafe {
+                    (
+                        htslib::bam_cigar2rlen(raw_cigar.len() as i32, raw_cigar.as_ptr()),
+                        htslib::bam_cigar2qlen(raw_cigar.len() as i32, raw_cigar.as_ptr()),
+                    )
+                };
+                assert_eq!(cigar.ref_len(), rlen);
+                assert_eq!(cigar.read_len(), qlen);
+                if !record.is_unmapped() && !cigar.is_empty() {
+                    assert_eq!(cigar.end_pos(), unsafe {
+                        htslib::bam_endpos(record.inner_ptr())
+                    });
+                }
+                if !cigar.is_empty() {
+                    assert_eq!(cigar.read_len(), record.seq_len() as i64);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cigar_read_pos_softclip_before_reference_start() {
+        // ref:               00 01 02 03
+        // c:    S  S  S  S  S  M  M  M
+        // qpos: 00 01 02 03 04 05 06 07
+        let c = CigarString(vec![Cigar::SoftClip(5), Cigar::Match(3)]).into_view(0);
+        assert_eq!(c.read_pos(0, true, false).unwrap(), Some(5));
+        assert_eq!(c.read_pos(0, false, false).unwrap(), Some(5));
+        let c = CigarString(vec![Cigar::SoftClip(5), Cigar::Match(3)]).into_view(2);
+        assert_eq!(c.read_pos(0, true, false).unwrap(), Some(3));
+        assert_eq!(c.read_pos(0, false, false).unwrap(), None);
+        assert_eq!(c.read_pos(2, true, false).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn test_cigar_read_pos_mixed() {
+        // ref:     10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26
+        // c:  3H 2S M  M  I  I  D  D  M  N  N  N  M  M  S  4H
+        // qpos: 00 01 02 03 04 05       06          07 08 09
+        let c = CigarString::try_from("3H2S2M2I2D1M3N2M1S4H")
+            .unwrap()
+            .into_view(10);
+        let expected = [
+            (8, None, Some(0)),
+            (9, None, Some(1)),
+            (10, Some(2), Some(2)),
+            (11, Some(3), Some(3)),
+            (12, None, None),
+            (13, None, None),
+            (14, Some(6), Some(6)),
+            (15, None, None),
+            (16, None, None),
+            (17, None, None),
+            (18, Some(7), Some(7)),
+            (19, Some(8), Some(8)),
+            (20, None, Some(9)),
+            (21, None, None),
+        ];
+        for (ref_pos, without_softclips, with_softclips) in expected {
+            assert_eq!(
+                c.read_pos(ref_pos, false, false).unwrap(),
+                without_softclips
+            );
+            assert_eq!(c.read_pos(ref_pos, true, false).unwrap(), with_softclips);
+        }
+        // Deletions project to the read position following them
+        assert_eq!(c.read_pos(12, false, true).unwrap(), Some(6));
+        assert_eq!(c.read_pos(13, false, true).unwrap(), Some(6));
+        // Reference skips never do
+        assert_eq!(c.read_pos(16, false, true).unwrap(), None);
+        // Before the start and at or after the end of the alignment
+        assert_eq!(c.read_pos(0, true, true).unwrap(), None);
+        assert_eq!(c.read_pos(c.end_pos() as u32, false, true).unwrap(), None);
+        assert_eq!(c.read_pos(u32::MAX, true, true).unwrap(), None);
+    }
+
     #[test]
     fn test_cigar_read_pos() {
         let vpos = 5; // variant position
@@ -2705,12 +3997,239 @@ mod tests {
         assert_eq!(rec.is_supplementary(), false);
     }
 
+    type FlagAccessors = (fn(&Record) -> bool, fn(&mut Record), fn(&mut Record), u16);
+
+    fn flag_accessors() -> Vec<FlagAccessors> {
+        vec![
+            (
+                Record::is_paired,
+                Record::set_paired,
+                Record::unset_paired,
+                0x1,
+            ),
+            (
+                Record::is_proper_pair,
+                Record::set_proper_pair,
+                Record::unset_proper_pair,
+                0x2,
+            ),
+            (
+                Record::is_unmapped,
+                Record::set_unmapped,
+                Record::unset_unmapped,
+                0x4,
+            ),
+            (
+                Record::is_mate_unmapped,
+                Record::set_mate_unmapped,
+                Record::unset_mate_unmapped,
+                0x8,
+            ),
+            (
+                Record::is_reverse,
+                Record::set_reverse,
+                Record::unset_reverse,
+                0x10,
+            ),
+            (
+                Record::is_mate_reverse,
+                Record::set_mate_reverse,
+                Record::unset_mate_reverse,
+                0x20,
+            ),
+            (
+                Record::is_first_in_template,
+                Record::set_first_in_template,
+                Record::unset_first_in_template,
+                0x40,
+            ),
+            (
+                Record::is_last_in_template,
+                Record::set_last_in_template,
+                Record::unset_last_in_template,
+                0x80,
+            ),
+            (
+                Record::is_secondary,
+                Record::set_secondary,
+                Record::unset_secondary,
+                0x100,
+            ),
+            (
+                Record::is_quality_check_failed,
+                Record::set_quality_check_failed,
+                Record::unset_quality_check_failed,
+                0x200,
+            ),
+            (
+                Record::is_duplicate,
+                Record::set_duplicate,
+                Record::unset_duplicate,
+                0x400,
+            ),
+            (
+                Record::is_supplementary,
+                Record::set_supplementary,
+                Record::unset_supplementary,
+                0x800,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_flag_setters_touch_only_their_bit() {
+        for (get, set, unset, bit) in flag_accessors() {
+            for background in &[0u16, 0xfff] {
+                let mut rec = Record::new();
+                rec.set_flags(*background);
+
+                set(&mut rec);
+                assert!(get(&rec));
+                assert_eq!(rec.flags(), background | bit);
+
+                unset(&mut rec);
+                assert!(!get(&rec));
+                assert_eq!(rec.flags(), background & !bit);
+            }
+        }
+    }
+
+    #[test]
+    fn test_flag_setters_write_read_back() {
+        use crate::bam::{Format, Header, Read, Reader, Writer};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("flags.bam");
+        let mut reader = Reader::from_path("test/test.bam").unwrap();
+        let header = Header::from_template(reader.header());
+        let mut expected = Vec::new();
+        {
+            let mut writer = Writer::from_path(&bampath, &header, Format::Bam).unwrap();
+            let template = reader.records().next().unwrap().unwrap();
+            for (i, (_, set, unset, bit)) in flag_accessors().into_iter().enumerate() {
+                let mut rec = template.clone();
+                if i % 2 == 0 {
+                    set(&mut rec);
+                    expected.push(template.flags() | bit);
+                } else {
+                    unset(&mut rec);
+                    expected.push(template.flags() & !bit);
+                }
+                writer.write(&rec).unwrap();
+            }
+        }
+
+        let flags: Vec<u16> = Reader::from_path(&bampath)
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap().flags())
+            .collect();
+        assert_eq!(flags, expected);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
     #[test]
     fn test_cigar_parse() {
         let cigar = "1S20M1D2I3X1=2H";
         let parsed = CigarString::try_from(cigar).unwrap();
         assert_eq!(parsed.to_string(), cigar);
     }
+
+    #[test]
+    fn test_record_eq_hash() {
+        use crate::bam::Read;
+        use std::collections::HashSet;
+
+        let mut bam = crate::bam::Reader::from_path("test/test.bam").unwrap();
+        let records: Vec<Record> = bam.records().map(|r| r.unwrap()).collect();
+        let read = &records[0];
+
+        // Rebuild the record in a buffer that is larger than needed.
+        let mut rebuilt = Record::new();
+        rebuilt.set(b"a_much_longer_name", None, &[b'A'; 500], &[30; 500]);
+        rebuilt.set(
+            read.qname(),
+            Some(&read.cigar().take()),
+            &read.seq().as_bytes(),
+            read.qual(),
+        );
+        rebuilt.set_tid(read.tid());
+        rebuilt.set_pos(read.pos());
+        rebuilt.set_mapq(read.mapq());
+        rebuilt.set_flags(read.flags());
+        rebuilt.set_mtid(read.mtid());
+        rebuilt.set_mpos(read.mpos());
+        rebuilt.set_insert_size(read.insert_size());
+        for aux in read.aux_iter() {
+            let (tag, value) = aux.unwrap();
+            rebuilt.push_aux(tag, value).unwrap();
+        }
+        assert!(rebuilt.inner().m_data > read.inner().l_data as u32);
+        assert_eq!(&rebuilt, read);
+        assert_eq!(&read.clone(), read);
+
+        let mut set: HashSet<Record> = records.iter().cloned().collect();
+        assert!(!set.insert(rebuilt.clone()));
+        assert_eq!(set.len(), records.len());
+
+        rebuilt.set_mapq(read.mapq() + 1);
+        assert_ne!(&rebuilt, read);
+        assert!(set.insert(rebuilt));
+    }
+
+    #[test]
+    fn test_set_seq_qual_in_place() {
+        let header = HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n");
+        let mut record = Record::from_sam(
+            &header,
+            b"r1\t0\tchr1\t10\t60\t2S3M\t*\t0\t0\tACGTA\tIIIII\tNM:i:1",
+        )
+        .unwrap();
+        let unchanged = record.clone();
+
+        record.set_seq(b"TGCAN").unwrap();
+        record.set_qual(&[2, 12, 23, 37, 40]).unwrap();
+        assert_eq!(record.seq().as_bytes(), b"TGCAN");
+        assert_eq!(record.qual(), &[2, 12, 23, 37, 40]);
+        assert_eq!(record.qname(), b"r1");
+        assert_eq!(record.cigar().to_string(), "2S3M");
+        assert_eq!(record.aux(b"NM").unwrap(), Aux::U8(1));
+
+        assert_eq!(
+            record.set_seq(b"ACGT"),
+            Err(Error::BamReadLengthMismatch {
+                expected: 5,
+                actual: 4
+            })
+        );
+        assert!(record.set_qual(&[30; 6]).is_err());
+        assert_eq!(record.seq().as_bytes(), b"TGCAN");
+
+        // the record is still valid after writing and reading it back
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bampath = tmp.path().join("out.bam");
+        {
+            let header = crate::bam::Header::from_template(&header);
+            let mut writer =
+                crate::bam::Writer::from_path(&bampath, &header, crate::bam::Format::Bam).unwrap();
+            writer.write(&record).unwrap();
+            writer.write(&unchanged).unwrap();
+        }
+        let mut reader = crate::bam::Reader::from_path(&bampath).unwrap();
+        let records: Vec<Record> = crate::bam::Read::records(&mut reader)
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(records, vec![record, unchanged]);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
 }
 
 #[cfg(test)]
@@ -2839,6 +4358,72 @@ mod alignment_cigar_tests {
         assert_eq!(expected_error, result);
     }
 
+    #[test]
+    pub fn test_cigar_parsing_invalid() {
+        for cigar_str in ["10", "10M5", "M", "10M4Q", "268435456M", "4294967296M"] {
+            assert!(
+                matches!(
+                    CigarString::try_from(cigar_str),
+                    Err(Error::BamParseCigar { .. })
+                ),
+                "{}",
+                cigar_str
+            );
+        }
+        assert_eq!(
+            CigarString::try_from("268435455M".as_bytes()),
+            Ok(CigarString(vec![Cigar::Match(268435455)]))
+        );
+    }
+
+    #[test]
+    pub fn test_mate_cigar() {
+        let mut record = Record::new();
+        record.set(b"read", None, b"ACGT", b"IIII");
+        record.set_paired();
+        record.set_mtid(0);
+        record.set_mpos(100);
+        assert_eq!(record.mate_cigar(), Ok(None));
+        assert_eq!(record.mate_end_pos(), Ok(None));
+
+        let mut with_mc = record.clone();
+        with_mc
+            .push_aux(b"MC", Aux::String("5S10M2D3I4M1N2H"))
+            .unwrap();
+        assert_eq!(
+            with_mc.mate_cigar(),
+            Ok(Some(CigarString(vec![
+                Cigar::SoftClip(5),
+                Cigar::Match(10),
+                Cigar::Del(2),
+                Cigar::Ins(3),
+                Cigar::Match(4),
+                Cigar::RefSkip(1),
+                Cigar::HardClip(2),
+            ])))
+        );
+        assert_eq!(with_mc.mate_end_pos(), Ok(Some(117)));
+        with_mc.set_mate_unmapped();
+        assert_eq!(with_mc.mate_end_pos(), Ok(None));
+
+        let mut invalid = record.clone();
+        invalid.push_aux(b"MC", Aux::String("10Q")).unwrap();
+        assert!(matches!(
+            invalid.mate_cigar(),
+            Err(Error::BamParseCigar { .. })
+        ));
+        assert!(invalid.mate_end_pos().is_err());
+
+        let mut wrong_type = record;
+        wrong_type.push_aux(b"MC", Aux::I32(10)).unwrap();
+        assert_eq!(
+            wrong_type.mate_cigar(),
+            Err(Error::BamAuxTypeMismatch {
+                tag: "MC".to_owned()
+            })
+        );
+    }
+
     #[test]
     pub fn test_cigar_parsing() {
         // parsing test cases
@@ -2898,7 +4483,9 @@ mod alignment_cigar_tests {
 
 #[cfg(test)]
 mod basemod_tests {
-    use crate::bam::{Read, Reader};
+    use crate::bam::record::Aux;
+    use crate::bam::{Read, Reader, Record};
+    use crate::errors::Error;
 
     #[test]
     pub fn test_count_recorded() {
@@ -2956,6 +4543,86 @@ mod basemod_tests {
         }
     }
 
+    #[test]
+    pub fn test_mod_iter_modes() {
+        let mut bam = Reader::from_path("test/base_mods/MM-modes.sam").unwrap();
+        let records: Vec<Record> = bam.records().map(|r| r.unwrap()).collect();
+        let calls = |record: &Record| -> Vec<(i32, i32, u8, i32, i32)> {
+            record
+                .basemods_iter()
+                .unwrap()
+                .map(|res| {
+                    let (position, m) = res.unwrap();
+                    (
+                        position,
+                        m.modified_base,
+                        m.canonical_base as u8,
+                        m.strand,
+                        m.qual,
+                    )
+                })
+                .collect()
+        };
+        let m = b'm' as i32;
+        let h = b'h' as i32;
+
+        // MM without ML: probabilities are unknown
+        assert_eq!(
+            calls(&records[0]),
+            vec![(1, m, b'C', 0, -1), (4, m, b'C', 0, -1)]
+        );
+
+        // Explicit ("?") and implicit (".") modes
+        assert_eq!(
+            calls(&records[1]),
+            vec![
+                (1, m, b'C', 0, 200),
+                (3, h, b'C', 0, 180),
+                (4, m, b'C', 0, 50)
+            ]
+        );
+        let mods = records[1].basemods_iter().unwrap();
+        assert_eq!(mods.recorded(), &[m, h]);
+        assert_eq!(mods.query_type(m).unwrap().implicit, 0);
+        assert_eq!(mods.query_type(h).unwrap().implicit, 1);
+        assert_eq!(mods.query_type(h).unwrap().canonical, b'C');
+        assert!(mods.query_type(b'a' as i32).is_err());
+
+        // ChEBI codes are reported as negative numbers
+        assert_eq!(calls(&records[2]), vec![(4, -76792, b'C', 0, 7)]);
+
+        // MM refers to the original orientation of reverse strand reads,
+        // positions are reported for the stored sequence
+        assert_eq!(
+            calls(&records[3]),
+            vec![(3, m, b'C', 0, 50), (6, m, b'C', 0, 200)]
+        );
+    }
+
+    #[test]
+    pub fn test_mod_iter_no_mods() {
+        let mut record = Record::new();
+        record.set(b"read", None, b"ACGT", b"IIII");
+        let mods = record.basemods_iter().unwrap();
+        assert!(mods.recorded().is_empty());
+        assert_eq!(mods.count(), 0);
+        // Malformed MM tags
+        let mut malformed = record.clone();
+        malformed.push_aux(b"MM", Aux::String("Cm,0;")).unwrap();
+        assert!(matches!(
+            malformed.basemods_iter(),
+            Err(Error::BamBaseModificationTagNotFound)
+        ));
+        // MM tag referring to more bases than the read has
+        let mut too_long = record.clone();
+        too_long.push_aux(b"MM", Aux::String("C+m,5;")).unwrap();
+        let results: Vec<_> = too_long.basemods_iter().unwrap().collect();
+        assert!(matches!(
+            results[..],
+            [Err(Error::BamBaseModificationIterationFailed)]
+        ));
+    }
+
     #[test]
     pub fn test_position_iter() {
         let mut bam = Reader::from_path(&"test/base_mods/MM-double.sam").unwrap();
@@ -299,8 +299,18 @@ impl<'de> Deserialize<'de> for Record {
         }
 
         const FIELDS: &[&str] = &[
-            "tid", "pos", "bin", "qual", "l_qname", "flag", "n_cigar", "seq_len", "mtid", "mpos",
-            "isize", "data",
+            "tid",
+            "pos",
+            "bin",
+            "mapq",
+            "qname_len",
+            "flag",
+            "n_cigar",
+            "seq_len",
+            "mtid",
+            "mpos",
+            "isize",
+            "data",
         ];
         deserializer.deserialize_struct("Record", FIELDS, RecordVisitor)
     }
@@ -308,7 +318,7 @@ impl<'de> Deserialize<'de> for Record {
 
 #[cfg(test)]
 mod tests {
-    use crate::bam::record::Record;
+    use crate::bam::record::{Aux, Cigar, CigarString, Record};
     use crate::bam::Read;
     use crate::bam::Reader;
 
@@ -345,4 +355,91 @@ mod tests {
         let decoded: Vec<Record> = serde_json::from_str(&encoded).unwrap();
         assert_eq!(recs, decoded);
     }
+
+    fn long_cigar_record() -> Record {
+        // More CIGAR operations than fit into the BAM CIGAR field, and array aux tags
+        let n_ops = 70_000;
+        let cigar = CigarString(
+            (0..n_ops)
+                .map(|i| {
+                    if i % 2 == 0 {
+                        Cigar::Match(1)
+                    } else {
+                        Cigar::Del(1)
+                    }
+                })
+                .collect(),
+        );
+        let seq_len = n_ops / 2;
+        let seq: Vec<u8> = b"ACGT".iter().cycle().take(seq_len).copied().collect();
+        let qual: Vec<u8> = (0..seq_len).map(|i| (i % 40) as u8).collect();
+
+        let mut record = Record::new();
+        record.set(b"long_cigar", Some(&cigar), &seq, &qual);
+        record.set_tid(0);
+        record.set_pos(100);
+        record
+            .push_aux(b"XA", Aux::ArrayI32((&[-1i32, 0, 70_000][..]).into()))
+            .unwrap();
+        record
+            .push_aux(b"XB", Aux::ArrayFloat((&[0.5f32, 1.5][..]).into()))
+            .unwrap();
+        record.push_aux(b"XC", Aux::String("text")).unwrap();
+        record
+    }
+
+    #[test]
+    fn test_long_cigar_and_array_round_trip() {
+        let record = long_cigar_record();
+
+        let decoded: Record = deserialize(&serialize(&record).unwrap()).unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.cigar().len(), 70_000);
+        assert_eq!(*decoded.cigar(), *record.cigar());
+        assert_eq!(decoded.aux(b"XA").unwrap(), record.aux(b"XA").unwrap());
+        assert_eq!(decoded.aux(b"XB").unwrap(), record.aux(b"XB").unwrap());
+
+        let decoded: Record =
+            serde_json::from_str(&serde_json::to_string(&record).unwrap()).unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.seq().as_bytes(), record.seq().as_bytes());
+        assert_eq!(decoded.qual(), record.qual());
+        assert_eq!(decoded.aux(b"XC").unwrap(), Aux::String("text"));
+    }
+
+    #[test]
+    fn test_cigar_string_round_trip() {
+        let cigar = CigarString(vec![
+            Cigar::SoftClip(2),
+            Cigar::Match(10),
+            Cigar::Ins(1),
+            Cigar::Equal(3),
+            Cigar::Diff(1),
+            Cigar::RefSkip(100),
+            Cigar::Pad(1),
+            Cigar::HardClip(5),
+        ]);
+        let json = serde_json::to_string(&cigar).unwrap();
+        assert_eq!(serde_json::from_str::<CigarString>(&json).unwrap(), cigar);
+        let encoded = serialize(&cigar).unwrap();
+        assert_eq!(deserialize::<CigarString>(&encoded).unwrap(), cigar);
+    }
+
+    #[test]
+    fn test_serialize_aux() {
+        let record = long_cigar_record();
+        assert_eq!(
+            serde_json::to_string(&record.aux(b"XA").unwrap()).unwrap(),
+            r#"{"ArrayI32":[-1,0,70000]}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&record.aux(b"XB").unwrap()).unwrap(),
+            r#"{"ArrayFloat":[0.5,1.5]}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&record.aux(b"XC").unwrap()).unwrap(),
+            r#"{"String":"text"}"#
+        );
+        assert_eq!(serde_json::to_string(&Aux::U8(3)).unwrap(), r#"{"U8":3}"#);
+    }
 }
@@ -0,0 +1,268 @@
+//! Summary statistics over SAM/BAM/CRAM records.
+
+use std::fmt;
+
+use crate::bam;
+use crate::bam::record::Record;
+use crate::errors::Result;
+use crate::htslib;
+
+/// A count that is split by whether reads passed quality control (i.e. do not have the
+/// `BAM_FQCFAIL` flag), as in the two columns of `samtools flagstat`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QcCounts {
+    pub passed: u64,
+    pub failed: u64,
+}
+
+impl QcCounts {
+    fn add(&mut self, qc_fail: bool) {
+        if qc_fail {
+            self.failed += 1;
+        } else {
+            self.passed += 1;
+        }
+    }
+}
+
+/// Flag statistics, counted like `samtools flagstat`.
+///
+/// # Example
+///
+/// ```
+/// use rust_htslib::bam::{self, stats::Flagstat};
+///
+/// let mut bam = bam::Reader::from_path("test/test.bam").unwrap();
+/// let stats = Flagstat::from_reader(&mut bam).unwrap();
+/// assert_eq!(stats.supplementary.passed, 1);
+/// println!("{}", stats);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flagstat {
+    /// All records.
+    pub total: QcCounts,
+    /// Records that are neither secondary nor supplementary.
+    pub primary: QcCounts,
+    pub secondary: QcCounts,
+    pub supplementary: QcCounts,
+    pub duplicates: QcCounts,
+    pub primary_duplicates: QcCounts,
+    pub mapped: QcCounts,
+    pub primary_mapped: QcCounts,
+    /// Primary records that are paired in sequencing. This, and all counts below, only
+    /// consider primary records.
+    pub paired: QcCounts,
+    pub read1: QcCounts,
+    pub read2: QcCounts,
+    /// Mapped records with the `BAM_FPROPER_PAIR` flag.
+    pub properly_paired: QcCounts,
+    /// Mapped records with a mapped mate.
+    pub with_mate_mapped: QcCounts,
+    /// Mapped records with an unmapped mate.
+    pub singletons: QcCounts,
+    /// Mapped records with a mate mapped to a different contig.
+    pub mate_on_different_contig: QcCounts,
+    /// Like `mate_on_different_contig`, but only counting records with a mapping quality of
+    /// at least 5.
+    pub mate_on_different_contig_mapq5: QcCounts,
+}
+
+impl Flagstat {
+    /// Create empty statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compute the statistics over all remaining records of the given reader.
+    pub fn from_reader<R: bam::Read>(reader: &mut R) -> Result<Self> {
+        let mut stats = Self::new();
+        let mut record = Record::new();
+        while let Some(result) = reader.read(&mut record) {
+            result?;
+            stats.update(&record);
+        }
+        Ok(stats)
+    }
+
+    /// Add the given record to the statistics.
+    pub fn update(&mut self, record: &Record) {
+        let flags = u32::from(record.flags());
+        let has = |flag: u32| flags & flag != 0;
+        let qc_fail = has(htslib::BAM_FQCFAIL);
+
+        self.total.add(qc_fail);
+        if has(htslib::BAM_FSECONDARY) {
+            self.secondary.add(qc_fail);
+        } else if has(htslib::BAM_FSUPPLEMENTARY) {
+            self.supplementary.add(qc_fail);
+        } else {
+            self.primary.add(qc_fail);
+            if has(htslib::BAM_FPAIRED) {
+                self.paired.add(qc_fail);
+                if has(htslib::BAM_FPROPER_PAIR) && !has(htslib::BAM_FUNMAP) {
+                    self.properly_paired.add(qc_fail);
+                }
+                if has(htslib::BAM_FREAD1) {
+                    self.read1.add(qc_fail);
+                }
+                if has(htslib::BAM_FREAD2) {
+                    self.read2.add(qc_fail);
+                }
+                if has(htslib::BAM_FMUNMAP) && !has(htslib::BAM_FUNMAP) {
+                    self.singletons.add(qc_fail);
+                }
+                if !has(htslib::BAM_FUNMAP) && !has(htslib::BAM_FMUNMAP) {
+                    self.with_mate_mapped.add(qc_fail);
+                    if record.mtid() != record.tid() {
+                        self.mate_on_different_contig.add(qc_fail);
+                        if record.mapq() >= 5 {
+                            self.mate_on_different_contig_mapq5.add(qc_fail);
+                        }
+                    }
+                }
+            }
+            if !has(htslib::BAM_FUNMAP) {
+                self.primary_mapped.add(qc_fail);
+            }
+            if has(htslib::BAM_FDUP) {
+                self.primary_duplicates.add(qc_fail);
+            }
+        }
+        if !has(htslib::BAM_FUNMAP) {
+            self.mapped.add(qc_fail);
+        }
+        if has(htslib::BAM_FDUP) {
+            self.duplicates.add(qc_fail);
+        }
+    }
+}
+
+fn percent(n: u64, total: u64) -> String {
+    if total == 0 {
+        "N/A".to_owned()
+    } else {
+        format!("{:.2}%", 100.0 * n as f64 / total as f64)
+    }
+}
+
+impl fmt::Display for Flagstat {
+    /// Format the statistics exactly like the default output of `samtools flagstat`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = |f: &mut fmt::Formatter<'_>, counts: &QcCounts, label: &str| {
+            writeln!(f, "{} + {} {}", counts.passed, counts.failed, label)
+        };
+        let line_with_percent =
+            |f: &mut fmt::Formatter<'_>, counts: &QcCounts, of: &QcCounts, label: &str| {
+                writeln!(
+                    f,
+                    "{} + {} {} ({} : {})",
+                    counts.passed,
+                    counts.failed,
+                    label,
+                    percent(counts.passed, of.passed),
+                    percent(counts.failed, of.failed)
+                )
+            };
+
+        line(
+            f,
+            &self.total,
+            "in total (QC-passed reads + QC-failed reads)",
+        )?;
+        line(f, &self.primary, "primary")?;
+        line(f, &self.secondary, "secondary")?;
+        line(f, &self.supplementary, "supplementary")?;
+        line(f, &self.duplicates, "duplicates")?;
+        line(f, &self.primary_duplicates, "primary duplicates")?;
+        line_with_percent(f, &self.mapped, &self.total, "mapped")?;
+        line_with_percent(f, &self.primary_mapped, &self.primary, "primary mapped")?;
+        line(f, &self.paired, "paired in sequencing")?;
+        line(f, &self.read1, "read1")?;
+        line(f, &self.read2, "read2")?;
+        line_with_percent(f, &self.properly_paired, &self.paired, "properly paired")?;
+        line(f, &self.with_mate_mapped, "with itself and mate mapped")?;
+        line_with_percent(f, &self.singletons, &self.paired, "singletons")?;
+        line(
+            f,
+            &self.mate_on_different_contig,
+            "with mate mapped to a different chr",
+        )?;
+        line(
+            f,
+            &self.mate_on_different_contig_mapq5,
+            "with mate mapped to a different chr (mapQ>=5)",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flagstat_test_bam() {
+        let mut bam = bam::Reader::from_path("test/test.bam").unwrap();
+        let stats = Flagstat::from_reader(&mut bam).unwrap();
+        // output of `samtools flagstat test/test.bam`
+        let expected = "6 + 0 in total (QC-passed reads + QC-failed reads)\n\
+            5 + 0 primary\n\
+            0 + 0 secondary\n\
+            1 + 0 supplementary\n\
+            0 + 0 duplicates\n\
+            0 + 0 primary duplicates\n\
+            6 + 0 mapped (100.00% : N/A)\n\
+            5 + 0 primary mapped (100.00% : N/A)\n\
+            0 + 0 paired in sequencing\n\
+            0 + 0 read1\n\
+            0 + 0 read2\n\
+            0 + 0 properly paired (N/A : N/A)\n\
+            0 + 0 with itself and mate mapped\n\
+            0 + 0 singletons (N/A : N/A)\n\
+            0 + 0 with mate mapped to a different chr\n\
+            0 + 0 with mate mapped to a different chr (mapQ>=5)\n";
+        assert_eq!(stats.to_string(), expected);
+    }
+
+    #[test]
+    fn test_flagstat_pairs() {
+        let header = bam::HeaderView::from_bytes(b"@SQ\tSN:chr1\tLN:1000\n@SQ\tSN:chr2\tLN:1000\n");
+        let lines = [
+            // proper pair
+            "a\t99\tchr1\t10\t60\t4M\t=\t20\t14\tACGT\tIIII",
+            "a\t147\tchr1\t20\t60\t4M\t=\t10\t-14\tACGT\tIIII",
+            // mates on different contigs, one with low mapq, QC-failed duplicates
+            "b\t1633\tchr1\t30\t3\t4M\tchr2\t40\t0\tACGT\tIIII",
+            "b\t1681\tchr2\t40\t60\t4M\tchr1\t30\t0\tACGT\tIIII",
+            // singleton and its unmapped mate
+            "c\t73\tchr1\t50\t60\t4M\t=\t50\t0\tACGT\tIIII",
+            "c\t133\tchr1\t50\t0\t*\t=\t50\t0\tACGT\tIIII",
+            // secondary alignment
+            "c\t329\tchr2\t100\t0\t4M\tchr1\t50\t0\tACGT\tIIII",
+        ];
+        let mut stats = Flagstat::new();
+        for line in lines {
+            stats.update(&Record::from_sam(&header, line.as_bytes()).unwrap());
+        }
+
+        let counts = |passed, failed| QcCounts { passed, failed };
+        assert_eq!(stats.total, counts(5, 2));
+        assert_eq!(stats.primary, counts(4, 2));
+        assert_eq!(stats.secondary, counts(1, 0));
+        assert_eq!(stats.duplicates, counts(0, 2));
+        assert_eq!(stats.primary_duplicates, counts(0, 2));
+        assert_eq!(stats.mapped, counts(4, 2));
+        assert_eq!(stats.primary_mapped, counts(3, 2));
+        assert_eq!(stats.paired, counts(4, 2));
+        assert_eq!(stats.read1, counts(2, 1));
+        assert_eq!(stats.read2, counts(2, 1));
+        assert_eq!(stats.properly_paired, counts(2, 0));
+        assert_eq!(stats.with_mate_mapped, counts(2, 2));
+        assert_eq!(stats.singletons, counts(1, 0));
+        assert_eq!(stats.mate_on_different_contig, counts(0, 2));
+        assert_eq!(stats.mate_on_different_contig_mapq5, counts(0, 1));
+
+        let output = stats.to_string();
+        assert!(output.contains("3 + 2 primary mapped (75.00% : 100.00%)\n"));
+        assert!(output.contains("1 + 0 singletons (25.00% : 0.00%)\n"));
+    }
+}
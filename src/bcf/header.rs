@@ -141,7 +141,9 @@ impl Header {
         self
     }
 
-    /// Add a record to the header.
+    /// Add a record to the header. Invalid lines are ignored by htslib; use `try_push_record`
+    /// or the typed `push_info`, `push_format`, `push_filter` and `push_contig` to get an error
+    /// for them.
     ///
     /// # Arguments
     ///
@@ -158,6 +160,114 @@ impl Header {
         self
     }
 
+    /// Add a record to the header, like `push_record`, but return
+    /// `Error::BcfInvalidHeaderRecord` if htslib rejects the line.
+    ///
+    /// # Arguments
+    ///
+    /// - `record` - String representation of the header line
+    pub fn try_push_record(&mut self, record: &[u8]) -> Result<&mut Self> {
+        let invalid = || Error::BcfInvalidHeaderRecord {
+            line: String::from_utf8_lossy(record).into_owned(),
+        };
+        let c_str = ffi::CString::new(record).map_err(|_| invalid())?;
+        // Sync right away, so that the new IDs can be used on records immediately.
+        if unsafe { htslib::bcf_hdr_append(self.inner, c_str.as_ptr()) } != 0
+            || unsafe { htslib::bcf_hdr_sync(self.inner) } != 0
+        {
+            return Err(invalid());
+        }
+        Ok(self)
+    }
+
+    /// Add an `INFO` definition to the header.
+    ///
+    /// # Arguments
+    ///
+    /// - `id` - Name of the `INFO` tag.
+    /// - `number` - Number of values, e.g. `TagLength::Fixed(1)` or `TagLength::AltAlleles`.
+    /// - `ty` - Type of the values.
+    /// - `description` - Description of the tag.
+    pub fn push_info(
+        &mut self,
+        id: &[u8],
+        number: TagLength,
+        ty: TagType,
+        description: &str,
+    ) -> Result<&mut Self> {
+        self.push_typed(b"INFO", id, number, ty, description)
+    }
+
+    /// Add a `FORMAT` definition to the header, with arguments like in `push_info`.
+    pub fn push_format(
+        &mut self,
+        id: &[u8],
+        number: TagLength,
+        ty: TagType,
+        description: &str,
+    ) -> Result<&mut Self> {
+        self.push_typed(b"FORMAT", id, number, ty, description)
+    }
+
+    /// Add a `FILTER` definition to the header.
+    ///
+    /// # Arguments
+    ///
+    /// - `id` - Name of the filter.
+    /// - `description` - Description of the filter.
+    pub fn push_filter(&mut self, id: &[u8], description: &str) -> Result<&mut Self> {
+        let mut line = b"##FILTER=<ID=".to_vec();
+        line.extend_from_slice(id);
+        line.extend_from_slice(b",Description=");
+        push_quoted(&mut line, description);
+        line.push(b'>');
+        self.try_push_record(&line)
+    }
+
+    /// Add a contig definition to the header.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - Name of the contig.
+    /// - `length` - Length of the contig.
+    pub fn push_contig(&mut self, name: &[u8], length: u64) -> Result<&mut Self> {
+        let mut line = b"##contig=<ID=".to_vec();
+        line.extend_from_slice(name);
+        line.extend_from_slice(format!(",length={}>", length).as_bytes());
+        self.try_push_record(&line)
+    }
+
+    fn push_typed(
+        &mut self,
+        key: &[u8],
+        id: &[u8],
+        number: TagLength,
+        ty: TagType,
+        description: &str,
+    ) -> Result<&mut Self> {
+        let number = match number {
+            TagLength::Fixed(n) => n.to_string(),
+            TagLength::AltAlleles => "A".to_owned(),
+            TagLength::Alleles => "R".to_owned(),
+            TagLength::Genotypes => "G".to_owned(),
+            TagLength::Variable => ".".to_owned(),
+        };
+        let ty = match ty {
+            TagType::Flag => "Flag",
+            TagType::Integer => "Integer",
+            TagType::Float => "Float",
+            TagType::String => "String",
+        };
+        let mut line = b"##".to_vec();
+        line.extend_from_slice(key);
+        line.extend_from_slice(b"=<ID=");
+        line.extend_from_slice(id);
+        line.extend_from_slice(format!(",Number={},Type={},Description=", number, ty).as_bytes());
+        push_quoted(&mut line, description);
+        line.push(b'>');
+        self.try_push_record(&line)
+    }
+
     /// Remove a `FILTER` entry from the header.
     ///
     /// # Arguments
@@ -223,6 +333,18 @@ impl Header {
     }
 }
 
+/// Append the given header value in double quotes, escaping quotes and backslashes.
+fn push_quoted(line: &mut Vec<u8>, value: &str) {
+    line.push(b'"');
+    for &c in value.as_bytes() {
+        if c == b'"' || c == b'\\' {
+            line.push(b'\\');
+        }
+        line.push(c);
+    }
+    line.push(b'"');
+}
+
 impl Drop for Header {
     fn drop(&mut self) {
         unsafe { htslib::bcf_hdr_destroy(self.inner) };
@@ -261,6 +383,65 @@ pub enum HeaderRecord {
     Generic { key: String, value: String },
 }
 
+impl HeaderRecord {
+    fn values(&self) -> Option<&LinearMap<String, String>> {
+        match self {
+            HeaderRecord::Filter { values, .. }
+            | HeaderRecord::Info { values, .. }
+            | HeaderRecord::Format { values, .. }
+            | HeaderRecord::Contig { values, .. }
+            | HeaderRecord::Structured { values, .. } => Some(values),
+            HeaderRecord::Generic { .. } => None,
+        }
+    }
+
+    /// The `ID` of a structured header record, e.g. the tag of an `INFO` record.
+    pub fn id(&self) -> Option<&str> {
+        self.values()?.get("ID").map(|id| id.as_str())
+    }
+
+    /// The `Description` of a structured header record, without the enclosing quotes.
+    pub fn description(&self) -> Option<&str> {
+        let description = self.values()?.get("Description")?;
+        let description = description.strip_prefix('"').unwrap_or(description);
+        Some(description.strip_suffix('"').unwrap_or(description))
+    }
+
+    /// The `Type` of an `INFO` or `FORMAT` record. Like in htslib, `Character` values are
+    /// treated as strings. Returns `None` for other records, and for unknown types.
+    pub fn tag_type(&self) -> Option<TagType> {
+        match self {
+            HeaderRecord::Info { values, .. } | HeaderRecord::Format { values, .. } => {
+                match values.get("Type")?.as_str() {
+                    "Flag" => Some(TagType::Flag),
+                    "Integer" => Some(TagType::Integer),
+                    "Float" => Some(TagType::Float),
+                    "String" | "Character" => Some(TagType::String),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The `Number` of an `INFO` or `FORMAT` record. Returns `None` for other records, and
+    /// for invalid numbers.
+    pub fn tag_length(&self) -> Option<TagLength> {
+        match self {
+            HeaderRecord::Info { values, .. } | HeaderRecord::Format { values, .. } => {
+                match values.get("Number")?.as_str() {
+                    "A" => Some(TagLength::AltAlleles),
+                    "R" => Some(TagLength::Alleles),
+                    "G" => Some(TagLength::Genotypes),
+                    "." => Some(TagLength::Variable),
+                    n => n.parse().ok().map(TagLength::Fixed),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct HeaderView {
     pub inner: *mut htslib::bcf_hdr_t,
@@ -302,13 +483,36 @@ impl HeaderView {
         self.inner().n[htslib::BCF_DT_CTG as usize] as u32
     }
 
+    /// Retrieve the name of the contig with the given (internal) chromosome identifier, as
+    /// returned by [`Record::rid`](../record/struct.Record.html#method.rid).
+    /// Returns `Error::BcfUnknownRID` if the header defines no such contig. Note that records
+    /// without a contig have no `rid` (i.e. `Record::rid` returns `None`).
     pub fn rid2name(&self, rid: u32) -> Result<&[u8]> {
-        if rid <= self.contig_count() {
-            unsafe {
-                let dict = self.inner().id[htslib::BCF_DT_CTG as usize];
-                let ptr = (*dict.offset(rid as isize)).key;
-                Ok(ffi::CStr::from_ptr(ptr).to_bytes())
-            }
+        let ptr = self.contig_pair(rid)?.key;
+        if ptr.is_null() {
+            return Err(Error::BcfUnknownRID { rid });
+        }
+        Ok(unsafe { ffi::CStr::from_ptr(ptr).to_bytes() })
+    }
+
+    /// Retrieve the length of the contig with the given (internal) chromosome identifier, as
+    /// given by the `length` field of its `##contig` header line.
+    /// Returns `None` if the header line has no `length`, and `Error::BcfUnknownRID` if the
+    /// header defines no such contig.
+    pub fn contig_length(&self, rid: u32) -> Result<Option<u64>> {
+        let val = self.contig_pair(rid)?.val;
+        if val.is_null() {
+            return Err(Error::BcfUnknownRID { rid });
+        }
+        match unsafe { (*val).info[0] } {
+            0 => Ok(None),
+            length => Ok(Some(length)),
+        }
+    }
+
+    fn contig_pair(&self, rid: u32) -> Result<&htslib::bcf_idpair_t> {
+        if rid < self.contig_count() {
+            Ok(unsafe { &*self.inner().id[htslib::BCF_DT_CTG as usize].offset(rid as isize) })
         } else {
             Err(Error::BcfUnknownRID { rid })
         }
@@ -334,32 +538,38 @@ impl HeaderView {
     /// # Errors
     /// If `name` does not match a chromosome currently in the VCF header, returns [`Error::BcfUnknownContig`]
     pub fn name2rid(&self, name: &[u8]) -> Result<u32> {
-        let c_str = ffi::CString::new(name).unwrap();
+        let unknown_contig = || Error::BcfUnknownContig {
+            contig: String::from_utf8_lossy(name).into_owned(),
+        };
+        let c_str = ffi::CString::new(name).map_err(|_| unknown_contig())?;
         unsafe {
             match htslib::bcf_hdr_id2int(
                 self.inner,
                 htslib::BCF_DT_CTG as i32,
                 c_str.as_ptr() as *mut c_char,
             ) {
-                -1 => Err(Error::BcfUnknownContig {
-                    contig: str::from_utf8(name).unwrap().to_owned(),
-                }),
+                -1 => Err(unknown_contig()),
                 i => Ok(i as u32),
             }
         }
     }
 
+    /// Return the type and length (i.e. `Type` and `Number`) of the given INFO tag.
+    /// Returns `Error::BcfUndefinedTag` if the header does not define the tag as INFO.
     pub fn info_type(&self, tag: &[u8]) -> Result<(TagType, TagLength)> {
         self.tag_type(tag, htslib::BCF_HL_INFO)
     }
 
+    /// Return the type and length (i.e. `Type` and `Number`) of the given FORMAT tag.
+    /// Returns `Error::BcfUndefinedTag` if the header does not define the tag as FORMAT.
     pub fn format_type(&self, tag: &[u8]) -> Result<(TagType, TagLength)> {
         self.tag_type(tag, htslib::BCF_HL_FMT)
     }
 
     fn tag_type(&self, tag: &[u8], hdr_type: ::libc::c_uint) -> Result<(TagType, TagLength)> {
-        let tag_desc = || str::from_utf8(tag).unwrap().to_owned();
-        let c_str_tag = ffi::CString::new(tag).unwrap();
+        let tag_desc = || String::from_utf8_lossy(tag).into_owned();
+        let c_str_tag =
+            ffi::CString::new(tag).map_err(|_| Error::BcfUndefinedTag { tag: tag_desc() })?;
         let (_type, length, num_values) = unsafe {
             let id = htslib::bcf_hdr_id2int(
                 self.inner,
@@ -372,6 +582,10 @@ impl HeaderView {
             let n = (*self.inner).n[htslib::BCF_DT_ID as usize] as usize;
             let entry = slice::from_raw_parts((*self.inner).id[htslib::BCF_DT_ID as usize], n);
             let d = (*entry[id as usize].val).info[hdr_type as usize];
+            if d & 0xf == 0xf {
+                // the ID is only defined for another header line type
+                return Err(Error::BcfUndefinedTag { tag: tag_desc() });
+            }
             (d >> 4 & 0xf, d >> 8 & 0xf, d >> 12)
         };
         let _type = match _type as ::libc::c_uint {
@@ -411,6 +625,25 @@ impl HeaderView {
         }
     }
 
+    /// Convert a `FILTER` name to its numeric identifier. Unlike `name_to_id`, this fails for
+    /// names that are only defined by other header lines (e.g. `INFO`), and accepts `.` for
+    /// `PASS`.
+    pub fn filter_to_id(&self, name: &[u8]) -> Result<Id> {
+        let name = if name == b"." { b"PASS" } else { name };
+        let id = self.name_to_id(name)?;
+        let flt = unsafe {
+            let entry = (*self.inner).id[htslib::BCF_DT_ID as usize].add(*id as usize);
+            (*(*entry).val).info[htslib::BCF_HL_FLT as usize]
+        };
+        if flt & 0xf == 0xf {
+            Err(Error::BcfUnknownID {
+                id: str::from_utf8(name).unwrap().to_owned(),
+            })
+        } else {
+            Ok(id)
+        }
+    }
+
     /// Convert integer representing an identifier (e.g., a `FILTER` value) to its string
     /// name.bam.
     pub fn id_to_name(&self, id: Id) -> Vec<u8> {
@@ -0,0 +1,200 @@
+//! Module for building indices of BCF or BGZF compressed VCF files, like `bcftools index`.
+//!
+//! # Example
+//!
+//! ```
+//! use rust_htslib::bcf::{self, Read};
+//!
+//! let tmp = tempfile::Builder::new().suffix(".bcf").tempfile().unwrap();
+//! {
+//!     let mut reader = bcf::Reader::from_path("test/test.bcf").unwrap();
+//!     let header = bcf::Header::from_template(reader.header());
+//!     let mut writer = bcf::Writer::from_path(tmp.path(), &header, false, bcf::Format::Bcf).unwrap();
+//!     for record in reader.records() {
+//!         writer.write(&record.unwrap()).unwrap();
+//!     }
+//! }
+//! bcf::index::build(tmp.path(), None, 14, 1).unwrap();
+//!
+//! let mut reader = bcf::IndexedReader::from_path(tmp.path()).unwrap();
+//! reader.fetch_str(b"1:10034-10061").unwrap();
+//! assert_eq!(reader.records().count(), 28);
+//! ```
+
+use std::path::Path;
+use std::ptr;
+
+use crate::bcf::{self, Read};
+use crate::errors::{Error, Result};
+use crate::htslib;
+use crate::utils;
+
+/// Build an index for the given BCF or BGZF compressed VCF file.
+///
+/// With a `min_shift` of 0, a `.tbi` index is written (only supported for VCF), otherwise a
+/// `.csi` index with the given minimum shift (14 is the usual choice, as used by
+/// `bcftools index`). If `idx_path` is `None`, the index is written next to `path`.
+///
+/// Returns `Error::FileNotFound` if the file does not exist, `Error::BcfNotIndexable` if it is
+/// an uncompressed VCF (or not a variant file at all), and, if htslib fails to index it,
+/// `Error::BcfUnsorted` if it is not sorted by position, or the read error (e.g.
+/// `Error::BcfTruncatedRecord`) if it is truncated or corrupt.
+pub fn build<P: AsRef<Path>>(
+    path: P,
+    idx_path: Option<P>,
+    min_shift: u32,
+    n_threads: u32,
+) -> Result<()> {
+    let path = path.as_ref();
+    let target = path.to_string_lossy().into_owned();
+    if !path.exists() {
+        return Err(Error::FileNotFound {
+            path: path.to_owned(),
+        });
+    }
+    let idx_path_cstr;
+    let idx_path_ptr = if let Some(p) = idx_path {
+        idx_path_cstr = utils::path_to_cstring(&p).ok_or(Error::NonUnicodePath)?;
+        idx_path_cstr.as_ptr()
+    } else {
+        ptr::null()
+    };
+    let ret = unsafe {
+        htslib::bcf_index_build3(
+            utils::path_to_cstring(&path)
+                .ok_or(Error::NonUnicodePath)?
+                .as_ptr(),
+            idx_path_ptr,
+            min_shift as i32,
+            n_threads as i32,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        -2 => Err(Error::BcfOpen { target }),
+        -3 => Err(Error::BcfNotIndexable { target }),
+        -4 => Err(Error::BcfWriteIndex { target }),
+        _ => Err(diagnose(path).unwrap_or(Error::BcfBuildIndex { target })),
+    }
+}
+
+/// Find out why indexing the given file failed.
+fn diagnose(path: &Path) -> Option<Error> {
+    let mut reader = match bcf::Reader::from_path(path) {
+        Ok(reader) => reader,
+        Err(e) => return Some(e),
+    };
+    let mut record = reader.empty_record();
+    let unsorted = utils::find_unsorted(|| {
+        let res = reader.read(&mut record)?;
+        Some(res.map(|()| Some((record.rid(), record.pos()))))
+    });
+    match unsorted {
+        // the offending record is the last one read
+        Ok(Some(_)) => Some(Error::BcfUnsorted {
+            target: path.to_string_lossy().into_owned(),
+            record: record.desc(),
+        }),
+        Ok(None) => None,
+        Err(e) => Some(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcf::{Format, IndexedReader, Record};
+    use crate::utils::fixtures::{bcf_records, write_bcf};
+
+    fn test_records() -> Vec<Record> {
+        bcf_records("test/test.bcf")
+    }
+
+    #[test]
+    fn test_build_bcf_and_vcf() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let records = test_records();
+        let last = records.last().unwrap();
+        let contig = last
+            .header()
+            .rid2name(last.rid().unwrap())
+            .unwrap()
+            .to_owned();
+
+        for (name, format, min_shift, idx_name) in [
+            ("test.bcf", Format::Bcf, 14, "test.bcf.csi"),
+            ("test.vcf.gz", Format::Vcf, 0, "test.vcf.gz.tbi"),
+            ("test_csi.vcf.gz", Format::Vcf, 14, "test_csi.vcf.gz.csi"),
+        ] {
+            let path = tmp.path().join(name);
+            write_bcf(&path, &records, format);
+            build(&path, None, min_shift, 1).unwrap();
+            assert!(tmp.path().join(idx_name).exists());
+
+            let mut reader = IndexedReader::from_path(&path).unwrap();
+            let rid = reader.header().name2rid(&contig).unwrap();
+            reader
+                .fetch(rid, last.pos() as u64, Some(last.pos() as u64))
+                .unwrap();
+            let fetched: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+            assert!(!fetched.is_empty());
+            assert!(fetched.iter().all(|r| r.pos() == last.pos()));
+            assert_eq!(fetched.last().unwrap().alleles(), last.alleles());
+        }
+
+        // explicit index path, with two threads
+        let path = tmp.path().join("test.bcf");
+        let idx_path = tmp.path().join("other.csi");
+        build(&path, Some(&idx_path), 14, 2).unwrap();
+        assert!(idx_path.exists());
+
+        // TBI indices cannot be built for BCF
+        assert_eq!(
+            build(&path, None, 0, 1),
+            Err(Error::BcfBuildIndex {
+                target: path.to_string_lossy().into_owned()
+            })
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let mut records = test_records();
+
+        let missing = tmp.path().join("missing.bcf");
+        assert_eq!(
+            build(&missing, None, 14, 1),
+            Err(Error::FileNotFound { path: missing })
+        );
+
+        let plain = Path::new("test/test_string.vcf");
+        assert_eq!(
+            build(plain, None, 0, 1),
+            Err(Error::BcfNotIndexable {
+                target: "test/test_string.vcf".to_owned()
+            })
+        );
+
+        let unsorted = tmp.path().join("unsorted.bcf");
+        records.swap(3, 4);
+        write_bcf(&unsorted, &records, Format::Bcf);
+        assert_eq!(
+            build(&unsorted, None, 14, 1),
+            Err(Error::BcfUnsorted {
+                target: unsorted.to_string_lossy().into_owned(),
+                record: records[4].desc(),
+            })
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+}
@@ -60,20 +60,21 @@
 //! # Example (writing)
 //!
 //!   - Setting up a VCF writer from scratch (including a simple header)
-//!   - Creating a VCF record and writing it to the VCF file
+//!   - Creating a two-sample SNV record and writing it to the VCF file
 //!
 //! ```
 //! use rust_htslib::bcf::{Format, Writer};
 //! use rust_htslib::bcf::header::Header;
 //! use rust_htslib::bcf::record::GenotypeAllele;
 //!
-//! // Create minimal VCF header with a single contig and a single sample
+//! // Create minimal VCF header with a single contig and two samples
 //! let mut header = Header::new();
 //! let header_contig_line = r#"##contig=<ID=1,length=10>"#;
 //! header.push_record(header_contig_line.as_bytes());
 //! let header_gt_line = r#"##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">"#;
 //! header.push_record(header_gt_line.as_bytes());
-//! header.push_sample("test_sample".as_bytes());
+//! header.push_sample("sample1".as_bytes());
+//! header.push_sample("sample2".as_bytes());
 //!
 //! // Write uncompressed VCF to stdout with above header and get an empty record
 //! let mut vcf = Writer::from_stdout(&header, true, Format::Vcf).unwrap();
@@ -83,13 +84,23 @@
 //! let rid = vcf.header().name2rid(b"1").unwrap();
 //! record.set_rid(Some(rid));
 //! record.set_pos(6);
+//! record.set_alleles(&[b"A", b"T"]).unwrap();
+//! record.set_qual(30.0);
 //!
-//! // Set record genotype to 0|1 - note first allele is always unphased
-//! let alleles = &[GenotypeAllele::Unphased(0), GenotypeAllele::Phased(1)];
+//! // Set the genotypes to 0|1 and 1/1 - note first allele is always unphased
+//! let alleles = &[
+//!     GenotypeAllele::Unphased(0),
+//!     GenotypeAllele::Phased(1),
+//!     GenotypeAllele::Unphased(1),
+//!     GenotypeAllele::Unphased(1),
+//! ];
 //! record.push_genotypes(alleles).unwrap();
 //!
 //! // Write record
-//! vcf.write(&record).unwrap()
+//! vcf.write(&record).unwrap();
+//!
+//! // Reuse the record for the next one
+//! record.clear();
 //! ```
 //!
 //! This will print the following VCF to stdout:
@@ -99,11 +110,14 @@
 //! ##FILTER=<ID=PASS,Description="All filters passed">
 //! ##contig=<ID=1,length=10>
 //! ##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">
-//! #CHROM  POS     ID      REF     ALT     QUAL    FILTER  INFO    FORMAT  test_sample
-//! 1       7       .       .       .       0       .       .       GT      0|1
+//! #CHROM  POS     ID      REF     ALT     QUAL    FILTER  INFO    FORMAT  sample1 sample2
+//! 1       7       .       A       T       30      .       .       GT      0|1     1/1
 //! ```
 
+use std::borrow::Borrow;
+use std::collections::VecDeque;
 use std::ffi;
+use std::io;
 use std::path::Path;
 use std::rc::Rc;
 use std::str;
@@ -112,11 +126,17 @@ use url::Url;
 
 pub mod buffer;
 pub mod header;
+pub mod index;
 pub mod record;
 
+#[cfg(feature = "serde_feature")]
+pub mod record_serde;
+
 use crate::bcf::header::{HeaderView, SampleSubset};
 use crate::errors::{Error, Result};
 use crate::htslib;
+use crate::tpool::ThreadPool;
+use crate::utils::{self, contig_not_found, parse_region, path_as_bytes, HTS_POS_MAX};
 
 pub use crate::bcf::header::{Header, HeaderRecord};
 pub use crate::bcf::record::Record;
@@ -130,10 +150,14 @@ pub trait Read: Sized {
     ///
     /// # Returns
     /// None if end of file was reached, otherwise Some will contain
-    /// a result with an error in case of failure.
+    /// a result with an error in case of failure: `Error::BcfTruncatedRecord` if the file
+    /// ends in the middle of a record or is missing its BGZF EOF marker, and
+    /// `Error::BcfInvalidRecord` if the record cannot be parsed.
     fn read(&mut self, record: &mut record::Record) -> Option<Result<()>>;
 
-    /// Return an iterator over all records of the VCF/BCF file.
+    /// Return an iterator over all records of the VCF/BCF file. Each record is newly
+    /// allocated; use `read` to reuse a single record instead. Like `read`, the iterator
+    /// yields an error for a truncated file before it ends.
     fn records(&mut self) -> Records<'_, Self>;
 
     /// Return the header.
@@ -159,6 +183,9 @@ pub trait Read: Sized {
 pub struct Reader {
     inner: *mut htslib::htsFile,
     header: Rc<HeaderView>,
+    tpool: Option<ThreadPool>,
+    /// Whether the end of the file has been reached and its EOF marker checked.
+    eof_checked: bool,
 }
 
 unsafe impl Send for Reader {}
@@ -176,6 +203,20 @@ pub unsafe fn set_threads(hts_file: *mut htslib::htsFile, n_threads: usize) -> R
     }
 }
 
+/// # Safety
+///
+/// Implementation for `Reader::set_thread_pool()`, `IndexedReader::set_thread_pool()` and
+/// `Writer::set_thread_pool()`.
+unsafe fn set_thread_pool(hts_file: *mut htslib::htsFile, tpool: &ThreadPool) -> Result<()> {
+    let mut b = tpool.handle.borrow_mut();
+
+    if htslib::hts_set_thread_pool(hts_file, &mut b.inner as *mut _) != 0 {
+        Err(Error::ThreadPool)
+    } else {
+        Ok(())
+    }
+}
+
 impl Reader {
     /// Create a new reader from a given path.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
@@ -191,8 +232,13 @@ impl Reader {
         Self::new(url.as_str().as_bytes())
     }
 
-    /// Create a new reader from standard input.
+    /// Create a new reader from standard input, e.g. for `bcftools view ... | mytool`.
+    /// VCF (plain or bgzipped) and BCF input is detected automatically. Returns
+    /// `Error::StdinIsTerminal` instead of waiting for input if STDIN is a terminal.
     pub fn from_stdin() -> Result<Self> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+            return Err(Error::StdinIsTerminal);
+        }
         Self::new(b"-")
     }
 
@@ -202,12 +248,68 @@ impl Reader {
         Ok(Reader {
             inner: htsfile,
             header: Rc::new(HeaderView::new(header)),
+            tpool: None,
+            eof_checked: false,
         })
     }
+
+    /// Use a shared thread-pool for reading. This permits controlling the total
+    /// thread count when multiple readers and writers are working simultaneously.
+    /// A thread pool can be created with `crate::tpool::ThreadPool::new(n_threads)`
+    ///
+    /// # Arguments
+    ///
+    /// * `tpool` - thread pool to use for decompression work.
+    pub fn set_thread_pool(&mut self, tpool: &ThreadPool) -> Result<()> {
+        unsafe { set_thread_pool(self.inner, tpool)? }
+        self.tpool = Some(tpool.clone());
+        Ok(())
+    }
+
+    /// Only read the FORMAT values of the given samples (or of all samples except the given
+    /// ones, if `exclude` is true), like `bcftools view -s`. This is much faster than
+    /// subsetting records after reading them, since the values of the other samples are not
+    /// decoded. Afterwards, the header only lists the selected samples, in their original
+    /// order, so a `Writer` created from it writes the subset. Has to be called before
+    /// reading any records.
+    ///
+    /// Returns `Error::BcfUnknownSample` for sample names that are not in the header.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - names of the samples to select.
+    /// * `exclude` - whether to select all samples except the given ones.
+    pub fn set_samples(&mut self, samples: &[&[u8]], exclude: bool) -> Result<()> {
+        for &sample in samples {
+            // htslib takes a comma-separated list of names
+            if self.header.sample_id(sample).is_none() || sample.contains(&b',') {
+                return Err(Error::BcfUnknownSample {
+                    name: String::from_utf8_lossy(sample).into_owned(),
+                });
+            }
+        }
+        let mut list = if exclude { b"^".to_vec() } else { Vec::new() };
+        list.extend_from_slice(&samples.join(&b","[..]));
+        let list = ffi::CString::new(list).unwrap();
+        if unsafe { htslib::bcf_hdr_set_samples(self.header.inner, list.as_ptr(), 0) } != 0 {
+            Err(Error::BcfSetSamples)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Reader {
+    /// Whether reading the underlying BGZF stream failed, e.g. because the file is truncated.
+    fn bgzf_failed(&self) -> bool {
+        let bgzf = unsafe { htslib::hts_get_bgzfp(self.inner) };
+        !bgzf.is_null() && unsafe { (*bgzf).errcode() } != 0
+    }
 }
 
 impl Read for Reader {
     fn read(&mut self, record: &mut record::Record) -> Option<Result<()>> {
+        record.inner_mut().errcode = 0;
         match unsafe { htslib::bcf_read(self.inner, self.header.inner, record.inner) } {
             0 => {
                 unsafe {
@@ -217,13 +319,31 @@ impl Read for Reader {
                 record.set_header(Rc::clone(&self.header));
                 Some(Ok(()))
             }
-            -1 => None,
+            -1 => {
+                // A file cut at a BGZF block boundary reads like a complete one, except for
+                // the missing EOF marker.
+                if !self.eof_checked {
+                    self.eof_checked = true;
+                    if unsafe { htslib::hts_check_EOF(self.inner) } == 0 {
+                        return Some(Err(Error::BcfTruncatedRecord));
+                    }
+                }
+                None
+            }
+            // Records failing validation are flagged, records cut short are not. A compressed
+            // VCF cut short leaves the BGZF stream in an error state instead.
+            -2 if record.inner().errcode == 0
+                && (unsafe { (*self.inner).format.format } == htslib::htsExactFormat_bcf
+                    || self.bgzf_failed()) =>
+            {
+                Some(Err(Error::BcfTruncatedRecord))
+            }
             _ => Some(Err(Error::BcfInvalidRecord)),
         }
     }
 
     fn records(&mut self) -> Records<'_, Self> {
-        Records { reader: self }
+        Records::new(self)
     }
 
     fn set_threads(&mut self, n_threads: usize) -> Result<()> {
@@ -248,6 +368,27 @@ impl Drop for Reader {
     }
 }
 
+/// Number of records per contig as stored in a CSI or TBI index, see
+/// `IndexedReader::index_stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexStats {
+    /// Contig ID (`rid`) and number of records of each contig of the header.
+    pub contigs: Vec<(u32, Option<u64>)>,
+    /// Number of records without coordinates.
+    pub no_coordinate: u64,
+}
+
+impl IndexStats {
+    /// Total number of records, or `None` if the count of a contig is unknown.
+    pub fn total(&self) -> Option<u64> {
+        self.contigs
+            .iter()
+            .map(|(_, count)| *count)
+            .sum::<Option<u64>>()
+            .map(|n| n + self.no_coordinate)
+    }
+}
+
 /// An indexed VCF/BCF reader.
 #[derive(Debug)]
 pub struct IndexedReader {
@@ -258,6 +399,13 @@ pub struct IndexedReader {
 
     /// The position of the previous fetch, if any.
     current_region: Option<(u32, u64, Option<u64>)>,
+    /// The remaining regions of `fetch_multiple`.
+    pending_regions: VecDeque<(u32, u64, u64)>,
+    /// Records before this position have already been yielded for a previous region.
+    skip_before: Option<(u32, u64)>,
+    /// Whether all fetched regions have been read.
+    exhausted: bool,
+    tpool: Option<ThreadPool>,
 }
 
 unsafe impl Send for IndexedReader {}
@@ -279,6 +427,29 @@ impl IndexedReader {
         }
     }
 
+    /// Create a new `IndexedReader` from path, using the index at `index_path` instead of
+    /// looking for a `.csi` or `.tbi` index next to the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to open.
+    /// * `index_path` - the path of the index.
+    pub fn from_path_and_index<P: AsRef<Path>>(path: P, index_path: P) -> Result<Self> {
+        let (path, index_path) = (path.as_ref(), index_path.as_ref());
+        for p in [path, index_path] {
+            if !p.exists() {
+                return Err(Error::FileNotFound { path: p.into() });
+            }
+        }
+        match (path.to_str(), index_path.to_str()) {
+            (Some(p), Some(idx)) => Self::new(
+                &ffi::CString::new(format!("{}##idx##{}", p, idx))
+                    .map_err(|_| Error::NonUnicodePath)?,
+            ),
+            _ => Err(Error::NonUnicodePath),
+        }
+    }
+
     /// Create a new `IndexedReader` from an URL.
     pub fn from_url(url: &Url) -> Result<Self> {
         Self::new(&ffi::CString::new(url.as_str()).unwrap())
@@ -296,7 +467,7 @@ impl IndexedReader {
             htslib::bcf_sr_set_opt(ser_reader, 0);
         } // 0: BCF_SR_REQUIRE_IDX
           // Attach a file with the path from the arguments.
-        if unsafe { htslib::bcf_sr_add_reader(ser_reader, path.as_ptr()) } >= 0 {
+        if unsafe { htslib::bcf_sr_add_reader(ser_reader, path.as_ptr()) } != 0 {
             let header = Rc::new(HeaderView::new(unsafe {
                 htslib::bcf_hdr_dup((*(*ser_reader).readers.offset(0)).header)
             }));
@@ -304,11 +475,20 @@ impl IndexedReader {
                 inner: ser_reader,
                 header,
                 current_region: None,
+                pending_regions: VecDeque::new(),
+                skip_before: None,
+                exhausted: false,
+                tpool: None,
             })
         } else {
-            Err(Error::BcfOpen {
-                target: path.to_str().unwrap().to_owned(),
-            })
+            let target = path.to_str().unwrap().to_owned();
+            let errnum = unsafe { (*ser_reader).errnum };
+            unsafe { htslib::bcf_sr_destroy(ser_reader) };
+            if errnum == htslib::bcf_sr_error_idx_load_failed {
+                Err(Error::BcfInvalidIndex { target })
+            } else {
+                Err(Error::BcfOpen { target })
+            }
         }
     }
 
@@ -325,6 +505,133 @@ impl IndexedReader {
     /// # Note
     /// The entire contig can be fetched by setting `start` to `0` and `end` to `None`.
     pub fn fetch(&mut self, rid: u32, start: u64, end: Option<u64>) -> Result<()> {
+        self.pending_regions.clear();
+        self.skip_before = None;
+        self.exhausted = false;
+        self.seek(rid, start, end)
+    }
+
+    /// Fetch the records overlapping any of the given regions, as `(rid, start, end)` with
+    /// `0`-based, half-open coordinates (like in BED files). Regions are sorted by contig (in
+    /// the order of the header) and position, overlapping and adjacent regions are merged,
+    /// and each record is yielded once, even if it overlaps several regions. An empty list of
+    /// regions yields no records.
+    ///
+    /// Returns `Error::BcfUnknownRID` if a `rid` is not in the header.
+    pub fn fetch_multiple(&mut self, regions: &[(u32, u64, u64)]) -> Result<()> {
+        for &(rid, _, _) in regions {
+            self.header.rid2name(rid)?;
+        }
+        let mut sorted: Vec<_> = regions
+            .iter()
+            .copied()
+            .filter(|&(_, start, end)| end > start)
+            .collect();
+        sorted.sort_unstable();
+        let mut merged: VecDeque<(u32, u64, u64)> = VecDeque::with_capacity(sorted.len());
+        for (rid, start, end) in sorted {
+            match merged.back_mut() {
+                Some(last) if last.0 == rid && start <= last.2 => last.2 = last.2.max(end),
+                _ => merged.push_back((rid, start, end)),
+            }
+        }
+        self.current_region = None;
+        self.skip_before = None;
+        self.exhausted = merged.is_empty();
+        self.pending_regions = merged;
+        self.next_region().unwrap_or(Ok(()))
+    }
+
+    /// Fetch the records overlapping any of the intervals of the given BED file (plain or
+    /// compressed), like `fetch_multiple`. Empty lines and header lines (starting with `#`,
+    /// `track` or `browser`) are ignored.
+    ///
+    /// Intervals on contigs that are not in the header are skipped instead of failing the
+    /// whole fetch, and a warning is returned for each of them. Returns
+    /// `Error::BedInvalidLine` if a line does not start with a contig name and valid start
+    /// and end coordinates.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bcf::{IndexedReader, Read};
+    ///
+    /// let dir = tempfile::tempdir().unwrap();
+    /// let bed = dir.path().join("panel.bed");
+    /// std::fs::write(&bed, "1\t10033\t10050\n1\t10040\t10061\nchrX\t0\t100\n").unwrap();
+    ///
+    /// let mut bcf = IndexedReader::from_path("test/test.bcf").unwrap();
+    /// let warnings = bcf.fetch_regions_from_bed(&bed).unwrap();
+    /// assert_eq!(warnings.len(), 1);
+    /// assert_eq!(bcf.records().count(), 28);
+    /// ```
+    pub fn fetch_regions_from_bed<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<String>> {
+        let path = path.as_ref();
+        let target = path.to_string_lossy().into_owned();
+        let reader = crate::bgzf::Reader::from_path(path)?;
+        let mut regions = Vec::new();
+        let mut warnings = Vec::new();
+        for (i, line) in io::BufRead::split(reader, b'\n').enumerate() {
+            let line = line.map_err(|_| Error::BedRead {
+                target: target.clone(),
+            })?;
+            let line_number = i as u64 + 1;
+            if line.iter().all(u8::is_ascii_whitespace)
+                || line.starts_with(b"#")
+                || line.starts_with(b"track")
+                || line.starts_with(b"browser")
+            {
+                continue;
+            }
+            let invalid = || Error::BedInvalidLine {
+                target: target.clone(),
+                line: line_number,
+            };
+            let mut fields = line
+                .split(|c| c.is_ascii_whitespace())
+                .filter(|field| !field.is_empty());
+            let contig = fields.next().ok_or_else(invalid)?;
+            let mut coordinate = || -> Result<u64> {
+                let field = fields.next().ok_or_else(invalid)?;
+                str::from_utf8(field)
+                    .ok()
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(invalid)
+            };
+            let (start, end) = (coordinate()?, coordinate()?);
+            if end < start {
+                return Err(invalid());
+            }
+            match self.header.name2rid(contig) {
+                Ok(rid) => regions.push((rid, start, end)),
+                Err(_) => warnings.push(format!(
+                    "skipping line {} of {}: contig {} is not in the header",
+                    line_number,
+                    target,
+                    String::from_utf8_lossy(contig)
+                )),
+            }
+        }
+        self.fetch_multiple(&regions)?;
+        Ok(warnings)
+    }
+
+    /// Seek to the next region of `fetch_multiple`, or return `None` if there is none left.
+    fn next_region(&mut self) -> Option<Result<()>> {
+        let (rid, start, end) = match self.pending_regions.pop_front() {
+            Some(region) => region,
+            None => {
+                self.exhausted = true;
+                return None;
+            }
+        };
+        self.skip_before = self
+            .current_region
+            .and_then(|(rid, _, end)| end.map(|end| (rid, end + 1)));
+        Some(self.seek(rid, start, Some(end - 1)))
+    }
+
+    fn seek(&mut self, rid: u32, start: u64, end: Option<u64>) -> Result<()> {
         let contig = self.header.rid2name(rid)?;
         let contig = ffi::CString::new(contig).unwrap();
         if unsafe { htslib::bcf_sr_seek(self.inner, contig.as_ptr(), start as i64) } != 0 {
@@ -337,58 +644,152 @@ impl IndexedReader {
             Ok(())
         }
     }
+
+    /// Fetch the records overlapping the `0`-based, half-open region `[start, end)` of the contig
+    /// with the given name, so `u64::MAX` as `end` fetches everything from `start` onwards.
+    /// Returns `Error::ContigNotFound`, which lists some contigs of the header, if there is no
+    /// contig with this name.
+    pub fn fetch_by_name(&mut self, name: &[u8], start: u64, end: u64) -> Result<()> {
+        let rid = match self.header.name2rid(name) {
+            Ok(rid) => rid,
+            Err(_) => {
+                let header = &self.header;
+                let names = (0..header.contig_count()).filter_map(|rid| header.rid2name(rid).ok());
+                return Err(contig_not_found(name, names));
+            }
+        };
+        self.fetch_multiple(&[(rid, start, end)])
+    }
+
+    /// Jump to the region given by a samtools style region string such as `chr1:1000-2000`
+    /// (1-based, inclusive). `chr1` jumps to the whole contig and `chr1:1000` to everything
+    /// from position 1000 onwards. Contig names containing colons can be given as
+    /// `{chr1:A}:1-100`.
+    pub fn fetch_str(&mut self, region: &[u8]) -> Result<()> {
+        let header = Rc::clone(&self.header);
+        let (rid, start, end) = parse_region(region, |name| {
+            header.name2rid(name).ok().map(|rid| rid as i32)
+        })?;
+        let end = if end == HTS_POS_MAX {
+            None
+        } else {
+            Some(end as u64 - 1)
+        };
+        self.fetch(rid as u32, start as u64, end)
+    }
+
+    /// Return the number of records of each contig of the header as stored in the CSI or TBI
+    /// index, without reading the file. The count of a contig is `None` if the index does not
+    /// store it, which is the case for TBI files written by old versions of tabix.
+    pub fn index_stats(&self) -> IndexStats {
+        let reader = unsafe { &*(*self.inner).readers.offset(0) };
+        let idx = if reader.bcf_idx.is_null() {
+            unsafe { (*reader.tbx_idx).idx }
+        } else {
+            reader.bcf_idx
+        };
+        let contigs = (0..self.header.contig_count())
+            .map(|rid| {
+                let tid = if reader.bcf_idx.is_null() {
+                    // TBI indices have their own numbering of the contigs
+                    match self.header.rid2name(rid).map(ffi::CString::new) {
+                        Ok(Ok(name)) => unsafe {
+                            htslib::tbx_name2id(reader.tbx_idx, name.as_ptr())
+                        },
+                        _ => -1,
+                    }
+                } else {
+                    rid as i32
+                };
+                (rid, unsafe { utils::index_record_count(idx, tid) })
+            })
+            .collect();
+        IndexStats {
+            contigs,
+            no_coordinate: unsafe { htslib::hts_idx_get_n_no_coor(idx) },
+        }
+    }
+
+    /// Use a shared thread-pool for reading, like `Reader::set_thread_pool`. This is an
+    /// alternative to `set_threads`, and must not be combined with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpool` - thread pool to use for decompression work.
+    pub fn set_thread_pool(&mut self, tpool: &ThreadPool) -> Result<()> {
+        unsafe { set_thread_pool((*(*self.inner).readers.offset(0)).file, tpool)? }
+        self.tpool = Some(tpool.clone());
+        Ok(())
+    }
 }
 
 impl Read for IndexedReader {
     fn read(&mut self, record: &mut record::Record) -> Option<Result<()>> {
-        match unsafe { htslib::bcf_sr_next_line(self.inner) } {
-            0 => {
-                if unsafe { (*self.inner).errnum } != 0 {
-                    Some(Err(Error::BcfInvalidRecord))
-                } else {
-                    None
-                }
-            }
-            i => {
-                assert!(i > 0, "Must not be negative");
-                // Note that the sync BCF reader has a different interface than the others
-                // as it keeps its own buffer already for each record.  An alternative here
-                // would be to replace the `inner` value by an enum that can be a pointer
-                // into a synced reader or an owning popinter to an allocated record.
-                unsafe {
-                    htslib::bcf_copy(
-                        record.inner,
-                        *(*(*self.inner).readers.offset(0)).buffer.offset(0),
-                    );
-                }
-
-                unsafe {
-                    // Always unpack record.
-                    htslib::bcf_unpack(record.inner_mut(), htslib::BCF_UN_ALL as i32);
-                }
+        if self.exhausted {
+            return None;
+        }
+        loop {
+            match unsafe { htslib::bcf_sr_next_line(self.inner) } {
+                0 => match unsafe { (*self.inner).errnum } {
+                    0 => match self.next_region()? {
+                        Ok(()) => continue,
+                        Err(e) => return Some(Err(e)),
+                    },
+                    htslib::bcf_sr_error_bcf_read_error => {
+                        return Some(Err(Error::BcfTruncatedRecord))
+                    }
+                    _ => return Some(Err(Error::BcfInvalidRecord)),
+                },
+                i => {
+                    assert!(i > 0, "Must not be negative");
+                    // Note that the sync BCF reader has a different interface than the others
+                    // as it keeps its own buffer already for each record.  An alternative here
+                    // would be to replace the `inner` value by an enum that can be a pointer
+                    // into a synced reader or an owning popinter to an allocated record.
+                    unsafe {
+                        htslib::bcf_copy(
+                            record.inner,
+                            *(*(*self.inner).readers.offset(0)).buffer.offset(0),
+                        );
+                    }
 
-                record.set_header(Rc::clone(&self.header));
+                    unsafe {
+                        // Always unpack record.
+                        htslib::bcf_unpack(record.inner_mut(), htslib::BCF_UN_ALL as i32);
+                    }
 
-                match self.current_region {
-                    Some((rid, _start, end)) => {
-                        let endpos = match end {
-                            Some(e) => e,
-                            None => u64::MAX,
-                        };
-                        if Some(rid) == record.rid() && record.pos() as u64 <= endpos {
-                            Some(Ok(()))
-                        } else {
-                            None
+                    record.set_header(Rc::clone(&self.header));
+
+                    match self.current_region {
+                        Some((rid, _start, end)) => {
+                            let endpos = match end {
+                                Some(e) => e,
+                                None => u64::MAX,
+                            };
+                            let pos = record.pos() as u64;
+                            if Some(rid) == record.rid() && pos <= endpos {
+                                if let Some((skip_rid, skip_end)) = self.skip_before {
+                                    if skip_rid == rid && pos < skip_end {
+                                        continue;
+                                    }
+                                }
+                                return Some(Ok(()));
+                            }
+                            // past the current region, continue with the next one (if any)
+                            match self.next_region()? {
+                                Ok(()) => continue,
+                                Err(e) => return Some(Err(e)),
+                            }
                         }
+                        None => return Some(Ok(())),
                     }
-                    None => Some(Ok(())),
                 }
             }
         }
     }
 
     fn records(&mut self) -> Records<'_, Self> {
-        Records { reader: self }
+        Records::new(self)
     }
 
     fn set_threads(&mut self, n_threads: usize) -> Result<()> {
@@ -487,6 +888,43 @@ pub mod synced {
             }
         }
 
+        /// Restrict the traversal to the given regions, a comma-separated list of samtools
+        /// style regions such as `chr1:1000-2000,chr2` (1-based, inclusive). This requires
+        /// indices for all readers.
+        pub fn set_regions(&mut self, regions: &str) -> Result<()> {
+            let c_regions = ffi::CString::new(regions).map_err(|_| Error::InvalidRegion {
+                region: regions.to_owned(),
+            })?;
+            self.set_regions_impl(&c_regions, false, regions)
+        }
+
+        /// Restrict the traversal to the regions in the given file, either a BED file (0-based,
+        /// half-open, detected by the `.bed` or `.bed.gz` extension) or a tab-separated file
+        /// with contig, 1-based start and end position (inclusive) columns. This requires
+        /// indices for all readers.
+        pub fn set_regions_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+            let c_path = ffi::CString::new(path_as_bytes(&path, true)?).unwrap();
+            self.set_regions_impl(&c_path, true, &path.as_ref().to_string_lossy())
+        }
+
+        fn set_regions_impl(
+            &mut self,
+            regions: &ffi::CStr,
+            is_file: bool,
+            desc: &str,
+        ) -> Result<()> {
+            let ret = unsafe {
+                crate::htslib::bcf_sr_set_regions(self.inner, regions.as_ptr(), is_file as i32)
+            };
+            if ret != 0 || unsafe { (*self.inner).regions.is_null() } {
+                return Err(Error::InvalidRegion {
+                    region: desc.to_owned(),
+                });
+            }
+            self.current_region = None;
+            Ok(())
+        }
+
         /// Add new reader with the path to the file.
         pub fn add_reader<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
             match path.as_ref().to_str() {
@@ -589,6 +1027,14 @@ pub mod synced {
             }
         }
 
+        /// Iterator over the loci of all readers. For each locus, the record of each reader is
+        /// given in the order the readers were added, or `None` if the reader does not have
+        /// the locus (with the alleles required by `set_pairing`). Each record uses the header
+        /// of its reader.
+        pub fn records(&mut self) -> SyncedRecords<'_> {
+            SyncedRecords { reader: self }
+        }
+
         /// Return header from the given reader.
         pub fn header(&self, idx: u32) -> &HeaderView {
             // TODO: is the mutability here correct?
@@ -624,6 +1070,26 @@ pub mod synced {
         }
     }
 
+    /// Iterator over the loci of a `SyncedReader`, see `SyncedReader::records`.
+    #[derive(Debug)]
+    pub struct SyncedRecords<'a> {
+        reader: &'a mut SyncedReader,
+    }
+
+    impl<'a> Iterator for SyncedRecords<'a> {
+        type Item = Result<Vec<Option<Record>>>;
+
+        fn next(&mut self) -> Option<Result<Vec<Option<Record>>>> {
+            match self.reader.read_next() {
+                Ok(0) => None,
+                Ok(_) => Some(Ok((0..self.reader.reader_count())
+                    .map(|idx| self.reader.record(idx))
+                    .collect())),
+                Err(e) => Some(Err(e)),
+            }
+        }
+    }
+
     impl Drop for SyncedReader {
         fn drop(&mut self) {
             unsafe { crate::htslib::bcf_sr_destroy(self.inner) };
@@ -631,18 +1097,44 @@ pub mod synced {
     }
 }
 
+/// Output format of a `Writer`.
+///
+/// Together with the `uncompressed` flag of the `Writer` constructors, this selects one of
+/// the htslib output modes: plain VCF text (`"w"`), bgzipped VCF, e.g. for tabix (`"wz"`),
+/// compressed BCF (`"wb"`) and uncompressed BCF, e.g. for piping into other tools (`"wbu"`,
+/// which htslib writes as BGZF with compression level 0).
 #[derive(Clone, Copy, Debug)]
 pub enum Format {
     Vcf,
     Bcf,
 }
 
+impl Format {
+    /// The format and whether it is uncompressed, as indicated by the extension of the given
+    /// path (`.vcf`, `.vcf.gz`, `.vcf.bgz` or `.bcf`, case-insensitive), or `None` if the
+    /// extension is unknown. For `.bcf`, the compression is left open (`None`), since
+    /// uncompressed BCF is still BGZF.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<(Self, Option<bool>)> {
+        let name = path.as_ref().file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".vcf") {
+            Some((Format::Vcf, Some(true)))
+        } else if name.ends_with(".vcf.gz") || name.ends_with(".vcf.bgz") {
+            Some((Format::Vcf, Some(false)))
+        } else if name.ends_with(".bcf") {
+            Some((Format::Bcf, None))
+        } else {
+            None
+        }
+    }
+}
+
 /// A VCF/BCF writer.
 #[derive(Debug)]
 pub struct Writer {
     inner: *mut htslib::htsFile,
     header: Rc<HeaderView>,
     subset: Option<SampleSubset>,
+    tpool: Option<ThreadPool>,
 }
 
 unsafe impl Send for Writer {}
@@ -650,17 +1142,44 @@ unsafe impl Send for Writer {}
 impl Writer {
     /// Create a new writer that writes to the given path.
     ///
+    /// The format is inferred from the extension of the path: `.vcf` is written as plain
+    /// VCF, `.vcf.gz` and `.vcf.bgz` as BGZF-compressed VCF, and `.bcf` as BCF. The given
+    /// `uncompressed` and `format` are used for other extensions, and `uncompressed` also
+    /// for `.bcf`. Use `from_path_with_format` to enforce a format regardless of the extension.
+    ///
     /// # Arguments
     ///
     /// * `path` - the path
     /// * `header` - header definition to use
-    /// * `uncompressed` - disable compression
-    /// * `vcf` - write VCF instead of BCF
+    /// * `uncompressed` - disable compression (BGZF for VCF) if not given by the extension
+    /// * `format` - whether to write VCF or BCF if the extension is unknown, see `Format`
     pub fn from_path<P: AsRef<Path>>(
         path: P,
         header: &Header,
         uncompressed: bool,
         format: Format,
+    ) -> Result<Self> {
+        let (format, uncompressed) = match Format::from_extension(&path) {
+            Some((format, inferred)) => (format, inferred.unwrap_or(uncompressed)),
+            None => (format, uncompressed),
+        };
+        Self::from_path_with_format(path, header, uncompressed, format)
+    }
+
+    /// Create a new writer that writes to the given path in the given format, regardless of
+    /// the extension of the path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path
+    /// * `header` - header definition to use
+    /// * `uncompressed` - disable compression (BGZF for VCF)
+    /// * `format` - whether to write VCF or BCF, see `Format`
+    pub fn from_path_with_format<P: AsRef<Path>>(
+        path: P,
+        header: &Header,
+        uncompressed: bool,
+        format: Format,
     ) -> Result<Self> {
         if let Some(p) = path.as_ref().to_str() {
             Ok(Self::new(p.as_bytes(), header, uncompressed, format)?)
@@ -675,8 +1194,8 @@ impl Writer {
     ///
     /// * `url` - the URL
     /// * `header` - header definition to use
-    /// * `uncompressed` - disable compression
-    /// * `vcf` - write VCF instead of BCF
+    /// * `uncompressed` - disable compression (BGZF for VCF)
+    /// * `format` - whether to write VCF or BCF, see `Format`
     pub fn from_url(
         url: &Url,
         header: &Header,
@@ -686,13 +1205,14 @@ impl Writer {
         Self::new(url.as_str().as_bytes(), header, uncompressed, format)
     }
 
-    /// Create a new writer to stdout.
+    /// Create a new writer to stdout, e.g. for `mytool | bcftools sort`. Uncompressed BCF is
+    /// the fastest format for piping into other tools.
     ///
     /// # Arguments
     ///
     /// * `header` - header definition to use
-    /// * `uncompressed` - disable compression
-    /// * `vcf` - write VCF instead of BCF
+    /// * `uncompressed` - disable compression (BGZF for VCF)
+    /// * `format` - whether to write VCF or BCF, see `Format`
     pub fn from_stdout(header: &Header, uncompressed: bool, format: Format) -> Result<Self> {
         Self::new(b"-", header, uncompressed, format)
     }
@@ -713,6 +1233,7 @@ impl Writer {
                 htslib::bcf_hdr_dup(header.inner)
             })),
             subset: header.subset.clone(),
+            tpool: None,
         })
     }
 
@@ -730,6 +1251,9 @@ impl Writer {
 
     /// Translate record to header of this writer.
     ///
+    /// Contigs and tags that are missing in the header of this writer are not detected, and
+    /// lead to corrupt output. Use [`try_translate`](#method.try_translate) to check for them.
+    ///
     /// # Arguments
     ///
     /// - `record` - The `Record` to translate.
@@ -740,6 +1264,68 @@ impl Writer {
         record.set_header(Rc::clone(&self.header));
     }
 
+    /// Translate record to header of this writer, like `translate`, after checking that the
+    /// header defines its contig, filters and tags. This is required whenever records are
+    /// written with a different header than the one they were read with, e.g. one with
+    /// additional lines, or with contigs in a different order.
+    ///
+    /// Returns `Error::BcfUnknownContig`, `Error::BcfUnknownID` (for filters), or
+    /// `Error::BcfUndefinedTag` (for INFO and FORMAT tags) if the header of this writer lacks
+    /// them, `Error::BcfUndefinedTag` as well if it defines an INFO or FORMAT tag with another
+    /// `Type` or `Number`, and `Error::BcfInvalidRecord` if the record could not be parsed.
+    /// The record is left unchanged in this case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bcf::{Format, Header, Read, Reader, Writer};
+    ///
+    /// let mut reader = Reader::from_path("test/test_string.vcf").unwrap();
+    /// // add an INFO tag and only keep the second sample
+    /// let mut header = Header::from_template_subset(reader.header(), &[b"two"]).unwrap();
+    /// header.push_record(br#"##INFO=<ID=NEW,Number=1,Type=Integer,Description="New">"#);
+    /// let mut writer = Writer::from_stdout(&header, true, Format::Vcf).unwrap();
+    /// for record in reader.records() {
+    ///     let mut record = record.unwrap();
+    ///     writer.try_translate(&mut record).unwrap();
+    ///     writer.subset(&mut record);
+    ///     record.push_info_integer(b"NEW", &[1]).unwrap();
+    ///     writer.write(&record).unwrap();
+    /// }
+    /// ```
+    pub fn try_translate(&mut self, record: &mut record::Record) -> Result<()> {
+        if record.inner().errcode != 0 {
+            return Err(Error::BcfInvalidRecord);
+        }
+        record.unpack();
+        let src = record.header();
+        if let Some(rid) = record.rid() {
+            self.header.name2rid(src.rid2name(rid)?)?;
+        }
+        for id in record.filters() {
+            self.header.filter_to_id(&src.id_to_name(id))?;
+        }
+        // the values of a tag are stored according to its type and length, which thus have to
+        // be the same in both headers
+        let mismatch = |tag: &[u8]| Error::BcfUndefinedTag {
+            tag: String::from_utf8_lossy(tag).into_owned(),
+        };
+        for id in record.info_ids() {
+            let tag = src.id_to_name(id);
+            if self.header.info_type(&tag)? != src.info_type(&tag)? {
+                return Err(mismatch(&tag));
+            }
+        }
+        for id in record.format_ids() {
+            let tag = src.id_to_name(id);
+            if self.header.format_type(&tag)? != src.format_type(&tag)? {
+                return Err(mismatch(&tag));
+            }
+        }
+        self.translate(record);
+        Ok(())
+    }
+
     /// Subset samples of record to match header of this writer.
     ///
     /// # Arguments
@@ -760,17 +1346,61 @@ impl Writer {
 
     /// Write `record` to the Writer.
     ///
+    /// Returns `Error::BcfWriteRecord` with the position of the record if htslib fails to
+    /// write it (e.g. because the disk is full, or the record does not match the header).
+    /// Since output is buffered, failures to write to disk may only be detected by a later
+    /// write, or by `flush` or `close`.
+    ///
     /// # Arguments
     ///
     /// - `record` - The `Record` to write.
     pub fn write(&mut self, record: &record::Record) -> Result<()> {
-        if unsafe { htslib::bcf_write(self.inner, self.header.inner, record.inner) } == -1 {
-            Err(Error::WriteRecord)
+        if unsafe { htslib::bcf_write(self.inner, self.header.inner, record.inner) } < 0 {
+            Err(Error::BcfWriteRecord {
+                record: record.desc(),
+            })
         } else {
             Ok(())
         }
     }
 
+    /// Write all given records, returning how many have been written. Writing stops at the
+    /// first error, which is returned as `Error::WriteRecordAt` with the 0-based index of the
+    /// failing record.
+    ///
+    /// # Arguments
+    ///
+    /// - `records` - The records (or references to them) to write.
+    pub fn write_all<I>(&mut self, records: I) -> Result<usize>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<record::Record>,
+    {
+        let mut n = 0;
+        for record in records {
+            self.write(record.borrow())
+                .map_err(|e| Error::WriteRecordAt {
+                    index: n,
+                    source: Box::new(e),
+                })?;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Flush buffered records to the file. Returns `Error::FileFlush` if this fails.
+    pub fn flush(&mut self) -> Result<()> {
+        unsafe { utils::flush(self.inner) }
+    }
+
+    /// Write the remaining buffered records and close the file. Returns `Error::FileClose` if
+    /// this fails, e.g. because the disk is full. Dropping the writer closes the file as well,
+    /// but has to ignore such failures.
+    pub fn close(mut self) -> Result<()> {
+        let inner = std::mem::replace(&mut self.inner, std::ptr::null_mut());
+        unsafe { utils::close(inner) }
+    }
+
     /// Activate multi-threaded BCF write support in htslib. This should permit faster
     /// writing of large BCF files.
     ///
@@ -780,12 +1410,27 @@ impl Writer {
     pub fn set_threads(&mut self, n_threads: usize) -> Result<()> {
         unsafe { set_threads(self.inner, n_threads) }
     }
+
+    /// Use a shared thread-pool for writing. This permits controlling the total
+    /// thread count when multiple readers and writers are working simultaneously.
+    /// A thread pool can be created with `crate::tpool::ThreadPool::new(n_threads)`
+    ///
+    /// # Arguments
+    ///
+    /// * `tpool` - thread pool to use for compression work.
+    pub fn set_thread_pool(&mut self, tpool: &ThreadPool) -> Result<()> {
+        unsafe { set_thread_pool(self.inner, tpool)? }
+        self.tpool = Some(tpool.clone());
+        Ok(())
+    }
 }
 
 impl Drop for Writer {
     fn drop(&mut self) {
-        unsafe {
-            htslib::hts_close(self.inner);
+        if !self.inner.is_null() {
+            unsafe {
+                htslib::hts_close(self.inner);
+            }
         }
     }
 }
@@ -793,15 +1438,32 @@ impl Drop for Writer {
 #[derive(Debug)]
 pub struct Records<'a, R: Read> {
     reader: &'a mut R,
+    truncated: bool,
+}
+
+impl<'a, R: Read> Records<'a, R> {
+    fn new(reader: &'a mut R) -> Self {
+        Records {
+            reader,
+            truncated: false,
+        }
+    }
 }
 
 impl<'a, R: Read> Iterator for Records<'a, R> {
     type Item = Result<record::Record>;
 
     fn next(&mut self) -> Option<Result<record::Record>> {
+        if self.truncated {
+            // nothing can be read after the end of a truncated file
+            return None;
+        }
         let mut record = self.reader.empty_record();
         match self.reader.read(&mut record) {
-            Some(Err(e)) => Some(Err(e)),
+            Some(Err(e)) => {
+                self.truncated = e == Error::BcfTruncatedRecord;
+                Some(Err(e))
+            }
             Some(Ok(_)) => Some(Ok(record)),
             None => None,
         }
@@ -864,6 +1526,7 @@ mod tests {
                     .float()
                     .expect("Error reading info.")
                     .expect("Missing tag")[0]
+                    .unwrap()
                     - 1.0)
                     .abs()
                     < std::f32::EPSILON
@@ -875,6 +1538,7 @@ mod tests {
                         .float()
                         .expect("Error reading info.")
                         .expect("Missing tag")[0]
+                        .unwrap()
                         - -0.379885)
                         .abs()
                         < std::f32::EPSILON
@@ -922,6 +1586,102 @@ mod tests {
         writer.set_threads(2).unwrap();
     }
 
+    #[test]
+    fn test_shared_tpool() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bcfpath = tmp.path().join("test.bcf");
+        {
+            let pool = crate::tpool::ThreadPool::new(2).unwrap();
+            let mut bcf = Reader::from_path("test/test.bcf").expect("Error opening file.");
+            bcf.set_thread_pool(&pool).unwrap();
+            let header = Header::from_template(bcf.header());
+            let mut writer = Writer::from_path(&bcfpath, &header, false, Format::Bcf)
+                .expect("Error opening file.");
+            writer.set_thread_pool(&pool).unwrap();
+            for rec in bcf.records() {
+                let mut record = rec.expect("Error reading record.");
+                writer.translate(&mut record);
+                writer.write(&record).expect("Error writing record");
+            }
+        }
+        _test_read(&bcfpath);
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_threads_many_records() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bcfpath = tmp.path().join("many.bcf");
+        let mut header = Header::new();
+        header
+            .push_contig(b"1", 1_000_000)
+            .unwrap()
+            .push_info(
+                b"N",
+                header::TagLength::Fixed(1),
+                header::TagType::Integer,
+                "Index",
+            )
+            .unwrap();
+        {
+            let mut writer = Writer::from_path(&bcfpath, &header, false, Format::Bcf).unwrap();
+            writer.set_threads(4).unwrap();
+            let mut record = writer.empty_record();
+            for i in 0..10_000 {
+                record.clear();
+                record.set_rid(Some(0));
+                record.set_pos(i * 10);
+                record.set_alleles(&[b"A", b"T"]).unwrap();
+                record.push_info_integer(b"N", &[i as i32]).unwrap();
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let mut reader = Reader::from_path(&bcfpath).unwrap();
+        reader.set_threads(4).unwrap();
+        let mut n = 0;
+        for (i, record) in reader.records().enumerate() {
+            let record = record.unwrap();
+            assert_eq!(record.pos(), i as i64 * 10);
+            assert_eq!(record.alleles(), vec![b"A", b"T"]);
+            assert_eq!(
+                record.info(b"N").integer().unwrap().unwrap()[0],
+                Some(i as i32)
+            );
+            n += 1;
+        }
+        assert_eq!(n, 10_000);
+
+        // one pool for a BAM reader, and a BCF reader and writer
+        crate::bcf::index::build(&bcfpath, None, 14, 4).unwrap();
+        let pool = crate::tpool::ThreadPool::new(4).unwrap();
+        let mut bam = crate::bam::Reader::from_path("test/test.bam").unwrap();
+        crate::bam::Read::set_thread_pool(&mut bam, &pool).unwrap();
+        let mut bcf = IndexedReader::from_path(&bcfpath).unwrap();
+        bcf.set_thread_pool(&pool).unwrap();
+        let copypath = tmp.path().join("copy.bcf");
+        let mut writer = Writer::from_path(&copypath, &header, false, Format::Bcf).unwrap();
+        writer.set_thread_pool(&pool).unwrap();
+        assert_eq!(crate::bam::Read::records(&mut bam).count(), 6);
+        bcf.fetch(0, 50_000, Some(59_999)).unwrap();
+        for record in bcf.records() {
+            writer.write(&record.unwrap()).unwrap();
+        }
+        drop(writer);
+        assert_eq!(
+            Reader::from_path(&copypath).unwrap().records().count(),
+            1000
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
     #[test]
     fn test_fetch() {
         let mut bcf = IndexedReader::from_path(&"test/test.bcf").expect("Error opening file.");
@@ -936,8 +1696,130 @@ mod tests {
     }
 
     #[test]
-    fn test_fetch_all() {
-        let mut bcf = IndexedReader::from_path(&"test/test.bcf").expect("Error opening file.");
+    fn test_records_truncated() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("test.bcf");
+        let mut bcf = Reader::from_path("test/test.bcf").unwrap();
+        let records: Vec<Record> = bcf.records().map(|r| r.unwrap()).collect();
+        {
+            let header = Header::from_template(bcf.header());
+            let mut writer = Writer::from_path(&path, &header, true, Format::Bcf).unwrap();
+            // span several BGZF blocks, so that the beginning of the file stays readable
+            for _ in 0..100 {
+                for record in &records {
+                    writer.write(record).unwrap();
+                }
+            }
+        }
+        // each record is owned, and independent of the following ones
+        assert_eq!(records.len(), 62);
+        assert_ne!(records[1].pos(), records[0].pos());
+
+        let mut data = std::fs::read(&path).unwrap();
+        data.truncate(data.len() / 2);
+        std::fs::write(&path, data).unwrap();
+        let mut bcf = Reader::from_path(&path).unwrap();
+        let records: Vec<Result<Record>> = bcf.records().collect();
+        assert!(records.len() < 6200);
+        assert!(records[..records.len() - 1].iter().all(|r| r.is_ok()));
+        assert_eq!(
+            records.last().unwrap().as_ref().unwrap_err(),
+            &Error::BcfTruncatedRecord
+        );
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_records_truncated_vcf_gz_and_missing_eof() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let mut bcf = Reader::from_path("test/test.bcf").unwrap();
+        let records: Vec<Record> = bcf.records().map(|r| r.unwrap()).collect();
+        let header = Header::from_template(bcf.header());
+
+        for (name, format) in [("test.vcf.gz", Format::Vcf), ("test.bcf", Format::Bcf)] {
+            let path = tmp.path().join(name);
+            {
+                let mut writer = Writer::from_path(&path, &header, false, format).unwrap();
+                for _ in 0..100 {
+                    for record in &records {
+                        writer.write(record).unwrap();
+                    }
+                }
+            }
+            let data = std::fs::read(&path).unwrap();
+            // cut in the middle of a record, and cut off only the 28 byte EOF block
+            for (len, complete) in [(data.len() / 2, false), (data.len() - 28, true)] {
+                std::fs::write(&path, &data[..len]).unwrap();
+                let mut reader = Reader::from_path(&path).unwrap();
+                let read: Vec<Result<Record>> = reader.records().collect();
+                assert!(read[..read.len() - 1].iter().all(|r| r.is_ok()), "{}", name);
+                assert_eq!(
+                    read.last().unwrap().as_ref().unwrap_err(),
+                    &Error::BcfTruncatedRecord,
+                    "{}",
+                    name
+                );
+                assert_eq!(read.len() == 6201, complete, "{}", name);
+            }
+        }
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_fetch_str() {
+        let mut bcf = IndexedReader::from_path("test/test.bcf").expect("Error opening file.");
+        bcf.fetch_str(b"1:10034-10061").expect("Fetching failed");
+        assert_eq!(bcf.records().count(), 28);
+        bcf.fetch_str(b"1").expect("Fetching failed");
+        assert_eq!(bcf.records().count(), 62);
+        bcf.fetch_str(b"1:10078").expect("Fetching failed");
+        assert_eq!(bcf.records().count(), 6);
+
+        assert_eq!(
+            bcf.fetch_str(b"nonexistent:1-100").unwrap_err(),
+            Error::UnknownSequence {
+                sequence: "nonexistent".to_owned()
+            }
+        );
+        assert_eq!(
+            bcf.fetch_str(b"1:200-100").unwrap_err(),
+            Error::InvalidRegion {
+                region: "1:200-100".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_fetch_by_name() {
+        let mut bcf = IndexedReader::from_path("test/test.bcf").expect("Error opening file.");
+        bcf.fetch_by_name(b"1", 10_033, 10_061).unwrap();
+        assert_eq!(bcf.records().count(), 28);
+        bcf.fetch_by_name(b"1", 10_033, 10_060).unwrap();
+        assert_eq!(bcf.records().count(), 27);
+        bcf.fetch_by_name(b"1", 10_033, 10_033).unwrap();
+        assert_eq!(bcf.records().count(), 0);
+        bcf.fetch_by_name(b"1", 0, u64::MAX).unwrap();
+        assert_eq!(bcf.records().count(), 62);
+
+        match bcf.fetch_by_name(b"chr1", 0, u64::MAX).unwrap_err() {
+            Error::ContigNotFound { contig, known } => {
+                assert_eq!(contig, "chr1");
+                assert!(known.starts_with("1, "));
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn test_fetch_all() {
+        let mut bcf = IndexedReader::from_path(&"test/test.bcf").expect("Error opening file.");
         bcf.set_threads(2).unwrap();
         let rid = bcf
             .header()
@@ -948,57 +1830,1474 @@ mod tests {
     }
 
     #[test]
-    fn test_fetch_open_ended() {
-        let mut bcf = IndexedReader::from_path(&"test/test.bcf").expect("Error opening file.");
-        bcf.set_threads(2).unwrap();
-        let rid = bcf
-            .header()
-            .name2rid(b"1")
-            .expect("Translating from contig '1' to ID failed.");
-        bcf.fetch(rid, 10077, None).expect("Fetching failed");
-        assert_eq!(bcf.records().count(), 6);
+    fn test_fetch_open_ended() {
+        let mut bcf = IndexedReader::from_path(&"test/test.bcf").expect("Error opening file.");
+        bcf.set_threads(2).unwrap();
+        let rid = bcf
+            .header()
+            .name2rid(b"1")
+            .expect("Translating from contig '1' to ID failed.");
+        bcf.fetch(rid, 10077, None).expect("Fetching failed");
+        assert_eq!(bcf.records().count(), 6);
+    }
+
+    #[test]
+    fn test_fetch_start_greater_than_last_vcf_pos() {
+        let mut bcf = IndexedReader::from_path(&"test/test.bcf").expect("Error opening file.");
+        bcf.set_threads(2).unwrap();
+        let rid = bcf
+            .header()
+            .name2rid(b"1")
+            .expect("Translating from contig '1' to ID failed.");
+        bcf.fetch(rid, 20077, None).expect("Fetching failed");
+        assert_eq!(bcf.records().count(), 0);
+    }
+
+    #[test]
+    fn test_fetch_vcf_tbi() {
+        let mut vcf = IndexedReader::from_path("test/test_left.vcf.gz").unwrap();
+        vcf.fetch_str(b"1:101").unwrap();
+        let positions: Vec<i64> = vcf.records().map(|r| r.unwrap().pos()).collect();
+        assert_eq!(positions, vec![100]);
+
+        // a region between the variants
+        let rid = vcf.header().name2rid(b"1").unwrap();
+        vcf.fetch(rid, 50, Some(98)).unwrap();
+        assert_eq!(vcf.records().count(), 0);
+        vcf.fetch(rid, 0, None).unwrap();
+        assert_eq!(vcf.records().count(), 2);
+    }
+
+    #[test]
+    fn test_from_path_and_index() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("test.bcf");
+        let idx_path = tmp.path().join("other.csi");
+        std::fs::copy("test/test.bcf", &path).unwrap();
+
+        assert_eq!(
+            IndexedReader::from_path(&path).unwrap_err(),
+            Error::BcfInvalidIndex {
+                target: path.to_str().unwrap().to_owned()
+            }
+        );
+
+        std::fs::copy("test/test.bcf.csi", &idx_path).unwrap();
+        let mut bcf = IndexedReader::from_path_and_index(&path, &idx_path).unwrap();
+        bcf.fetch_str(b"1:10034-10061").unwrap();
+        assert_eq!(bcf.records().count(), 28);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_write() {
+        let mut bcf = Reader::from_path(&"test/test_multi.bcf").expect("Error opening file.");
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bcfpath = tmp.path().join("test.bcf");
+        println!("{:?}", bcfpath);
+        {
+            let header = Header::from_template_subset(&bcf.header, &[b"NA12878.subsample-0.25-0"])
+                .expect("Error subsetting samples.");
+            let mut writer = Writer::from_path(&bcfpath, &header, false, Format::Bcf)
+                .expect("Error opening file.");
+            for rec in bcf.records() {
+                let mut record = rec.expect("Error reading record.");
+                writer.translate(&mut record);
+                writer.subset(&mut record);
+                record.trim_alleles().expect("Error trimming alleles.");
+                writer.write(&record).expect("Error writing record");
+            }
+        }
+        {
+            _test_read(&bcfpath);
+        }
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_write_format_from_extension() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let mut header = Header::new();
+        header.push_record(b"##contig=<ID=chr1,length=10000>");
+
+        let decompressed = |path: &Path| {
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(
+                &mut crate::bgzf::Reader::from_path(path).unwrap(),
+                &mut content,
+            )
+            .unwrap();
+            content
+        };
+
+        // the given format and compression are overridden by the extension
+        for (name, uncompressed, format, bgzf, magic) in [
+            (
+                "out.vcf",
+                false,
+                Format::Bcf,
+                false,
+                &b"##fileformat=VCF"[..],
+            ),
+            ("out.VCF.GZ", true, Format::Bcf, true, b"##fileformat=VCF"),
+            ("out.vcf.bgz", true, Format::Bcf, true, b"##fileformat=VCF"),
+            ("out.bcf", true, Format::Vcf, true, b"BCF\x02"),
+            ("out.bcf", false, Format::Vcf, true, b"BCF\x02"),
+            ("out.txt", true, Format::Vcf, false, b"##fileformat=VCF"),
+        ] {
+            let path = tmp.path().join(name);
+            {
+                let mut writer = Writer::from_path(&path, &header, uncompressed, format).unwrap();
+                let mut record = writer.empty_record();
+                record.set_rid(Some(0));
+                record.set_pos(9);
+                record.set_alleles(&[b"A", b"C"]).unwrap();
+                writer.write(&record).unwrap();
+            }
+            assert_eq!(crate::bgzf::is_bgzip(&path).unwrap(), bgzf, "{}", name);
+            let content = if bgzf {
+                decompressed(&path)
+            } else {
+                std::fs::read(&path).unwrap()
+            };
+            assert!(content.starts_with(magic), "{}", name);
+
+            let mut reader = Reader::from_path(&path).unwrap();
+            assert_eq!(reader.records().count(), 1, "{}", name);
+        }
+
+        // an explicit format is written regardless of the extension
+        let path = tmp.path().join("explicit.vcf");
+        Writer::from_path_with_format(&path, &header, false, Format::Bcf).unwrap();
+        assert!(crate::bgzf::is_bgzip(&path).unwrap());
+        assert!(decompressed(&path).starts_with(b"BCF\x02"));
+
+        assert!(Format::from_extension("out.tsv").is_none());
+        assert!(Format::from_extension("vcf").is_none());
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_write_formats() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let mut header = Header::new();
+        header.push_record(b"##contig=<ID=chr1,length=10000>");
+        header.push_record(br#"##INFO=<ID=DP,Number=1,Type=Integer,Description="Depth">"#);
+
+        let variants = [
+            (99, &[&b"A"[..], b"C"][..], 10),
+            (199, &[b"GT", b"G", b"GTT"], 20),
+        ];
+        for (uncompressed, format, name) in [
+            (true, Format::Vcf, "out.vcf"),
+            (false, Format::Vcf, "out.vcf.gz"),
+            (true, Format::Bcf, "out.ubcf"),
+            (false, Format::Bcf, "out.bcf"),
+        ] {
+            let path = tmp.path().join(name);
+            {
+                let mut writer = Writer::from_path(&path, &header, uncompressed, format).unwrap();
+                let mut record = writer.empty_record();
+                for (pos, alleles, depth) in variants {
+                    record.clear();
+                    record.set_rid(Some(0));
+                    record.set_pos(pos);
+                    record.set_alleles(alleles).unwrap();
+                    record.push_info_integer(b"DP", &[depth]).unwrap();
+                    writer.write(&record).unwrap();
+                }
+            }
+            // BCF is always BGZF, uncompressed BCF uses compression level 0.
+            let content = std::fs::read(&path).unwrap();
+            assert_eq!(
+                crate::bgzf::is_bgzip(&path).unwrap(),
+                !(uncompressed && matches!(format, Format::Vcf)),
+                "{}",
+                name
+            );
+            assert_eq!(
+                content.windows(16).any(|w| w == b"##fileformat=VCF"),
+                uncompressed,
+                "{}",
+                name
+            );
+
+            let mut reader = Reader::from_path(&path).unwrap();
+            let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+            assert_eq!(records.len(), variants.len(), "{}", name);
+            for (record, (pos, alleles, depth)) in records.iter().zip(variants) {
+                let rid = record.rid().unwrap();
+                assert_eq!(record.header().rid2name(rid).unwrap(), b"chr1");
+                assert_eq!(record.pos(), pos);
+                assert_eq!(record.alleles(), alleles);
+                assert_eq!(
+                    record.info(b"DP").integer().unwrap().unwrap(),
+                    [Some(depth)]
+                );
+            }
+        }
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_stdin_stdout_pipe() {
+        // Like `bam::tests::test_read_from_stdin_pipe`, the test re-runs itself in a child
+        // process with its STDIN and STDOUT redirected, like in `bcftools view | mytool`.
+        if std::env::var_os("RUST_HTSLIB_TEST_STDIN").is_some() {
+            let mut reader = Reader::from_stdin().unwrap();
+            let header = Header::from_template(reader.header());
+            let mut writer = Writer::from_stdout(&header, true, Format::Vcf).unwrap();
+            for record in reader.records() {
+                writer.write(&record.unwrap()).unwrap();
+            }
+            return;
+        }
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let vcf_gz = tmp.path().join("test_string.vcf.gz");
+        {
+            use std::io::Write;
+            let mut writer = crate::bgzf::Writer::from_path(&vcf_gz).unwrap();
+            writer
+                .write_all(&std::fs::read("test/test_string.vcf").unwrap())
+                .unwrap();
+        }
+
+        for path in [
+            Path::new("test/test.bcf"),
+            Path::new("test/test_string.vcf"),
+            &vcf_gz,
+        ] {
+            let child = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "bcf::tests::test_stdin_stdout_pipe"])
+                .env("RUST_HTSLIB_TEST_STDIN", "1")
+                .stdin(std::fs::File::open(path).unwrap())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success(), "{:?}", path);
+
+            // The test harness also writes to STDOUT, but never lines with tabs.
+            let output = String::from_utf8(output.stdout).unwrap();
+            assert!(output.contains("##fileformat=VCF"));
+            let positions: Vec<i64> = output
+                .lines()
+                .filter(|line| !line.starts_with('#') && line.contains('\t'))
+                .map(|line| line.split('\t').nth(1).unwrap().parse::<i64>().unwrap() - 1)
+                .collect();
+            let mut reader = Reader::from_path(path).unwrap();
+            let expected: Vec<i64> = reader.records().map(|r| r.unwrap().pos()).collect();
+            assert_eq!(positions, expected, "{:?}", path);
+        }
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_push_info() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.bcf");
+        let annotated_path = tmp.path().join("annotated.bcf");
+
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap()
+            .push_info(b"AF", TagLength::AltAlleles, TagType::Float, "Frequency")
+            .unwrap()
+            .push_info(b"NAMES", TagLength::Variable, TagType::String, "Names")
+            .unwrap()
+            .push_info(b"DB", TagLength::Fixed(0), TagType::Flag, "In dbSNP")
+            .unwrap()
+            .push_format(b"FT", TagLength::Fixed(1), TagType::String, "Filter")
+            .unwrap();
+
+        {
+            let mut writer = Writer::from_path(&path, &header, false, Format::Bcf).unwrap();
+            let mut record = writer.empty_record();
+            record.set_rid(Some(0));
+            record.set_pos(9);
+            record.set_alleles(&[b"A", b"C", b"G"]).unwrap();
+            record.push_info_integer(b"DP", &[10]).unwrap();
+            record.push_info_integer(b"DP", &[12]).unwrap();
+            record
+                .push_info_float(b"AF", &[0.5, f32::missing()])
+                .unwrap();
+            record.push_info_string(b"NAMES", &[b"x", b"yz"]).unwrap();
+            record.push_info_flag(b"DB").unwrap();
+
+            assert_eq!(
+                record.push_info_integer(b"XX", &[1]),
+                Err(Error::BcfUndefinedTag {
+                    tag: "XX".to_owned()
+                })
+            );
+            assert_eq!(
+                record.push_info_string(b"FT", &[b"PASS"]),
+                Err(Error::BcfUndefinedTag {
+                    tag: "FT".to_owned()
+                })
+            );
+            assert_eq!(
+                record.push_info_float(b"AF", &[0.1]),
+                Err(Error::BcfInvalidTagLength {
+                    tag: "AF".to_owned(),
+                    expected: 2,
+                    actual: 1
+                })
+            );
+            assert_eq!(
+                record.push_info_integer(b"DP", &[1, 2]),
+                Err(Error::BcfInvalidTagLength {
+                    tag: "DP".to_owned(),
+                    expected: 1,
+                    actual: 2
+                })
+            );
+            writer.write(&record).unwrap();
+        }
+
+        {
+            // removing entries, like `bcftools annotate -x`
+            let mut reader = Reader::from_path(&path).unwrap();
+            let header = Header::from_template(reader.header());
+            let mut writer =
+                Writer::from_path(&annotated_path, &header, false, Format::Bcf).unwrap();
+            for record in reader.records() {
+                let mut record = record.unwrap();
+                writer.translate(&mut record);
+                writer.write(&record).unwrap();
+                record.set_pos(19);
+                record.clear_info_flag(b"DB").unwrap();
+                record.push_info_string(b"NAMES", &[]).unwrap();
+                record.push_info_integer(b"DP", &[]).unwrap();
+                record.clear_info_float(b"AF").unwrap();
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let mut reader = Reader::from_path(&annotated_path).unwrap();
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+
+        let record = &records[0];
+        assert_eq!(record.info(b"DP").integer().unwrap().unwrap(), [Some(12)]);
+        assert_eq!(
+            record.info(b"AF").float().unwrap().unwrap(),
+            [Some(0.5), None]
+        );
+        assert_eq!(
+            *record.info(b"NAMES").string().unwrap().unwrap(),
+            [&b"x"[..], &b"yz"[..]]
+        );
+        assert!(record.info(b"DB").flag().unwrap());
+
+        let record = &records[1];
+        assert!(record.info(b"DP").integer().unwrap().is_none());
+        assert!(record.info(b"AF").float().unwrap().is_none());
+        assert!(record.info(b"NAMES").string().unwrap().is_none());
+        assert!(!record.info(b"DB").flag().unwrap());
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_push_format() {
+        use crate::bcf::header::{TagLength, TagType};
+        use crate::bcf::record::GenotypeAllele::*;
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+
+        let mut header = Header::new();
+        header
+            .push_contig(b"chrX", 1000)
+            .unwrap()
+            .push_info(
+                b"AC",
+                TagLength::AltAlleles,
+                TagType::Integer,
+                "Allele count",
+            )
+            .unwrap()
+            .push_format(b"GT", TagLength::Fixed(1), TagType::String, "Genotype")
+            .unwrap()
+            .push_format(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap()
+            .push_format(b"PL", TagLength::Genotypes, TagType::Integer, "Likelihoods")
+            .unwrap()
+            .push_format(b"AB", TagLength::Variable, TagType::Float, "Allele balance")
+            .unwrap()
+            .push_format(b"FT", TagLength::Fixed(1), TagType::String, "Filter")
+            .unwrap();
+        header
+            .push_sample(b"male")
+            .push_sample(b"female")
+            .push_sample(b"unknown");
+
+        for (uncompressed, format, name) in [
+            (true, Format::Vcf, "out.vcf"),
+            (false, Format::Bcf, "out.bcf"),
+        ] {
+            let path = tmp.path().join(name);
+            {
+                let mut writer = Writer::from_path(&path, &header, uncompressed, format).unwrap();
+                let mut record = writer.empty_record();
+                record.set_rid(Some(0));
+                record.set_pos(99);
+                record.set_alleles(&[b"A", b"C"]).unwrap();
+                record
+                    .push_genotypes_per_sample(&[&[Unphased(1)], &[Unphased(0), Phased(1)], &[]])
+                    .unwrap();
+                record.push_format_integer(b"DP", &[3, 7, 0]).unwrap();
+                record
+                    .push_format_integer_per_sample(b"PL", &[&[40, 0], &[30, 0, 20], &[]])
+                    .unwrap();
+                record
+                    .push_format_float_per_sample(b"AB", &[&[], &[0.5], &[0.25, 0.75]])
+                    .unwrap();
+                record
+                    .push_format_string(b"FT", &[&b"PASS"[..], b"LowDP", b"."])
+                    .unwrap();
+
+                assert_eq!(
+                    record.push_format_integer(b"XX", &[1, 2, 3]),
+                    Err(Error::BcfUndefinedTag {
+                        tag: "XX".to_owned()
+                    })
+                );
+                // only defined as INFO
+                assert_eq!(
+                    record.push_format_integer(b"AC", &[1, 2, 3]),
+                    Err(Error::BcfUndefinedTag {
+                        tag: "AC".to_owned()
+                    })
+                );
+                for result in [
+                    record.push_format_integer(b"DP", &[1, 2]),
+                    record.push_format_integer_per_sample(b"PL", &[&[1], &[2]]),
+                    record.push_format_string(b"FT", &[&b"a"[..]]),
+                    record.push_genotypes(&[Unphased(0)]),
+                ] {
+                    assert!(matches!(
+                        result,
+                        Err(Error::BcfSampleCountMismatch { samples: 3, .. })
+                    ));
+                }
+                writer.write(&record).unwrap();
+            }
+
+            let mut reader = Reader::from_path(&path).unwrap();
+            let record = reader.records().next().unwrap().unwrap();
+            let genotypes = record.genotypes().unwrap();
+            let genotypes: Vec<String> = (0..3).map(|i| genotypes.get(i).to_string()).collect();
+            assert_eq!(genotypes, ["1", "0|1", "."]);
+            assert_eq!(
+                record.format(b"PL").integer().unwrap(),
+                [
+                    vec![Some(40), Some(0)],
+                    vec![Some(30), Some(0), Some(20)],
+                    vec![None]
+                ]
+            );
+            assert_eq!(
+                record.format(b"AB").float().unwrap(),
+                [vec![None], vec![Some(0.5)], vec![Some(0.25), Some(0.75)]]
+            );
+            assert_eq!(
+                record.format(b"DP").integer().unwrap(),
+                [[Some(3)], [Some(7)], [Some(0)]]
+            );
+            assert_eq!(
+                *record.format(b"FT").string().unwrap(),
+                [&b"PASS"[..], b"LowDP", b"."]
+            );
+        }
+
+        let vcf = std::fs::read_to_string(tmp.path().join("out.vcf")).unwrap();
+        assert!(vcf.ends_with(
+            "GT:DP:PL:AB:FT\t1:3:40,0:.:PASS\t0|1:7:30,0,20:0.5:LowDP\t.:0:.:0.25,0.75:.\n"
+        ));
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_filters() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.vcf");
+
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_filter(b"q10", "Quality below 10")
+            .unwrap()
+            .push_filter(b"s50", "Less than 50% of samples have data")
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap();
+
+        {
+            let mut writer = Writer::from_path(&path, &header, true, Format::Vcf).unwrap();
+            let mut record = writer.empty_record();
+            record.set_rid(Some(0));
+            record.set_alleles(&[b"A", b"C"]).unwrap();
+            let q10 = record.header().name_to_id(b"q10").unwrap();
+
+            for name in [&b"q1O"[..], b"DP"] {
+                let unknown = Err(Error::BcfUnknownID {
+                    id: str::from_utf8(name).unwrap().to_owned(),
+                });
+                assert_eq!(record.set_filters(&[name]), unknown);
+                assert_eq!(record.push_filter(name), unknown);
+                assert_eq!(record.remove_filter(name, true), unknown);
+                assert!(!record.has_filter(name));
+            }
+
+            let mut write = |record: &mut Record, pos| {
+                record.set_pos(pos);
+                writer.write(record).unwrap();
+            };
+            // .
+            write(&mut record, 0);
+            record.set_filters(&[&b"PASS"[..]]).unwrap();
+            assert!(record.has_filter(&b"."[..]));
+            write(&mut record, 1);
+            record.set_filters(&[&b"q10"[..], b"s50"]).unwrap();
+            assert!(record.has_filter(&b"s50"[..]) && record.has_filter(&q10));
+            assert!(!record.has_filter(&b"PASS"[..]));
+            assert_eq!(record.filters().collect::<Vec<_>>().len(), 2);
+            write(&mut record, 2);
+            record.remove_filter(&b"s50"[..], true).unwrap();
+            write(&mut record, 3);
+            record.remove_filter(&b"q10"[..], false).unwrap();
+            assert!(record.has_filter(&b"PASS"[..]));
+            write(&mut record, 4);
+            record.push_filter(&b"q10"[..]).unwrap();
+            record.remove_filter(&b"q10"[..], true).unwrap();
+            write(&mut record, 5);
+            record.push_filter(&b"s50"[..]).unwrap();
+            record.push_filter(&b"."[..]).unwrap();
+            write(&mut record, 6);
+            record.set_filters::<[u8]>(&[]).unwrap();
+            write(&mut record, 7);
+        }
+
+        let filters: Vec<String> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split('\t').nth(6).unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            filters,
+            [".", "PASS", "q10;s50", "q10", ".", "PASS", "PASS", "."]
+        );
+
+        let mut reader = Reader::from_path(&path).unwrap();
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert!(records[2].has_filter(&b"q10"[..]) && records[2].has_filter(&b"s50"[..]));
+        assert!(records[0].has_filter(&b"PASS"[..]) && records[1].has_filter(&b"PASS"[..]));
+        assert!(!records[3].has_filter(&b"PASS"[..]));
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_set_id() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.vcf");
+
+        let mut header = Header::new();
+        header.push_contig(b"chr1", 1000).unwrap();
+        {
+            let mut writer = Writer::from_path(&path, &header, true, Format::Vcf).unwrap();
+            let mut record = writer.empty_record();
+            record.set_rid(Some(0));
+            record.set_alleles(&[b"A", b"C"]).unwrap();
+            assert_eq!(record.id(), b".");
+            writer.write(&record).unwrap();
+
+            record.set_id(b"rs123").unwrap();
+            record.push_id(b"COSV1").unwrap();
+            record.push_id(b"rs123").unwrap();
+            assert_eq!(record.id(), b"rs123;COSV1");
+            for id in [&b"rs 1"[..], b"rs1\t", b"\nrs1"] {
+                let invalid = Err(Error::BcfInvalidId {
+                    id: String::from_utf8_lossy(id).into_owned(),
+                });
+                assert_eq!(record.set_id(id), invalid);
+                assert_eq!(record.push_id(id), invalid);
+            }
+            assert_eq!(record.id(), b"rs123;COSV1");
+            writer.write(&record).unwrap();
+
+            record.clear_id().unwrap();
+            record.push_id(b"rs456").unwrap();
+            writer.write(&record).unwrap();
+        }
+
+        let ids: Vec<Vec<u8>> = Reader::from_path(&path)
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap().id())
+            .collect();
+        assert_eq!(ids, [&b"."[..], b"rs123;COSV1", b"rs456"]);
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("chr1\t1\trs123;COSV1\tA\tC\t"));
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_set_alleles() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.vcf");
+
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap()
+            .push_info(b"AF", TagLength::AltAlleles, TagType::Float, "Frequency")
+            .unwrap()
+            .push_format(b"AD", TagLength::Alleles, TagType::Integer, "Allele depth")
+            .unwrap();
+        header.push_sample(b"s1");
+        {
+            let mut writer = Writer::from_path(&path, &header, true, Format::Vcf).unwrap();
+            let mut record = writer.empty_record();
+            record.set_rid(Some(0));
+            record.set_pos(9);
+            record.set_alleles(&[b"A", b"<DEL>"]).unwrap();
+            assert_eq!(record.rlen(), 1);
+            record.set_alleles(&[b"ACG", b"A", b"*"]).unwrap();
+            assert_eq!(record.rlen(), 3);
+            record.push_info_integer(b"DP", &[10]).unwrap();
+            record.push_info_float(b"AF", &[0.25, 0.5]).unwrap();
+            record.push_format_integer(b"AD", &[3, 2, 5]).unwrap();
+            writer.write(&record).unwrap();
+
+            let dependent = |tag: &str| {
+                Err(Error::BcfAlleleDependentTag {
+                    tag: tag.to_owned(),
+                })
+            };
+            assert_eq!(record.set_alleles(&[b"ACG", b"A"]), dependent("AF"));
+            record.clear_info_float(b"AF").unwrap();
+            assert_eq!(record.set_alleles(&[b"ACG", b"A"]), dependent("AD"));
+            record.push_format_integer(b"AD", &[]).unwrap();
+            record.set_alleles(&[b"AC", b"A"]).unwrap();
+            assert_eq!(record.rlen(), 2);
+            // the number of alleles is unchanged
+            record.push_format_integer(b"AD", &[3, 7]).unwrap();
+            record.set_alleles(&[b"C", b"<INS>"]).unwrap();
+            writer.write(&record).unwrap();
+        }
+
+        let mut reader = Reader::from_path(&path).unwrap();
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records[0].alleles(), [&b"ACG"[..], b"A", b"*"]);
+        assert_eq!(records[0].end(), 12);
+        assert_eq!(records[1].alleles(), [&b"C"[..], b"<INS>"]);
+        assert_eq!(
+            records[1].format(b"AD").integer().unwrap(),
+            [[Some(3), Some(7)]]
+        );
+        assert_eq!(
+            records[1].info(b"DP").integer().unwrap().unwrap(),
+            [Some(10)]
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_info_flag() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.vcf.gz");
+
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_info(b"DB", TagLength::Fixed(0), TagType::Flag, "In dbSNP")
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap();
+        {
+            let mut writer = Writer::from_path(&path, &header, false, Format::Vcf).unwrap();
+            let mut record = writer.empty_record();
+            record.set_rid(Some(0));
+            record.set_alleles(&[b"A", b"C"]).unwrap();
+            for (pos, db) in [(0, true), (1, false), (2, true)] {
+                record.set_pos(pos);
+                if db {
+                    record.push_info_flag(b"DB").unwrap();
+                } else {
+                    record.clear_info_flag(b"DB").unwrap();
+                }
+                record.push_info_integer(b"DP", &[7]).unwrap();
+                assert_eq!(record.info(b"DB").flag(), Ok(db));
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let mut reader = Reader::from_path(&path).unwrap();
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        let flags: Vec<bool> = records
+            .iter()
+            .map(|record| record.info(b"DB").flag().unwrap())
+            .collect();
+        assert_eq!(flags, [true, false, true]);
+        assert_eq!(
+            records[0].info(b"DP").flag(),
+            Err(Error::BcfUnexpectedType {
+                tag: "DP".to_owned()
+            })
+        );
+        assert_eq!(
+            records[0].info(b"XX").flag(),
+            Err(Error::BcfUndefinedTag {
+                tag: "XX".to_owned()
+            })
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_header_from_scratch() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.vcf");
+
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 248_956_422)
+            .unwrap()
+            .push_info(
+                b"AF",
+                TagLength::AltAlleles,
+                TagType::Float,
+                "Allele frequency, \"estimated\"",
+            )
+            .unwrap()
+            .push_format(b"GT", TagLength::Fixed(1), TagType::String, "Genotype")
+            .unwrap()
+            .push_filter(b"q10", "Quality below 10")
+            .unwrap();
+        header.push_sample(b"s1").push_sample(b"s2");
+        assert_eq!(
+            header.try_push_record(b"#not a header line").unwrap_err(),
+            Error::BcfInvalidHeaderRecord {
+                line: "#not a header line".to_owned()
+            }
+        );
+
+        {
+            let mut writer = Writer::from_path(&path, &header, true, Format::Vcf).unwrap();
+            let mut record = writer.empty_record();
+            for (pos, af, gt) in [(9, 0.25, 1), (19, 0.75, 0)] {
+                record.clear();
+                record.set_rid(Some(0));
+                record.set_pos(pos);
+                record.set_alleles(&[b"A", b"T"]).unwrap();
+                record.push_info_float(b"AF", &[af]).unwrap();
+                record
+                    .push_genotypes(&[
+                        GenotypeAllele::Unphased(0),
+                        GenotypeAllele::Unphased(gt),
+                        GenotypeAllele::Unphased(1),
+                        GenotypeAllele::Phased(1),
+                    ])
+                    .unwrap();
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains(
+            "##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency, \\\"estimated\\\"\">"
+        ));
+        assert!(text.contains("##FILTER=<ID=q10,Description=\"Quality below 10\">"));
+        assert!(text.contains("##contig=<ID=chr1,length=248956422>"));
+
+        let mut reader = Reader::from_path(&path).unwrap();
+        assert_eq!(reader.header().samples(), vec![b"s1", b"s2"]);
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].pos(), 9);
+        assert_eq!(
+            records[1].info(b"AF").float().unwrap().unwrap(),
+            [Some(0.75)]
+        );
+        let genotypes = records[0].genotypes().unwrap();
+        assert_eq!(genotypes.get(0).to_string(), "0/1");
+        assert_eq!(genotypes.get(1).to_string(), "1|1");
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_set_samples() {
+        let mut vcf = Reader::from_path("test/test_string.vcf").unwrap();
+        assert_eq!(
+            vcf.set_samples(&[b"two", b"three"], false),
+            Err(Error::BcfUnknownSample {
+                name: "three".to_owned()
+            })
+        );
+        vcf.set_samples(&[b"two"], false).unwrap();
+        assert_eq!(vcf.header().sample_count(), 1);
+        assert_eq!(vcf.header().samples(), vec![b"two"]);
+        assert_eq!(vcf.header().sample_id(b"two"), Some(0));
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("subset.vcf");
+        {
+            let header = Header::from_template(vcf.header());
+            let mut writer = Writer::from_path(&path, &header, true, Format::Vcf).unwrap();
+            for record in vcf.records() {
+                let record = record.unwrap();
+                assert_eq!(record.sample_count(), 1);
+                assert_eq!(record.format(b"FN1").integer().unwrap()[0], [Some(2)]);
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let mut subset = Reader::from_path(&path).unwrap();
+        assert_eq!(subset.header().samples(), vec![b"two"]);
+        let genotypes: Vec<String> = subset
+            .records()
+            .map(|r| r.unwrap().genotypes().unwrap().get(0).to_string())
+            .collect();
+        assert_eq!(genotypes, vec!["1/1"; 6]);
+        tmp.close().expect("Failed to delete temp dir");
+
+        let mut vcf = Reader::from_path("test/test_string.vcf").unwrap();
+        vcf.set_samples(&[b"one"], true).unwrap();
+        assert_eq!(vcf.header().samples(), vec![b"two"]);
+        let record = vcf.records().next().unwrap().unwrap();
+        assert_eq!(record.format(b"FN1").integer().unwrap()[0], [Some(2)]);
+    }
+
+    #[test]
+    fn test_try_translate() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let input = tmp.path().join("input.vcf");
+        std::fs::write(
+            &input,
+            "##fileformat=VCFv4.2\n\
+             ##FILTER=<ID=q10,Description=\"Quality below 10\">\n\
+             ##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+             ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+             ##contig=<ID=chr1,length=1000>\n\
+             ##contig=<ID=chr2,length=1000>\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\ts1\n\
+             chr1\t10\t.\tA\tC\t.\tPASS\tDP=5\tGT\t0/1\n\
+             chr2\t20\t.\tG\tT\t.\tq10\tDP=7\tGT\t1/1\n",
+        )
+        .unwrap();
+
+        // contigs in reverse order, and an additional INFO tag before the existing one
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr2", 1000)
+            .unwrap()
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_info(b"NEW", TagLength::Fixed(1), TagType::Integer, "New")
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap()
+            .push_filter(b"q10", "Quality below 10")
+            .unwrap()
+            .push_format(b"GT", TagLength::Fixed(1), TagType::String, "Genotype")
+            .unwrap()
+            .push_sample(b"s1");
+        let output = tmp.path().join("output.bcf");
+        {
+            let mut reader = Reader::from_path(&input).unwrap();
+            let mut writer = Writer::from_path(&output, &header, false, Format::Bcf).unwrap();
+            for record in reader.records() {
+                let mut record = record.unwrap();
+                writer.try_translate(&mut record).unwrap();
+                record.push_info_integer(b"NEW", &[1]).unwrap();
+                writer.write(&record).unwrap();
+            }
+        }
+
+        let mut reader = Reader::from_path(&output).unwrap();
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        for (record, (contig, pos, dp)) in records
+            .iter()
+            .zip([(&b"chr1"[..], 9, 5), (&b"chr2"[..], 19, 7)])
+        {
+            let header = record.header();
+            assert_eq!(header.rid2name(record.rid().unwrap()).unwrap(), contig);
+            assert_eq!(record.pos(), pos);
+            assert_eq!(record.info(b"DP").integer().unwrap().unwrap(), [Some(dp)]);
+            assert_eq!(record.info(b"NEW").integer().unwrap().unwrap(), [Some(1)]);
+        }
+        assert!(records[0].has_filter("PASS".as_bytes()));
+        assert!(records[1].has_filter("q10".as_bytes()));
+        assert_eq!(records[1].genotypes().unwrap().get(0).to_string(), "1/1");
+
+        // a destination header lacking the contig, filter or tag of a record
+        let check = |header: &Header| {
+            let mut reader = Reader::from_path(&input).unwrap();
+            let path = tmp.path().join("check.vcf");
+            let mut writer = Writer::from_path(path, header, true, Format::Vcf).unwrap();
+            reader
+                .records()
+                .map(|record| writer.try_translate(&mut record.unwrap()))
+                .find(|result| result.is_err())
+                .unwrap_or(Ok(()))
+        };
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap()
+            .push_filter(b"q10", "Quality below 10")
+            .unwrap()
+            .push_format(b"GT", TagLength::Fixed(1), TagType::String, "Genotype")
+            .unwrap();
+        assert_eq!(
+            check(&header),
+            Err(Error::BcfUnknownContig {
+                contig: "chr2".to_owned()
+            })
+        );
+        header.push_contig(b"chr2", 1000).unwrap();
+        assert_eq!(check(&header), Ok(()));
+
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_contig(b"chr2", 1000)
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Depth")
+            .unwrap();
+        assert_eq!(
+            check(&header),
+            Err(Error::BcfUndefinedTag {
+                tag: "GT".to_owned()
+            })
+        );
+        header
+            .push_format(b"GT", TagLength::Fixed(1), TagType::String, "Genotype")
+            .unwrap();
+        assert_eq!(
+            check(&header),
+            Err(Error::BcfUnknownID {
+                id: "q10".to_owned()
+            })
+        );
+
+        // a destination header defining a tag with another type or number
+        let with_tags = |dp: (TagLength, TagType), gt: (TagLength, TagType)| {
+            let mut header = Header::new();
+            header
+                .push_contig(b"chr1", 1000)
+                .unwrap()
+                .push_contig(b"chr2", 1000)
+                .unwrap()
+                .push_filter(b"q10", "Quality below 10")
+                .unwrap()
+                .push_info(b"DP", dp.0, dp.1, "Depth")
+                .unwrap()
+                .push_format(b"GT", gt.0, gt.1, "Genotype")
+                .unwrap();
+            header
+        };
+        let dp = (TagLength::Fixed(1), TagType::Integer);
+        let gt = (TagLength::Fixed(1), TagType::String);
+        assert_eq!(check(&with_tags(dp, gt)), Ok(()));
+        let dp_error = Err(Error::BcfUndefinedTag {
+            tag: "DP".to_owned(),
+        });
+        assert_eq!(
+            check(&with_tags((TagLength::Fixed(1), TagType::Float), gt)),
+            dp_error
+        );
+        assert_eq!(
+            check(&with_tags((TagLength::Fixed(2), TagType::Integer), gt)),
+            dp_error
+        );
+        assert_eq!(
+            check(&with_tags(dp, (TagLength::Variable, TagType::String))),
+            Err(Error::BcfUndefinedTag {
+                tag: "GT".to_owned()
+            })
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_variant_kinds() {
+        use crate::bcf::record::VariantKind::*;
+        use bio_types::variant::Kind;
+
+        let mut vcf = Reader::from_path("test/test_variant_kinds.vcf").unwrap();
+        let records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+        let kinds: Vec<_> = records.iter().map(|r| r.variant_kinds()).collect();
+        assert_eq!(
+            kinds,
+            [
+                vec![Snv, Ref],
+                vec![Mnv],
+                vec![Insertion(2)],
+                vec![Deletion(2)],
+                vec![Symbolic],
+                vec![Breakend, Breakend],
+                vec![Other],
+                vec![SpanningDeletion, Snv],
+                vec![Ref, Ref],
+                vec![],
+                vec![Snv],
+            ]
+        );
+
+        assert_eq!(records[2].ref_allele(), b"A");
+        assert_eq!(records[2].alt_alleles(), [b"ATT"]);
+        assert_eq!(records[7].alt_alleles(), [b"*", b"C"]);
+        assert!(records[9].alt_alleles().is_empty());
+        assert_eq!(records[9].allele_count(), 1);
+
+        let bio: Vec<_> = records.iter().map(|r| r.bio_variant_kinds()).collect();
+        assert_eq!(bio[0], [Some(Kind::SNV(b'G')), None]);
+        assert_eq!(bio[1], [Some(Kind::MNV(b"GT".to_vec()))]);
+        assert_eq!(bio[2], [Some(Kind::Insertion(b"TT".to_vec()))]);
+        assert_eq!(bio[3], [Some(Kind::Deletion(2))]);
+        assert_eq!(bio[4], [None]);
+        assert_eq!(bio[7], [None, Some(Kind::SNV(b'C'))]);
+        assert_eq!(bio[10], [Some(Kind::SNV(b'G'))]);
+
+        // the classification follows changes of the alleles
+        let mut record = records[0].clone();
+        record.set_alleles(&[b"A", b"AC"]).unwrap();
+        assert_eq!(record.variant_kinds(), [Insertion(1)]);
+        // an empty record has no alleles
+        assert_eq!(vcf.empty_record().ref_allele(), b"");
+        assert!(vcf.empty_record().variant_kinds().is_empty());
     }
 
     #[test]
-    fn test_fetch_start_greater_than_last_vcf_pos() {
-        let mut bcf = IndexedReader::from_path(&"test/test.bcf").expect("Error opening file.");
-        bcf.set_threads(2).unwrap();
-        let rid = bcf
-            .header()
-            .name2rid(b"1")
-            .expect("Translating from contig '1' to ID failed.");
-        bcf.fetch(rid, 20077, None).expect("Fetching failed");
-        assert_eq!(bcf.records().count(), 0);
+    fn test_index_stats() {
+        for path in &["test/test.bcf", "test/test_left.vcf.gz"] {
+            let mut counts = Vec::new();
+            let mut reader = Reader::from_path(path).unwrap();
+            for record in reader.records() {
+                let rid = record.unwrap().rid().unwrap();
+                counts.resize(counts.len().max(rid as usize + 1), 0);
+                counts[rid as usize] += 1;
+            }
+
+            let stats = IndexedReader::from_path(path).unwrap().index_stats();
+            assert_eq!(stats.contigs.len() as u32, reader.header().contig_count());
+            for (rid, count) in &stats.contigs {
+                let expected = counts.get(*rid as usize).copied().unwrap_or(0);
+                assert_eq!(*count, Some(expected), "{} rid {}", path, rid);
+            }
+            assert_eq!(stats.no_coordinate, 0);
+            assert_eq!(stats.total(), Some(counts.iter().sum()));
+            assert!(stats.total().unwrap() > 0);
+        }
     }
 
     #[test]
-    fn test_write() {
-        let mut bcf = Reader::from_path(&"test/test_multi.bcf").expect("Error opening file.");
+    fn test_fetch_multiple_and_bed() {
         let tmp = tempfile::Builder::new()
             .prefix("rust-htslib")
             .tempdir()
             .expect("Cannot create temp dir");
-        let bcfpath = tmp.path().join("test.bcf");
-        println!("{:?}", bcfpath);
+        let path = tmp.path().join("regions.bcf");
+        let mut header = Header::new();
+        header.push_record(b"##contig=<ID=chr1,length=10000>");
+        header.push_record(b"##contig=<ID=chr2,length=10000>");
         {
-            let header = Header::from_template_subset(&bcf.header, &[b"NA12878.subsample-0.25-0"])
-                .expect("Error subsetting samples.");
-            let mut writer = Writer::from_path(&bcfpath, &header, false, Format::Bcf)
-                .expect("Error opening file.");
-            for rec in bcf.records() {
-                let mut record = rec.expect("Error reading record.");
-                writer.translate(&mut record);
-                writer.subset(&mut record);
-                record.trim_alleles().expect("Error trimming alleles.");
-                writer.write(&record).expect("Error writing record");
+            let mut writer = Writer::from_path(&path, &header, false, Format::Bcf).unwrap();
+            let mut record = writer.empty_record();
+            for rid in 0..2 {
+                for pos in (0..2000).step_by(100) {
+                    record.clear();
+                    record.set_rid(Some(rid));
+                    record.set_pos(pos);
+                    record.set_alleles(&[b"A", b"C"]).unwrap();
+                    writer.write(&record).unwrap();
+                    if rid == 0 && pos == 400 {
+                        // a deletion spanning from the first to the second region below
+                        record.clear();
+                        record.set_rid(Some(0));
+                        record.set_pos(410);
+                        record.set_alleles(&[&[b'A'; 800], b"A"]).unwrap();
+                        writer.write(&record).unwrap();
+                    }
+                }
+            }
+        }
+        crate::bcf::index::build(&path, None, 14, 1).unwrap();
+
+        let mut bcf = IndexedReader::from_path(&path).unwrap();
+        let positions = |bcf: &mut IndexedReader| -> Vec<(u32, i64)> {
+            bcf.records()
+                .map(|r| {
+                    let r = r.unwrap();
+                    (r.rid().unwrap(), r.pos())
+                })
+                .collect()
+        };
+        bcf.fetch_multiple(&[(1, 0, 150), (0, 1200, 1301), (0, 150, 350), (0, 300, 420)])
+            .unwrap();
+        assert_eq!(
+            positions(&mut bcf),
+            vec![
+                (0, 200),
+                (0, 300),
+                (0, 400),
+                (0, 410),
+                (0, 1200),
+                (0, 1300),
+                (1, 0),
+                (1, 100)
+            ]
+        );
+        // nothing is read beyond the last region
+        assert!(positions(&mut bcf).is_empty());
+
+        bcf.fetch_multiple(&[]).unwrap();
+        assert!(positions(&mut bcf).is_empty());
+        assert_eq!(
+            bcf.fetch_multiple(&[(2, 0, 100)]),
+            Err(Error::BcfUnknownRID { rid: 2 })
+        );
+
+        // a single fetch afterwards is not restricted to the regions
+        bcf.fetch(1, 1850, None).unwrap();
+        assert_eq!(positions(&mut bcf), vec![(1, 1900)]);
+
+        let bed = tmp.path().join("panel.bed");
+        std::fs::write(
+            &bed,
+            "track name=panel\nchr1\t150\t350\tA\nchr1\t300\t420\tB\nchrX\t0\t100\tC\n",
+        )
+        .unwrap();
+        let warnings = bcf.fetch_regions_from_bed(&bed).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("line 4"));
+        assert!(warnings[0].contains("chrX"));
+        assert_eq!(
+            positions(&mut bcf),
+            vec![(0, 200), (0, 300), (0, 400), (0, 410)]
+        );
+
+        std::fs::write(&bed, "chr1\t150\t350\nchr1\t300\n").unwrap();
+        assert_eq!(
+            bcf.fetch_regions_from_bed(&bed),
+            Err(Error::BedInvalidLine {
+                target: bed.to_string_lossy().into_owned(),
+                line: 2
+            })
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_write_errors() {
+        let mut reader = Reader::from_path("test/test_string.vcf").unwrap();
+        let header = Header::from_template(reader.header());
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.bcf");
+        let mut writer = Writer::from_path(&path, &header, false, Format::Bcf).unwrap();
+        assert_eq!(writer.write_all(&records).unwrap(), records.len());
+        writer.close().unwrap();
+        let mut reader = Reader::from_path(&path).unwrap();
+        assert_eq!(reader.records().count(), records.len());
+        tmp.close().expect("Failed to delete temp dir");
+
+        // /dev/full is not available on e.g. macOS
+        if !std::path::Path::new("/dev/full").exists() {
+            return;
+        }
+        // output is buffered, so that writing to a full disk first fails when flushing
+        let mut writer = Writer::from_path("/dev/full", &header, true, Format::Vcf).unwrap();
+        writer.write(&records[0]).unwrap();
+        assert_eq!(writer.flush(), Err(Error::FileFlush));
+        let mut writer = Writer::from_path("/dev/full", &header, true, Format::Vcf).unwrap();
+        writer.write(&records[0]).unwrap();
+        assert_eq!(writer.close(), Err(Error::FileClose));
+
+        // once the buffer is full, the failing record is reported
+        let mut writer = Writer::from_path("/dev/full", &header, true, Format::Vcf).unwrap();
+        match writer.write_all(records.iter().cycle().take(100_000)) {
+            Err(Error::WriteRecordAt { index, source }) => {
+                assert!(index > 0);
+                let record = records[index % records.len()].desc();
+                assert_eq!(*source, Error::BcfWriteRecord { record });
             }
+            result => panic!("unexpected result {:?}", result),
         }
+    }
+
+    #[test]
+    fn test_write_record_from_scratch() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("out.vcf");
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_filter(b"q10", "Quality below 10")
+            .unwrap()
+            .push_info(b"DP", TagLength::Fixed(1), TagType::Integer, "Total depth")
+            .unwrap()
+            .push_info(
+                b"AF",
+                TagLength::AltAlleles,
+                TagType::Float,
+                "Allele frequency",
+            )
+            .unwrap()
+            .push_format(b"GT", TagLength::Fixed(1), TagType::String, "Genotype")
+            .unwrap()
+            .push_format(b"AD", TagLength::Alleles, TagType::Integer, "Allele depth")
+            .unwrap();
+        header.push_sample(b"tumor").push_sample(b"normal");
         {
-            _test_read(&bcfpath);
+            let mut writer = Writer::from_path(&path, &header, true, Format::Vcf).unwrap();
+            let mut record = writer.empty_record();
+            // a blank record has a missing quality and no alleles
+            assert!(record.qual().is_missing());
+            assert!(record.alleles().is_empty());
+
+            let rid = writer.header().name2rid(b"chr1").unwrap();
+            record.set_rid(Some(rid));
+            record.set_pos(99);
+            record.set_id(b"rs123").unwrap();
+            record.set_alleles(&[b"A", b"G"]).unwrap();
+            record.set_qual(42.5);
+            record.set_filters(&[&b"PASS"[..]]).unwrap();
+            record.push_info_integer(b"DP", &[30]).unwrap();
+            record.push_info_float(b"AF", &[0.25]).unwrap();
+            record
+                .push_genotypes(&[
+                    GenotypeAllele::Unphased(0),
+                    GenotypeAllele::Unphased(1),
+                    GenotypeAllele::Unphased(0),
+                    GenotypeAllele::Unphased(0),
+                ])
+                .unwrap();
+            record.push_format_integer(b"AD", &[10, 5, 15, 0]).unwrap();
+            writer.write(&record).unwrap();
+
+            // reuse the record for a second, filtered SNV without a quality
+            record.clear();
+            record.set_rid(Some(rid));
+            record.set_pos(199);
+            record.set_alleles(&[b"C", b"T"]).unwrap();
+            record.set_filters(&[&b"q10"[..]]).unwrap();
+            writer.write(&record).unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let body: Vec<&str> = content.lines().filter(|l| !l.starts_with("##")).collect();
+        assert_eq!(
+            body,
+            [
+                "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\ttumor\tnormal",
+                "chr1\t100\trs123\tA\tG\t42.5\tPASS\tDP=30;AF=0.25\tGT:AD\t0/1:10,5\t0/0:15,0",
+                "chr1\t200\t.\tC\tT\t.\tq10\t.\t.\t.\t.",
+            ]
+        );
+
+        let mut reader = Reader::from_path(&path).unwrap();
+        let records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id(), b"rs123");
+        assert_eq!(records[0].qual(), 42.5);
+        assert_eq!(records[0].genotypes().unwrap().get(0).to_string(), "0/1");
+        assert!(records[1].qual().is_missing());
+        assert!(records[1].has_filter("q10".as_bytes()));
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_to_vcf_string() {
+        for path in ["test/test_trim_alleles.vcf", "test/test_end.vcf"] {
+            let lines: Vec<String> = std::fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .map(|line| line.to_owned())
+                .collect();
+            let mut vcf = Reader::from_path(path).unwrap();
+            let records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+            assert_eq!(records.len(), lines.len());
+            for (record, line) in records.iter().zip(&lines) {
+                assert_eq!(&record.to_vcf_string().unwrap(), line);
+                assert_eq!(&record.to_string(), line);
+            }
         }
+
+        // a record built from scratch
+        let mut header = Header::new();
+        header
+            .push_contig(b"chr1", 1000)
+            .unwrap()
+            .push_info(
+                b"DP",
+                header::TagLength::Fixed(1),
+                header::TagType::Integer,
+                "Depth",
+            )
+            .unwrap()
+            .push_format(
+                b"GT",
+                header::TagLength::Fixed(1),
+                header::TagType::String,
+                "Genotype",
+            )
+            .unwrap()
+            .push_sample(b"s1")
+            .push_sample(b"s2");
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let writer =
+            Writer::from_path(tmp.path().join("out.vcf"), &header, true, Format::Vcf).unwrap();
+        let mut record = writer.empty_record();
+        record.set_rid(Some(0));
+        record.set_pos(99);
+        record.set_id(b"rs1").unwrap();
+        record.set_alleles(&[b"A", b"G"]).unwrap();
+        record.set_qual(30.0);
+        record.push_info_integer(b"DP", &[12]).unwrap();
+        record
+            .push_genotypes(&[
+                GenotypeAllele::Unphased(0),
+                GenotypeAllele::Phased(1),
+                GenotypeAllele::UnphasedMissing,
+                GenotypeAllele::PhasedMissing,
+            ])
+            .unwrap();
+        assert_eq!(
+            record.to_vcf_string().unwrap(),
+            "chr1\t100\trs1\tA\tG\t30\t.\tDP=12\tGT\t0|1\t.|."
+        );
+        drop(writer);
         tmp.close().expect("Failed to delete temp dir");
     }
 
+    #[test]
+    fn test_sample_ad_pl() {
+        let mut vcf = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+        let records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records[0].sample_ad(0), Ok(vec![Some(5), Some(0), Some(4)]));
+        assert_eq!(records[0].sample_ad(1), Ok(vec![Some(0), Some(1), Some(9)]));
+        assert_eq!(
+            records[0].sample_pl(1),
+            Ok(vec![
+                Some(60),
+                Some(70),
+                Some(80),
+                Some(90),
+                Some(100),
+                Some(0)
+            ])
+        );
+        // the second sample only has `.`, i.e. a single missing value padded with vector ends
+        assert_eq!(records[1].sample_ad(0), Ok(vec![Some(1), Some(2), Some(3)]));
+        assert_eq!(records[1].sample_ad(1), Ok(vec![None]));
+        assert_eq!(
+            records[1].sample_ad(2),
+            Err(Error::BcfInvalidSampleIndex {
+                index: 2,
+                samples: 2
+            })
+        );
+        assert!(matches!(
+            records[1].sample_pl(0),
+            Err(Error::BcfMissingTag { .. })
+        ));
+    }
+
     #[test]
     fn test_strings() {
         let mut vcf = Reader::from_path(&"test/test_string.vcf").expect("Error opening file.");
@@ -1041,15 +3340,7 @@ mod tests {
     #[test]
     fn test_missing() {
         let mut vcf = Reader::from_path(&"test/test_missing.vcf").expect("Error opening file.");
-        let fn4 = [
-            &[
-                i32::missing(),
-                i32::missing(),
-                i32::missing(),
-                i32::missing(),
-            ][..],
-            &[i32::missing()][..],
-        ];
+        let fn4 = [vec![None; 4], vec![None]];
         let f1 = [false, true];
         let mut buffer = Buffer::new();
         for (i, rec) in vcf.records().enumerate() {
@@ -1060,7 +3351,7 @@ mod tests {
                     .float()
                     .expect("Error reading float.")
                     .expect("Missing tag")[0]
-                    .is_nan(),
+                    .is_none(),
                 f1[i]
             );
             assert_eq!(
@@ -1073,11 +3364,48 @@ mod tests {
             assert!(
                 record.format(b"FF4").float().expect("Error reading float.")[1]
                     .iter()
-                    .all(|&v| v.is_missing())
+                    .all(|v| v.is_none())
             );
         }
     }
 
+    #[test]
+    fn test_missing_values() {
+        let mut vcf = Reader::from_path("test/test_missing.vcf").expect("Error opening file.");
+        let records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+
+        assert_eq!(
+            records[0].info(b"F1").float().unwrap(),
+            Some(vec![Some(1.0)])
+        );
+        assert_eq!(records[1].info(b"F1").float().unwrap(), Some(vec![None]));
+        assert_eq!(
+            records[1].info(b"N1").integer().unwrap(),
+            Some(vec![Some(1)])
+        );
+        assert_eq!(
+            records[1].info(b"XX").integer(),
+            Err(Error::BcfUndefinedTag {
+                tag: "XX".to_owned()
+            })
+        );
+
+        assert_eq!(
+            records[0].format(b"FN4").integer().unwrap(),
+            [vec![Some(1), Some(2), Some(3), Some(4)], vec![None; 4]]
+        );
+        // the second sample has fewer values, the padding is removed
+        assert_eq!(
+            records[1].format(b"FN4").integer().unwrap(),
+            [vec![Some(1), Some(2), Some(3), Some(4)], vec![None]]
+        );
+        assert_eq!(
+            records[1].format(b"FF4").float().unwrap(),
+            [vec![Some(0.0); 4], vec![None]]
+        );
+        assert_eq!(records[1].format(b"FF4").float().unwrap()[1].len(), 1);
+    }
+
     #[test]
     fn test_genotypes() {
         let mut vcf = Reader::from_path(&"test/test_string.vcf").expect("Error opening file.");
@@ -1089,6 +3417,102 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_genotypes_ploidy_and_missing() {
+        use crate::bcf::record::GenotypeAllele::*;
+
+        let mut vcf = Reader::from_path("test/test_genotypes.vcf").unwrap();
+        let records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+        let expected = [
+            ["0|1", "./.", "1/2"],
+            [".|1", ".", "0/0"],
+            ["0", "1|1", "."],
+            ["0/1/2", "1", "0|0|1"],
+        ];
+        for (record, expected) in records.iter().zip(expected.iter()) {
+            let genotypes = record.genotypes().unwrap();
+            for (i, exp_gt) in expected.iter().enumerate() {
+                assert_eq!(&genotypes.get(i).to_string(), exp_gt);
+            }
+        }
+
+        let gt = |record: &Record, i: usize| record.genotypes().unwrap().get(i).to_vec();
+        assert_eq!(gt(&records[0], 0), [Unphased(0), Phased(1)]);
+        assert_eq!(gt(&records[0], 1), [UnphasedMissing, UnphasedMissing]);
+        assert_eq!(gt(&records[1], 0), [UnphasedMissing, Phased(1)]);
+        assert_eq!(gt(&records[1], 1), [UnphasedMissing]);
+        assert_eq!(gt(&records[2], 0), [Unphased(0)]);
+        assert_eq!(gt(&records[2], 1), [Unphased(1), Phased(1)]);
+        assert_eq!(gt(&records[3], 0), [Unphased(0), Unphased(1), Unphased(2)]);
+        assert_eq!(gt(&records[3], 2), [Unphased(0), Phased(0), Phased(1)]);
+        assert_eq!(records[3].genotypes().unwrap().get(1)[0].index(), Some(1));
+        assert_eq!(records[1].genotypes().unwrap().get(1)[0].index(), None);
+
+        // record without GT values
+        assert!(matches!(
+            records[4].genotypes(),
+            Err(Error::BcfMissingTag { .. })
+        ));
+    }
+
+    #[test]
+    fn test_genotype_queries() {
+        let mut vcf = Reader::from_path("test/test_genotypes.vcf").unwrap();
+        let records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+        let gt = |record: usize, sample: usize| records[record].genotypes().unwrap().get(sample);
+
+        // (record, sample, ploidy, missing, hom ref, het, hom alt, dosage of allele 1)
+        let expected = [
+            (0, 0, 2, false, false, true, false, Some(1)),
+            (0, 1, 2, true, false, false, false, None),
+            (0, 2, 2, false, false, true, false, Some(1)),
+            (1, 0, 2, true, false, false, false, None),
+            (1, 1, 1, true, false, false, false, None),
+            (1, 2, 2, false, true, false, false, Some(0)),
+            (2, 0, 1, false, true, false, false, Some(0)),
+            (2, 1, 2, false, false, false, true, Some(2)),
+            (2, 2, 1, true, false, false, false, None),
+            (3, 0, 3, false, false, true, false, Some(1)),
+            (3, 1, 1, false, false, false, true, Some(1)),
+            (3, 2, 3, false, false, true, false, Some(1)),
+        ];
+        for &(record, sample, ploidy, missing, hom_ref, het, hom_alt, dosage) in &expected {
+            let genotype = gt(record, sample);
+            assert_eq!(genotype.ploidy(), ploidy, "{}", genotype);
+            assert_eq!(genotype.is_missing(), missing, "{}", genotype);
+            assert_eq!(genotype.is_hom_ref(), hom_ref, "{}", genotype);
+            assert_eq!(genotype.is_het(), het, "{}", genotype);
+            assert_eq!(genotype.is_hom_alt(), hom_alt, "{}", genotype);
+            assert_eq!(genotype.dosage(1), dosage, "{}", genotype);
+        }
+        // 0/1/2 and 0|0|1
+        assert_eq!(gt(3, 0).dosage(0), Some(1));
+        assert_eq!(gt(3, 0).dosage(2), Some(1));
+        assert_eq!(gt(3, 2).dosage(0), Some(2));
+        assert_eq!(gt(3, 2).dosage(2), Some(0));
+
+        assert_eq!(records[0].allele_frequency(0).unwrap(), Some(0.25));
+        assert_eq!(records[0].allele_frequency(1).unwrap(), Some(0.5));
+        assert_eq!(records[0].allele_frequency(2).unwrap(), Some(0.25));
+        // only 0/0 is called
+        assert_eq!(records[1].allele_frequency(1).unwrap(), Some(0.0));
+        // mixed ploidy: 0 and 1|1
+        assert_eq!(records[2].allele_frequency(1).unwrap(), Some(2.0 / 3.0));
+        assert_eq!(records[3].allele_frequency(1).unwrap(), Some(3.0 / 7.0));
+        assert_eq!(records[3].allele_frequency(2).unwrap(), Some(1.0 / 7.0));
+        assert!(matches!(
+            records[4].allele_frequency(1),
+            Err(Error::BcfMissingTag { .. })
+        ));
+
+        // all genotypes missing
+        let mut record = records[1].clone();
+        record
+            .push_genotypes(&[GenotypeAllele::UnphasedMissing; 3])
+            .unwrap();
+        assert_eq!(record.allele_frequency(1).unwrap(), None);
+    }
+
     #[test]
     fn test_header_ids() {
         let vcf = Reader::from_path(&"test/test_string.vcf").expect("Error opening file.");
@@ -1129,6 +3553,43 @@ mod tests {
         // test nonexistent contig names and IDs
         assert!(header.name2rid(b"nonexistent_contig").is_err());
         assert!(header.rid2name(100).is_err());
+        assert_eq!(header.rid2name(86), Err(Error::BcfUnknownRID { rid: 86 }));
+        assert_eq!(
+            header.name2rid(b"nul\0"),
+            Err(Error::BcfUnknownContig {
+                contig: "nul\0".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_contig_length() {
+        let mut header = Header::new();
+        header.push_contig(b"chr1", 248956422).unwrap();
+        header.push_record(b"##contig=<ID=chrUn>");
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let writer =
+            Writer::from_path(tmp.path().join("out.vcf"), &header, true, Format::Vcf).unwrap();
+        let header = writer.header();
+        assert_eq!(header.contig_count(), 2);
+        assert_eq!(header.contig_length(0), Ok(Some(248956422)));
+        assert_eq!(header.contig_length(1), Ok(None));
+        assert_eq!(
+            header.contig_length(2),
+            Err(Error::BcfUnknownRID { rid: 2 })
+        );
+        for rid in 0..header.contig_count() {
+            assert_eq!(header.name2rid(header.rid2name(rid).unwrap()), Ok(rid));
+        }
+
+        let mut record = writer.empty_record();
+        record.set_rid(Some(1));
+        assert_eq!(header.rid2name(record.rid().unwrap()), Ok(&b"chrUn"[..]));
+        drop(writer);
+        tmp.close().expect("Failed to delete temp dir");
     }
 
     #[test]
@@ -1151,6 +3612,95 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_header_record_definitions() {
+        use crate::bcf::header::{TagLength, TagType};
+
+        let mut header = Header::new();
+        for line in [
+            r#"##INFO=<ID=DP,Number=1,Type=Integer,Description="Total depth">"#,
+            r#"##INFO=<ID=AF,Number=A,Type=Float,Description="Allele frequency">"#,
+            r#"##INFO=<ID=DB,Number=0,Type=Flag,Description="In \"dbSNP\"">"#,
+            r#"##INFO=<ID=NAMES,Number=.,Type=String,Description="Names">"#,
+            r#"##FORMAT=<ID=AD,Number=R,Type=Integer,Description="Allele depth">"#,
+            r#"##FORMAT=<ID=PL,Number=G,Type=Integer,Description="Likelihoods">"#,
+            r#"##FORMAT=<ID=CH,Number=1,Type=Character,Description="Char">"#,
+            r#"##FILTER=<ID=q10,Description="Quality below 10">"#,
+            "##contig=<ID=chr1,length=1000>",
+            "##source=test",
+        ] {
+            header.push_record(line.as_bytes());
+        }
+        let view = HeaderView::new(unsafe { htslib::bcf_hdr_dup(header.inner) });
+
+        let definitions: Vec<_> = view
+            .header_records()
+            .iter()
+            .filter(|record| {
+                matches!(
+                    record,
+                    HeaderRecord::Info { .. } | HeaderRecord::Format { .. }
+                )
+            })
+            .map(|record| {
+                (
+                    record.id().unwrap().to_owned(),
+                    record.tag_type().unwrap(),
+                    record.tag_length().unwrap(),
+                )
+            })
+            .collect();
+        let expected = [
+            ("DP", TagType::Integer, TagLength::Fixed(1)),
+            ("AF", TagType::Float, TagLength::AltAlleles),
+            ("DB", TagType::Flag, TagLength::Fixed(0)),
+            ("NAMES", TagType::String, TagLength::Variable),
+            ("AD", TagType::Integer, TagLength::Alleles),
+            ("PL", TagType::Integer, TagLength::Genotypes),
+            ("CH", TagType::String, TagLength::Fixed(1)),
+        ];
+        assert_eq!(definitions.len(), expected.len());
+        for ((id, ty, length), (expected_id, expected_ty, expected_length)) in
+            definitions.iter().zip(expected)
+        {
+            assert_eq!(
+                (id.as_str(), *ty, *length),
+                (expected_id, expected_ty, expected_length)
+            );
+            let lookup = if view.info_type(id.as_bytes()).is_ok() {
+                view.info_type(id.as_bytes())
+            } else {
+                view.format_type(id.as_bytes())
+            };
+            assert_eq!(lookup, Ok((*ty, *length)));
+        }
+
+        let records = view.header_records();
+        let db = records.iter().find(|r| r.id() == Some("DB")).unwrap();
+        assert_eq!(db.description(), Some(r#"In \"dbSNP\""#));
+        let filter = records.iter().find(|r| r.id() == Some("q10")).unwrap();
+        assert!(matches!(filter, HeaderRecord::Filter { .. }));
+        assert_eq!(filter.description(), Some("Quality below 10"));
+        assert_eq!(filter.tag_type(), None);
+        let source = records
+            .iter()
+            .find(|r| matches!(r, HeaderRecord::Generic { .. }));
+        assert_eq!(source.unwrap().id(), None);
+
+        assert_eq!(
+            view.info_type(b"AD"),
+            Err(Error::BcfUndefinedTag {
+                tag: "AD".to_owned()
+            })
+        );
+        assert_eq!(
+            view.format_type(b"XX"),
+            Err(Error::BcfUndefinedTag {
+                tag: "XX".to_owned()
+            })
+        );
+    }
+
     #[test]
     fn test_header_info_types() {
         let vcf = Reader::from_path(&"test/test.bcf").unwrap();
@@ -1236,6 +3786,123 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_trim_and_remove_alleles() {
+        use crate::bcf::record::GenotypeAllele::*;
+
+        let mut vcf = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+        let records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+
+        let mut record = records[0].clone();
+        record.trim_alleles().unwrap();
+        assert_eq!(record.alleles(), [b"A", b"G"]);
+        let genotypes = record.genotypes().unwrap();
+        assert_eq!(*genotypes.get(0), [Unphased(0), Unphased(1)]);
+        assert_eq!(*genotypes.get(1), [Unphased(1), Phased(1)]);
+        assert_eq!(record.info(b"AF").float().unwrap().unwrap(), [Some(0.4)]);
+        assert_eq!(record.info(b"DP").integer().unwrap().unwrap(), [Some(19)]);
+        assert_eq!(
+            record.format(b"AD").integer().unwrap(),
+            [[Some(5), Some(4)], [Some(0), Some(9)]]
+        );
+        assert_eq!(
+            record.format(b"PL").integer().unwrap(),
+            [[Some(10), Some(0), Some(50)], [Some(60), Some(90), Some(0)]]
+        );
+        // nothing left to trim
+        record.trim_alleles().unwrap();
+        assert_eq!(record.allele_count(), 2);
+
+        let mut record = records[0].clone();
+        assert_eq!(
+            record.remove_alleles(&[false, true]),
+            Err(Error::BcfInvalidAlleleMask)
+        );
+        assert_eq!(
+            record.remove_alleles(&[true, false, false]),
+            Err(Error::BcfInvalidAlleleMask)
+        );
+        record.remove_alleles(&[false, false, true]).unwrap();
+        assert_eq!(record.alleles(), [b"A", b"C"]);
+        let genotypes = record.genotypes().unwrap();
+        assert_eq!(*genotypes.get(0), [Unphased(0), UnphasedMissing]);
+        assert_eq!(*genotypes.get(1), [UnphasedMissing, PhasedMissing]);
+        assert_eq!(record.info(b"AF").float().unwrap().unwrap(), [Some(0.1)]);
+        assert_eq!(
+            record.format(b"AD").integer().unwrap(),
+            [[Some(5), Some(0)], [Some(0), Some(1)]]
+        );
+        assert_eq!(
+            record.format(b"PL").integer().unwrap(),
+            [
+                [Some(10), Some(20), Some(30)],
+                [Some(60), Some(70), Some(80)]
+            ]
+        );
+
+        // genotype refers to a nonexistent allele
+        let mut record = records[1].clone();
+        assert_eq!(record.trim_alleles(), Err(Error::BcfRemoveAlleles));
+    }
+
+    #[test]
+    fn test_split_multiallelics() {
+        let mut vcf = Reader::from_path("test/test_split_multiallelics.vcf").unwrap();
+        let header = vcf.header().clone();
+        let mut split = Vec::new();
+        for record in vcf.records() {
+            let record = record.unwrap();
+            for r in record.split_multiallelics(&header).unwrap() {
+                assert_eq!(r.rlen(), record.rlen());
+                split.push(r.to_vcf_string().unwrap());
+            }
+        }
+        let expected = std::fs::read_to_string("test/test_split_multiallelics.out.vcf").unwrap();
+        let expected: Vec<&str> = expected.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(split, expected);
+
+        // values not matching the alleles
+        let mut vcf = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+        let mut record = vcf.records().next().unwrap().unwrap();
+        record.push_format_integer(b"AD", &[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            record.split_multiallelics(vcf.header()).unwrap_err(),
+            Error::BcfSplitAlleles {
+                tag: "AD".to_owned(),
+                record: "1:9".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_end_and_rlen() {
+        let mut vcf = Reader::from_path("test/test_end.vcf").unwrap();
+        let mut records: Vec<Record> = vcf.records().map(|r| r.unwrap()).collect();
+        // gVCF reference block, deletion with END far beyond the REF allele, and simple indel;
+        // (POS, END) in 1-based coordinates as given by `bcftools query -f '%POS\t%END'`
+        for (record, (pos, end, rlen)) in
+            records
+                .iter()
+                .zip([(100, 199, 100), (1000, 50000, 49001), (60000, 60002, 3)])
+        {
+            assert_eq!(record.pos() + 1, pos);
+            assert_eq!(record.end(), end);
+            assert_eq!(record.rlen(), rlen);
+        }
+
+        let record = &mut records[0];
+        // END is kept when the alleles change
+        record.set_alleles(&[b"C", b"<*>"]).unwrap();
+        assert_eq!(record.end(), 199);
+        record.push_info_integer(b"END", &[299]).unwrap();
+        assert_eq!(record.rlen(), 200);
+        record.clear_info_integer(b"END").unwrap();
+        assert_eq!(record.rlen(), 1);
+        assert_eq!(record.end(), 100);
+        record.set_rlen(10);
+        assert_eq!(record.end(), 109);
+    }
+
     // Helper function reading full file into string.
     fn read_all<P: AsRef<Path>>(path: P) -> String {
         let mut file = File::open(path.as_ref())
@@ -1422,6 +4089,68 @@ mod tests {
         assert_eq!(res4.unwrap(), 0);
     }
 
+    #[test]
+    fn test_synced_reader_records() {
+        let open = || {
+            let mut reader = synced::SyncedReader::new().unwrap();
+            reader.set_require_index(true);
+            reader.set_pairing(synced::pairing::EXACT);
+            reader.add_reader("test/test_left.vcf.gz").unwrap();
+            reader.add_reader("test/test_right.vcf.gz").unwrap();
+            reader
+        };
+        // position and first sample of the record of each reader
+        type Locus = Vec<Option<(i64, Vec<u8>)>>;
+        let summarize = |loci: Vec<Vec<Option<Record>>>| -> Vec<Locus> {
+            loci.into_iter()
+                .map(|records| {
+                    records
+                        .into_iter()
+                        .map(|r| {
+                            r.map(|r| {
+                                let sample = r.header().samples()[0].to_owned();
+                                (r.pos(), sample)
+                            })
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        let mut reader = open();
+        let loci: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            summarize(loci),
+            vec![
+                vec![Some((99, b"one".to_vec())), Some((99, b"three".to_vec()))],
+                vec![Some((100, b"one".to_vec())), None],
+                vec![None, Some((101, b"three".to_vec()))],
+            ]
+        );
+
+        let mut reader = open();
+        reader.set_regions("1:101-102").unwrap();
+        let loci: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            summarize(loci),
+            vec![
+                vec![Some((100, b"one".to_vec())), None],
+                vec![None, Some((101, b"three".to_vec()))],
+            ]
+        );
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bed = tmp.path().join("regions.bed");
+        std::fs::write(&bed, "1\t99\t100\n").unwrap();
+        let mut reader = open();
+        reader.set_regions_file(&bed).unwrap();
+        assert_eq!(reader.records().count(), 1);
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
     #[test]
     fn test_svlen() {
         let mut reader = Reader::from_path("test/test_svlen.vcf").unwrap();
@@ -1430,8 +4159,8 @@ mod tests {
         reader.read(&mut record).unwrap().unwrap();
 
         assert_eq!(
-            *record.info(b"SVLEN").integer().unwrap().unwrap(),
-            &[-127][..]
+            record.info(b"SVLEN").integer().unwrap().unwrap(),
+            [Some(-127)]
         );
     }
 
@@ -17,11 +17,12 @@ use std::slice;
 use std::str;
 
 use bio_types::genome;
+use bio_types::variant;
 use derive_new::new;
 use ieee754::Ieee754;
 use lazy_static::lazy_static;
 
-use crate::bcf::header::{HeaderView, Id};
+use crate::bcf::header::{HeaderView, Id, TagLength, TagType};
 use crate::bcf::Error;
 use crate::errors::Result;
 use crate::htslib;
@@ -66,18 +67,149 @@ impl Numeric for i32 {
 trait NumericUtils {
     /// Return true if entry marks the end of the record.
     fn is_vector_end(&self) -> bool;
+
+    /// Return the value that pads shorter per-sample vectors.
+    fn vector_end() -> Self;
 }
 
 impl NumericUtils for f32 {
     fn is_vector_end(&self) -> bool {
         self.bits() == VECTOR_END_FLOAT.bits()
     }
+
+    fn vector_end() -> f32 {
+        *VECTOR_END_FLOAT
+    }
 }
 
 impl NumericUtils for i32 {
     fn is_vector_end(&self) -> bool {
         *self == VECTOR_END_INTEGER
     }
+
+    fn vector_end() -> i32 {
+        VECTOR_END_INTEGER
+    }
+}
+
+/// Flatten per-sample vectors of different lengths, padding shorter ones with the vector end
+/// value. Samples without values get a single missing value.
+fn pad_samples<T: Numeric + NumericUtils + Copy>(data: &[&[T]]) -> Vec<T> {
+    let width = data
+        .iter()
+        .map(|values| values.len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let mut flat = Vec::with_capacity(width * data.len());
+    for values in data {
+        if values.is_empty() {
+            flat.push(T::missing());
+        }
+        flat.extend_from_slice(values);
+        flat.resize(flat.len().div_ceil(width) * width, T::vector_end());
+    }
+    flat
+}
+
+/// Values of an INFO or FORMAT tag with `Number=A`, `R` or `G`, with one vector per sample
+/// (or a single one for INFO tags).
+enum AlleleValues {
+    Integer(Vec<Vec<i32>>),
+    Float(Vec<Vec<f32>>),
+    String(Vec<Vec<Vec<u8>>>),
+}
+
+impl AlleleValues {
+    /// Whether the tag has a single missing value, which is the case for INFO tags written as
+    /// `.`.
+    fn is_missing(&self) -> bool {
+        match self {
+            AlleleValues::Integer(v) => {
+                matches!(v.as_slice(), [s] if s.len() == 1 && s[0].is_missing())
+            }
+            AlleleValues::Float(v) => {
+                matches!(v.as_slice(), [s] if s.len() == 1 && s[0].is_missing())
+            }
+            AlleleValues::String(v) => matches!(v.as_slice(), [s] if s.len() == 1 && s[0] == b"."),
+        }
+    }
+
+    /// Select the values of the REF and the given ALT allele for each sample, or `None` if the
+    /// number of values of a sample does not match the alleles.
+    fn subset(&self, length: TagLength, n_allele: usize, alt: usize) -> Option<AlleleValues> {
+        fn samples<T: Clone>(
+            values: &[Vec<T>],
+            is_missing: impl Fn(&T) -> bool,
+            length: TagLength,
+            n_allele: usize,
+            alt: usize,
+        ) -> Option<Vec<Vec<T>>> {
+            values
+                .iter()
+                .map(|values| match values.as_slice() {
+                    [] => Some(Vec::new()),
+                    [value] if is_missing(value) => Some(values.clone()),
+                    values => allele_value_indices(length, values.len(), n_allele, alt)
+                        .map(|indices| indices.iter().map(|&i| values[i].clone()).collect()),
+                })
+                .collect()
+        }
+
+        Some(match self {
+            AlleleValues::Integer(v) => {
+                AlleleValues::Integer(samples(v, |v: &i32| v.is_missing(), length, n_allele, alt)?)
+            }
+            AlleleValues::Float(v) => {
+                AlleleValues::Float(samples(v, |v: &f32| v.is_missing(), length, n_allele, alt)?)
+            }
+            AlleleValues::String(v) => {
+                AlleleValues::String(samples(v, |v: &Vec<u8>| v == b".", length, n_allele, alt)?)
+            }
+        })
+    }
+}
+
+/// Indices of the values of the REF and the given ALT allele among `n_values` values of a tag
+/// with `Number=A`, `R` or `G`. Genotype values are ordered as in the VCF spec, and may be
+/// given for haploid or diploid genotypes.
+fn allele_value_indices(
+    length: TagLength,
+    n_values: usize,
+    n_allele: usize,
+    alt: usize,
+) -> Option<Vec<usize>> {
+    match length {
+        TagLength::AltAlleles if n_values == n_allele - 1 => Some(vec![alt - 1]),
+        TagLength::Alleles if n_values == n_allele => Some(vec![0, alt]),
+        TagLength::Genotypes if n_values == n_allele => Some(vec![0, alt]),
+        TagLength::Genotypes if n_values == n_allele * (n_allele + 1) / 2 => {
+            // 0/0, 0/alt and alt/alt
+            let het = alt * (alt + 1) / 2;
+            Some(vec![0, het, het + alt])
+        }
+        _ => None,
+    }
+}
+
+/// Map a genotype allele to the record of the given ALT allele: `alt` becomes `1`, and all
+/// other ALT alleles become the REF allele.
+fn remap_genotype_allele(allele: GenotypeAllele, alt: usize) -> GenotypeAllele {
+    let remap = |a: i32| (a as usize == alt) as i32;
+    match allele {
+        GenotypeAllele::Unphased(a) => GenotypeAllele::Unphased(remap(a)),
+        GenotypeAllele::Phased(a) => GenotypeAllele::Phased(remap(a)),
+        missing => missing,
+    }
+}
+
+fn id_to_c_string(id: &[u8]) -> Result<ffi::CString> {
+    if id.iter().any(|c| c.is_ascii_whitespace() || *c == 0) {
+        return Err(Error::BcfInvalidId {
+            id: String::from_utf8_lossy(id).into_owned(),
+        });
+    }
+    Ok(ffi::CString::new(id).unwrap())
 }
 
 /// A trait to allow for seamless use of bytes or integer identifiers for filters
@@ -88,7 +220,7 @@ pub trait FilterId {
 
 impl FilterId for [u8] {
     fn id_from_header(&self, header: &HeaderView) -> Result<Id> {
-        header.name_to_id(self)
+        header.filter_to_id(self)
     }
     fn is_pass(&self) -> bool {
         matches!(self, b"PASS" | b".")
@@ -183,7 +315,9 @@ impl Record {
             htslib::bcf_unpack(inner, htslib::BCF_UN_ALL as i32);
             inner
         };
-        Record { inner, header }
+        let record = Record { inner, header };
+        record.clear();
+        record
     }
 
     /// Force unpacking of internal record values.
@@ -270,17 +404,23 @@ impl Record {
         }
     }
 
-    /// Return **0-based** position
+    /// Return **0-based** position, i.e. the VCF `POS` minus one.
     pub fn pos(&self) -> i64 {
         self.inner().pos
     }
 
-    /// Set **0-based** position
+    /// Set **0-based** position, i.e. the VCF `POS` minus one.
+    ///
+    /// Note that `rlen` is not updated, so for a record with an `END` INFO tag, the position
+    /// has to be set before the alleles or `END`.
     pub fn set_pos(&mut self, pos: i64) {
         self.inner_mut().pos = pos;
     }
 
-    /// Return the **0-based, exclusive** end position
+    /// Return the **0-based, exclusive** end position, i.e. `pos() + rlen()`. This is equal to
+    /// the 1-based, inclusive end of the VCF record: the `INFO/END` value for symbolic alleles
+    /// like `<DEL>` and gVCF reference blocks, and otherwise `POS` plus the length of the
+    /// reference allele minus one.
     ///
     /// # Example
     /// ```rust
@@ -313,9 +453,12 @@ impl Record {
         }
     }
 
-    /// Update the ID string to the given value.
+    /// Update the ID string to the given value. Multiple IDs are separated by semicolons.
+    ///
+    /// Returns `Error::BcfInvalidId` if the ID contains whitespace, which would corrupt the
+    /// VCF line.
     pub fn set_id(&mut self, id: &[u8]) -> Result<()> {
-        let c_str = ffi::CString::new(id).unwrap();
+        let c_str = id_to_c_string(id)?;
         if unsafe {
             htslib::bcf_update_id(
                 self.header().inner,
@@ -347,9 +490,12 @@ impl Record {
         }
     }
 
-    /// Add the ID string (the ID field is semicolon-separated), checking for duplicates.
+    /// Add the ID string (the ID field is semicolon-separated), checking for duplicates:
+    /// adding an ID that is already present does nothing.
+    ///
+    /// Returns `Error::BcfInvalidId` if the ID contains whitespace.
     pub fn push_id(&mut self, id: &[u8]) -> Result<()> {
-        let c_str = ffi::CString::new(id).unwrap();
+        let c_str = id_to_c_string(id)?;
         if unsafe {
             htslib::bcf_add_id(
                 self.header().inner,
@@ -413,7 +559,8 @@ impl Record {
 
     /// Set the given filter IDs to the FILTER column.
     ///
-    /// Setting an empty slice removes all filters and sets `PASS`.
+    /// Setting an empty slice removes all filters, so that the FILTER column is written as `.`
+    /// (while `has_filter` still reports `PASS`). Use `&[b"PASS"]` to write `PASS` explicitly.
     ///
     /// # Example
     /// ```rust
@@ -506,7 +653,8 @@ impl Record {
     /// # Arguments
     ///
     /// - `flt_id` - The corresponding filter ID to remove.
-    /// - `pass_on_empty` - Set to `PASS` when removing the last filter.
+    /// - `pass_on_empty` - Set to `PASS` when removing the last filter. Otherwise, the FILTER
+    ///   column is written as `.`, like `bcftools annotate -x FILTER`.
     ///
     /// # Example
     /// ```rust
@@ -563,13 +711,23 @@ impl Record {
         unsafe { htslib::bcf_unpack(self.inner, htslib::BCF_UN_ALL as i32) };
         let n = self.inner().n_allele() as usize;
         let dec = self.inner().d;
+        if n == 0 {
+            return Vec::new();
+        }
         let alleles = unsafe { slice::from_raw_parts(dec.allele, n) };
         (0..n)
             .map(|i| unsafe { ffi::CStr::from_ptr(alleles[i]).to_bytes() })
             .collect()
     }
 
-    /// Set alleles. The first allele is the reference allele.
+    /// Set alleles. The first allele is the reference allele. Symbolic alleles like `<DEL>` or
+    /// `*` are accepted. `rlen` is updated to the length of the new reference allele, unless
+    /// the record has an `END` INFO tag.
+    ///
+    /// Returns `Error::BcfAlleleDependentTag` if the number of alleles changes while the
+    /// record has INFO or FORMAT values with `Number=A`, `R` or `G`, as they would no longer
+    /// match the alleles. Use [`remove_alleles`](#method.remove_alleles) to drop alleles
+    /// together with their values.
     ///
     /// # Example
     /// ```rust
@@ -590,6 +748,19 @@ impl Record {
     /// assert_eq!(record.allele_count(), 2)
     /// ```
     pub fn set_alleles(&mut self, alleles: &[&[u8]]) -> Result<()> {
+        let n_allele = self.allele_count() as usize;
+        if n_allele > 0 && n_allele != alleles.len() {
+            if let Some(tag) = self.allele_dependent_tag() {
+                return Err(Error::BcfAlleleDependentTag {
+                    tag: String::from_utf8_lossy(&tag).into_owned(),
+                });
+            }
+        }
+        self.update_alleles(alleles)
+    }
+
+    /// Set the alleles without checking the INFO and FORMAT values.
+    fn update_alleles(&mut self, alleles: &[&[u8]]) -> Result<()> {
         let cstrings: Vec<ffi::CString> = alleles
             .iter()
             .map(|vec| ffi::CString::new(*vec).unwrap())
@@ -613,12 +784,66 @@ impl Record {
         }
     }
 
+    /// Return the first INFO or FORMAT tag set in this record with `Number=A`, `R` or `G`.
+    fn allele_dependent_tag(&mut self) -> Option<Vec<u8>> {
+        self.unpack();
+        let depends_on_alleles = |length: Result<(_, TagLength)>| {
+            matches!(
+                length,
+                Ok((
+                    _,
+                    TagLength::AltAlleles | TagLength::Alleles | TagLength::Genotypes
+                ))
+            )
+        };
+        let header = self.header();
+        let info_tag = self
+            .info_ids()
+            .into_iter()
+            .map(|id| header.id_to_name(id))
+            .find(|tag| depends_on_alleles(header.info_type(tag)));
+        info_tag.or_else(|| {
+            self.format_ids()
+                .into_iter()
+                .map(|id| header.id_to_name(id))
+                .find(|tag| depends_on_alleles(header.format_type(tag)))
+        })
+    }
+
+    /// IDs of the INFO tags set in this record (which has to be unpacked).
+    pub(crate) fn info_ids(&self) -> Vec<Id> {
+        let inner = self.inner();
+        if inner.n_info() == 0 {
+            return Vec::new();
+        }
+        let infos = unsafe { slice::from_raw_parts(inner.d.info, inner.n_info() as usize) };
+        infos
+            .iter()
+            .filter(|info| !info.vptr.is_null())
+            .map(|info| Id(info.key as u32))
+            .collect()
+    }
+
+    /// IDs of the FORMAT tags set in this record (which has to be unpacked).
+    pub(crate) fn format_ids(&self) -> Vec<Id> {
+        let inner = self.inner();
+        if inner.n_fmt() == 0 {
+            return Vec::new();
+        }
+        let fmts = unsafe { slice::from_raw_parts(inner.d.fmt, inner.n_fmt() as usize) };
+        fmts.iter()
+            .filter(|fmt| !fmt.p.is_null())
+            .map(|fmt| Id(fmt.id as u32))
+            .collect()
+    }
+
     /// Get variant quality.
     pub fn qual(&self) -> f32 {
         self.inner().qual
     }
 
-    /// Set variant quality.
+    /// Set variant quality. Use `f32::missing()` (see `Numeric`) to write `.`, which is the
+    /// quality of an empty record.
     pub fn set_qual(&mut self, qual: f32) {
         self.inner_mut().qual = qual;
     }
@@ -650,6 +875,95 @@ impl Record {
         self.inner().n_allele()
     }
 
+    /// Get the reference allele, which is empty if the record has no alleles.
+    pub fn ref_allele(&self) -> &[u8] {
+        self.alleles().first().copied().unwrap_or(b"")
+    }
+
+    /// Get the alternative alleles, i.e. all alleles except for the reference allele.
+    pub fn alt_alleles(&self) -> Vec<&[u8]> {
+        self.alleles().into_iter().skip(1).collect()
+    }
+
+    /// Classify each alternative allele against the reference allele, in the order of
+    /// `alt_alleles`. The classification is done by htslib, like `bcf_get_variant_types`
+    /// (and hence `bcftools view --types`) does it, see `VariantKind`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bcf::{record::VariantKind, Read, Reader};
+    ///
+    /// let mut vcf = Reader::from_path("test/test_variant_kinds.vcf").unwrap();
+    /// let record = vcf.records().next().unwrap().unwrap();
+    /// assert_eq!(record.alleles(), [&b"A"[..], b"G", b"<NON_REF>"]);
+    /// assert_eq!(record.variant_kinds(), [VariantKind::Snv, VariantKind::Ref]);
+    /// ```
+    pub fn variant_kinds(&self) -> Vec<VariantKind> {
+        let alleles = self.alleles();
+        if alleles.len() < 2 {
+            return Vec::new();
+        }
+        let variants = unsafe {
+            // the cached types are not reset by htslib when the alleles are updated
+            (*self.inner).d.var_type = -1;
+            htslib::bcf_get_variant_types(self.inner);
+            slice::from_raw_parts(self.inner().d.var, alleles.len())
+        };
+        alleles[1..]
+            .iter()
+            .zip(&variants[1..])
+            .map(|(alt, variant)| {
+                let kind = variant.type_ as u32;
+                let len = variant.n.unsigned_abs();
+                if kind & htslib::VCF_OVERLAP != 0 {
+                    VariantKind::SpanningDeletion
+                } else if kind & htslib::VCF_BND != 0 {
+                    VariantKind::Breakend
+                } else if kind & htslib::VCF_INDEL != 0 {
+                    if variant.n > 0 {
+                        VariantKind::Insertion(len)
+                    } else {
+                        VariantKind::Deletion(len)
+                    }
+                } else if kind & htslib::VCF_MNP != 0 {
+                    VariantKind::Mnv
+                } else if kind & htslib::VCF_SNP != 0 {
+                    VariantKind::Snv
+                } else if kind & htslib::VCF_OTHER != 0 {
+                    if alt.first() == Some(&b'<') {
+                        VariantKind::Symbolic
+                    } else {
+                        VariantKind::Other
+                    }
+                } else {
+                    VariantKind::Ref
+                }
+            })
+            .collect()
+    }
+
+    /// Convert each alternative allele into a `bio_types::variant::Kind`, in the order of
+    /// `alt_alleles`. This is `None` for alleles that have no equivalent, i.e. all but
+    /// `VariantKind::Snv`, `Mnv`, `Insertion` and `Deletion`.
+    pub fn bio_variant_kinds(&self) -> Vec<Option<variant::Kind>> {
+        let ref_allele = self.ref_allele();
+        self.alt_alleles()
+            .into_iter()
+            .zip(self.variant_kinds())
+            .map(|(alt, kind)| {
+                let (_, alt) = trim_common_affixes(ref_allele, alt);
+                match kind {
+                    VariantKind::Snv => Some(variant::Kind::SNV(alt[0])),
+                    VariantKind::Mnv => Some(variant::Kind::MNV(alt.to_owned())),
+                    VariantKind::Insertion(_) => Some(variant::Kind::Insertion(alt.to_owned())),
+                    VariantKind::Deletion(len) => Some(variant::Kind::Deletion(u64::from(len))),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
     /// Add/replace genotypes in FORMAT GT tag.
     ///
     /// # Arguments
@@ -688,6 +1002,26 @@ impl Record {
         self.push_format_integer(b"GT", &encoded)
     }
 
+    /// Add/replace genotypes in FORMAT GT tag, given one genotype per sample. The samples
+    /// may differ in ploidy (e.g. haploid and diploid samples on chrX): shorter genotypes
+    /// are padded as required by the BCF spec, and empty genotypes are written as `.`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if GT tag is not present in header, or if the number of genotypes
+    /// differs from the number of samples.
+    pub fn push_genotypes_per_sample(&mut self, genotypes: &[&[GenotypeAllele]]) -> Result<()> {
+        let encoded: Vec<Vec<i32>> = genotypes
+            .iter()
+            .map(|gt| match gt {
+                [] => vec![i32::from(GenotypeAllele::UnphasedMissing)],
+                gt => gt.iter().map(|&allele| i32::from(allele)).collect(),
+            })
+            .collect();
+        let encoded: Vec<&[i32]> = encoded.iter().map(|gt| gt.as_slice()).collect();
+        self.push_format_integer_per_sample(b"GT", &encoded)
+    }
+
     /// Get genotypes as vector of one `Genotype` per sample.
     ///
     /// # Example
@@ -713,7 +1047,39 @@ impl Record {
         B: BorrowMut<Buffer> + Borrow<Buffer> + 'a,
     {
         Ok(Genotypes {
-            encoded: self.format_shared_buffer(b"GT", buffer).integer()?,
+            encoded: self.format_shared_buffer(b"GT", buffer).integer_raw()?,
+        })
+    }
+
+    /// Frequency of the allele with the given index (0 being the reference allele) among the
+    /// alleles of all samples, ignoring missing genotypes (see `Genotype::is_missing`). Returns
+    /// `None` if all genotypes are missing, and `Error::BcfMissingTag` if the record has no `GT`
+    /// values.
+    ///
+    /// # Example
+    /// ```
+    /// use rust_htslib::bcf::{Read, Reader};
+    ///
+    /// let mut vcf = Reader::from_path("test/test_genotypes.vcf").unwrap();
+    /// // genotypes 0|1, ./. and 1/2
+    /// let record = vcf.records().next().unwrap().unwrap();
+    /// assert_eq!(record.allele_frequency(1).unwrap(), Some(0.5));
+    /// assert_eq!(record.allele_frequency(2).unwrap(), Some(0.25));
+    /// ```
+    pub fn allele_frequency(&self, allele_index: u32) -> Result<Option<f64>> {
+        let genotypes = self.genotypes()?;
+        let (mut count, mut total) = (0, 0);
+        for i in 0..self.sample_count() as usize {
+            let genotype = genotypes.get(i);
+            if let Some(dosage) = genotype.dosage(allele_index) {
+                count += dosage;
+                total += genotype.ploidy();
+            }
+        }
+        Ok(if total == 0 {
+            None
+        } else {
+            Some(f64::from(count) / total as f64)
         })
     }
 
@@ -737,17 +1103,11 @@ impl Record {
     /// record.push_format_integer(b"DP", &[20, 12]).expect("Failed to set DP format field");
     ///
     /// let read_depths = record.format(b"DP").integer().expect("Couldn't retrieve DP field");
-    /// let sample1_depth = read_depths[0];
-    /// assert_eq!(sample1_depth, &[20]);
-    /// let sample2_depth = read_depths[1];
-    /// assert_eq!(sample2_depth, &[12])
+    /// let sample1_depth = &read_depths[0];
+    /// assert_eq!(sample1_depth, &[Some(20)]);
+    /// let sample2_depth = &read_depths[1];
+    /// assert_eq!(sample2_depth, &[Some(12)])
     /// ```
-    ///
-    /// # Errors
-    /// **Attention:** the returned [`BufferBacked`] from [`integer()`](Format::integer)
-    /// (`read_depths`), which holds the data, has to be kept in scope as long as the data is
-    /// accessed. If parts of the data are accessed after the `BufferBacked` object is been
-    /// dropped, you will access unallocated memory.
     pub fn format<'a>(&'a self, tag: &'a [u8]) -> Format<'a, Buffer> {
         self.format_shared_buffer(tag, Buffer::new())
     }
@@ -761,6 +1121,33 @@ impl Record {
         Format::new(self, tag, buffer)
     }
 
+    /// Get the allele depths (`AD`) of the sample with the given index, one per allele, with
+    /// missing values as `None`. Samples with fewer values than others (e.g. just `.`) get a
+    /// shorter vector.
+    ///
+    /// Returns `Error::BcfInvalidSampleIndex` if there is no such sample, and the errors of
+    /// [`Format::integer`](struct.Format.html#method.integer) if `AD` is not set.
+    pub fn sample_ad(&self, sample_idx: usize) -> Result<Vec<Option<i32>>> {
+        self.sample_integer_values(b"AD", sample_idx)
+    }
+
+    /// Get the phred-scaled genotype likelihoods (`PL`) of the sample with the given index,
+    /// one per genotype, with missing values as `None`, like [`sample_ad`](#method.sample_ad).
+    pub fn sample_pl(&self, sample_idx: usize) -> Result<Vec<Option<i32>>> {
+        self.sample_integer_values(b"PL", sample_idx)
+    }
+
+    fn sample_integer_values(&self, tag: &[u8], sample_idx: usize) -> Result<Vec<Option<i32>>> {
+        let samples = self.sample_count();
+        if sample_idx >= samples as usize {
+            return Err(Error::BcfInvalidSampleIndex {
+                index: sample_idx,
+                samples,
+            });
+        }
+        Ok(self.format(tag).integer()?.swap_remove(sample_idx))
+    }
+
     /// Add/replace an integer-typed FORMAT tag.
     ///
     /// # Arguments
@@ -771,11 +1158,25 @@ impl Record {
     ///
     /// # Errors
     ///
-    /// Returns error if tag is not present in header.
+    /// Returns error if tag is not present in header, or if the number of values is not a
+    /// multiple of the number of samples.
     pub fn push_format_integer(&mut self, tag: &[u8], data: &[i32]) -> Result<()> {
         self.push_format(tag, data, htslib::BCF_HT_INT)
     }
 
+    /// Add/replace an integer-typed FORMAT tag, given one array of values per sample. Arrays
+    /// of different lengths are padded as required by the BCF spec, and samples without
+    /// values are written as `.`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if tag is not present in header, or if the number of arrays differs
+    /// from the number of samples.
+    pub fn push_format_integer_per_sample(&mut self, tag: &[u8], data: &[&[i32]]) -> Result<()> {
+        self.check_sample_count(tag, data.len())?;
+        self.push_format(tag, &pad_samples(data), htslib::BCF_HT_INT)
+    }
+
     /// Add/replace a float-typed FORMAT tag.
     ///
     /// # Arguments
@@ -786,7 +1187,8 @@ impl Record {
     ///
     /// # Errors
     ///
-    /// Returns error if tag is not present in header.
+    /// Returns error if tag is not present in header, or if the number of values is not a
+    /// multiple of the number of samples.
     ///
     /// # Example
     ///
@@ -807,12 +1209,25 @@ impl Record {
     /// # let mut vcf = Writer::from_stdout(&header, true, Format::Vcf).unwrap();
     /// # let mut record = vcf.empty_record();
     /// record.push_format_float(b"AF", &[0.5]);
-    /// assert_eq!(0.5, record.format(b"AF").float().unwrap()[0][0]);
+    /// assert_eq!(Some(0.5), record.format(b"AF").float().unwrap()[0][0]);
     /// ```
     pub fn push_format_float(&mut self, tag: &[u8], data: &[f32]) -> Result<()> {
         self.push_format(tag, data, htslib::BCF_HT_REAL)
     }
 
+    /// Add/replace a float-typed FORMAT tag, given one array of values per sample. Arrays of
+    /// different lengths are padded as required by the BCF spec, and samples without values
+    /// are written as `.`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if tag is not present in header, or if the number of arrays differs
+    /// from the number of samples.
+    pub fn push_format_float_per_sample(&mut self, tag: &[u8], data: &[&[f32]]) -> Result<()> {
+        self.check_sample_count(tag, data.len())?;
+        self.push_format(tag, &pad_samples(data), htslib::BCF_HT_REAL)
+    }
+
     /// Add/replace a single-char-typed FORMAT tag.
     ///
     /// # Arguments
@@ -823,14 +1238,38 @@ impl Record {
     ///
     /// # Errors
     ///
-    /// Returns error if tag is not present in header.
+    /// Returns error if tag is not present in header, or if the number of values is not a
+    /// multiple of the number of samples.
     pub fn push_format_char(&mut self, tag: &[u8], data: &[u8]) -> Result<()> {
         self.push_format(tag, data, htslib::BCF_HT_STR)
     }
 
+    /// Check that the tag is defined as FORMAT in the header, and that the header has exactly
+    /// `n` samples.
+    fn check_sample_count(&self, tag: &[u8], n: usize) -> Result<()> {
+        self.header().format_type(tag)?;
+        let samples = self.header().sample_count();
+        if n == samples as usize {
+            Ok(())
+        } else {
+            Err(Error::BcfSampleCountMismatch {
+                tag: str::from_utf8(tag).unwrap().to_owned(),
+                samples,
+            })
+        }
+    }
+
     /// Add a format tag. Data is a flattened two-dimensional array.
     /// The first dimension contains one array for each sample.
     fn push_format<T>(&mut self, tag: &[u8], data: &[T], ht: u32) -> Result<()> {
+        self.header().format_type(tag)?;
+        let samples = self.header().sample_count() as usize;
+        if !data.is_empty() && (samples == 0 || data.len() % samples != 0) {
+            return Err(Error::BcfSampleCountMismatch {
+                tag: str::from_utf8(tag).unwrap().to_owned(),
+                samples: samples as u32,
+            });
+        }
         let tag_c_str = ffi::CString::new(tag).unwrap();
         unsafe {
             if htslib::bcf_update_format(
@@ -864,12 +1303,14 @@ impl Record {
     ///
     /// # Errors
     ///
-    /// Returns error if tag is not present in header.
+    /// Returns error if tag is not present in header, or if the number of strings differs
+    /// from the number of samples.
     pub fn push_format_string<D: Borrow<[u8]>>(&mut self, tag: &[u8], data: &[D]) -> Result<()> {
         assert!(
             !data.is_empty(),
             "given string data must have at least 1 element"
         );
+        self.check_sample_count(tag, data.len())?;
         let c_data = data
             .iter()
             .map(|s| ffi::CString::new(s.borrow()).unwrap())
@@ -897,7 +1338,32 @@ impl Record {
         }
     }
 
+    /// Check that the given number of values matches the `Number=` of the tag. Lengths that
+    /// depend on the alleles are only checked if the alleles are already set.
+    fn check_tag_length(&self, tag: &[u8], length: TagLength, actual: usize) -> Result<()> {
+        let n_allele = self.allele_count() as usize;
+        let expected = match length {
+            TagLength::Fixed(n) => n as usize,
+            TagLength::AltAlleles if n_allele > 0 => n_allele - 1,
+            TagLength::Alleles if n_allele > 0 => n_allele,
+            _ => return Ok(()),
+        };
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(Error::BcfInvalidTagLength {
+                tag: str::from_utf8(tag).unwrap().to_owned(),
+                expected,
+                actual,
+            })
+        }
+    }
+
     /// Add/replace an integer-typed INFO entry.
+    ///
+    /// Empty data removes the entry. Returns `Error::BcfUndefinedTag` if the tag is not defined
+    /// in the header, and `Error::BcfInvalidTagLength` if the number of values does not match
+    /// its `Number=`.
     pub fn push_info_integer(&mut self, tag: &[u8], data: &[i32]) -> Result<()> {
         self.push_info(tag, data, htslib::BCF_HT_INT)
     }
@@ -908,6 +1374,10 @@ impl Record {
     }
 
     /// Add/replace a float-typed INFO entry.
+    ///
+    /// Empty data removes the entry. Returns `Error::BcfUndefinedTag` if the tag is not defined
+    /// in the header, and `Error::BcfInvalidTagLength` if the number of values does not match
+    /// its `Number=`.
     pub fn push_info_float(&mut self, tag: &[u8], data: &[f32]) -> Result<()> {
         self.push_info(tag, data, htslib::BCF_HT_REAL)
     }
@@ -917,13 +1387,17 @@ impl Record {
         self.push_info::<u8>(tag, &[], htslib::BCF_HT_REAL)
     }
 
-    /// Add/replace an INFO tag.
+    /// Add/replace an INFO tag. Empty data removes the tag.
     ///
     /// # Arguments
     /// * `tag` - the tag to add/replace
     /// * `data` - the data to set
     /// * `ht` - the HTSLib type to use
     fn push_info<T>(&mut self, tag: &[u8], data: &[T], ht: u32) -> Result<()> {
+        let (_, length) = self.header().info_type(tag)?;
+        if !data.is_empty() {
+            self.check_tag_length(tag, length, data.len())?;
+        }
         let tag_c_str = ffi::CString::new(tag).unwrap();
         unsafe {
             if htslib::bcf_update_info(
@@ -955,6 +1429,10 @@ impl Record {
     }
 
     /// Add/replace a string-typed INFO entry.
+    ///
+    /// Empty data removes the entry. Returns `Error::BcfUndefinedTag` if the tag is not defined
+    /// in the header, and `Error::BcfInvalidTagLength` if the number of values does not match
+    /// its `Number=`.
     pub fn push_info_string(&mut self, tag: &[u8], data: &[&[u8]]) -> Result<()> {
         self.push_info_string_impl(tag, data, htslib::BCF_HT_STR)
     }
@@ -964,8 +1442,12 @@ impl Record {
         self.push_info_string_impl(tag, &[], htslib::BCF_HT_STR)
     }
 
-    /// Add an string-valued INFO tag.
+    /// Add an string-valued INFO tag. Empty data removes the tag.
     fn push_info_string_impl(&mut self, tag: &[u8], data: &[&[u8]], ht: u32) -> Result<()> {
+        let (_, length) = self.header().info_type(tag)?;
+        if ht != htslib::BCF_HT_FLAG && !data.is_empty() {
+            self.check_tag_length(tag, length, data.len())?;
+        }
         let mut buf: Vec<u8> = Vec::new();
         for (i, &s) in data.iter().enumerate() {
             if i > 0 {
@@ -999,15 +1481,32 @@ impl Record {
         }
     }
 
-    /// Remove unused alleles.
+    /// Remove ALT alleles that are not used by any genotype, like `bcftools view --trim-alt-alleles`.
+    /// INFO and FORMAT values with `Number=A`, `R` or `G` are shrunk accordingly, and the
+    /// allele indices of the genotypes are updated. Records without `GT` stay unchanged.
+    ///
+    /// Returns `Error::BcfRemoveAlleles` if the record is inconsistent, e.g. if a genotype
+    /// refers to a nonexistent allele or the values of a tag do not match the alleles.
     pub fn trim_alleles(&mut self) -> Result<()> {
+        self.unpack();
         match unsafe { htslib::bcf_trim_alleles(self.header().inner, self.inner) } {
             -1 => Err(Error::BcfRemoveAlleles),
             _ => Ok(()),
         }
     }
 
+    /// Remove the ALT alleles given by the mask, which has one entry per allele (including the
+    /// REF allele, which cannot be removed). INFO and FORMAT values and genotypes are updated
+    /// like in [`trim_alleles`](#method.trim_alleles); genotypes of removed alleles become
+    /// missing.
+    ///
+    /// Returns `Error::BcfInvalidAlleleMask` if the mask does not match the alleles, and
+    /// `Error::BcfRemoveAlleles` if the record is inconsistent.
     pub fn remove_alleles(&mut self, remove: &[bool]) -> Result<()> {
+        if remove.len() != self.allele_count() as usize || remove.first() == Some(&true) {
+            return Err(Error::BcfInvalidAlleleMask);
+        }
+        self.unpack();
         let rm_set = unsafe { htslib::kbs_init(remove.len() as u64) };
 
         for (i, &r) in remove.iter().enumerate() {
@@ -1030,8 +1529,187 @@ impl Record {
         }
     }
 
-    /// Get the length of the reference allele. If the record has no reference allele, then the
-    /// result will be `0`.
+    /// Split a record with several ALT alleles into one record per ALT allele, like
+    /// `bcftools norm -m-`. Records with at most one ALT allele are returned unchanged.
+    ///
+    /// Each record keeps the REF allele, ID, QUAL, FILTER and all other values, except for
+    /// INFO and FORMAT tags whose `Number` in the given header is `A`, `R` or `G`: these are
+    /// reduced to the values of the REF and the respective ALT allele (`G` values assume
+    /// diploid or haploid genotypes). Single missing values (`.`) stay as they are. Genotypes
+    /// of the ALT allele become `1`, those of other ALT alleles become `0`. `rlen` is
+    /// recomputed from the REF allele or `INFO/END`. Alleles are not trimmed or left-aligned.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - the header defining the tags of the record
+    ///
+    /// Returns `Error::BcfSplitAlleles` if the number of values of a tag does not match the
+    /// alleles.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bcf::{Read, Reader};
+    ///
+    /// let mut vcf = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+    /// let record = vcf.records().next().unwrap().unwrap();
+    /// let split = record.split_multiallelics(vcf.header()).unwrap();
+    /// assert_eq!(split.len(), 2);
+    /// assert_eq!(
+    ///     split[1].to_vcf_string().unwrap(),
+    ///     "1\t10\t.\tA\tG\t.\tPASS\tAF=0.4;DP=19\tGT:AD:PL\t0/1:5,4:10,0,50\t1|1:0,9:60,90,0"
+    /// );
+    /// ```
+    pub fn split_multiallelics(&self, header: &HeaderView) -> Result<Vec<Record>> {
+        let mut source = self.clone();
+        source.unpack();
+        if source.allele_count() <= 2 {
+            return Ok(vec![source]);
+        }
+        let alleles: Vec<Vec<u8>> = source.alleles().iter().map(|a| a.to_vec()).collect();
+        let n_allele = alleles.len();
+
+        let mut info = Vec::new();
+        for id in source.info_ids() {
+            let tag = header.id_to_name(id);
+            let (tag_type, length) = header.info_type(&tag)?;
+            if let TagLength::AltAlleles | TagLength::Alleles | TagLength::Genotypes = length {
+                let values = match tag_type {
+                    TagType::Integer => AlleleValues::Integer(
+                        source
+                            .info(&tag)
+                            .integer_raw()?
+                            .map(|v| vec![v.to_vec()])
+                            .unwrap_or_default(),
+                    ),
+                    TagType::Float => AlleleValues::Float(
+                        source
+                            .info(&tag)
+                            .float_raw()?
+                            .map(|v| vec![v.to_vec()])
+                            .unwrap_or_default(),
+                    ),
+                    TagType::String => AlleleValues::String(
+                        source
+                            .info(&tag)
+                            .string()?
+                            .map(|v| vec![v.iter().map(|s| s.to_vec()).collect()])
+                            .unwrap_or_default(),
+                    ),
+                    TagType::Flag => continue,
+                };
+                // a single missing value is kept as it is
+                if !values.is_missing() {
+                    info.push((tag, length, values));
+                }
+            }
+        }
+
+        let mut genotypes = None;
+        let mut format = Vec::new();
+        for id in source.format_ids() {
+            let tag = header.id_to_name(id);
+            if tag == b"GT" {
+                let gts = source.genotypes()?;
+                genotypes = Some(
+                    (0..source.sample_count() as usize)
+                        .map(|i| gts.get(i).to_vec())
+                        .collect::<Vec<_>>(),
+                );
+                continue;
+            }
+            let (tag_type, length) = header.format_type(&tag)?;
+            if let TagLength::AltAlleles | TagLength::Alleles | TagLength::Genotypes = length {
+                let values = match tag_type {
+                    TagType::Integer => AlleleValues::Integer(
+                        source
+                            .format(&tag)
+                            .integer_raw()?
+                            .iter()
+                            .map(|v| v.to_vec())
+                            .collect(),
+                    ),
+                    TagType::Float => AlleleValues::Float(
+                        source
+                            .format(&tag)
+                            .float_raw()?
+                            .iter()
+                            .map(|v| v.to_vec())
+                            .collect(),
+                    ),
+                    TagType::String => AlleleValues::String(
+                        source
+                            .format(&tag)
+                            .string()?
+                            .iter()
+                            .map(|v| v.split(|c| *c == b',').map(|s| s.to_vec()).collect())
+                            .collect(),
+                    ),
+                    TagType::Flag => continue,
+                };
+                format.push((tag, length, values));
+            }
+        }
+
+        let split_error = |tag: &[u8]| Error::BcfSplitAlleles {
+            tag: String::from_utf8_lossy(tag).into_owned(),
+            record: source.desc(),
+        };
+        let mut records = Vec::with_capacity(n_allele - 1);
+        for alt in 1..n_allele {
+            let mut record = source.clone();
+            record.update_alleles(&[&alleles[0], &alleles[alt]])?;
+            for (tag, length, values) in &info {
+                match values
+                    .subset(*length, n_allele, alt)
+                    .ok_or_else(|| split_error(tag))?
+                {
+                    AlleleValues::Integer(v) => record.push_info_integer(tag, &v[0])?,
+                    AlleleValues::Float(v) => record.push_info_float(tag, &v[0])?,
+                    AlleleValues::String(v) => {
+                        let v: Vec<&[u8]> = v[0].iter().map(|s| s.as_slice()).collect();
+                        record.push_info_string(tag, &v)?
+                    }
+                }
+            }
+            for (tag, length, values) in &format {
+                match values
+                    .subset(*length, n_allele, alt)
+                    .ok_or_else(|| split_error(tag))?
+                {
+                    AlleleValues::Integer(v) => {
+                        let v: Vec<&[i32]> = v.iter().map(|s| s.as_slice()).collect();
+                        record.push_format_integer_per_sample(tag, &v)?
+                    }
+                    AlleleValues::Float(v) => {
+                        let v: Vec<&[f32]> = v.iter().map(|s| s.as_slice()).collect();
+                        record.push_format_float_per_sample(tag, &v)?
+                    }
+                    AlleleValues::String(v) => {
+                        let v: Vec<Vec<u8>> = v.iter().map(|s| s.join(&b',')).collect();
+                        record.push_format_string(tag, &v)?
+                    }
+                }
+            }
+            if let Some(genotypes) = &genotypes {
+                let remapped: Vec<Vec<GenotypeAllele>> = genotypes
+                    .iter()
+                    .map(|gt| gt.iter().map(|&a| remap_genotype_allele(a, alt)).collect())
+                    .collect();
+                let remapped: Vec<&[GenotypeAllele]> =
+                    remapped.iter().map(|gt| gt.as_slice()).collect();
+                record.push_genotypes_per_sample(&remapped)?;
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Get the length of the reference region of the record on the reference sequence. This is
+    /// computed by htslib when reading the record, setting the alleles or pushing `INFO/END`:
+    /// if the record has an `END` tag, it is `END - POS + 1`, otherwise it is the length of
+    /// the reference allele. If the record has no reference allele, then the result will be
+    /// `0`.
     ///
     /// # Example
     /// ```rust
@@ -1054,8 +1732,19 @@ impl Record {
         self.inner().rlen
     }
 
+    /// Set the length of the reference region of the record. This is written to BCF files and
+    /// their indices, but not to VCF files, where it is given by the reference allele or
+    /// `INFO/END` instead.
+    pub fn set_rlen(&mut self, rlen: i64) {
+        self.inner_mut().rlen = rlen;
+    }
+
     /// Clear all parts of the record. Useful if you plan to reuse a record object multiple times.
     ///
+    /// Afterwards, the record is blank like a new one from `empty_record`: it has no alleles,
+    /// ID, filters or INFO and FORMAT values, a missing quality, position 0 on the first contig,
+    /// and `.` for each sample of the header.
+    ///
     /// # Example
     /// ```rust
     /// # use rust_htslib::bcf::{Format, Writer};
@@ -1076,7 +1765,18 @@ impl Record {
     /// assert_eq!(record.pos(), 0)
     /// ```
     pub fn clear(&self) {
-        unsafe { htslib::bcf_clear(self.inner) }
+        unsafe {
+            htslib::bcf_clear(self.inner);
+            // otherwise, records without FORMAT values could not be written
+            (*self.inner).set_n_sample(self.header.sample_count());
+            htslib::bcf_unpack(self.inner, htslib::BCF_UN_ALL as i32);
+            // otherwise, the ID column would be empty
+            htslib::bcf_update_id(
+                self.header.inner,
+                self.inner,
+                b".\0".as_ptr() as *const c_char,
+            );
+        }
     }
 
     /// Provide short description of record for locating it in the BCF/VCF file.
@@ -1088,6 +1788,51 @@ impl Record {
         }
         "".to_owned()
     }
+
+    /// Render the record as a line of VCF text (without the trailing newline), exactly as
+    /// htslib (and hence bcftools) writes it with the header of the record, including all
+    /// FORMAT columns. This also works for records that have been created or modified in
+    /// memory. The same line is written by the `Display` implementation.
+    ///
+    /// Returns `Error::BcfInvalidRecord` if htslib cannot format the record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_htslib::bcf::{Read, Reader};
+    ///
+    /// let mut vcf = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+    /// let mut record = vcf.records().next().unwrap().unwrap();
+    /// record.set_pos(19);
+    /// assert_eq!(
+    ///     record.to_vcf_string().unwrap(),
+    ///     "1\t20\t.\tA\tC,G\t.\tPASS\tAF=0.1,0.4;DP=19\tGT:AD:PL\t\
+    ///      0/2:5,0,4:10,20,30,0,40,50\t2|2:0,1,9:60,70,80,90,100,0"
+    /// );
+    /// ```
+    pub fn to_vcf_string(&self) -> Result<String> {
+        let mut text = htslib::kstring_t {
+            l: 0,
+            m: 0,
+            s: ptr::null_mut(),
+        };
+        let ret = unsafe { htslib::vcf_format(self.header().inner, self.inner, &mut text) };
+        let line = if ret < 0 || text.s.is_null() {
+            Err(Error::BcfInvalidRecord)
+        } else {
+            let bytes = unsafe { slice::from_raw_parts(text.s as *const u8, text.l as usize) };
+            let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+            Ok(String::from_utf8_lossy(bytes).into_owned())
+        };
+        unsafe { libc::free(text.s as *mut libc::c_void) };
+        line
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_vcf_string().map_err(|_| fmt::Error)?)
+    }
 }
 
 impl Clone for Record {
@@ -1118,6 +1863,53 @@ impl genome::AbstractLocus for Record {
     }
 }
 
+/// The kind of an alternative allele relative to the reference allele, see
+/// `Record::variant_kinds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VariantKind {
+    /// Not a variant, e.g. an allele equal to the reference, `.`, or the gVCF alleles
+    /// `<NON_REF>` and `<*>`.
+    Ref,
+    /// A single nucleotide variant, e.g. `A` to `G`, also if the alleles share a prefix or
+    /// suffix, e.g. `AC` to `GC`.
+    Snv,
+    /// A multi nucleotide variant of equal length, e.g. `AC` to `GT`.
+    Mnv,
+    /// An insertion of the given length, e.g. 2 for `A` to `ATT`.
+    Insertion(u32),
+    /// A deletion of the given length, e.g. 2 for `ATG` to `A`.
+    Deletion(u32),
+    /// The spanning deletion allele `*`, i.e. a deletion described by another record.
+    SpanningDeletion,
+    /// A symbolic allele like `<DEL>` or `<DUP>`.
+    Symbolic,
+    /// A breakend like `G]chr2:100]`.
+    Breakend,
+    /// A complex replacement, e.g. `ACG` to `TT`.
+    Other,
+}
+
+/// Strip the common prefix and then the common suffix (case-insensitively) from the given
+/// alleles.
+fn trim_common_affixes<'a>(ref_allele: &'a [u8], alt: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+    let prefix = ref_allele
+        .iter()
+        .zip(alt)
+        .take_while(|(r, a)| r.eq_ignore_ascii_case(a))
+        .count();
+    let (ref_allele, alt) = (&ref_allele[prefix..], &alt[prefix..]);
+    let suffix = ref_allele
+        .iter()
+        .rev()
+        .zip(alt.iter().rev())
+        .take_while(|(r, a)| r.eq_ignore_ascii_case(a))
+        .count();
+    (
+        &ref_allele[..ref_allele.len() - suffix],
+        &alt[..alt.len() - suffix],
+    )
+}
+
 /// Phased or unphased alleles, represented as indices.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GenotypeAllele {
@@ -1191,11 +1983,64 @@ custom_derive! {
     pub struct Genotype(Vec<GenotypeAllele>);
 }
 
+impl Genotype {
+    /// Number of alleles, i.e. chromosome copies, of the genotype.
+    pub fn ploidy(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether any allele of the genotype is missing. Half-missing genotypes like `./1` count as
+    /// missing, as do genotypes without any alleles.
+    pub fn is_missing(&self) -> bool {
+        self.0.is_empty() || self.0.iter().any(|allele| allele.index().is_none())
+    }
+
+    /// Whether all alleles are the reference allele. False for missing genotypes.
+    pub fn is_hom_ref(&self) -> bool {
+        !self.is_missing() && self.0.iter().all(|allele| allele.index() == Some(0))
+    }
+
+    /// Whether the alleles are not all equal, e.g. `0/1` or `1/2`. False for missing and
+    /// haploid genotypes.
+    pub fn is_het(&self) -> bool {
+        !self.is_missing()
+            && self
+                .0
+                .iter()
+                .any(|allele| allele.index() != self.0[0].index())
+    }
+
+    /// Whether all alleles are the same alternative allele, e.g. `1/1` or `2`. False for
+    /// missing genotypes.
+    pub fn is_hom_alt(&self) -> bool {
+        !self.is_missing() && !self.is_hom_ref() && !self.is_het()
+    }
+
+    /// Number of occurrences of the allele with the given index (0 being the reference allele),
+    /// or `None` if the genotype is missing (see `is_missing`).
+    pub fn dosage(&self, allele_index: u32) -> Option<u32> {
+        if self.is_missing() {
+            return None;
+        }
+        Some(
+            self.0
+                .iter()
+                .filter(|allele| allele.index() == Some(allele_index))
+                .count() as u32,
+        )
+    }
+}
+
 impl fmt::Display for Genotype {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let &Genotype(ref alleles) = self;
-        write!(f, "{}", alleles[0])?;
-        for a in &alleles[1..] {
+        let (first, rest) = match alleles.split_first() {
+            Some(split) => split,
+            // e.g. a sample without any GT values in a BCF file
+            None => return write!(f, "."),
+        };
+        write!(f, "{}", first)?;
+        for a in rest {
             let sep = match a {
                 GenotypeAllele::Phased(_) | GenotypeAllele::PhasedMissing => '|',
                 GenotypeAllele::Unphased(_) | GenotypeAllele::UnphasedMissing => '/',
@@ -1216,7 +2061,9 @@ where
 }
 
 impl<'a, B: Borrow<Buffer> + 'a> Genotypes<'a, B> {
-    /// Get genotype of ith sample. So far, only supports diploid genotypes.
+    /// Get genotype of ith sample. The genotype has one allele per chromosome copy of the
+    /// sample, so samples of the same record can differ in ploidy (e.g. haploid and diploid
+    /// samples on chrX); padding at the end of shorter genotypes is removed.
     ///
     /// Note that the result complies with the BCF spec. This means that the
     /// first allele will always be marked as `Unphased`. That is, if you have 1|1 in the VCF,
@@ -1274,50 +2121,72 @@ impl<'a, 'b, B: BorrowMut<Buffer> + Borrow<Buffer> + 'b> Info<'a, B> {
         }
     }
 
-    /// Get integers from tag. `None` if tag not present in record.
-    ///
-    /// Import `bcf::record::Numeric` for missing value handling.
+    /// Get integers from tag, with missing values (`.`) as `None`. `None` if tag not present in
+    /// record.
+    pub fn integer(self) -> Result<Option<Vec<Option<i32>>>> {
+        Ok(self.integer_raw()?.map(|values| numeric_values(&values)))
+    }
+
+    /// Get floats from tag, with missing values (`.`) as `None`. `None` if tag not present in
+    /// record.
+    pub fn float(self) -> Result<Option<Vec<Option<f32>>>> {
+        Ok(self.float_raw()?.map(|values| numeric_values(&values)))
+    }
+
+    /// Get integers from tag as stored by htslib, i.e. with missing values encoded as
+    /// `MISSING_INTEGER`. `None` if tag not present in record.
     ///
     /// **Attention:** the returned BufferBacked which holds the data has to be kept in scope
     /// as along as the data is accessed. If parts of the data are accessed while
     /// the BufferBacked object is already dropped, you will access unallocated
     /// memory.
-    pub fn integer(mut self) -> Result<Option<BufferBacked<'b, &'b [i32], B>>> {
+    pub(crate) fn integer_raw(mut self) -> Result<Option<BufferBacked<'b, &'b [i32], B>>> {
         self.data(htslib::BCF_HT_INT).map(|data| {
             data.map(|ret| {
                 let values = unsafe {
                     slice::from_raw_parts(self.buffer.borrow().inner as *const i32, ret as usize)
                 };
-                BufferBacked::new(&values[..ret as usize], self.buffer)
+                BufferBacked::new(values, self.buffer)
             })
         })
     }
 
-    /// Get floats from tag. `None` if tag not present in record.
-    ///
-    /// Import `bcf::record::Numeric` for missing value handling.
+    /// Get floats from tag as stored by htslib, i.e. with missing values encoded as
+    /// `MISSING_FLOAT`. `None` if tag not present in record.
     ///
     /// **Attention:** the returned BufferBacked which holds the data has to be kept in scope
     /// as along as the data is accessed. If parts of the data are accessed while
     /// the BufferBacked object is already dropped, you will access unallocated
     /// memory.
-    pub fn float(mut self) -> Result<Option<BufferBacked<'b, &'b [f32], B>>> {
+    pub(crate) fn float_raw(mut self) -> Result<Option<BufferBacked<'b, &'b [f32], B>>> {
         self.data(htslib::BCF_HT_REAL).map(|data| {
             data.map(|ret| {
                 let values = unsafe {
                     slice::from_raw_parts(self.buffer.borrow().inner as *const f32, ret as usize)
                 };
-                BufferBacked::new(&values[..ret as usize], self.buffer)
+                BufferBacked::new(values, self.buffer)
             })
         })
     }
 
     /// Get flags from tag. `false` if not set.
+    ///
+    /// Returns `Error::BcfUnexpectedType` if the tag is defined in the header, but not as a
+    /// `Flag`, and `Error::BcfUndefinedTag` if it is not defined at all.
     pub fn flag(&mut self) -> Result<bool> {
-        self.data(htslib::BCF_HT_FLAG).map(|data| match data {
-            Some(ret) => ret == 1,
-            None => false,
-        })
+        match self.data(htslib::BCF_HT_FLAG)? {
+            // htslib also reports flags that have been cleared in this record
+            Some(1) => Ok(self.is_set()),
+            _ => Ok(false),
+        }
+    }
+
+    /// Whether the tag is present in the record and not marked for removal.
+    fn is_set(&self) -> bool {
+        match self.record.header().name_to_id(self.tag) {
+            Ok(id) => self.record.info_ids().contains(&id),
+            Err(_) => false,
+        }
     }
 
     /// Get strings from tag. `None` if tag not present in record.
@@ -1352,6 +2221,18 @@ unsafe impl<'a, 'b, B: BorrowMut<Buffer> + Borrow<Buffer> + 'b> Send for Info<'a
 
 unsafe impl<'a, 'b, B: BorrowMut<Buffer> + Borrow<Buffer> + 'b> Sync for Info<'a, B> {}
 
+fn numeric_values<T: Numeric + NumericUtils + Copy>(s: &[T]) -> Vec<Option<T>> {
+    s.iter()
+        .map(|v| {
+            if v.is_missing() || v.is_vector_end() {
+                None
+            } else {
+                Some(*v)
+            }
+        })
+        .collect()
+}
+
 fn trim_slice<T: PartialEq + NumericUtils>(s: &[T]) -> &[T] {
     s.split(|v| v.is_vector_end())
         .next()
@@ -1429,13 +2310,35 @@ impl<'a, 'b, B: BorrowMut<Buffer> + Borrow<Buffer> + 'b> Format<'a, B> {
         }
     }
 
-    /// Get format data as integers.
+    /// Get format data as integers, with one vector per sample and missing values (`.`) as
+    /// `None`. Padding at the end of samples with fewer values is removed.
+    pub fn integer(self) -> Result<Vec<Vec<Option<i32>>>> {
+        Ok(self
+            .integer_raw()?
+            .iter()
+            .map(|values| numeric_values(values))
+            .collect())
+    }
+
+    /// Get format data as floats, with one vector per sample and missing values (`.`) as
+    /// `None`. Padding at the end of samples with fewer values is removed.
+    pub fn float(self) -> Result<Vec<Vec<Option<f32>>>> {
+        Ok(self
+            .float_raw()?
+            .iter()
+            .map(|values| numeric_values(values))
+            .collect())
+    }
+
+    /// Get format data as integers as stored by htslib, with one slice per sample. Padding at
+    /// the end of samples with fewer values is removed, but missing values are encoded as
+    /// `MISSING_INTEGER`.
     ///
     /// **Attention:** the returned BufferBacked which holds the data has to be kept in scope
     /// as long as the data is accessed. If parts of the data are accessed while
     /// the BufferBacked object is already dropped, you will access unallocated
     /// memory.
-    pub fn integer(mut self) -> Result<BufferBacked<'b, Vec<&'b [i32]>, B>> {
+    pub(crate) fn integer_raw(mut self) -> Result<BufferBacked<'b, Vec<&'b [i32]>, B>> {
         self.data(htslib::BCF_HT_INT).map(|ret| {
             BufferBacked::new(
                 unsafe {
@@ -1452,13 +2355,15 @@ impl<'a, 'b, B: BorrowMut<Buffer> + Borrow<Buffer> + 'b> Format<'a, B> {
         })
     }
 
-    /// Get format data as floats.
+    /// Get format data as floats as stored by htslib, with one slice per sample. Padding at
+    /// the end of samples with fewer values is removed, but missing values are encoded as
+    /// `MISSING_FLOAT`.
     ///
     /// **Attention:** the returned BufferBacked which holds the data has to be kept in scope
     /// as along as the data is accessed. If parts of the data are accessed while
     /// the BufferBacked object is already dropped, you will access unallocated
     /// memory.
-    pub fn float(mut self) -> Result<BufferBacked<'b, Vec<&'b [f32]>, B>> {
+    pub(crate) fn float_raw(mut self) -> Result<BufferBacked<'b, Vec<&'b [f32]>, B>> {
         self.data(htslib::BCF_HT_REAL).map(|ret| {
             BufferBacked::new(
                 unsafe {
@@ -1592,8 +2497,12 @@ mod tests {
         record.clear();
 
         assert_eq!(record.rlen(), 0);
-        assert_eq!(record.sample_count(), 0);
-        assert_eq!(record.pos(), 0)
+        // the samples of the header are kept, so that the record can be written
+        assert_eq!(record.sample_count(), 1);
+        assert_eq!(record.pos(), 0);
+        assert!(record.alleles().is_empty());
+        assert_eq!(record.id(), b".");
+        assert!(record.qual().is_missing());
     }
 
     #[test]
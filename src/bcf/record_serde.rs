@@ -0,0 +1,319 @@
+//! Serialization of BCF records with serde.
+//!
+//! A record is only meaningful together with its header, which defines its contigs, tags and
+//! samples. Hence, records are serialized in their BCF encoding along with a checksum of the
+//! header, and deserialized with a [`RecordSeed`](struct.RecordSeed.html) that provides the
+//! header. Like records read from a BCF file, deserialized records are validated by htslib, and
+//! unlike VCF text, the encoding preserves floats exactly. Deserializing with a header differing
+//! from the one used for serialization fails.
+//!
+//! # Example
+//!
+//! ```
+//! use rust_htslib::bcf::{record_serde::RecordSeed, Read, Reader, Record};
+//! use serde::de::DeserializeSeed;
+//!
+//! let mut reader = Reader::from_path("test/test_string.vcf").unwrap();
+//! let record = reader.records().next().unwrap().unwrap();
+//! let json = serde_json::to_string(&record).unwrap();
+//!
+//! let seed = RecordSeed::new(reader.header());
+//! let mut deserializer = serde_json::Deserializer::from_str(&json);
+//! let decoded: Record = seed.deserialize(&mut deserializer).unwrap();
+//! assert_eq!(decoded.pos(), record.pos());
+//! ```
+
+use std::os::raw::c_char;
+use std::rc::Rc;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer};
+use serde::{ser, Serialize, Serializer};
+use serde_bytes::ByteBuf;
+
+use crate::bcf::header::HeaderView;
+use crate::bcf::record::Record;
+use crate::htslib;
+use crate::utils;
+
+/// The serialized representation of a record.
+#[derive(Serialize, serde::Deserialize)]
+#[serde(rename = "Record")]
+struct RawRecord {
+    header_checksum: u64,
+    /// The record as stored in a BCF file.
+    bcf: ByteBuf,
+}
+
+/// The header in BCF format (i.e. including the IDX fields).
+fn header_text(header: &HeaderView) -> Vec<u8> {
+    let mut text = htslib::kstring_t {
+        l: 0,
+        m: 0,
+        s: std::ptr::null_mut(),
+    };
+    unsafe { htslib::bcf_hdr_format(header.inner, 1, &mut text) };
+    if text.s.is_null() {
+        return Vec::new();
+    }
+    let bytes =
+        unsafe { std::slice::from_raw_parts(text.s as *const u8, text.l as usize) }.to_vec();
+    unsafe { libc::free(text.s as *mut libc::c_void) };
+    bytes
+}
+
+/// FNV-1a hash of the header in BCF format.
+fn header_checksum(header: &HeaderView) -> u64 {
+    header_text(header)
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+unsafe fn block(s: &htslib::kstring_t) -> &[u8] {
+    if s.s.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(s.s as *const u8, s.l as usize)
+    }
+}
+
+impl Serialize for Record {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // copying the record encodes any modifications into the BCF blocks
+        let synced = self.clone();
+        let inner = synced.inner();
+        if inner.pos > i64::from(i32::MAX) || inner.rlen > i64::from(i32::MAX) {
+            return Err(ser::Error::custom("BCF record position too large for BCF"));
+        }
+        let (shared, indiv) = unsafe { (block(&inner.shared), block(&inner.indiv)) };
+        // the fixed fields, as written by bcf_write
+        let mut bcf = Vec::with_capacity(32 + shared.len() + indiv.len());
+        for x in [
+            shared.len() as u32 + 24,
+            indiv.len() as u32,
+            inner.rid as u32,
+            inner.pos as u32,
+            inner.rlen as u32,
+            inner.qual.to_bits(),
+            inner.n_info() | inner.n_allele() << 16,
+            inner.n_sample() | inner.n_fmt() << 24,
+        ] {
+            bcf.extend_from_slice(&x.to_le_bytes());
+        }
+        bcf.extend_from_slice(shared);
+        bcf.extend_from_slice(indiv);
+        RawRecord {
+            header_checksum: header_checksum(self.header()),
+            bcf: ByteBuf::from(bcf),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserializes a record for the given header, which has to be equal to the header of the
+/// serialized record. Cloning the seed is cheap, so that it can be used for many records.
+#[derive(Debug, Clone)]
+pub struct RecordSeed {
+    header: Rc<HeaderView>,
+}
+
+impl RecordSeed {
+    pub fn new(header: &HeaderView) -> Self {
+        RecordSeed {
+            header: Rc::new(header.clone()),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for RecordSeed {
+    type Value = Record;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Record, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = RawRecord::deserialize(deserializer)?;
+        if raw.header_checksum != header_checksum(&self.header) {
+            return Err(de::Error::custom(
+                "BCF record was serialized with a different header",
+            ));
+        }
+        let text = header_text(&self.header);
+        // Read the record from an in-memory BCF file, such that it is validated by htslib like
+        // any record read from a file.
+        let mut file = b"BCF\x02\x02".to_vec();
+        file.extend_from_slice(&(text.len() as u32 + 1).to_le_bytes());
+        file.extend_from_slice(&text);
+        file.push(0);
+        file.extend_from_slice(&raw.bcf);
+        // the in-memory file takes ownership of the buffer
+        let buf = unsafe { libc::malloc(file.len()) } as *mut u8;
+        if buf.is_null() {
+            return Err(de::Error::custom("failed to allocate BCF record"));
+        }
+        let mut record = Record::new(self.header);
+        let ret = unsafe {
+            std::ptr::copy_nonoverlapping(file.as_ptr(), buf, file.len());
+            let hfile = utils::hopen(
+                b"mem:\0".as_ptr() as *const c_char,
+                b"r:\0".as_ptr() as *const c_char,
+                buf,
+                file.len(),
+            );
+            if hfile.is_null() {
+                return Err(de::Error::custom("failed to open BCF record"));
+            }
+            let fp = htslib::hts_hopen(
+                hfile,
+                b"mem:\0".as_ptr() as *const c_char,
+                b"r\0".as_ptr() as *const c_char,
+            );
+            if fp.is_null() {
+                utils::hclose(hfile);
+                return Err(de::Error::custom("failed to open BCF record"));
+            }
+            let header = htslib::bcf_hdr_read(fp);
+            let ret = if header.is_null() {
+                -1
+            } else {
+                htslib::bcf_hdr_destroy(header);
+                htslib::bcf_read(fp, record.header().inner, record.inner_mut())
+            };
+            htslib::hts_close(fp);
+            ret
+        };
+        if ret != 0 {
+            return Err(de::Error::custom("invalid BCF record"));
+        }
+        unsafe { htslib::bcf_unpack(record.inner_mut(), htslib::BCF_UN_ALL as i32) };
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcf::{Read, Reader};
+
+    use bincode::Options;
+
+    fn bincode_round_trip(records: &[Record], header: &HeaderView) -> Vec<Record> {
+        let seed = RecordSeed::new(header);
+        records
+            .iter()
+            .map(|record| {
+                let encoded = bincode::serialize(record).unwrap();
+                bincode::DefaultOptions::new()
+                    .with_fixint_encoding()
+                    .deserialize_seed(seed.clone(), &encoded)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bincode_multiallelic_multisample() {
+        let mut reader = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+        let mut records: Vec<Record> = reader.records().map(|r| r.unwrap()).collect();
+        // modifications have to be serialized as well
+        records[1].set_pos(29);
+        records[1].push_info_float(b"AF", &[0.25, 0.5]).unwrap();
+
+        let decoded = bincode_round_trip(&records, reader.header());
+        assert_eq!(decoded.len(), 2);
+        for (record, decoded) in records.iter().zip(&decoded) {
+            assert_eq!(decoded.rid(), record.rid());
+            assert_eq!(decoded.pos(), record.pos());
+            assert_eq!(decoded.qual().to_bits(), record.qual().to_bits());
+            assert_eq!(decoded.alleles(), record.alleles());
+            assert_eq!(decoded.sample_count(), 2);
+            assert_eq!(
+                decoded.info(b"AF").float().unwrap(),
+                record.info(b"AF").float().unwrap()
+            );
+            assert_eq!(
+                decoded.format(b"GT").integer_raw().unwrap().to_vec(),
+                record.format(b"GT").integer_raw().unwrap().to_vec()
+            );
+        }
+        assert_eq!(decoded[1].pos(), 29);
+        assert_eq!(decoded[0].genotypes().unwrap().get(1).to_string(), "2|2");
+        assert_eq!(
+            decoded[0].format(b"PL").integer().unwrap()[1],
+            [Some(60), Some(70), Some(80), Some(90), Some(100), Some(0)]
+        );
+    }
+
+    #[test]
+    fn test_serde_json_header_mismatch() {
+        let mut reader = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+        let record = reader.records().next().unwrap().unwrap();
+        let json = serde_json::to_string(&record).unwrap();
+
+        let other = Reader::from_path("test/test_string.vcf").unwrap();
+        let seed = RecordSeed::new(other.header());
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        assert!(seed.deserialize(&mut deserializer).is_err());
+
+        let seed = RecordSeed::new(reader.header());
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let decoded = seed.deserialize(&mut deserializer).unwrap();
+        assert_eq!(decoded.alleles(), vec![b"A", b"C", b"G"]);
+    }
+
+    #[test]
+    fn test_serde_json_malformed_record() {
+        let mut reader = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+        let record = reader.records().next().unwrap().unwrap();
+        let raw: serde_json::Value = serde_json::to_value(&record).unwrap();
+        let bcf: Vec<u8> = serde_json::from_value(raw["bcf"].clone()).unwrap();
+        let seed = RecordSeed::new(reader.header());
+
+        let mut too_long = bcf.clone();
+        too_long[0] = too_long[0].wrapping_add(100);
+        let mut too_many_info = bcf.clone();
+        too_many_info[24] = 100;
+        let mut bad_type = bcf.clone();
+        // the type of the ID, the first value of the shared block
+        bad_type[32] = 0xff;
+        for malformed in [
+            bcf[..bcf.len() - 1].to_vec(),
+            too_long,
+            too_many_info,
+            bad_type,
+        ] {
+            let mut raw = raw.clone();
+            raw["bcf"] = serde_json::to_value(malformed).unwrap();
+            assert!(seed.clone().deserialize(&raw).is_err());
+        }
+        let decoded = seed.deserialize(&raw).unwrap();
+        assert_eq!(decoded.alleles(), record.alleles());
+    }
+
+    #[test]
+    fn test_bincode_floats_exact() {
+        let mut reader = Reader::from_path("test/test_trim_alleles.vcf").unwrap();
+        let mut record = reader.records().next().unwrap().unwrap();
+        let third = 1.0f32 / 3.0;
+        record.set_qual(29.123455);
+        record
+            .push_info_float(b"AF", &[third, 2.0 * third])
+            .unwrap();
+
+        let decoded = &bincode_round_trip(&[record], reader.header())[0];
+        assert_eq!(decoded.qual().to_bits(), 29.123455f32.to_bits());
+        let af: Vec<u32> = decoded
+            .info(b"AF")
+            .float()
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|v| v.unwrap().to_bits())
+            .collect();
+        assert_eq!(af, vec![third.to_bits(), (2.0 * third).to_bits()]);
+    }
+}
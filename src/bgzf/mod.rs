@@ -6,6 +6,26 @@
 //!
 //! Module for working with bgzipped file.
 //!
+//! `Reader` and `Writer` implement the standard `std::io` traits, so arbitrary text or binary
+//! data can be written as BGZF (e.g. to tabix-index it afterwards) and read back, for example
+//! line by line via `std::io::BufRead`.
+//!
+//! # Example
+//!
+//! ```
+//! use rust_htslib::bgzf;
+//! use std::io::{BufRead, Write};
+//!
+//! let tmp = tempfile::NamedTempFile::new().unwrap();
+//! {
+//!     let mut writer = bgzf::Writer::from_path(tmp.path()).unwrap();
+//!     writer.write_all(b"chr1\t10\t20\nchr1\t30\t40\n").unwrap();
+//! }
+//! assert!(bgzf::Reader::is_bgzf(tmp.path()).unwrap());
+//! let reader = bgzf::Reader::from_path(tmp.path()).unwrap();
+//! let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+//! assert_eq!(lines, ["chr1\t10\t20", "chr1\t30\t40"]);
+//! ```
 
 use std::ffi;
 use std::path::Path;
@@ -87,9 +107,56 @@ impl Reader {
         let mode = ffi::CString::new("r").unwrap();
         let cpath = ffi::CString::new(path).unwrap();
         let inner = unsafe { htslib::bgzf_open(cpath.as_ptr(), mode.as_ptr()) };
+        if inner.is_null() {
+            return Err(Error::BgzfOpen {
+                target: String::from_utf8_lossy(path).into_owned(),
+            });
+        }
         Ok(Self { inner })
     }
 
+    /// Test if a file is BGZIP compressed, see `is_bgzip`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to test.
+    pub fn is_bgzf<P: AsRef<Path>>(path: P) -> Result<bool> {
+        is_bgzip(path)
+    }
+
+    /// Return the current virtual file offset, i.e. the offset of the current BGZF block in the
+    /// compressed file in the upper 48 bits and the offset within the uncompressed block in the
+    /// lower 16 bits. Such offsets can be passed to `seek`, e.g. to build custom indices.
+    pub fn tell(&self) -> i64 {
+        let fp = unsafe { &*self.inner };
+        (fp.block_address << 16) | (i64::from(fp.block_offset) & 0xFFFF)
+    }
+
+    /// Seek to the given virtual file offset, as returned by `tell`.
+    /// Only works for BGZIP compressed files.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - the virtual file offset to seek to.
+    pub fn seek(&mut self, offset: i64) -> Result<()> {
+        let ret = unsafe { htslib::bgzf_seek(self.inner, offset, libc::SEEK_SET) };
+        if ret < 0 {
+            Err(Error::FileSeek)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Use the given number of threads for decompression, in addition to the main thread.
+    /// This creates a thread pool owned by the reader; use `set_thread_pool` to share one.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_threads` - the number of threads to use.
+    pub fn set_threads(&mut self, n_threads: usize) -> Result<()> {
+        set_threads(self.inner, n_threads)
+    }
+
     /// Set the thread pool to use for parallel decompression.
     ///
     /// # Arguments
@@ -109,6 +176,17 @@ impl Reader {
     }
 }
 
+/// Implementation for `Reader::set_threads` and `Writer::set_threads`.
+fn set_threads(fp: *mut htslib::BGZF, n_threads: usize) -> Result<()> {
+    assert!(n_threads != 0, "n_threads must be > 0");
+
+    if unsafe { htslib::bgzf_mt(fp, n_threads as i32, 256) } != 0 {
+        Err(Error::SetThreads)
+    } else {
+        Ok(())
+    }
+}
+
 impl std::io::Read for Reader {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let nbytes = unsafe {
@@ -129,6 +207,46 @@ impl std::io::Read for Reader {
     }
 }
 
+impl std::io::BufRead for Reader {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        // Like bgzf_getline, this directly exposes the current uncompressed block.
+        // After a seek, the offset can point to the end of a block, which is why this loops.
+        loop {
+            let fp = unsafe { &*self.inner };
+            if fp.block_offset < fp.block_length {
+                return Ok(unsafe {
+                    std::slice::from_raw_parts(
+                        (fp.uncompressed_block as *const u8).add(fp.block_offset as usize),
+                        (fp.block_length - fp.block_offset) as usize,
+                    )
+                });
+            }
+            if unsafe { htslib::bgzf_read_block(self.inner) } != 0 {
+                return Err(std::io::Error::other("Can not read"));
+            }
+            if unsafe { (*self.inner).block_length } == 0 {
+                // end of file
+                return Ok(&[]);
+            }
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let fp = unsafe { &mut *self.inner };
+        let amt = amt.min((fp.block_length - fp.block_offset).max(0) as usize);
+        fp.block_offset += amt as i32;
+        fp.uncompressed_address += amt as i64;
+    }
+}
+
+impl std::ops::Drop for Reader {
+    fn drop(&mut self) {
+        unsafe {
+            htslib::bgzf_close(self.inner);
+        }
+    }
+}
+
 /// The CompressionLevel used by the underlying GZIP writer
 /// Note that the special level NoCompression will not use the GZIP writer.
 /// Compression levels in BGZF files
@@ -215,6 +333,11 @@ impl Writer {
         let mode = Self::get_open_mode(level)?;
         let cpath = ffi::CString::new(path).unwrap();
         let inner = unsafe { htslib::bgzf_open(cpath.as_ptr(), mode.as_ptr()) };
+        if inner.is_null() {
+            return Err(Error::BgzfOpen {
+                target: String::from_utf8_lossy(path).into_owned(),
+            });
+        }
         Ok(Self { inner, tpool: None })
     }
 
@@ -253,6 +376,16 @@ impl Writer {
             Ok(())
         }
     }
+
+    /// Use the given number of threads for compression, in addition to the main thread.
+    /// This creates a thread pool owned by the writer; use `set_thread_pool` to share one.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_threads` - the number of threads to use.
+    pub fn set_threads(&mut self, n_threads: usize) -> Result<()> {
+        set_threads(self.inner, n_threads)
+    }
 }
 
 impl std::io::Write for Writer {
@@ -604,4 +737,89 @@ mod tests {
 
         tmp.close().expect("Failed to delete temp dir");
     }
+
+    #[test]
+    fn test_write_read_lines_seek_tabix() {
+        use crate::tbx::{self, Read as TbxRead};
+        use std::io::BufRead;
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let out_path = tmp.path().join("test.bed.gz");
+
+        // a few MB, i.e. many BGZF blocks
+        let n_lines = 200_000;
+        {
+            let mut w = Writer::from_path_with_level(&out_path, CompressionLevel::Fastest).unwrap();
+            w.set_threads(2).unwrap();
+            for i in 0..n_lines {
+                writeln!(w, "chr1\t{}\t{}\tfeature{}", i * 10, i * 10 + 5, i).unwrap();
+            }
+        }
+        assert!(Reader::is_bgzf(&out_path).unwrap());
+
+        // read back line by line, remembering the virtual offset of some lines
+        let mut reader = Reader::from_path(&out_path).unwrap();
+        let mut offsets = Vec::new();
+        let mut line = String::new();
+        for i in 0..n_lines {
+            if i % 50_000 == 7 {
+                offsets.push((i, reader.tell()));
+            }
+            line.clear();
+            assert!(reader.read_line(&mut line).unwrap() > 0);
+            assert_eq!(
+                line,
+                format!("chr1\t{}\t{}\tfeature{}\n", i * 10, i * 10 + 5, i)
+            );
+        }
+        line.clear();
+        assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+        assert_eq!(unsafe { htslib::bgzf_check_EOF(reader.inner) }, 1);
+
+        for &(i, offset) in offsets.iter().rev() {
+            reader.seek(offset).unwrap();
+            assert_eq!(reader.tell(), offset);
+            let line = reader.by_ref().lines().next().unwrap().unwrap();
+            assert_eq!(
+                line,
+                format!("chr1\t{}\t{}\tfeature{}", i * 10, i * 10 + 5, i)
+            );
+        }
+
+        let mut reader = Reader::from_path(&out_path).unwrap();
+        reader.set_threads(2).unwrap();
+        assert_eq!(reader.lines().count(), n_lines);
+
+        // the file is accepted by tabix
+        let cpath = ffi::CString::new(out_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            unsafe { htslib::tbx_index_build(cpath.as_ptr(), 0, &htslib::tbx_conf_bed) },
+            0
+        );
+        let mut tbx_reader = tbx::Reader::from_path(&out_path).unwrap();
+        let tid = tbx_reader.tid("chr1").unwrap();
+        tbx_reader.fetch(tid, 1_000_000, 1_000_100).unwrap();
+        assert_eq!(tbx_reader.records().count(), 10);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_open_missing() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let out_path = tmp.path().join("missing").join("test.gz");
+        assert_eq!(
+            Writer::from_path(&out_path).unwrap_err(),
+            Error::BgzfOpen {
+                target: out_path.to_str().unwrap().to_owned()
+            }
+        );
+        tmp.close().expect("Failed to delete temp dir");
+    }
 }
@@ -12,43 +12,87 @@ pub enum Error {
     FileNotFound { path: PathBuf },
     #[error("invalid (non-unicode) characters in path")]
     NonUnicodePath,
-    #[error("failed to fetch region")]
-    Fetch,
+    #[error("cannot check for the EOF marker (file is not seekable or not BGZF/CRAM)")]
+    CheckEof,
+    #[error("failed to fetch region {region:?}")]
+    Fetch { region: String },
     #[error("error seeking to file offset")]
     FileSeek,
     #[error("error seeking to {contig:?}:{start} in indexed file")]
     GenomicSeek { contig: String, start: u64 },
     #[error("sequence {sequence} not found in index")]
     UnknownSequence { sequence: String },
+    #[error("contig {contig:?} not found in header (known contigs: {known})")]
+    ContigNotFound { contig: String, known: String },
+    #[error("invalid region {region:?}")]
+    InvalidRegion { region: String },
+    #[error("failed to read BED file {target}")]
+    BedRead { target: String },
+    #[error("invalid BED line {line} in {target} (expected contig, start and end)")]
+    BedInvalidLine { target: String, line: u64 },
+    #[error("invalid quality character {character:?} at position {position} for offset {offset}")]
+    InvalidQuality {
+        character: char,
+        position: usize,
+        offset: u8,
+    },
+    #[error("failed to read {target} to detect its format")]
+    DetectFormat { target: String },
     #[error("error setting threads for file reading")]
     SetThreads,
     #[error("failed to create htslib thread pool")]
     ThreadPool,
+    #[error("network error while accessing {target}")]
+    Network { target: String },
+    #[error("refusing to read from STDIN, which is a terminal (pipe a file into it instead)")]
+    StdinIsTerminal,
 
     #[error("failed to write BAM/BCF record (out of disk space?)")]
     WriteRecord,
+    #[error("failed to write record {index}: {source}")]
+    WriteRecordAt { index: usize, source: Box<Error> },
+    #[error("failed to flush output file (out of disk space?)")]
+    FileFlush,
+    #[error("failed to close output file, its end may not have been written (out of disk space?)")]
+    FileClose,
 
     // Errors for faidx
+    #[error("unable to open FASTA file {target} or build its index")]
+    FaidxOpen { target: String },
     #[error("The given position is too large to be converted to i64")]
     FaidxPositionTooLarge,
     #[error("bad conversion of sequence name")]
     FaidxBadSeqName,
 
     // Errors for Tbx
+    #[error("failed to open tabix-indexed file {target}")]
+    TabixOpen { target: String },
     #[error("previous iterator generation failed")]
     TabixNoIter,
     #[error("truncated tabix record")]
     TabixTruncatedRecord,
-    #[error("invalid tabix index")]
-    TabixInvalidIndex,
+    #[error("invalid or missing tabix index for {target}")]
+    TabixInvalidIndex { target: String },
+    #[error("file {target} is not BGZF compressed (compress it with bgzip)")]
+    TabixNotBgzf { target: String },
+    #[error("file {target} is not sorted by position (first unsorted line: {line})")]
+    TabixUnsorted { target: String, line: u64 },
+    #[error("failed to build tabix index for {target}")]
+    TabixBuildIndex { target: String },
 
     // Errors for BAM
     #[error("error parsing CIGAR string: {msg}")]
     BamParseCigar { msg: String },
     #[error("unexpected CIGAR operation: {msg}")]
     BamUnexpectedCigarOperation { msg: String },
+    #[error("error parsing MD tag: {msg}")]
+    BamParseMd { msg: String },
+    #[error("MD tag is inconsistent with CIGAR: {msg}")]
+    BamMdCigarMismatch { msg: String },
     #[error("error parsing SAM record: {rec}")]
     BamParseSAM { rec: String },
+    #[error("failed to format record as SAM (is its contig in the header?)")]
+    BamFormatSAM,
     #[error("invalid path to CRAM-reference {path}")]
     BamInvalidReferencePath { path: PathBuf },
     #[error("invalid compression level {level}")]
@@ -57,6 +101,8 @@ pub enum Error {
     BamOpen { target: String },
     #[error("unable to open SAM/BAM/CRAM index for {target}; please create an index")]
     BamInvalidIndex { target: String },
+    #[error("no SAM/BAM/CRAM index found for {target}; please create an index")]
+    BamIndexNotFound { target: String },
     #[error("invalid record in SAM/BAM/CRAM file")]
     BamInvalidRecord,
     #[error("truncated record in SAM/BAM/CRAM file")]
@@ -73,6 +119,33 @@ pub enum Error {
     BamPileup,
     #[error("file is not sorted by position")]
     BamUnsorted,
+    #[error("record has no header (this is the case if it has not been read from a file)")]
+    BamRecordWithoutHeader,
+    #[error("name of contig {tid} is not valid UTF-8: {name:?}")]
+    BamContigNameNotUtf8 { tid: i32, name: String },
+    #[error("record {qname} is unmapped")]
+    BamUnmappedRecord { qname: String },
+    #[error("no reference set for SAM/BAM/CRAM reader (use set_reference)")]
+    BamReferenceNotSet,
+    #[error("failed to compute BAQ of record {qname}")]
+    BamBaq { qname: String },
+    #[error("records have to be grouped by query name, but the file is sorted by coordinate")]
+    BamCoordinateSorted,
+    #[error(
+        "records of read {qname} do not form a pair, is the file grouped by query name (e.g. with samtools sort -n or samtools collate)?"
+    )]
+    BamNotQueryGrouped { qname: String },
+    #[error("invalid record {qname}: {}", .errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    BamInvalidRecordFields {
+        qname: String,
+        errors: Vec<crate::bam::record::ValidationError>,
+    },
+    #[error("failed to write record {qname} to SAM/BAM/CRAM file (out of disk space?)")]
+    BamWriteRecord { qname: String },
+    #[error("contig {contig} of record is not in the header of the SAM/BAM/CRAM writer")]
+    BamWriteUnknownContig { contig: String },
+    #[error("expected {expected} bases or qualities (the read length), got {actual}")]
+    BamReadLengthMismatch { expected: usize, actual: usize },
 
     // Errors for BAM auxiliary fields
     #[error("failed to add aux field (out of memory?)")]
@@ -85,6 +158,8 @@ pub enum Error {
     BamAuxTagNotFound,
     #[error("data type of aux field is not known")]
     BamAuxUnknownType,
+    #[error("aux field {tag} does not have the requested type")]
+    BamAuxTypeMismatch { tag: String },
     #[error("failed to add aux field, tag is already present")]
     BamAuxTagAlreadyPresent,
 
@@ -98,13 +173,33 @@ pub enum Error {
     #[error("base modification found too many modifications")]
     BamBaseModificationTooManyMods,
 
+    // Errors for SAM
+    #[error("{target} is not a SAM text file")]
+    SamInvalidFormat { target: String },
+    #[error("invalid SAM record at line {line}")]
+    SamInvalidRecord { line: u64 },
+
     // Errors for BCF
     #[error("error allocating internal data structure for BCF/VCF reader (out of memory?)")]
     BcfAllocationError,
     #[error("failed to open BCF/VCF from {target:?}")]
     BcfOpen { target: String },
+    #[error("invalid or missing index for BCF/VCF file {target}")]
+    BcfInvalidIndex { target: String },
+    #[error("file {target} is neither BCF nor BGZF compressed VCF (compress it with bgzip)")]
+    BcfNotIndexable { target: String },
+    #[error("BCF/VCF file {target} is not sorted by position (first unsorted record: {record})")]
+    BcfUnsorted { target: String, record: String },
+    #[error("failed to build index for BCF/VCF file {target}")]
+    BcfBuildIndex { target: String },
+    #[error("failed to write index for BCF/VCF file {target} (out of disk space?)")]
+    BcfWriteIndex { target: String },
+    #[error("failed to write record {record} to BCF/VCF file (out of disk space?)")]
+    BcfWriteRecord { record: String },
     #[error("invalid record in BCF/VCF file")]
     BcfInvalidRecord,
+    #[error("truncated record in BCF/VCF file")]
+    BcfTruncatedRecord,
     #[error("tag {tag} undefined in BCF/VCF header")]
     BcfUndefinedTag { tag: String },
     #[error("unexpected type for tag {tag} in BCF/VCF file")]
@@ -113,6 +208,14 @@ pub enum Error {
     BcfMissingTag { tag: String, record: String },
     #[error("error setting tag {tag} in BCF/VCF record (out of memory?)")]
     BcfSetTag { tag: String },
+    #[error("tag {tag} takes {expected} values in BCF/VCF record, but {actual} were given")]
+    BcfInvalidTagLength {
+        tag: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("values given for tag {tag} do not match the {samples} samples of the BCF/VCF header")]
+    BcfSampleCountMismatch { tag: String, samples: u32 },
     #[error("ID {rid} not found in BCF/VCF header")]
     BcfUnknownRID { rid: u32 },
     #[error("contig {contig} not found in BCF/VCF header")]
@@ -121,13 +224,31 @@ pub enum Error {
     BcfUnknownID { id: String },
     #[error("sample {name} not found in BCF/VCF header")]
     BcfUnknownSample { name: String },
+    #[error("sample index {index} out of range for the {samples} samples of the BCF/VCF record")]
+    BcfInvalidSampleIndex { index: usize, samples: u32 },
     #[error("duplicate sample names given for subsetting BCF/VCF")]
     BcfDuplicateSampleNames,
+    #[error("failed to set the samples to read from BCF/VCF")]
+    BcfSetSamples,
+    #[error("invalid BCF/VCF header line {line:?}")]
+    BcfInvalidHeaderRecord { line: String },
     #[error("failed to set values in BCF/VCF record (out of memory?)")]
     BcfSetValues,
+    #[error("invalid ID {id:?} for BCF/VCF record (must not contain whitespace)")]
+    BcfInvalidId { id: String },
+    #[error("cannot change the number of alleles of a BCF/VCF record with Number=A/R/G tag {tag}; remove the tag first or use remove_alleles")]
+    BcfAlleleDependentTag { tag: String },
     #[error("failed to remove alleles in BCF/VCF record")]
     BcfRemoveAlleles,
+    #[error("allele mask must have one entry per allele of the BCF/VCF record and must not remove the reference allele")]
+    BcfInvalidAlleleMask,
+    #[error(
+        "cannot split BCF/VCF record {record}: the values of tag {tag} do not match the alleles"
+    )]
+    BcfSplitAlleles { tag: String, record: String },
 
+    #[error("failed to open BGZF file {target}")]
+    BgzfOpen { target: String },
     #[error("invalid compression level {level}")]
     BgzfInvalidCompressionLevel { level: i8 },
     #[error("failed setting hts reading options")]
@@ -6,6 +6,9 @@
 //!
 //! Module for working with faidx-indexed FASTA files.
 //!
+//! Both plain and bgzip-compressed FASTA files are supported. A missing index (`.fai`, and
+//! `.gzi` for compressed files) is built when opening the file.
+//!
 
 use std::ffi;
 use std::path::Path;
@@ -50,37 +53,102 @@ impl Reader {
     fn new(path: &[u8]) -> Result<Self, Error> {
         let cpath = ffi::CString::new(path).unwrap();
         let inner = unsafe { htslib::fai_load(cpath.as_ptr()) };
+        if inner.is_null() {
+            return Err(Error::FaidxOpen {
+                target: String::from_utf8_lossy(path).into_owned(),
+            });
+        }
         Ok(Self { inner })
     }
 
-    /// Fetch the sequence as a byte array.
+    /// Fetch the sequence of the `0`-based, half-open region `[begin, end)` as a byte array.
+    ///
+    /// Returns `Error::UnknownSequence` if there is no sequence with the given name, and
+    /// `Error::InvalidRegion` if `begin` is not within the sequence or `end` is smaller than
+    /// `begin`. An `end` beyond the sequence is clamped to its length, and `end == begin`
+    /// yields an empty sequence.
     ///
     /// # Arguments
     ///
     /// * `name` - the name of the template sequence (e.g., "chr1")
     /// * `begin` - the offset within the template sequence (starting with 0)
-    /// * `end` - the end position to return (if smaller than `begin`, the behavior is undefined).
-    pub fn fetch_seq<N: AsRef<str>>(&self, name: N, begin: usize, end: usize) -> Result<&[u8]> {
+    /// * `end` - the (exclusive) end position to return
+    pub fn fetch_seq_range<N: AsRef<str>>(
+        &self,
+        name: N,
+        begin: usize,
+        end: usize,
+    ) -> Result<Vec<u8>> {
         if begin > std::i64::MAX as usize {
             return Err(Error::FaidxPositionTooLarge);
         }
         if end > std::i64::MAX as usize {
             return Err(Error::FaidxPositionTooLarge);
         }
-        let cname = ffi::CString::new(name.as_ref().as_bytes()).unwrap();
-        let len_out: i64 = 0;
-        let cseq = unsafe {
-            let ptr = htslib::faidx_fetch_seq64(
-                self.inner,                          //*const faidx_t,
-                cname.as_ptr(),                      // c_name
-                begin as htslib::hts_pos_t,          // p_beg_i
-                end as htslib::hts_pos_t,            // p_end_i
-                &mut (len_out as htslib::hts_pos_t), //len
-            );
-            ffi::CStr::from_ptr(ptr)
+        let name = name.as_ref();
+        let region = || format!("{}:{}-{}", name, begin + 1, end);
+        if end < begin || begin as u64 >= self.seq_len(name)? {
+            return Err(Error::InvalidRegion { region: region() });
+        }
+        if end == begin {
+            return Ok(Vec::new());
+        }
+        let cname = ffi::CString::new(name.as_bytes()).unwrap();
+        let mut len: htslib::hts_pos_t = 0;
+        let ptr = unsafe {
+            htslib::faidx_fetch_seq64(
+                self.inner,
+                cname.as_ptr(),
+                begin as htslib::hts_pos_t,
+                // faidx_fetch_seq64 takes an inclusive end
+                end as htslib::hts_pos_t - 1,
+                &mut len,
+            )
         };
+        if ptr.is_null() {
+            return Err(Error::Fetch { region: region() });
+        }
+        // The sequence is allocated by htslib, so copy it and free it.
+        let seq = unsafe { std::slice::from_raw_parts(ptr as *const u8, len as usize) }.to_vec();
+        unsafe { libc::free(ptr as *mut libc::c_void) };
+        Ok(seq)
+    }
 
-        Ok(cseq.to_bytes())
+    /// Fetch the sequence of the `0`-based, half-open region `[begin, end)` as string.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the template sequence (e.g., "chr1")
+    /// * `begin` - the offset within the template sequence (starting with 0)
+    /// * `end` - the (exclusive) end position to return
+    ///
+    /// Errors are returned as for [`fetch_seq_range`](#method.fetch_seq_range).
+    pub fn fetch_seq_range_string<N: AsRef<str>>(
+        &self,
+        name: N,
+        begin: usize,
+        end: usize,
+    ) -> Result<String> {
+        let bytes = self.fetch_seq_range(name, begin, end)?;
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    /// Fetch the sequence as a byte array.
+    ///
+    /// Errors are returned as for [`fetch_seq_range`](#method.fetch_seq_range). An `end`
+    /// beyond the sequence is clamped to its last base.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the template sequence (e.g., "chr1")
+    /// * `begin` - the offset within the template sequence (starting with 0)
+    /// * `end` - the (inclusive) end position to return
+    #[deprecated(
+        since = "0.46.0",
+        note = "Please use fetch_seq_range, which takes an exclusive end, instead."
+    )]
+    pub fn fetch_seq<N: AsRef<str>>(&self, name: N, begin: usize, end: usize) -> Result<Vec<u8>> {
+        self.fetch_seq_range(name, begin, end.saturating_add(1))
     }
 
     /// Fetches the sequence and returns it as string.
@@ -89,15 +157,18 @@ impl Reader {
     ///
     /// * `name` - the name of the template sequence (e.g., "chr1")
     /// * `begin` - the offset within the template sequence (starting with 0)
-    /// * `end` - the end position to return (if smaller than `begin`, the behavior is undefined).
+    /// * `end` - the (inclusive) end position to return
+    #[deprecated(
+        since = "0.46.0",
+        note = "Please use fetch_seq_range_string, which takes an exclusive end, instead."
+    )]
     pub fn fetch_seq_string<N: AsRef<str>>(
         &self,
         name: N,
         begin: usize,
         end: usize,
     ) -> Result<String> {
-        let bytes = self.fetch_seq(name, begin, end)?;
-        Ok(std::str::from_utf8(bytes).unwrap().to_owned())
+        self.fetch_seq_range_string(name, begin, end.saturating_add(1))
     }
 
     /// Fetches the number of sequences in the fai index
@@ -112,6 +183,9 @@ impl Reader {
     ///
     /// * `i` - index to query
     pub fn seq_name(&self, i: i32) -> Result<String> {
+        if i < 0 || i as u64 >= self.n_seqs() {
+            return Err(Error::InvalidTid { tid: i });
+        }
         let cname = unsafe {
             let ptr = htslib::faidx_iseq(self.inner, i);
             ffi::CStr::from_ptr(ptr)
@@ -127,7 +201,24 @@ impl Reader {
         Ok(out)
     }
 
-    /// Fetches the length of the given sequence name.
+    /// Return the length of the sequence with the given name, or `Error::UnknownSequence` if
+    /// there is no such sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the template sequence (e.g., "chr1")
+    pub fn seq_len<N: AsRef<str>>(&self, name: N) -> Result<u64> {
+        let cname = ffi::CString::new(name.as_ref().as_bytes()).unwrap();
+        match unsafe { htslib::faidx_seq_len(self.inner, cname.as_ptr()) } {
+            len if len < 0 => Err(Error::UnknownSequence {
+                sequence: name.as_ref().to_owned(),
+            }),
+            len => Ok(len as u64),
+        }
+    }
+
+    /// Fetches the length of the given sequence name. Unlike [`seq_len`](#method.seq_len),
+    /// this returns a meaningless value if there is no such sequence.
     ///
     /// # Arguments
     ///
@@ -165,11 +256,11 @@ mod tests {
     fn faidx_read_chr_first_base() {
         let r = open_reader();
 
-        let bseq = r.fetch_seq("chr1", 0, 0).unwrap();
+        let bseq = r.fetch_seq_range("chr1", 0, 1).unwrap();
         assert_eq!(bseq.len(), 1);
         assert_eq!(bseq, b"G");
 
-        let seq = r.fetch_seq_string("chr1", 0, 0).unwrap();
+        let seq = r.fetch_seq_range_string("chr1", 0, 1).unwrap();
         assert_eq!(seq.len(), 1);
         assert_eq!(seq, "G");
     }
@@ -178,11 +269,11 @@ mod tests {
     fn faidx_read_chr_start() {
         let r = open_reader();
 
-        let bseq = r.fetch_seq("chr1", 0, 9).unwrap();
+        let bseq = r.fetch_seq_range("chr1", 0, 10).unwrap();
         assert_eq!(bseq.len(), 10);
         assert_eq!(bseq, b"GGGCACAGCC");
 
-        let seq = r.fetch_seq_string("chr1", 0, 9).unwrap();
+        let seq = r.fetch_seq_range_string("chr1", 0, 10).unwrap();
         assert_eq!(seq.len(), 10);
         assert_eq!(seq, "GGGCACAGCC");
     }
@@ -191,24 +282,40 @@ mod tests {
     fn faidx_read_chr_between() {
         let r = open_reader();
 
-        let bseq = r.fetch_seq("chr1", 4, 14).unwrap();
+        let bseq = r.fetch_seq_range("chr1", 4, 15).unwrap();
         assert_eq!(bseq.len(), 11);
         assert_eq!(bseq, b"ACAGCCTCACC");
 
-        let seq = r.fetch_seq_string("chr1", 4, 14).unwrap();
+        let seq = r.fetch_seq_range_string("chr1", 4, 15).unwrap();
         assert_eq!(seq.len(), 11);
         assert_eq!(seq, "ACAGCCTCACC");
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn faidx_read_inclusive_end() {
+        let r = open_reader();
+
+        assert_eq!(r.fetch_seq("chr1", 4, 14).unwrap(), b"ACAGCCTCACC");
+        assert_eq!(r.fetch_seq_string("chr1", 0, 0).unwrap(), "G");
+        assert_eq!(r.fetch_seq("chr1", 110, 200).unwrap(), b"CCCCTCCGTG");
+        assert_eq!(
+            r.fetch_seq("chr1", 120, 130),
+            Err(Error::InvalidRegion {
+                region: "chr1:121-131".to_owned()
+            })
+        );
+    }
+
     #[test]
     fn faidx_read_chr_end() {
         let r = open_reader();
 
-        let bseq = r.fetch_seq("chr1", 110, 120).unwrap();
+        let bseq = r.fetch_seq_range("chr1", 110, 120).unwrap();
         assert_eq!(bseq.len(), 10);
         assert_eq!(bseq, b"CCCCTCCGTG");
 
-        let seq = r.fetch_seq_string("chr1", 110, 120).unwrap();
+        let seq = r.fetch_seq_range_string("chr1", 110, 120).unwrap();
         assert_eq!(seq.len(), 10);
         assert_eq!(seq, "CCCCTCCGTG");
     }
@@ -216,11 +323,11 @@ mod tests {
     #[test]
     fn faidx_read_twice_string() {
         let r = open_reader();
-        let seq = r.fetch_seq_string("chr1", 110, 120).unwrap();
+        let seq = r.fetch_seq_range_string("chr1", 110, 120).unwrap();
         assert_eq!(seq.len(), 10);
         assert_eq!(seq, "CCCCTCCGTG");
 
-        let seq = r.fetch_seq_string("chr1", 5, 9).unwrap();
+        let seq = r.fetch_seq_range_string("chr1", 5, 10).unwrap();
         assert_eq!(seq.len(), 5);
         assert_eq!(seq, "CAGCC");
     }
@@ -228,11 +335,11 @@ mod tests {
     #[test]
     fn faidx_read_twice_bytes() {
         let r = open_reader();
-        let seq = r.fetch_seq("chr1", 110, 120).unwrap();
+        let seq = r.fetch_seq_range("chr1", 110, 120).unwrap();
         assert_eq!(seq.len(), 10);
         assert_eq!(seq, b"CCCCTCCGTG");
 
-        let seq = r.fetch_seq("chr1", 5, 9).unwrap();
+        let seq = r.fetch_seq_range("chr1", 5, 10).unwrap();
         assert_eq!(seq.len(), 5);
         assert_eq!(seq, b"CAGCC");
     }
@@ -241,7 +348,7 @@ mod tests {
     fn faidx_position_too_large() {
         let r = open_reader();
         let position_too_large = i64::MAX as usize;
-        let res = r.fetch_seq("chr1", position_too_large, position_too_large + 1);
+        let res = r.fetch_seq_range("chr1", position_too_large, position_too_large + 1);
         assert_eq!(res, Err(Error::FaidxPositionTooLarge));
     }
 
@@ -267,6 +374,63 @@ mod tests {
         assert_eq!(chr2_len, 120u64);
     }
 
+    #[test]
+    fn faidx_errors() {
+        let r = open_reader();
+        assert_eq!(
+            r.fetch_seq_range("chr4", 0, 10),
+            Err(Error::UnknownSequence {
+                sequence: "chr4".to_owned()
+            })
+        );
+        assert_eq!(
+            r.fetch_seq_range("chr1", 120, 130),
+            Err(Error::InvalidRegion {
+                region: "chr1:121-130".to_owned()
+            })
+        );
+        assert!(r.fetch_seq_range("chr1", 10, 9).is_err());
+        assert_eq!(r.fetch_seq_range("chr1", 10, 10), Ok(Vec::new()));
+        assert_eq!(r.seq_len("chr1"), Ok(120));
+        assert!(r.seq_len("chr4").is_err());
+        assert_eq!(r.seq_name(3), Err(Error::InvalidTid { tid: 3 }));
+        assert_eq!(
+            Reader::from_path("test/test.bam").unwrap_err(),
+            Error::FaidxOpen {
+                target: "test/test.bam".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn faidx_bgzip() {
+        use std::io::Write;
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let fasta =
+            std::fs::read(format!("{}/test/test_cram.fa", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let path = tmp.path().join("test.fa.gz");
+        let mut writer = crate::bgzf::Writer::from_path(&path).unwrap();
+        writer.write_all(&fasta).unwrap();
+        drop(writer);
+
+        // the .fai and .gzi indices are built when opening the file
+        let r = Reader::from_path(&path).unwrap();
+        assert!(tmp.path().join("test.fa.gz.fai").exists());
+        assert!(tmp.path().join("test.fa.gz.gzi").exists());
+        assert_eq!(r.n_seqs(), 3);
+        assert_eq!(r.fetch_seq_range("chr1", 110, 120).unwrap(), b"CCCCTCCGTG");
+        assert_eq!(
+            r.fetch_seq_range("chr3", 0, 200).unwrap(),
+            open_reader().fetch_seq_range("chr3", 0, 120).unwrap()
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
     #[test]
     fn open_many_readers() {
         for _ in 0..500_000 {
@@ -129,6 +129,7 @@ pub mod bgzf;
 pub mod errors;
 pub mod faidx;
 pub mod htslib;
+pub mod sam;
 pub mod tbx;
 pub mod tpool;
 pub mod utils;
@@ -0,0 +1,191 @@
+//! Module for reading SAM text files.
+//!
+//! Records are parsed by htslib into `bam::Record`s, so they can be processed and written
+//! (e.g. with `bam::Writer`) exactly like records read from BAM files. SAM output is written
+//! with `bam::Writer` and `bam::Format::Sam`.
+//!
+//! # Example
+//!
+//! ```
+//! use rust_htslib::sam;
+//!
+//! let mut reader = sam::Reader::from_path("test/test_cram.sam").unwrap();
+//! let tid = reader.header().tid(b"chr1").unwrap();
+//! for record in reader.records() {
+//!     let record = record.unwrap();
+//!     if record.tid() == tid as i32 {
+//!         println!("{}", String::from_utf8_lossy(record.qname()));
+//!     }
+//! }
+//! ```
+
+use std::path::Path;
+
+use crate::bam::{self, HeaderView, Read};
+use crate::errors::{Error, Result};
+use crate::htslib;
+
+/// A SAM reader.
+#[derive(Debug)]
+pub struct Reader {
+    inner: bam::Reader,
+    line: u64,
+}
+
+impl Reader {
+    /// Create a new Reader from path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to open.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let target = path.as_ref().to_string_lossy().into_owned();
+        Self::new(bam::Reader::from_path(path)?, target)
+    }
+
+    /// Create a new Reader from STDIN, e.g. for the output of `samtools view -h`.
+    pub fn from_stdin() -> Result<Self> {
+        Self::new(bam::Reader::from_stdin()?, "-".to_owned())
+    }
+
+    fn new(inner: bam::Reader, target: String) -> Result<Self> {
+        let format = unsafe { (*inner.htsfile()).format.format };
+        if format != htslib::htsExactFormat_sam {
+            return Err(Error::SamInvalidFormat { target });
+        }
+        // Records start on the line after the header.
+        let line = inner
+            .header()
+            .as_bytes()
+            .iter()
+            .filter(|&&c| c == b'\n')
+            .count() as u64;
+        Ok(Reader { inner, line })
+    }
+
+    /// Return the header.
+    pub fn header(&self) -> &HeaderView {
+        self.inner.header()
+    }
+
+    /// Read the next SAM line into the given record.
+    /// Returns `None` at the end of the file, and `Error::SamInvalidRecord` with the offending
+    /// (1-based) line number if the line cannot be parsed.
+    pub fn read(&mut self, record: &mut bam::Record) -> Option<Result<()>> {
+        self.line += 1;
+        match self.inner.read(record)? {
+            Ok(()) => Some(Ok(())),
+            Err(Error::BamInvalidRecord) | Err(Error::BamTruncatedRecord) => {
+                Some(Err(Error::SamInvalidRecord { line: self.line }))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Iterator over the records of the file.
+    pub fn records(&mut self) -> Records<'_> {
+        Records { reader: self }
+    }
+}
+
+/// Iterator over the records of a SAM file.
+#[derive(Debug)]
+pub struct Records<'a> {
+    reader: &'a mut Reader,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<bam::Record>;
+
+    fn next(&mut self) -> Option<Result<bam::Record>> {
+        let mut record = bam::Record::new();
+        match self.reader.read(&mut record) {
+            None => None,
+            Some(Ok(_)) => Some(Ok(record)),
+            Some(Err(err)) => Some(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bam::{Format, Header, Writer};
+    use std::fs;
+
+    const SAM: &str = "@HD\tVN:1.6\tSO:unsorted\n\
+        @SQ\tSN:chr1\tLN:1000\n\
+        @SQ\tSN:chr2\tLN:2000\n\
+        r1\t0\tchr1\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\tXA:A:x\tXB:i:-5\tXC:f:0.5\tXD:Z:text\t\
+        XE:H:1AE301\tXF:B:c,-1,2\tXG:B:S,1,65535\tXH:B:i,-70000,70000\tXI:B:f,1.5,-2.5\n\
+        r2\t16\tchr2\t20\t30\t2S2M\t*\t0\t0\tTTGG\t*\tNM:i:0\n";
+
+    #[test]
+    fn test_read_sam() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let sampath = tmp.path().join("test.sam");
+        fs::write(&sampath, SAM).unwrap();
+
+        let mut reader = Reader::from_path(&sampath).unwrap();
+        assert_eq!(reader.header().tid(b"chr2"), Some(1));
+        let records: Vec<bam::Record> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].qname(), b"r1");
+        assert_eq!(records[0].pos(), 9);
+        assert_eq!(records[1].tid(), 1);
+        assert!(records[1].is_reverse());
+        assert_eq!(records[1].aux(b"NM").unwrap(), bam::record::Aux::U8(0));
+
+        // SAM -> Record -> SAM has to preserve everything, including H and B aux fields
+        let outpath = tmp.path().join("out.sam");
+        {
+            let header = Header::from_template(reader.header());
+            let mut writer = Writer::from_path(&outpath, &header, Format::Sam).unwrap();
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+        }
+        assert_eq!(fs::read_to_string(&outpath).unwrap(), SAM);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_read_invalid_sam() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let sampath = tmp.path().join("invalid.sam");
+        fs::write(
+            &sampath,
+            "@SQ\tSN:chr1\tLN:1000\n\
+             r1\t0\tchr1\t10\t60\t4M\t*\t0\t0\tACGT\tIIII\n\
+             r2\t0\tchr1\tnot_a_position\t60\t4M\t*\t0\t0\tACGT\tIIII\n",
+        )
+        .unwrap();
+
+        let mut reader = Reader::from_path(&sampath).unwrap();
+        let mut records = reader.records();
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(
+            records.next().unwrap().unwrap_err(),
+            Error::SamInvalidRecord { line: 3 }
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_fails_on_bam() {
+        assert_eq!(
+            Reader::from_path("test/test.bam").unwrap_err(),
+            Error::SamInvalidFormat {
+                target: "test/test.bam".to_owned()
+            }
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Module for building tabix indices, like `tabix -p bed`.
+//!
+//! # Example
+//!
+//! ```
+//! use rust_htslib::{bgzf, tbx};
+//! use rust_htslib::tbx::Read;
+//! use std::io::Write;
+//!
+//! let tmp = tempfile::Builder::new().suffix(".bed.gz").tempfile().unwrap();
+//! {
+//!     let mut writer = bgzf::Writer::from_path(tmp.path()).unwrap();
+//!     writer.write_all(b"chr1\t10\t20\nchr1\t30\t40\n").unwrap();
+//! }
+//! tbx::index::build(tmp.path(), 0, &tbx::index::Config::Bed).unwrap();
+//!
+//! let mut reader = tbx::Reader::from_path(tmp.path()).unwrap();
+//! reader.fetch_str(b"chr1:25-35").unwrap();
+//! assert_eq!(reader.records().count(), 1);
+//! ```
+
+use std::io::BufRead;
+use std::path::Path;
+use std::ptr;
+
+use crate::bgzf;
+use crate::errors::{Error, Result};
+use crate::htslib;
+use crate::utils;
+
+/// Description of the columns of a tabix-indexed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Config {
+    /// BED files, like `tabix -p bed`.
+    Bed,
+    /// GFF or GTF files, like `tabix -p gff`.
+    Gff,
+    /// SAM files, like `tabix -p sam`.
+    Sam,
+    /// VCF files, like `tabix -p vcf`.
+    Vcf,
+    /// Any other tab-separated format.
+    Custom(CustomConfig),
+}
+
+/// Column layout of a custom tab-separated format, as given to `tabix -s -b -e -c -S -0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomConfig {
+    /// 1-based column of the sequence name.
+    pub seq_col: u32,
+    /// 1-based column of the begin position.
+    pub begin_col: u32,
+    /// 1-based column of the end position, or 0 if records only have a begin position.
+    pub end_col: u32,
+    /// Lines starting with this character are skipped as comments.
+    pub meta_char: u8,
+    /// Number of lines to skip at the beginning of the file.
+    pub line_skip: u32,
+    /// Whether positions are 0-based and intervals half-open (like in BED) rather than
+    /// 1-based and closed.
+    pub zero_based: bool,
+}
+
+impl Config {
+    fn to_conf(self) -> htslib::tbx_conf_t {
+        unsafe {
+            match self {
+                Config::Bed => htslib::tbx_conf_bed,
+                Config::Gff => htslib::tbx_conf_gff,
+                Config::Sam => htslib::tbx_conf_sam,
+                Config::Vcf => htslib::tbx_conf_vcf,
+                Config::Custom(custom) => htslib::tbx_conf_t {
+                    preset: if custom.zero_based {
+                        (htslib::TBX_GENERIC | htslib::TBX_UCSC) as i32
+                    } else {
+                        htslib::TBX_GENERIC as i32
+                    },
+                    sc: custom.seq_col as i32,
+                    bc: custom.begin_col as i32,
+                    // like tabix, use the begin column if there is no end column
+                    ec: if custom.end_col == 0 {
+                        custom.begin_col as i32
+                    } else {
+                        custom.end_col as i32
+                    },
+                    meta_char: i32::from(custom.meta_char),
+                    line_skip: custom.line_skip as i32,
+                },
+            }
+        }
+    }
+}
+
+/// Build a tabix index for the given BGZF compressed file, next to it.
+///
+/// With a `min_shift` of 0, a `.tbi` index is written, otherwise a `.csi` index with the given
+/// minimum shift (14 is the usual choice, e.g. for contigs longer than 512 Mbp).
+/// Returns `Error::TabixNotBgzf` if the file is not BGZF compressed, and
+/// `Error::TabixUnsorted` with the offending (1-based) line number if it is not sorted by
+/// position.
+pub fn build<P: AsRef<Path>>(path: P, min_shift: u32, config: &Config) -> Result<()> {
+    let path = path.as_ref();
+    let target = path.to_string_lossy().into_owned();
+    if !path.exists() {
+        return Err(Error::FileNotFound {
+            path: path.to_owned(),
+        });
+    }
+    let conf = config.to_conf();
+    let ret = unsafe {
+        htslib::tbx_index_build2(
+            utils::path_to_cstring(&path)
+                .ok_or(Error::NonUnicodePath)?
+                .as_ptr(),
+            ptr::null(),
+            min_shift as i32,
+            &conf,
+        )
+    };
+    match ret {
+        0 => Ok(()),
+        -2 => Err(Error::TabixNotBgzf { target }),
+        _ => Err(diagnose(path, &conf).unwrap_or(Error::TabixBuildIndex { target })),
+    }
+}
+
+/// Return the given 1-based column.
+fn column<'a>(fields: &[&'a [u8]], col: i32) -> Option<&'a [u8]> {
+    fields.get((col as usize).checked_sub(1)?).copied()
+}
+
+/// Find the first unsorted line of the given file, if any.
+fn diagnose(path: &Path, conf: &htslib::tbx_conf_t) -> Option<Error> {
+    let reader = bgzf::Reader::from_path(path).ok()?;
+    let mut lines = reader.split(b'\n').enumerate();
+    let unsorted = utils::find_unsorted(|| {
+        let (i, line) = lines.next()?;
+        // a read error ends the scan, leaving the failure unexplained
+        let line = line.ok()?;
+        if (i as i32) < conf.line_skip
+            || line.first().map(|&c| i32::from(c)) == Some(conf.meta_char)
+        {
+            return Some(Ok(None));
+        }
+        let fields: Vec<&[u8]> = line.split(|&c| c == b'\t').collect();
+        let position = column(&fields, conf.sc)
+            .zip(column(&fields, conf.bc))
+            .and_then(|(seq, begin)| {
+                let begin = std::str::from_utf8(begin).ok()?.parse().ok()?;
+                Some((seq.to_owned(), begin))
+            });
+        Some(Ok(position))
+    });
+    unsorted.ok()?.map(|i| Error::TabixUnsorted {
+        target: path.to_string_lossy().into_owned(),
+        line: i + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tbx::{self, Read};
+    use std::fs;
+    use std::io::Write;
+
+    fn write_bgzf(path: &Path, content: &[u8]) {
+        let mut writer = bgzf::Writer::from_path(path).unwrap();
+        writer.write_all(content).unwrap();
+    }
+
+    #[test]
+    fn test_build_bed() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let bed = tmp.path().join("test.bed.gz");
+        write_bgzf(
+            &bed,
+            b"#comment\nchr1\t100\t200\tA\nchr1\t150\t300\tB\nchr2\t10\t20\tC\n",
+        );
+
+        build(&bed, 0, &Config::Bed).unwrap();
+        assert!(tmp.path().join("test.bed.gz.tbi").exists());
+        let mut reader = tbx::Reader::from_path(&bed).unwrap();
+        assert_eq!(reader.header(), &vec!["#comment".to_owned()]);
+        reader.fetch_str(b"chr1:201-250").unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![b"chr1\t150\t300\tB".to_vec()]);
+
+        // CSI index
+        fs::remove_file(tmp.path().join("test.bed.gz.tbi")).unwrap();
+        build(&bed, 14, &Config::Bed).unwrap();
+        assert!(tmp.path().join("test.bed.gz.csi").exists());
+        let mut reader = tbx::Reader::from_path(&bed).unwrap();
+        reader.fetch_str(b"chr2").unwrap();
+        assert_eq!(reader.records().count(), 1);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_build_custom() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let tsv = tmp.path().join("test.tsv.gz");
+        write_bgzf(
+            &tsv,
+            b"value\tchrom\tpos\n0.5\tchr1\t1\n0.7\tchr1\t2\n0.1\tchr1\t3\n",
+        );
+
+        let config = Config::Custom(CustomConfig {
+            seq_col: 2,
+            begin_col: 3,
+            end_col: 0,
+            meta_char: b'#',
+            line_skip: 1,
+            zero_based: false,
+        });
+        build(&tsv, 0, &config).unwrap();
+        let mut reader = tbx::Reader::from_path(&tsv).unwrap();
+        assert_eq!(reader.header_lines(), vec![b"value\tchrom\tpos".to_vec()]);
+        reader.fetch_str(b"chr1:2-3").unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            records,
+            vec![b"0.7\tchr1\t2".to_vec(), b"0.1\tchr1\t3".to_vec()]
+        );
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+
+        let plain = tmp.path().join("plain.bed");
+        fs::write(&plain, b"chr1\t100\t200\n").unwrap();
+        assert_eq!(
+            build(&plain, 0, &Config::Bed),
+            Err(Error::TabixNotBgzf {
+                target: plain.to_str().unwrap().to_owned()
+            })
+        );
+
+        let unsorted = tmp.path().join("unsorted.bed.gz");
+        write_bgzf(
+            &unsorted,
+            b"chr1\t100\t200\nchr1\t300\t400\nchr1\t200\t300\n",
+        );
+        assert_eq!(
+            build(&unsorted, 0, &Config::Bed),
+            Err(Error::TabixUnsorted {
+                target: unsorted.to_str().unwrap().to_owned(),
+                line: 3
+            })
+        );
+
+        let interleaved = tmp.path().join("interleaved.bed.gz");
+        write_bgzf(
+            &interleaved,
+            b"chr1\t100\t200\nchr2\t100\t200\nchr1\t300\t400\n",
+        );
+        assert_eq!(
+            build(&interleaved, 0, &Config::Bed),
+            Err(Error::TabixUnsorted {
+                target: interleaved.to_str().unwrap().to_owned(),
+                line: 3
+            })
+        );
+
+        assert!(matches!(
+            build(tmp.path().join("missing.bed.gz"), 0, &Config::Bed),
+            Err(Error::FileNotFound { .. })
+        ));
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+}
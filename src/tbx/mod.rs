@@ -41,6 +41,8 @@
 //! }
 //! ```
 
+pub mod index;
+
 use std::ffi;
 use std::path::Path;
 use std::ptr;
@@ -48,7 +50,10 @@ use url::Url;
 
 use crate::errors::{Error, Result};
 use crate::htslib;
-use crate::utils::path_as_bytes;
+use crate::utils::{
+    contig_not_found, index_record_count, is_network_errno, parse_region, path_as_bytes,
+    HTS_POS_MAX,
+};
 
 /// A trait for a Tabix reader with a read method.
 pub trait Read: Sized {
@@ -87,6 +92,8 @@ pub trait Read: Sized {
 pub struct Reader {
     /// The header lines (if any).
     header: Vec<String>,
+    /// The header lines as read from the file.
+    header_lines: Vec<Vec<u8>>,
 
     /// The file to read from.
     hts_file: *mut htslib::htsFile,
@@ -119,11 +126,32 @@ impl Reader {
     ///
     /// * `path` - the path to open.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        Self::new(&path_as_bytes(path, true)?)
+        Self::new(&path_as_bytes(path, true)?, None)
     }
 
+    /// Create a new Reader from an URL, e.g. `https://...` or `s3://...`, using htslib's
+    /// support for remote files. The index is looked for next to the file and downloaded on
+    /// open. Only the blocks needed for fetched regions are then read, via range requests.
+    ///
+    /// Returns `Error::Network` if the server cannot be reached and
+    /// `Error::TabixInvalidIndex` if there is no index.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - the URL to open.
     pub fn from_url(url: &Url) -> Result<Self> {
-        Self::new(url.as_str().as_bytes())
+        Self::new(url.as_str().as_bytes(), None)
+    }
+
+    /// Create a new Reader from an URL, using the index at `index_url` instead of looking for
+    /// one next to the file. Otherwise like [`from_url`](#method.from_url).
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - the URL to open.
+    /// * `index_url` - the URL of the index.
+    pub fn from_url_and_index(url: &Url, index_url: &Url) -> Result<Self> {
+        Self::new(url.as_str().as_bytes(), Some(index_url.as_str().as_bytes()))
     }
 
     /// Create a new Reader.
@@ -131,37 +159,78 @@ impl Reader {
     /// # Arguments
     ///
     /// * `path` - the path.
-    fn new(path: &[u8]) -> Result<Self> {
+    /// * `index_path` - the path of the index, if not next to the file.
+    fn new(path: &[u8], index_path: Option<&[u8]>) -> Result<Self> {
+        let target = String::from_utf8_lossy(path).into_owned();
         let path = ffi::CString::new(path).unwrap();
         let c_str = ffi::CString::new("r").unwrap();
         let hts_file = unsafe { htslib::hts_open(path.as_ptr(), c_str.as_ptr()) };
+        if hts_file.is_null() {
+            let errno = std::io::Error::last_os_error().raw_os_error();
+            return Err(match errno {
+                Some(libc::ENOENT) => Error::FileNotFound {
+                    path: target.into(),
+                },
+                errno if is_network_errno(errno) => Error::Network { target },
+                _ => Error::TabixOpen { target },
+            });
+        }
         let hts_format: u32 = unsafe {
             let file_format: *const hts_sys::htsFormat = htslib::hts_get_format(hts_file);
             (*file_format).format
         };
 
-        let tbx = unsafe { htslib::tbx_index_load(path.as_ptr()) };
+        let tbx = unsafe {
+            match index_path {
+                Some(index_path) => {
+                    let index_path = ffi::CString::new(index_path).unwrap();
+                    htslib::tbx_index_load2(path.as_ptr(), index_path.as_ptr())
+                }
+                None => htslib::tbx_index_load(path.as_ptr()),
+            }
+        };
         if tbx.is_null() {
-            return Err(Error::TabixInvalidIndex);
+            let errno = std::io::Error::last_os_error().raw_os_error();
+            unsafe {
+                htslib::hts_close(hts_file);
+            }
+            return Err(if is_network_errno(errno) {
+                Error::Network { target }
+            } else {
+                Error::TabixInvalidIndex { target }
+            });
         }
-        let mut header = Vec::new();
+        let mut header_lines = Vec::new();
         let mut buf = htslib::kstring_t {
             l: 0,
             m: 0,
             s: ptr::null_mut(),
         };
         unsafe {
+            // Like tabix, treat the first `line_skip` lines and lines starting with the
+            // meta character as header.
+            let conf = &(*tbx).conf;
+            let mut lineno = 0;
             while htslib::hts_getline(hts_file, KS_SEP_LINE, &mut buf) >= 0 {
-                if buf.l > 0 && i32::from(*buf.s) == (*tbx).conf.meta_char {
-                    header.push(String::from(ffi::CStr::from_ptr(buf.s).to_str().unwrap()));
+                let line = std::slice::from_raw_parts(buf.s as *const u8, buf.l as usize);
+                if lineno < conf.line_skip
+                    || line.first().map(|&c| i32::from(c)) == Some(conf.meta_char)
+                {
+                    header_lines.push(line.to_owned());
+                    lineno += 1;
                 } else {
                     break;
                 }
             }
         }
+        let header = header_lines
+            .iter()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect();
 
         Ok(Reader {
             header,
+            header_lines,
             hts_file,
             hts_format,
             tbx,
@@ -208,14 +277,68 @@ impl Reader {
         };
         if itr.is_null() {
             self.itr = None;
-            Err(Error::Fetch)
+            let contig = self
+                .seqnames()
+                .into_iter()
+                .nth(tid as usize)
+                .unwrap_or_else(|| tid.to_string());
+            Err(Error::Fetch {
+                region: format!("{}:{}-{}", contig, start + 1, end),
+            })
         } else {
             self.itr = Some(itr);
             Ok(())
         }
     }
 
-    /// Return the sequence contig names.
+    /// Fetch the 0-based, half-open region `[start, end)` of the sequence with the given name.
+    /// Returns `Error::ContigNotFound`, which lists some sequences of the index, if there is no
+    /// sequence with this name.
+    pub fn fetch_by_name(&mut self, name: &[u8], start: u64, end: u64) -> Result<()> {
+        let tid = match str::from_utf8(name)
+            .ok()
+            .and_then(|name| self.tid(name).ok())
+        {
+            Some(tid) => tid,
+            None => {
+                let names = self.seqnames();
+                return Err(contig_not_found(name, names.iter().map(|n| n.as_bytes())));
+            }
+        };
+        self.fetch(tid, start, end)
+    }
+
+    /// Fetch all records of the sequence with the given name, without having to know its
+    /// length. Like `fetch_by_name`, returns `Error::ContigNotFound` for unknown sequences.
+    pub fn fetch_contig(&mut self, name: &[u8]) -> Result<()> {
+        self.fetch_by_name(name, 0, HTS_POS_MAX as u64)
+    }
+
+    /// Fetch region given by a samtools style region string such as `chr1:1000-2000`
+    /// (1-based, inclusive). `chr1` fetches the whole contig and `chr1:1000` everything from
+    /// position 1000 onwards. Contig names containing colons can be given as `{chr1:A}:1-100`.
+    pub fn fetch_str(&mut self, region: &[u8]) -> Result<()> {
+        let tbx = self.tbx;
+        let (tid, start, end) = parse_region(region, |name| {
+            let name = ffi::CString::new(name).ok()?;
+            let tid = unsafe { htslib::tbx_name2id(tbx, name.as_ptr()) };
+            if tid < 0 {
+                None
+            } else {
+                Some(tid)
+            }
+        })?;
+        self.fetch(tid as u64, start as u64, end as u64)
+    }
+
+    /// Return the header lines at the beginning of the file, i.e. lines starting with the
+    /// comment character of the index (e.g. `#`) and lines skipped when indexing, without
+    /// line breaks. Unlike `Read::header`, this does not require the lines to be valid UTF-8.
+    pub fn header_lines(&self) -> Vec<Vec<u8>> {
+        self.header_lines.clone()
+    }
+
+    /// Return the names of all sequences in the index, in the order of their numeric IDs.
     pub fn seqnames(&self) -> Vec<String> {
         let mut result = Vec::new();
 
@@ -237,6 +360,22 @@ impl Reader {
         result
     }
 
+    /// Return the number of records of each sequence as stored in the index, without reading
+    /// the file. The count of a sequence is `None` if the index does not store it, which is
+    /// the case for TBI files written by old versions of tabix.
+    pub fn index_stats(&self) -> IndexStats {
+        let idx = unsafe { (*self.tbx).idx };
+        IndexStats {
+            sequences: self
+                .seqnames()
+                .into_iter()
+                .enumerate()
+                .map(|(tid, name)| (name, unsafe { index_record_count(idx, tid as i32) }))
+                .collect(),
+            no_coordinate: unsafe { htslib::hts_idx_get_n_no_coor(idx) },
+        }
+    }
+
     /// Activate multi-threaded BGZF read support in htslib. This should permit faster
     /// reading of large BGZF files.
     ///
@@ -255,6 +394,26 @@ impl Reader {
     }
 }
 
+/// Number of records per sequence as stored in a tabix index, see `Reader::index_stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexStats {
+    /// Name and number of records of each sequence, in the order of their numeric IDs.
+    pub sequences: Vec<(String, Option<u64>)>,
+    /// Number of records without coordinates.
+    pub no_coordinate: u64,
+}
+
+impl IndexStats {
+    /// Total number of records, or `None` if the count of a sequence is unknown.
+    pub fn total(&self) -> Option<u64> {
+        self.sequences
+            .iter()
+            .map(|(_, count)| *count)
+            .sum::<Option<u64>>()
+            .map(|n| n + self.no_coordinate)
+    }
+}
+
 /// Return whether the two given genomic intervals overlap.
 fn overlap(tid1: i64, begin1: i64, end1: i64, tid2: i64, begin2: i64, end2: i64) -> bool {
     (tid1 == tid2) && (begin1 < end2) && (begin2 < end1)
@@ -388,6 +547,181 @@ mod tests {
         assert_eq!(records, vec![Vec::from("chr1\t1001\t1002")]);
     }
 
+    #[test]
+    fn bed_index_stats() {
+        let mut reader =
+            Reader::from_path("test/tabix_reader/test_bed3.bed.gz").expect("Error opening file.");
+        let stats = reader.index_stats();
+
+        let mut expected = Vec::new();
+        for name in reader.seqnames() {
+            reader.fetch_contig(name.as_bytes()).unwrap();
+            expected.push((name, Some(reader.records().count() as u64)));
+        }
+        assert_eq!(stats.sequences, expected);
+        assert!(expected.iter().all(|(_, n)| n.unwrap() > 0));
+        assert_eq!(stats.no_coordinate, 0);
+        assert_eq!(
+            stats.total(),
+            Some(expected.iter().map(|(_, n)| n.unwrap()).sum())
+        );
+    }
+
+    #[test]
+    fn index_stats_without_pseudo_bins() {
+        use std::io::{Read as _, Write as _};
+
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("regions.bed.gz");
+        let mut writer = crate::bgzf::Writer::from_path(&path).unwrap();
+        // the records of chr2 are far away from its start
+        writer
+            .write_all(b"chr1\t10\t20\nchr2\t1000000\t1000010\n")
+            .unwrap();
+        drop(writer);
+        index::build(&path, 0, &index::Config::Bed).unwrap();
+
+        // Remove the pseudo-bins holding the record counts, like in TBI files written by old
+        // versions of tabix.
+        let tbi = tmp.path().join("regions.bed.gz.tbi");
+        let mut data = Vec::new();
+        crate::bgzf::Reader::from_path(&tbi)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        let int = |at: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[at..at + 4]);
+            u32::from_le_bytes(bytes) as usize
+        };
+        let mut at = 36 + int(32);
+        let mut stripped = data[..at].to_vec();
+        for _ in 0..int(4) {
+            let (n_bin, mut bins, mut kept) = (int(at), Vec::new(), 0u32);
+            at += 4;
+            for _ in 0..n_bin {
+                let len = 8 + 16 * int(at + 4);
+                if int(at) != 37450 {
+                    bins.extend_from_slice(&data[at..at + len]);
+                    kept += 1;
+                }
+                at += len;
+            }
+            stripped.extend_from_slice(&kept.to_le_bytes());
+            stripped.extend_from_slice(&bins);
+            let len = 4 + 8 * int(at);
+            stripped.extend_from_slice(&data[at..at + len]);
+            at += len;
+        }
+        stripped.extend_from_slice(&data[at..]);
+        assert!(stripped.len() < data.len());
+        let mut writer = crate::bgzf::Writer::from_path(&tbi).unwrap();
+        writer.write_all(&stripped).unwrap();
+        drop(writer);
+
+        let mut reader = Reader::from_path(&path).unwrap();
+        let stats = reader.index_stats();
+        assert_eq!(
+            stats.sequences,
+            vec![("chr1".to_owned(), None), ("chr2".to_owned(), None)]
+        );
+        assert_eq!(stats.total(), None);
+        reader.fetch_by_name(b"chr2", 0, 2_000_000).unwrap();
+        assert_eq!(reader.records().count(), 1);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[test]
+    fn bed_fetch_by_name() {
+        let mut reader =
+            Reader::from_path("test/tabix_reader/test_bed3.bed.gz").expect("Error opening file.");
+
+        reader.fetch_by_name(b"chr1", 1000, 1003).unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![Vec::from("chr1\t1001\t1002")]);
+
+        let err = reader.fetch_by_name(b"1", 1000, 1003).unwrap_err();
+        assert_eq!(
+            err,
+            Error::ContigNotFound {
+                contig: "1".to_owned(),
+                known: reader.seqnames().join(", ")
+            }
+        );
+    }
+
+    #[test]
+    fn bed_fetch_str() {
+        let mut reader =
+            Reader::from_path("test/tabix_reader/test_bed3.bed.gz").expect("Error opening file.");
+
+        reader.fetch_str(b"chr1:1001-1003").unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![Vec::from("chr1\t1001\t1002")]);
+
+        reader.fetch_str(b"chr2").unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert!(records.iter().all(|r| r.starts_with(b"chr2\t")));
+        assert!(!records.is_empty());
+
+        assert_eq!(
+            reader.fetch_str(b"chr3:1-100"),
+            Err(Error::UnknownSequence {
+                sequence: "chr3".to_owned()
+            })
+        );
+        assert_eq!(
+            reader.fetch_str(b"chr1:100-x"),
+            Err(Error::InvalidRegion {
+                region: "chr1:100-x".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn bed_fetch_contig_and_last_record() {
+        let mut reader =
+            Reader::from_path("test/tabix_reader/test_bed3.bed.gz").expect("Error opening file.");
+
+        reader.fetch_contig(b"chr1").unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            records,
+            vec![Vec::from("chr1\t1001\t1002"), Vec::from("chr1\t1004\t1005")]
+        );
+
+        // The last record of chr1 covers the 1-based position 1005 only.
+        for (region, expected) in [
+            (&b"chr1:1005-1005"[..], 1),
+            (b"chr1:1004-1004", 0),
+            (b"chr1:1005", 1),
+            (b"chr1:1006", 0),
+        ] {
+            reader.fetch_str(region).unwrap();
+            let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+            assert_eq!(
+                records.len(),
+                expected,
+                "{}",
+                str::from_utf8(region).unwrap()
+            );
+            assert!(records.iter().all(|r| r == b"chr1\t1004\t1005"));
+        }
+
+        reader.fetch_contig(b"chr2").unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![Vec::from("chr2\t1005\t1006")]);
+
+        assert!(matches!(
+            reader.fetch_contig(b"chr3"),
+            Err(Error::ContigNotFound { .. })
+        ));
+    }
+
     #[test]
     fn test_fails_on_bam() {
         let reader = Reader::from_path("test/test.bam");
@@ -398,6 +732,12 @@ mod tests {
     fn test_fails_on_non_existiant() {
         let reader = Reader::from_path("test/no_such_file");
         assert!(reader.is_err());
+
+        let url = Url::parse("file:///no/such/file.bed.gz").unwrap();
+        assert!(matches!(
+            Reader::from_url(&url),
+            Err(Error::FileNotFound { .. })
+        ));
     }
 
     #[test]
@@ -406,6 +746,93 @@ mod tests {
         assert!(reader.is_err());
     }
 
+    #[test]
+    fn test_missing_index_names_file() {
+        let err = Reader::from_path("test/test_left.vcf").unwrap_err();
+        assert_eq!(
+            err,
+            Error::TabixInvalidIndex {
+                target: "test/test_left.vcf".to_owned()
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid or missing tabix index for test/test_left.vcf"
+        );
+    }
+
+    #[test]
+    fn test_header_lines_and_seqnames() {
+        let path = "test/tabix_reader/genomic_regions_header.txt.gz";
+        let reader = Reader::from_path(path).expect("Error opening file.");
+        assert_eq!(reader.header_lines(), vec![b"#contig\tstart\tend".to_vec()]);
+        assert_eq!(reader.header(), &vec!["#contig\tstart\tend".to_owned()]);
+        assert_eq!(reader.seqnames(), vec!["chr1", "chr2"]);
+
+        let url = Url::from_file_path(std::fs::canonicalize(path).unwrap()).unwrap();
+        let reader = Reader::from_url(&url).expect("Error opening file.");
+        assert_eq!(reader.header_lines(), vec![b"#contig\tstart\tend".to_vec()]);
+        assert_eq!(reader.seqnames(), vec!["chr1", "chr2"]);
+
+        let reader = Reader::from_path("test/tabix_reader/test_bed3.bed.gz").unwrap();
+        assert!(reader.header_lines().is_empty());
+    }
+
+    #[test]
+    fn test_from_url_and_index() {
+        let tmp = tempfile::Builder::new()
+            .prefix("rust-htslib")
+            .tempdir()
+            .expect("Cannot create temp dir");
+        let path = tmp.path().join("test.bed.gz");
+        let idx_path = tmp.path().join("other.tbi");
+        std::fs::copy("test/tabix_reader/test_bed3.bed.gz", &path).unwrap();
+        std::fs::copy("test/tabix_reader/test_bed3.bed.gz.tbi", &idx_path).unwrap();
+        let url = Url::from_file_path(&path).unwrap();
+        let idx_url = Url::from_file_path(&idx_path).unwrap();
+
+        assert_eq!(
+            Reader::from_url(&url).unwrap_err(),
+            Error::TabixInvalidIndex {
+                target: url.to_string()
+            }
+        );
+        let mut reader = Reader::from_url_and_index(&url, &idx_url).unwrap();
+        reader.fetch_str(b"chr1:1001-1003").unwrap();
+        let records: Vec<Vec<u8>> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![Vec::from("chr1\t1001\t1002")]);
+
+        tmp.close().expect("Failed to delete temp dir");
+    }
+
+    #[cfg(feature = "curl")]
+    #[test]
+    fn test_from_url_network_error() {
+        // nothing listens on port 1
+        let url = Url::parse("http://127.0.0.1:1/test.bed.gz").unwrap();
+        assert_eq!(
+            Reader::from_url(&url).unwrap_err(),
+            Error::Network {
+                target: url.to_string()
+            }
+        );
+    }
+
+    #[cfg(feature = "curl")]
+    #[test]
+    #[ignore = "requires network access"]
+    fn test_from_remote_url() {
+        let url = Url::parse(
+            "https://ftp.1000genomes.ebi.ac.uk/vol1/ftp/release/20130502/\
+             ALL.chrMT.phase3_callmom-v0_4.20130502.genotypes.vcf.gz",
+        )
+        .unwrap();
+        let mut reader = Reader::from_url(&url).unwrap();
+        assert_eq!(reader.seqnames(), vec!["MT"]);
+        reader.fetch_str(b"MT:1000-2000").unwrap();
+        assert!(reader.records().next().is_some());
+    }
+
     #[test]
     fn test_text_header_regions() {
         // This file has chromosome, start, and end positions with a header line.
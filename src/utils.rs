@@ -6,10 +6,82 @@
 //! Module with utility code.
 
 use crate::errors::{Error, Result};
+use crate::htslib;
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::ffi;
+use std::hash::Hash;
+use std::os::raw::{c_char, c_int, c_short, c_void};
 use std::path::Path;
 use std::ptr;
 
+extern "C" {
+    // Part of the bundled htslib, but missing from the generated bindings.
+    fn hts_flush(fp: *mut htslib::htsFile) -> c_int;
+    pub(crate) fn hopen(filename: *const c_char, mode: *const c_char, ...) -> *mut htslib::hFILE;
+    pub(crate) fn hclose(fp: *mut htslib::hFILE) -> c_int;
+    fn hts_idx_nseq(idx: *const htslib::hts_idx_t) -> c_int;
+    fn hts_detect_format2(
+        fp: *mut htslib::hFILE,
+        fname: *const c_char,
+        fmt: *mut htslib::htsFormat,
+    ) -> c_int;
+}
+
+/// # Safety
+///
+/// Implementation of `flush` for the SAM/BAM/CRAM and VCF/BCF writers.
+pub(crate) unsafe fn flush(htsfile: *mut htslib::htsFile) -> Result<()> {
+    if hts_flush(htsfile) < 0 {
+        Err(Error::FileFlush)
+    } else {
+        Ok(())
+    }
+}
+
+/// Implementation of `close` for the SAM/BAM/CRAM and VCF/BCF writers.
+///
+/// # Safety
+///
+/// `htsfile` must be valid and must not be used afterwards.
+pub(crate) unsafe fn close(htsfile: *mut htslib::htsFile) -> Result<()> {
+    if htslib::hts_close(htsfile) < 0 {
+        Err(Error::FileClose)
+    } else {
+        Ok(())
+    }
+}
+
+/// Number of records of the sequence `tid` as stored in the index `idx`. Sequences without
+/// records in the index have `Some(0)`, and `None` is returned if the index does not store the
+/// count (e.g. TBI files written by old versions of tabix).
+///
+/// # Safety
+///
+/// `idx` must be a valid index.
+pub(crate) unsafe fn index_record_count(idx: *const htslib::hts_idx_t, tid: i32) -> Option<u64> {
+    // hts_idx_get_stat does not check the bounds
+    if tid < 0 || tid >= hts_idx_nseq(idx) {
+        return Some(0);
+    }
+    let (mut mapped, mut unmapped) = (0, 0);
+    if htslib::hts_idx_get_stat(idx, tid, &mut mapped, &mut unmapped) == 0 {
+        return Some(mapped + unmapped);
+    }
+    // without any bins, the iterator over the whole sequence is finished right away
+    let itr = htslib::hts_itr_query(idx, tid, 0, HTS_POS_MAX, None);
+    if itr.is_null() {
+        return None;
+    }
+    let empty = (*itr).finished() != 0;
+    htslib::hts_itr_destroy(itr);
+    if empty {
+        Some(0)
+    } else {
+        None
+    }
+}
+
 /// Copies data from `src` to `dst`
 /// TODO remove once stable in standard library.
 ///
@@ -51,3 +123,708 @@ pub fn path_as_bytes<'a, P: 'a + AsRef<Path>>(path: P, must_exist: bool) -> Resu
         })
     }
 }
+
+/// Offset of the ASCII encoding of base qualities in SAM and (modern) FASTQ files.
+pub const PHRED33: u8 = 33;
+/// Offset of the ASCII encoding of base qualities in legacy (Illumina 1.3-1.7) FASTQ files.
+pub const PHRED64: u8 = 64;
+/// Largest quality that can be encoded with `PHRED33` (as `~`).
+pub const MAX_PHRED_QUAL: u8 = 93;
+
+/// Encode raw Phred qualities (as returned by `bam::Record::qual`) as ASCII with the given
+/// offset (usually `PHRED33`). Qualities that would exceed `~` are capped at `~`.
+pub fn phred_to_ascii(qual: &[u8], offset: u8) -> Vec<u8> {
+    let mut text = qual.to_owned();
+    phred_to_ascii_in_place(&mut text, offset);
+    text
+}
+
+/// Like `phred_to_ascii`, but encoding the qualities in place.
+pub fn phred_to_ascii_in_place(qual: &mut [u8], offset: u8) {
+    for q in qual {
+        *q = q.saturating_add(offset).min(b'~');
+    }
+}
+
+/// Decode ASCII encoded qualities with the given offset (usually `PHRED33`) into raw Phred
+/// qualities. Returns `Error::InvalidQuality` for characters below the offset or above `~`, or
+/// resulting in a quality above `MAX_PHRED_QUAL`.
+pub fn ascii_to_phred(text: &[u8], offset: u8) -> Result<Vec<u8>> {
+    let mut qual = text.to_owned();
+    ascii_to_phred_in_place(&mut qual, offset)?;
+    Ok(qual)
+}
+
+/// Like `ascii_to_phred`, but decoding the qualities in place. On error, the content of `text`
+/// is unspecified.
+pub fn ascii_to_phred_in_place(text: &mut [u8], offset: u8) -> Result<()> {
+    for (position, c) in text.iter_mut().enumerate() {
+        match c.checked_sub(offset) {
+            Some(q) if q <= MAX_PHRED_QUAL && *c <= b'~' => *c = q,
+            _ => {
+                return Err(Error::InvalidQuality {
+                    character: char::from(*c),
+                    position,
+                    offset,
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Mean of the given raw Phred qualities, computed in probability space, i.e. the quality
+/// corresponding to the mean error probability. This is dominated by the worst bases, e.g. the
+/// mean of 10 and 30 is about 13. Returns NaN if `qual` is empty.
+pub fn mean_qual(qual: &[u8]) -> f64 {
+    let error_prob = qual
+        .iter()
+        .map(|&q| 10f64.powf(-f64::from(q) / 10.0))
+        .sum::<f64>()
+        / qual.len() as f64;
+    -10.0 * error_prob.log10()
+}
+
+/// Arithmetic mean of the given raw Phred qualities (e.g. 20 for 10 and 30), which
+/// overestimates the accuracy compared to `mean_qual`. Returns NaN if `qual` is empty.
+pub fn mean_qual_naive(qual: &[u8]) -> f64 {
+    qual.iter().map(|&q| f64::from(q)).sum::<f64>() / qual.len() as f64
+}
+
+/// Verbosity of the messages htslib prints to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Off,
+    Error,
+    /// The default of htslib.
+    Warning,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn to_hts(self) -> htslib::htsLogLevel {
+        match self {
+            LogLevel::Off => htslib::htsLogLevel_HTS_LOG_OFF,
+            LogLevel::Error => htslib::htsLogLevel_HTS_LOG_ERROR,
+            LogLevel::Warning => htslib::htsLogLevel_HTS_LOG_WARNING,
+            LogLevel::Info => htslib::htsLogLevel_HTS_LOG_INFO,
+            LogLevel::Debug => htslib::htsLogLevel_HTS_LOG_DEBUG,
+            LogLevel::Trace => htslib::htsLogLevel_HTS_LOG_TRACE,
+        }
+    }
+
+    fn from_hts(level: htslib::htsLogLevel) -> Self {
+        match level {
+            htslib::htsLogLevel_HTS_LOG_OFF => LogLevel::Off,
+            htslib::htsLogLevel_HTS_LOG_ERROR => LogLevel::Error,
+            htslib::htsLogLevel_HTS_LOG_WARNING => LogLevel::Warning,
+            htslib::htsLogLevel_HTS_LOG_INFO => LogLevel::Info,
+            htslib::htsLogLevel_HTS_LOG_DEBUG => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
+/// Set the verbosity of htslib, e.g. `LogLevel::Error` to silence warnings like the one
+/// about a missing EOF marker when reading a streamed BAM file. The level is global to the
+/// process and applies to all readers and writers.
+///
+/// Note that htslib always prints its messages to stderr; the bundled version offers no way
+/// of redirecting them.
+pub fn set_log_level(level: LogLevel) {
+    unsafe { htslib::hts_set_log_level(level.to_hts()) }
+}
+
+/// Return the current verbosity of htslib.
+pub fn log_level() -> LogLevel {
+    LogLevel::from_hts(unsafe { htslib::hts_get_log_level() })
+}
+
+/// Kind of data of a file, see `detect_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FormatCategory {
+    /// Reads or sequences, e.g. SAM/BAM/CRAM or FASTA/FASTQ.
+    Sequence,
+    /// Variant calls, i.e. VCF/BCF.
+    Variant,
+    Index,
+    /// Genomic regions, i.e. BED.
+    Region,
+    Unknown,
+}
+
+/// Concrete format of a file, see `detect_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileFormat {
+    Sam,
+    Bam,
+    Cram,
+    Vcf,
+    Bcf,
+    Bed,
+    Fasta,
+    Fastq,
+    Bai,
+    Crai,
+    Csi,
+    Tbi,
+    Gzi,
+    Fai,
+    Fqi,
+    Htsget,
+    /// An empty file.
+    Empty,
+    /// Text in none of the formats above.
+    Text,
+    /// Binary data in none of the formats above.
+    Binary,
+    Unknown,
+}
+
+/// Compression of a file, see `detect_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+    None,
+    /// Plain gzip, which cannot be indexed (recompress with bgzip to do so).
+    Gzip,
+    Bgzf,
+    Bzip2,
+    /// Another compression, e.g. the format-specific block compression of CRAM.
+    Other,
+}
+
+/// The format of a file as detected by htslib, see `detect_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FormatInfo {
+    pub category: FormatCategory,
+    pub format: FileFormat,
+    pub compression: Compression,
+    /// Major version of the format, if it is known (e.g. 3 for CRAM 3.1).
+    pub version_major: Option<u16>,
+    /// Minor version of the format, if it is known (e.g. 1 for CRAM 3.1).
+    pub version_minor: Option<u16>,
+}
+
+impl FormatInfo {
+    fn from_hts(format: &htslib::htsFormat) -> Self {
+        let category = match format.category {
+            htslib::htsFormatCategory_sequence_data => FormatCategory::Sequence,
+            htslib::htsFormatCategory_variant_data => FormatCategory::Variant,
+            htslib::htsFormatCategory_index_file => FormatCategory::Index,
+            htslib::htsFormatCategory_region_list => FormatCategory::Region,
+            _ => FormatCategory::Unknown,
+        };
+        let file_format = match format.format {
+            htslib::htsExactFormat_sam => FileFormat::Sam,
+            htslib::htsExactFormat_bam => FileFormat::Bam,
+            htslib::htsExactFormat_cram => FileFormat::Cram,
+            htslib::htsExactFormat_vcf => FileFormat::Vcf,
+            htslib::htsExactFormat_bcf => FileFormat::Bcf,
+            htslib::htsExactFormat_bed => FileFormat::Bed,
+            htslib::htsExactFormat_fasta_format => FileFormat::Fasta,
+            htslib::htsExactFormat_fastq_format => FileFormat::Fastq,
+            htslib::htsExactFormat_bai => FileFormat::Bai,
+            htslib::htsExactFormat_crai => FileFormat::Crai,
+            htslib::htsExactFormat_csi => FileFormat::Csi,
+            htslib::htsExactFormat_tbi => FileFormat::Tbi,
+            htslib::htsExactFormat_gzi => FileFormat::Gzi,
+            htslib::htsExactFormat_fai_format => FileFormat::Fai,
+            htslib::htsExactFormat_fqi_format => FileFormat::Fqi,
+            htslib::htsExactFormat_htsget => FileFormat::Htsget,
+            htslib::htsExactFormat_empty_format => FileFormat::Empty,
+            htslib::htsExactFormat_text_format => FileFormat::Text,
+            htslib::htsExactFormat_binary_format => FileFormat::Binary,
+            _ => FileFormat::Unknown,
+        };
+        let compression = match format.compression {
+            htslib::htsCompression_no_compression => Compression::None,
+            htslib::htsCompression_gzip => Compression::Gzip,
+            htslib::htsCompression_bgzf => Compression::Bgzf,
+            htslib::htsCompression_bzip2_compression => Compression::Bzip2,
+            _ => Compression::Other,
+        };
+        let version = |v: c_short| u16::try_from(v).ok();
+        FormatInfo {
+            category,
+            format: file_format,
+            compression,
+            version_major: version(format.version.major),
+            version_minor: version(format.version.minor),
+        }
+    }
+}
+
+/// Detect the format of the file at the given path (or URL) with htslib, e.g. in order to
+/// decide whether to open it with `bam::Reader`, `bcf::Reader` or `tbx::Reader`. Only the
+/// first bytes of the file are read (decompressing them if it is compressed), and the
+/// extension is merely used to tell FASTA and FASTQ indices apart from BED files.
+///
+/// Returns `Error::FileNotFound` if the file does not exist, and `Error::DetectFormat` if it
+/// cannot be read.
+///
+/// # Example
+///
+/// ```
+/// use rust_htslib::utils::{detect_format, Compression, FileFormat, FormatCategory};
+///
+/// let info = detect_format("test/test.bcf").unwrap();
+/// assert_eq!(info.category, FormatCategory::Variant);
+/// assert_eq!(info.format, FileFormat::Bcf);
+/// assert_eq!(info.compression, Compression::Bgzf);
+/// ```
+pub fn detect_format<P: AsRef<Path>>(path: P) -> Result<FormatInfo> {
+    let target = path.as_ref().to_string_lossy().into_owned();
+    let c_path = ffi::CString::new(path_as_bytes(&path, false)?).unwrap();
+    let fp = unsafe { hopen(c_path.as_ptr(), b"r\0".as_ptr() as *const c_char) };
+    if fp.is_null() {
+        let errno = std::io::Error::last_os_error().raw_os_error();
+        return Err(match errno {
+            Some(libc::ENOENT) => Error::FileNotFound {
+                path: path.as_ref().to_owned(),
+            },
+            errno if is_network_errno(errno) => Error::Network { target },
+            _ => Error::DetectFormat { target },
+        });
+    }
+    let mut format = unsafe { std::mem::zeroed::<htslib::htsFormat>() };
+    let res = unsafe { hts_detect_format2(fp, c_path.as_ptr(), &mut format) };
+    unsafe { hclose(fp) };
+    if res < 0 {
+        Err(Error::DetectFormat { target })
+    } else {
+        Ok(FormatInfo::from_hts(&format))
+    }
+}
+
+/// Error for a contig that is not in the header, listing the first few contigs that are as a
+/// hint (e.g. for "chr1" vs "1" naming mismatches).
+pub(crate) fn contig_not_found<'a, I>(contig: &[u8], names: I) -> Error
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    const N_HINTS: usize = 5;
+    let mut names = names.into_iter();
+    let mut known: Vec<_> = names
+        .by_ref()
+        .take(N_HINTS)
+        .map(String::from_utf8_lossy)
+        .collect();
+    if names.next().is_some() {
+        known.push("...".into());
+    }
+    Error::ContigNotFound {
+        contig: String::from_utf8_lossy(contig).into_owned(),
+        known: if known.is_empty() {
+            "none".to_owned()
+        } else {
+            known.join(", ")
+        },
+    }
+}
+
+/// Largest position htslib can represent, used for regions without an end (`HTS_POS_MAX`).
+pub(crate) const HTS_POS_MAX: i64 = ((i32::MAX as i64) << 32) | i32::MAX as i64;
+
+/// Parse a region string of the form `chrom`, `chrom:beg` or `chrom:beg-end` (1-based,
+/// inclusive), returning the numeric id of `chrom` together with the 0-based, half-open
+/// interval `[beg, end)`. When no end is given, `end` is `HTS_POS_MAX`.
+///
+/// Parsing is delegated to `hts_parse_region`, so contig names containing colons are resolved
+/// the same way htslib does it, including the `{chrom}:beg-end` quoting syntax.
+/// `name2id` is used to resolve contig names and should return `None` for unknown contigs.
+pub(crate) fn parse_region<F>(region: &[u8], mut name2id: F) -> Result<(i32, i64, i64)>
+where
+    F: FnMut(&[u8]) -> Option<i32>,
+{
+    struct Lookup<'a> {
+        name2id: &'a mut dyn FnMut(&[u8]) -> Option<i32>,
+        last: Vec<u8>,
+        found: bool,
+    }
+
+    extern "C" fn getid(data: *mut c_void, name: *const c_char) -> c_int {
+        let lookup = unsafe { &mut *(data as *mut Lookup) };
+        let name = unsafe { ffi::CStr::from_ptr(name) }.to_bytes();
+        let id = (lookup.name2id)(name);
+        lookup.last = name.to_owned();
+        lookup.found = id.is_some();
+        id.unwrap_or(-1)
+    }
+
+    let invalid = || Error::InvalidRegion {
+        region: String::from_utf8_lossy(region).into_owned(),
+    };
+    let c_region = ffi::CString::new(region).map_err(|_| invalid())?;
+    let mut lookup = Lookup {
+        name2id: &mut name2id,
+        last: Vec::new(),
+        found: false,
+    };
+    let (mut tid, mut beg, mut end) = (0, 0, 0);
+    let res = unsafe {
+        htslib::hts_parse_region(
+            c_region.as_ptr(),
+            &mut tid,
+            &mut beg,
+            &mut end,
+            Some(getid),
+            &mut lookup as *mut Lookup as *mut c_void,
+            0,
+        )
+    };
+    if !res.is_null() {
+        Ok((tid, beg, end))
+    } else if tid < 0 && !lookup.found && !lookup.last.is_empty() {
+        Err(Error::UnknownSequence {
+            sequence: String::from_utf8_lossy(&lookup.last).into_owned(),
+        })
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Whether htslib failed to access a remote file because of the network (`hfile_libcurl`
+/// reports host lookup failures as `EDESTADDRREQ`).
+pub(crate) fn is_network_errno(errno: Option<i32>) -> bool {
+    matches!(
+        errno,
+        Some(
+            libc::EDESTADDRREQ
+                | libc::ECONNREFUSED
+                | libc::ECONNRESET
+                | libc::ECONNABORTED
+                | libc::EHOSTUNREACH
+                | libc::ENETUNREACH
+                | libc::ENETDOWN
+                | libc::ETIMEDOUT
+        )
+    )
+}
+
+/// Find the first record that keeps a file from being indexed, given the contigs and
+/// positions of its records in file order. Like htslib, positions must not decrease within a
+/// contig, and the records of each contig must form a single block. Records for which
+/// `next_position` returns `Ok(None)` (e.g. unplaced reads) are not checked.
+///
+/// Returns the (0-based) number of the first unsorted record, `None` if the records are sorted,
+/// or the first error returned by `next_position`.
+pub(crate) fn find_unsorted<K, F>(mut next_position: F) -> Result<Option<u64>>
+where
+    K: Eq + Hash + Clone,
+    F: FnMut() -> Option<Result<Option<(K, i64)>>>,
+{
+    let mut seen = HashSet::new();
+    let mut last: Option<(K, i64)> = None;
+    let mut i = 0;
+    while let Some(position) = next_position() {
+        if let Some((contig, pos)) = position? {
+            let unsorted = match &last {
+                Some((last_contig, last_pos)) if *last_contig == contig => pos < *last_pos,
+                _ => seen.contains(&contig),
+            };
+            if unsorted {
+                return Ok(Some(i));
+            }
+            seen.insert(contig.clone());
+            last = Some((contig, pos));
+        }
+        i += 1;
+    }
+    Ok(None)
+}
+
+/// Helpers for writing test files.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use crate::{bam, bcf};
+    use bam::Read as BamRead;
+    use bcf::Read as BcfRead;
+    use std::path::Path;
+
+    /// Read all records of the given BAM file, together with its header.
+    pub(crate) fn bam_records(path: &str) -> (Vec<bam::Record>, bam::HeaderView) {
+        let mut reader = bam::Reader::from_path(path).unwrap();
+        let records = reader.records().map(|r| r.unwrap()).collect();
+        (records, reader.header().clone())
+    }
+
+    pub(crate) fn write_bam(path: &Path, records: &[bam::Record], header: &bam::HeaderView) {
+        let header = bam::Header::from_template(header);
+        let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+    }
+
+    /// Read all records of the given VCF or BCF file.
+    pub(crate) fn bcf_records(path: &str) -> Vec<bcf::Record> {
+        let mut reader = bcf::Reader::from_path(path).unwrap();
+        reader.records().map(|r| r.unwrap()).collect()
+    }
+
+    pub(crate) fn write_bcf(path: &Path, records: &[bcf::Record], format: bcf::Format) {
+        let header = bcf::Header::from_template(records[0].header());
+        let mut writer = bcf::Writer::from_path(path, &header, false, format).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+    }
+
+    /// Cut off the last third of the given file.
+    pub(crate) fn truncate(path: &Path) {
+        let data = std::fs::read(path).unwrap();
+        std::fs::write(path, &data[..data.len() * 2 / 3]).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_unsorted() {
+        let find = |positions: &[Option<(&str, i64)>]| {
+            let mut positions = positions.iter().map(|&p| Ok(p));
+            find_unsorted(|| positions.next())
+        };
+        assert_eq!(find(&[]), Ok(None));
+        assert_eq!(
+            find(&[Some(("b", 1)), Some(("b", 1)), Some(("a", 0)), None]),
+            Ok(None)
+        );
+        assert_eq!(find(&[Some(("a", 2)), None, Some(("a", 1))]), Ok(Some(2)));
+        assert_eq!(
+            find(&[Some(("a", 0)), Some(("b", 0)), Some(("a", 1))]),
+            Ok(Some(2))
+        );
+
+        let mut positions = vec![Ok(Some((0, 1))), Err(Error::BamTruncatedRecord)].into_iter();
+        assert_eq!(
+            find_unsorted(|| positions.next()),
+            Err(Error::BamTruncatedRecord)
+        );
+    }
+
+    #[test]
+    fn test_detect_format() {
+        use Compression as C;
+        use FileFormat as F;
+        use FormatCategory as Cat;
+        for (path, category, format, compression, version) in [
+            (
+                "test/test.bam",
+                Cat::Sequence,
+                F::Bam,
+                C::Bgzf,
+                (Some(1), None),
+            ),
+            (
+                "test/test.bam.bai",
+                Cat::Index,
+                F::Bai,
+                C::None,
+                (None, None),
+            ),
+            (
+                "test/test_cram.cram",
+                Cat::Sequence,
+                F::Cram,
+                C::Other,
+                (Some(3), Some(0)),
+            ),
+            (
+                "test/test_cram.cram.crai",
+                Cat::Index,
+                F::Crai,
+                C::Gzip,
+                (None, None),
+            ),
+            (
+                "test/test_cram.sam",
+                Cat::Sequence,
+                F::Sam,
+                C::None,
+                (Some(1), Some(5)),
+            ),
+            (
+                "test/test_cram.fa",
+                Cat::Sequence,
+                F::Fasta,
+                C::None,
+                (None, None),
+            ),
+            (
+                "test/test_cram.fa.fai",
+                Cat::Index,
+                F::Fai,
+                C::None,
+                (None, None),
+            ),
+            (
+                "test/test.bcf",
+                Cat::Variant,
+                F::Bcf,
+                C::Bgzf,
+                (Some(2), Some(2)),
+            ),
+            (
+                "test/test.bcf.csi",
+                Cat::Index,
+                F::Csi,
+                C::Bgzf,
+                (Some(1), None),
+            ),
+            (
+                "test/test_string.vcf",
+                Cat::Variant,
+                F::Vcf,
+                C::None,
+                (Some(4), Some(1)),
+            ),
+            (
+                "test/test_left.vcf.gz",
+                Cat::Variant,
+                F::Vcf,
+                C::Bgzf,
+                (Some(4), Some(1)),
+            ),
+            (
+                "test/test_left.vcf.gz.tbi",
+                Cat::Index,
+                F::Tbi,
+                C::Bgzf,
+                (None, None),
+            ),
+            (
+                "test/bgzip/gzip.vcf.gz",
+                Cat::Variant,
+                F::Vcf,
+                C::Gzip,
+                (Some(4), Some(1)),
+            ),
+            (
+                "test/tabix_reader/test_bed3.bed.gz",
+                Cat::Region,
+                F::Bed,
+                C::Bgzf,
+                (None, None),
+            ),
+            ("Cargo.toml", Cat::Unknown, F::Text, C::None, (None, None)),
+        ] {
+            let info = detect_format(path).unwrap();
+            assert_eq!(
+                info,
+                FormatInfo {
+                    category,
+                    format,
+                    compression,
+                    version_major: version.0,
+                    version_minor: version.1,
+                },
+                "{}",
+                path
+            );
+        }
+
+        assert_eq!(
+            detect_format("test/nonexistent.bam"),
+            Err(Error::FileNotFound {
+                path: "test/nonexistent.bam".into()
+            })
+        );
+        assert_eq!(
+            detect_format("test"),
+            Err(Error::DetectFormat {
+                target: "test".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_phred_ascii_round_trip() {
+        let qual: Vec<u8> = (0..=MAX_PHRED_QUAL).collect();
+        let text = phred_to_ascii(&qual, PHRED33);
+        assert_eq!(text.first(), Some(&b'!'));
+        assert_eq!(text.last(), Some(&b'~'));
+        assert_eq!(ascii_to_phred(&text, PHRED33).unwrap(), qual);
+
+        // legacy offset, for the qualities it can represent
+        let qual: Vec<u8> = (0..=b'~' - PHRED64).collect();
+        let mut text = phred_to_ascii(&qual, PHRED64);
+        assert_eq!(text.first(), Some(&b'@'));
+        ascii_to_phred_in_place(&mut text, PHRED64).unwrap();
+        assert_eq!(text, qual);
+
+        // every character either decodes to the quality it encodes, or is rejected
+        for offset in [PHRED33, PHRED64] {
+            for c in 0..=u8::MAX {
+                match ascii_to_phred(&[c], offset) {
+                    Ok(q) => {
+                        assert!(c >= offset && c <= b'~' && c - offset <= MAX_PHRED_QUAL);
+                        assert_eq!(phred_to_ascii(&q, offset), [c]);
+                    }
+                    Err(e) => {
+                        assert!(c < offset || c > b'~');
+                        assert_eq!(
+                            e,
+                            Error::InvalidQuality {
+                                character: char::from(c),
+                                position: 0,
+                                offset
+                            }
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_phred_ascii_invalid() {
+        assert_eq!(phred_to_ascii(&[94, 255], PHRED33), b"~~");
+        assert_eq!(
+            ascii_to_phred(b"II ", PHRED33),
+            Err(Error::InvalidQuality {
+                character: ' ',
+                position: 2,
+                offset: PHRED33
+            })
+        );
+        assert!(ascii_to_phred(b"II5", PHRED64).is_err());
+        assert!(ascii_to_phred(b"", PHRED33).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mean_qual() {
+        assert!((mean_qual(&[10, 30]) - 12.967).abs() < 0.001);
+        assert_eq!(mean_qual_naive(&[10, 30]), 20.0);
+        for q in 0..=MAX_PHRED_QUAL {
+            let qual = [q; 7];
+            assert!((mean_qual(&qual) - f64::from(q)).abs() < 1e-9);
+            assert!((mean_qual_naive(&qual) - f64::from(q)).abs() < 1e-9);
+            // the mean error probability is dominated by the worst base
+            assert!(mean_qual(&[q, MAX_PHRED_QUAL]) <= mean_qual_naive(&[q, MAX_PHRED_QUAL]));
+        }
+        assert!(mean_qual(&[]).is_nan());
+        assert!(mean_qual_naive(&[]).is_nan());
+    }
+
+    #[test]
+    fn test_log_level() {
+        let default = log_level();
+        assert_eq!(default, LogLevel::Warning);
+        for level in [
+            LogLevel::Off,
+            LogLevel::Error,
+            LogLevel::Info,
+            LogLevel::Debug,
+            LogLevel::Trace,
+        ] {
+            set_log_level(level);
+            assert_eq!(log_level(), level);
+        }
+        set_log_level(default);
+    }
+}